@@ -87,6 +87,10 @@ mod bindings {
             "XorNameArray",
             JavaType::Array(Box::new(JavaType::Primitive(Primitive::Byte))),
         );
+        type_map.insert(
+            "Sha3Hash",
+            JavaType::Array(Box::new(JavaType::Primitive(Primitive::Byte))),
+        );
         type_map.insert(
             "SignSecretKey",
             JavaType::Array(Box::new(JavaType::Primitive(Primitive::Byte))),
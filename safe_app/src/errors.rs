@@ -77,11 +77,15 @@ mod codes {
     pub const ERR_SHARE_MDATA_DENIED: i32 = -206;
     pub const ERR_INVALID_OWNER: i32 = -207;
     pub const ERR_INCOMPATIBLE_MOCK_STATUS: i32 = -208;
+    pub const ERR_TRANS_OWNERSHIP_DENIED: i32 = -209;
+    pub const ERR_UNSUPPORTED_DATA_ITEM: i32 = -210;
 
     // NFS errors.
     pub const ERR_FILE_EXISTS: i32 = -300;
     pub const ERR_FILE_NOT_FOUND: i32 = -301;
     pub const ERR_INVALID_RANGE: i32 = -302;
+    pub const ERR_TOO_MANY_LINKS: i32 = -303;
+    pub const ERR_CONTENT_HASH_MISMATCH: i32 = -304;
 
     // App errors
     pub const ERR_NO_SUCH_CONTAINER: i32 = -1002;
@@ -102,6 +106,8 @@ mod codes {
     pub const ERR_INVALID_SIGN_SEC_KEY_HANDLE: i32 = -1017;
     pub const ERR_UNREGISTERED_CLIENT_ACCESS: i32 = -1018;
     pub const ERR_INVALID_PUB_KEY_HANDLE: i32 = -1019;
+    pub const ERR_INVALID_DIR_WATCHER_HANDLE: i32 = -1020;
+    pub const ERR_INVALID_ADATA_TAILER_HANDLE: i32 = -1021;
 
     pub const ERR_UNEXPECTED: i32 = -2000;
 
@@ -172,6 +178,10 @@ pub enum AppError {
     InvalidPubKeyHandle,
     /// Invalid file writer handle.
     InvalidFileContextHandle,
+    /// Invalid directory watcher handle.
+    InvalidDirWatcherHandle,
+    /// Invalid append-only data tailer handle.
+    InvalidADataTailerHandle,
 
     /// Error while self-encrypting data.
     SelfEncryption(SelfEncryptionError<SelfEncryptionStorageError>),
@@ -222,6 +232,10 @@ impl Display for AppError {
             Self::InvalidPubKeyHandle => write!(formatter, "Invalid public key handle"),
             Self::InvalidEncryptSecKeyHandle => write!(formatter, "Invalid secret key handle"),
             Self::InvalidFileContextHandle => write!(formatter, "Invalid file context handle"),
+            Self::InvalidDirWatcherHandle => write!(formatter, "Invalid directory watcher handle"),
+            Self::InvalidADataTailerHandle => {
+                write!(formatter, "Invalid append-only data tailer handle")
+            }
             Self::SelfEncryption(ref error) => {
                 write!(formatter, "Self-encryption error: {}", error)
             }
@@ -360,12 +374,16 @@ impl ErrorCode for AppError {
                 IpcError::ShareMDataDenied => ERR_SHARE_MDATA_DENIED,
                 IpcError::InvalidOwner(..) => ERR_INVALID_OWNER,
                 IpcError::IncompatibleMockStatus => ERR_INCOMPATIBLE_MOCK_STATUS,
+                IpcError::TransOwnershipDenied => ERR_TRANS_OWNERSHIP_DENIED,
+                IpcError::UnsupportedDataItem(..) => ERR_UNSUPPORTED_DATA_ITEM,
             },
             Self::NfsError(ref err) => match *err {
                 NfsError::CoreError(ref err) => core_error_code(err),
                 NfsError::FileExists => ERR_FILE_EXISTS,
                 NfsError::FileNotFound => ERR_FILE_NOT_FOUND,
                 NfsError::InvalidRange => ERR_INVALID_RANGE,
+                NfsError::TooManyLinks => ERR_TOO_MANY_LINKS,
+                NfsError::ContentHashMismatch => ERR_CONTENT_HASH_MISMATCH,
                 NfsError::EncodeDecodeError(_) => ERR_ENCODE_DECODE_ERROR,
                 NfsError::SelfEncryption(_) => ERR_SELF_ENCRYPTION,
                 NfsError::Unexpected(_) => ERR_UNEXPECTED,
@@ -384,6 +402,8 @@ impl ErrorCode for AppError {
             Self::InvalidEncryptSecKeyHandle => ERR_INVALID_ENCRYPT_SEC_KEY_HANDLE,
             Self::InvalidPubKeyHandle => ERR_INVALID_PUB_KEY_HANDLE,
             Self::InvalidFileContextHandle => ERR_INVALID_FILE_CONTEXT_HANDLE,
+            Self::InvalidDirWatcherHandle => ERR_INVALID_DIR_WATCHER_HANDLE,
+            Self::InvalidADataTailerHandle => ERR_INVALID_ADATA_TAILER_HANDLE,
             Self::InvalidFileMode => ERR_INVALID_FILE_MODE,
             Self::UnregisteredClientAccess => ERR_UNREGISTERED_CLIENT_ACCESS,
             Self::SelfEncryption(_) => ERR_SELF_ENCRYPTION,
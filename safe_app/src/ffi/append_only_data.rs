@@ -0,0 +1,143 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use crate::errors::AppError;
+use crate::ffi::helper::send_sync;
+use crate::ffi::object_cache::ADataTailerHandle;
+use crate::App;
+use ffi_utils::{catch_unwind_cb, FfiResult, OpaqueCtx, FFI_RESULT_OK};
+use futures::sync::oneshot;
+use futures::{Future, Stream};
+use safe_core::client::tail::{self, TailOptions};
+use safe_core::ffi::arrays::XorNameArray;
+use safe_core::ffi::ADataEntry;
+use safe_core::FutureExt;
+use safe_nd::{ADataAddress, XorName};
+use std::os::raw::c_void;
+use std::ptr;
+use std::time::Duration;
+
+/// Stops tailing started by `adata_tail()` when removed from the object cache.
+pub struct ADataTailer {
+    _stop_tx: oneshot::Sender<()>,
+}
+
+/// Tail the append-only data for new entries, starting with the entry at index `from`.
+///
+/// The data is polled every `min_interval_ms` milliseconds after new entries were appended, with
+/// the interval doubling while nothing is appended, up to `max_interval_ms`. Intervals shorter
+/// than 10 milliseconds are raised to it, and `min_interval_ms` can't be greater than
+/// `max_interval_ms`. `o_cb` receives the handle of the tailer, and `o_entry` is then called for
+/// every entry, in order, until the tailer is stopped with `adata_tail_stop()`. If tailing stops
+/// on its own, the handle of the tailer is freed and `o_entry` is called a last time, either with
+/// the error if polling failed, or with a null entry if the client was dropped.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn adata_tail(
+    app: *const App,
+    name: *const XorNameArray,
+    type_tag: u64,
+    is_pub: bool,
+    is_seq: bool,
+    from: u64,
+    min_interval_ms: u64,
+    max_interval_ms: u64,
+    user_data: *mut c_void,
+    o_entry: extern "C" fn(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        entry: *const ADataEntry,
+    ),
+    o_cb: extern "C" fn(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        tailer_h: ADataTailerHandle,
+    ),
+) {
+    catch_unwind_cb(user_data, o_cb, || -> Result<_, AppError> {
+        let name = XorName(*name);
+        let address = match (is_pub, is_seq) {
+            (true, true) => ADataAddress::PubSeq {
+                name,
+                tag: type_tag,
+            },
+            (true, false) => ADataAddress::PubUnseq {
+                name,
+                tag: type_tag,
+            },
+            (false, true) => ADataAddress::UnpubSeq {
+                name,
+                tag: type_tag,
+            },
+            (false, false) => ADataAddress::UnpubUnseq {
+                name,
+                tag: type_tag,
+            },
+        };
+        let options = TailOptions {
+            min_interval: Duration::from_millis(min_interval_ms),
+            max_interval: Duration::from_millis(max_interval_ms),
+        }
+        .checked()?;
+        let user_data = OpaqueCtx(user_data);
+
+        (*app).send(move |client, context| {
+            let context = context.clone();
+            let (stop_tx, stop_rx) = oneshot::channel();
+            let handle = context
+                .object_cache()
+                .insert_adata_tailer(ADataTailer { _stop_tx: stop_tx });
+            o_cb(user_data.0, FFI_RESULT_OK, handle);
+
+            let entries = tail::tail(client.clone(), address, from, options)
+                .map_err(AppError::from)
+                .for_each(move |(index, entry)| {
+                    let entry = ADataEntry {
+                        index,
+                        key: entry.key.as_ptr(),
+                        key_len: entry.key.len(),
+                        value: entry.value.as_ptr(),
+                        value_len: entry.value.len(),
+                    };
+                    o_entry(user_data.0, FFI_RESULT_OK, &entry);
+                    Ok(())
+                })
+                .then(move |result| {
+                    let _ = context.object_cache().remove_adata_tailer(handle);
+                    match result {
+                        Ok(()) => o_entry(user_data.0, FFI_RESULT_OK, ptr::null()),
+                        Err(err) => {
+                            call_result_cb!(Err::<(), _>(err), user_data, o_entry);
+                        }
+                    }
+                    Ok::<_, ()>(())
+                });
+            // The sender is dropped when the tailer is removed from the object cache.
+            let stopped = stop_rx.then(|_| Ok(()));
+
+            Some(entries.select(stopped).then(|_| Ok(())).into_box())
+        })
+    })
+}
+
+/// Stop tailing the append-only data and free the handle of the tailer.
+#[no_mangle]
+pub unsafe extern "C" fn adata_tail_stop(
+    app: *const App,
+    tailer_h: ADataTailerHandle,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        send_sync(app, user_data, o_cb, move |_, context| {
+            let _ = context.object_cache().remove_adata_tailer(tailer_h)?;
+            Ok(())
+        })
+    })
+}
@@ -13,6 +13,8 @@
 
 /// Access container.
 pub mod access_container;
+/// Low level manipulation of `AppendOnlyData`.
+pub mod append_only_data;
 /// Cipher options operations.
 pub mod cipher_opt;
 /// Crypto-related routines.
@@ -28,15 +28,19 @@ use ffi_utils::{
 };
 use futures::Future;
 use safe_core::ffi::ipc::req::PermissionSet;
+use safe_core::ffi::ipc::resp::MDataEntry;
 use safe_core::ffi::ipc::resp::MDataKey;
 use safe_core::ffi::ipc::resp::MDataValue;
 use safe_core::ffi::MDataInfo;
 use safe_core::ipc::req::{permission_set_clone_from_repr_c, permission_set_into_repr_c};
-use safe_core::ipc::resp::{MDataKey as NativeMDataKey, MDataValue as NativeMDataValue};
-use safe_core::Client;
+use safe_core::ipc::resp::{
+    MDataEntry as NativeMDataEntry, MDataKey as NativeMDataKey, MDataValue as NativeMDataValue,
+};
+use safe_core::{Client, CoreError};
 use safe_core::{FutureExt, MDataInfo as NativeMDataInfo};
-use safe_nd::SeqMutableData;
+use safe_nd::{MDataEntries, SeqMutableData};
 use std::os::raw::c_void;
+use std::ptr;
 
 /// Special value that represents an empty permission set.
 #[no_mangle]
@@ -224,6 +228,95 @@ pub unsafe extern "C" fn mdata_entries(
     })
 }
 
+/// Get a page of at most `limit` entries of the sequenced mutable data, in the order of their keys,
+/// following the key `cursor`, or starting with the first entry if `cursor` is null.
+///
+/// The callback receives the cursor of the next page, which is null if this is the last page.
+///
+/// Please notice that if the entries are fetched from a private `MutableData`,
+/// they're not automatically decrypted.
+///
+/// The network doesn't support paging yet, so outside of the mock network every call still
+/// fetches all the entries and only returns the requested page of them. Iterating all the pages of
+/// `n` entries this way transfers `n * n / limit` entries in total, so the `limit` should be large
+/// enough for the whole data to fit in a few pages; `mdata_entries` fetches all the entries in a
+/// single call.
+#[no_mangle]
+pub unsafe extern "C" fn seq_mdata_list_entries_page(
+    app: *const App,
+    info: *const MDataInfo,
+    cursor: *const u8,
+    cursor_len: usize,
+    limit: usize,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        entries: *const MDataEntry,
+        entries_len: usize,
+        next: *const u8,
+        next_len: usize,
+    ),
+) {
+    let user_data = OpaqueCtx(user_data);
+
+    catch_unwind_cb(user_data, o_cb, || {
+        let info = NativeMDataInfo::clone_from_repr_c(info)?;
+        let cursor = if cursor.is_null() {
+            None
+        } else {
+            Some(vec_clone_from_raw_parts(cursor, cursor_len))
+        };
+
+        (*app).send(move |client, _context| {
+            client
+                .list_mdata_entries_page(*info.address(), cursor, limit)
+                .map_err(AppError::from)
+                .and_then(|page| match page.entries {
+                    MDataEntries::Seq(entries) => Ok((entries, page.next)),
+                    MDataEntries::Unseq(_) => {
+                        Err(AppError::from(CoreError::ReceivedUnexpectedData))
+                    }
+                })
+                .then(move |result| {
+                    match result {
+                        Ok((entries, next)) => {
+                            let repr_c: Vec<_> = entries
+                                .into_iter()
+                                .map(|(key, value)| {
+                                    NativeMDataEntry {
+                                        key: NativeMDataKey(key),
+                                        value: NativeMDataValue::from_routing(value),
+                                    }
+                                    .into_repr_c()
+                                })
+                                .collect();
+                            let (next, next_len) = match next {
+                                Some(ref next) => (next.as_safe_ptr(), next.len()),
+                                None => (ptr::null(), 0),
+                            };
+
+                            o_cb(
+                                user_data.0,
+                                FFI_RESULT_OK,
+                                repr_c.as_safe_ptr(),
+                                repr_c.len(),
+                                next,
+                                next_len,
+                            )
+                        }
+                        Err(..) => {
+                            call_result_cb!(result, user_data, o_cb);
+                        }
+                    }
+                    Ok(())
+                })
+                .into_box()
+                .into()
+        })
+    })
+}
+
 /// Get list of all keys in the mutable data.
 #[no_mangle]
 pub unsafe extern "C" fn mdata_list_keys(
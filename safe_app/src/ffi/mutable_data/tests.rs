@@ -7,7 +7,7 @@
 // specific language governing permissions and limitations relating to use of the SAFE Network
 // Software.
 
-use crate::errors::{ERR_ACCESS_DENIED, ERR_INVALID_SUCCESSOR, ERR_NO_SUCH_ENTRY};
+use crate::errors::{AppError, ERR_ACCESS_DENIED, ERR_INVALID_SUCCESSOR, ERR_NO_SUCH_ENTRY};
 use crate::ffi::mdata_info::*;
 use crate::ffi::mutable_data::entries::*;
 use crate::ffi::mutable_data::entry_actions::*;
@@ -21,12 +21,18 @@ use ffi_utils::test_utils::{
     call_0, call_1, call_vec, call_vec_u8, send_via_user_data, sender_as_user_data,
 };
 use ffi_utils::{vec_clone_from_raw_parts, FfiResult};
+use futures::Future;
 use safe_core::ffi::ipc::req::PermissionSet as FfiPermissionSet;
+use safe_core::ffi::ipc::resp::MDataEntry;
 use safe_core::ffi::MDataInfo;
 use safe_core::ipc::req::{permission_set_clone_from_repr_c, permission_set_into_repr_c};
 use safe_core::ipc::resp::{MDataKey, MDataValue};
-use safe_core::MDataInfo as NativeMDataInfo;
-use safe_nd::{MDataAction, MDataPermissionSet};
+use safe_core::{Client, MDataInfo as NativeMDataInfo};
+use safe_nd::{
+    MDataAction, MDataKind, MDataPermissionSet, MDataSeqEntries, MDataSeqValue, SeqMutableData,
+};
+use std::ptr;
+use std::slice;
 use std::sync::mpsc;
 
 // The usual test to insert, update, delete and list all permissions from the FFI point of view.
@@ -635,3 +641,105 @@ fn entries_crud_ffi() {
         }
     }
 }
+
+// Test listing the entries of a mutable data page by page.
+#[test]
+fn entries_page_ffi() {
+    use std::os::raw::c_void;
+
+    type Page = (Vec<Vec<u8>>, Option<Vec<u8>>);
+
+    let app = create_app();
+    let info = unwrap!(NativeMDataInfo::random_public(MDataKind::Seq, 10_000));
+    let info2 = info.clone();
+
+    let entries: MDataSeqEntries = (0..5u8)
+        .map(|i| {
+            let value = MDataSeqValue {
+                data: vec![i],
+                version: 0,
+            };
+            (vec![i], value)
+        })
+        .collect();
+    let keys: Vec<_> = entries.keys().cloned().collect();
+
+    unwrap!(run(&app, move |client, _context| {
+        let permissions = btree_map![
+            client.public_key() => MDataPermissionSet::new().allow(MDataAction::Read)
+        ];
+        let data = SeqMutableData::new_with_data(
+            info2.name(),
+            info2.type_tag(),
+            entries,
+            permissions,
+            client.owner_key(),
+        );
+        client.put_seq_mutable_data(data).map_err(AppError::from)
+    }));
+
+    let info = info.into_repr_c();
+    let mut listed = Vec::new();
+    let mut cursor = None;
+
+    loop {
+        let (tx, rx) = mpsc::channel::<Result<Page, i32>>();
+        let mut ud = Default::default();
+
+        unsafe {
+            let (cursor_ptr, cursor_len) = match cursor {
+                Some(ref cursor) => (cursor.as_ptr(), cursor.len()),
+                None => (ptr::null(), 0),
+            };
+            seq_mdata_list_entries_page(
+                &app,
+                &info,
+                cursor_ptr,
+                cursor_len,
+                2,
+                sender_as_user_data(&tx, &mut ud),
+                page_cb,
+            )
+        };
+
+        let (mut page_keys, next) = unwrap!(unwrap!(rx.recv()));
+        assert!(page_keys.len() <= 2);
+        listed.append(&mut page_keys);
+
+        if next.is_none() {
+            break;
+        }
+        cursor = next;
+    }
+
+    assert_eq!(listed, keys);
+
+    extern "C" fn page_cb(
+        user_data: *mut c_void,
+        res: *const FfiResult,
+        entries: *const MDataEntry,
+        entries_len: usize,
+        next: *const u8,
+        next_len: usize,
+    ) {
+        unsafe {
+            let result: Result<Page, i32> = if (*res).error_code == 0 {
+                let entries = slice::from_raw_parts(entries, entries_len);
+                let keys = entries
+                    .iter()
+                    .map(|entry| vec_clone_from_raw_parts(entry.key.key, entry.key.key_len))
+                    .collect();
+                let next = if next.is_null() {
+                    None
+                } else {
+                    Some(vec_clone_from_raw_parts(next, next_len))
+                };
+                Ok((keys, next))
+            } else {
+                Err((*res).error_code)
+            };
+
+            send_via_user_data(user_data, result);
+        }
+    }
+}
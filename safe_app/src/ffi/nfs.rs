@@ -9,21 +9,35 @@
 
 use crate::client::AppClient;
 use crate::errors::AppError;
-use crate::ffi::helper::send;
-use crate::ffi::object_cache::FileContextHandle;
+use crate::ffi::helper::{send, send_sync};
+use crate::ffi::object_cache::{DirWatcherHandle, FileContextHandle};
 use crate::App;
 use ffi_utils::{
     catch_unwind_cb, vec_clone_from_raw_parts, FfiResult, OpaqueCtx, ReprC, SafePtr, FFI_RESULT_OK,
 };
 use futures::future::{self, Either};
-use futures::Future;
-use safe_core::ffi::nfs::File;
+use futures::sync::oneshot;
+use futures::{Future, Stream};
+use safe_core::ffi::nfs::{
+    DirEvent, File, FileEntry, FileVersion, ListOptions, PublishReport, TrashItem,
+};
 use safe_core::ffi::MDataInfo;
-use safe_core::nfs::file_helper::{self, Version};
-use safe_core::nfs::File as NativeFile;
-use safe_core::nfs::{Mode, Reader, Writer};
+use safe_core::nfs::conflict::{self, MergeStrategy, Resolution};
+use safe_core::nfs::file_helper::{
+    self, ListOptions as NativeListOptions, MoveRecovery, SortBy, Version,
+};
+use safe_core::nfs::file_history;
+use safe_core::nfs::link::{self, Link, Resolved};
+use safe_core::nfs::publish;
+use safe_core::nfs::trash::{self, TrashItem as NativeTrashItem};
+use safe_core::nfs::watch::{self, WatchOptions};
+use safe_core::nfs::{file_entries_into_vec, File as NativeFile};
+use safe_core::nfs::{Mode, Reader, Writer, DEFAULT_READ_AHEAD};
 use safe_core::{FutureExt, MDataInfo as NativeMDataInfo};
+use std::ffi::CString;
 use std::os::raw::{c_char, c_void};
+use std::ptr;
+use std::time::Duration;
 
 /// Holds context for file operations, depending on the mode.
 pub struct FileContext {
@@ -32,6 +46,11 @@ pub struct FileContext {
     original_file: NativeFile,
 }
 
+/// Stops a directory watcher started by `dir_watch()` when removed from the object cache.
+pub struct DirWatcher {
+    _stop_tx: oneshot::Sender<()>,
+}
+
 /// Constant to pass to `dir_update_file()` or `dir_delete_file()` when the next version should be
 /// retrieved and used automatically.
 pub const GET_NEXT_VERSION: u64 = 0;
@@ -44,9 +63,38 @@ pub static OPEN_MODE_OVERWRITE: u64 = 1;
 pub static OPEN_MODE_APPEND: u64 = 2;
 /// Open file to read.
 pub static OPEN_MODE_READ: u64 = 4;
+/// Modifies existing data in the file at arbitrary positions using `file_write_at()`, and allows
+/// truncating it with `file_truncate()`. Only the chunks affected by the changes are stored again.
+pub static OPEN_MODE_MODIFY: u64 = 8;
+/// Disables prefetching of the chunks following each read of a file opened with `OPEN_MODE_READ`,
+/// which suits random access.
+pub static OPEN_MODE_NO_READ_AHEAD: u64 = 16;
+/// Position of the open mode bits holding the number of chunks prefetched after each read, e.g.
+/// `OPEN_MODE_READ | (4 << OPEN_MODE_READ_AHEAD_SHIFT)`. If these bits are zero, a default of 2
+/// chunks is prefetched.
+pub static OPEN_MODE_READ_AHEAD_SHIFT: u64 = 8;
+/// Mask of the open mode bits holding the number of chunks prefetched after each read.
+pub static OPEN_MODE_READ_AHEAD_MASK: u64 = 0xff00;
 /// Read entire contents of a file.
 pub static FILE_READ_TO_END: u64 = 0;
 
+/// Overwrite a concurrent update of the file passed to `dir_update_file_with_policy()`.
+pub static MERGE_POLICY_LAST_WRITER_WINS: u64 = 0;
+/// Keep a concurrent update of the file passed to `dir_update_file_with_policy()`, and store the
+/// file under a new name, e.g. `name (conflict 2)`. The new version passed to the callback is 0.
+pub static MERGE_POLICY_KEEP_BOTH: u64 = 1;
+
+/// Sort listed files by name.
+pub static LIST_SORT_BY_NAME: u64 = 0;
+/// Sort listed files by size.
+pub static LIST_SORT_BY_SIZE: u64 = 1;
+/// Sort listed files by time of creation.
+pub static LIST_SORT_BY_CREATED: u64 = 2;
+/// Sort listed files by time of modification.
+pub static LIST_SORT_BY_MODIFIED: u64 = 3;
+/// Constant to use as the `limit` passed to `dir_list_files()` to return all matching files.
+pub static LIST_NO_LIMIT: u64 = 0;
+
 /// Retrieve file with the given name, and its version, from the directory.
 #[no_mangle]
 pub unsafe extern "C" fn dir_fetch_file(
@@ -82,6 +130,45 @@ pub unsafe extern "C" fn dir_fetch_file(
     })
 }
 
+/// List the files in the directory, together with their versions.
+///
+/// `options.sort_by` is one of the `LIST_SORT_BY_*` constants. If `options.prefix` is not null,
+/// only files whose names start with it are returned. `options.offset` matching files are skipped
+/// and at most `options.limit` files are returned, unless it is `LIST_NO_LIMIT`.
+#[no_mangle]
+pub unsafe extern "C" fn dir_list_files(
+    app: *const App,
+    parent_info: *const MDataInfo,
+    options: *const ListOptions,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        files: *const FileEntry,
+        files_len: usize,
+    ),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = NativeMDataInfo::clone_from_repr_c(parent_info)?;
+        let options = list_options_clone_from_repr_c(options)?;
+        let user_data = OpaqueCtx(user_data);
+
+        (*app).send(move |client, _| {
+            file_helper::list(client.clone(), parent_info, options)
+                .map_err(AppError::from)
+                .and_then(|files| file_entries_into_vec(files).map_err(AppError::from))
+                .map(move |files| {
+                    o_cb(user_data.0, FFI_RESULT_OK, files.as_safe_ptr(), files.len())
+                })
+                .map_err(move |err| {
+                    call_result_cb!(Err::<(), _>(err), user_data, o_cb);
+                })
+                .into_box()
+                .into()
+        })
+    })
+}
+
 /// Insert the file into the parent directory.
 #[no_mangle]
 pub unsafe extern "C" fn dir_insert_file(
@@ -105,6 +192,8 @@ pub unsafe extern "C" fn dir_insert_file(
 
 /// Replace the file in the parent directory.
 ///
+/// Symbolic links are followed, in which case the file they point to is replaced.
+/// If history is enabled for the file, the replaced version is recorded in it.
 /// If `version` is `GET_NEXT_VERSION`, the correct version is obtained automatically.
 #[no_mangle]
 pub unsafe extern "C" fn dir_update_file(
@@ -132,6 +221,85 @@ pub unsafe extern "C" fn dir_update_file(
     })
 }
 
+/// Replace the file in the parent directory, resolving a conflict with a concurrent update of the
+/// file according to `merge_policy`, which is one of the `MERGE_POLICY_*` constants.
+///
+/// If `version` is `GET_NEXT_VERSION`, the correct version is obtained automatically. The callback
+/// receives the name under which the file is stored, and the new version of its entry.
+#[no_mangle]
+pub unsafe extern "C" fn dir_update_file_with_policy(
+    app: *const App,
+    parent_info: *const MDataInfo,
+    file_name: *const c_char,
+    file: *const File,
+    version: u64,
+    merge_policy: u64,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        stored_name: *const c_char,
+        new_version: u64,
+    ),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = NativeMDataInfo::clone_from_repr_c(parent_info)?;
+        let file = NativeFile::clone_from_repr_c(file)?;
+        let file_name = String::clone_from_repr_c(file_name)?;
+        let user_data = OpaqueCtx(user_data);
+
+        let strategy = if merge_policy == MERGE_POLICY_LAST_WRITER_WINS {
+            MergeStrategy::LastWriterWins
+        } else if merge_policy == MERGE_POLICY_KEEP_BOTH {
+            MergeStrategy::KeepBoth
+        } else {
+            return Err(AppError::Unexpected(format!(
+                "Invalid merge policy: {}",
+                merge_policy
+            )));
+        };
+
+        (*app).send(move |client, _| {
+            let version = if version == GET_NEXT_VERSION {
+                Version::GetNext
+            } else {
+                Version::Custom(version)
+            };
+
+            conflict::update(
+                client.clone(),
+                parent_info,
+                file_name.clone(),
+                &file,
+                version,
+                strategy,
+            )
+            .map_err(AppError::from)
+            .and_then(move |resolution| {
+                let (stored_name, new_version) = match resolution {
+                    Resolution::Updated(version) | Resolution::Merged(version) => {
+                        (file_name, version)
+                    }
+                    Resolution::KeptBoth(name) => (name, 0),
+                };
+                let stored_name = CString::new(stored_name)?;
+                o_cb(
+                    user_data.0,
+                    FFI_RESULT_OK,
+                    stored_name.as_ptr(),
+                    new_version,
+                );
+                Ok(())
+            })
+            .map_err(move |err| {
+                call_result_cb!(Err::<(), _>(err), user_data, o_cb);
+            })
+            .into_box()
+            .into()
+        })
+    })
+}
+
 /// Delete the file in the parent directory.
 ///
 /// If `version` is `GET_NEXT_VERSION`, the correct version is obtained automatically.
@@ -162,6 +330,629 @@ pub unsafe extern "C" fn dir_delete_file(
     })
 }
 
+/// Rename the file in the parent directory.
+///
+/// The file is never lost or duplicated, even if the operation fails. If `version` is
+/// `GET_NEXT_VERSION`, the correct version of the old entry is obtained automatically.
+#[no_mangle]
+pub unsafe extern "C" fn dir_rename_file(
+    app: *const App,
+    parent_info: *const MDataInfo,
+    old_name: *const c_char,
+    new_name: *const c_char,
+    version: u64,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = NativeMDataInfo::clone_from_repr_c(parent_info)?;
+        let old_name = String::clone_from_repr_c(old_name)?;
+        let new_name = String::clone_from_repr_c(new_name)?;
+
+        send(app, user_data, o_cb, move |client, _| {
+            let version = if version == GET_NEXT_VERSION {
+                Version::GetNext
+            } else {
+                Version::Custom(version)
+            };
+            file_helper::rename(client.clone(), parent_info, old_name, new_name, version)
+        })
+    })
+}
+
+/// Copy the file into the destination directory without uploading its content again.
+#[no_mangle]
+pub unsafe extern "C" fn dir_copy_file(
+    app: *const App,
+    src_parent_info: *const MDataInfo,
+    src_name: *const c_char,
+    dst_parent_info: *const MDataInfo,
+    dst_name: *const c_char,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let src_parent_info = NativeMDataInfo::clone_from_repr_c(src_parent_info)?;
+        let src_name = String::clone_from_repr_c(src_name)?;
+        let dst_parent_info = NativeMDataInfo::clone_from_repr_c(dst_parent_info)?;
+        let dst_name = String::clone_from_repr_c(dst_name)?;
+
+        send(app, user_data, o_cb, move |client, _| {
+            file_helper::copy(
+                client.clone(),
+                src_parent_info,
+                src_name,
+                dst_parent_info,
+                dst_name,
+            )
+        })
+    })
+}
+
+/// Move the file into the destination directory.
+///
+/// If the move is interrupted, it can be completed with `dir_finish_move_file()` or undone with
+/// `dir_rollback_move_file()`, passing in the same arguments.
+#[no_mangle]
+pub unsafe extern "C" fn dir_move_file(
+    app: *const App,
+    src_parent_info: *const MDataInfo,
+    src_name: *const c_char,
+    dst_parent_info: *const MDataInfo,
+    dst_name: *const c_char,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let src_parent_info = NativeMDataInfo::clone_from_repr_c(src_parent_info)?;
+        let src_name = String::clone_from_repr_c(src_name)?;
+        let dst_parent_info = NativeMDataInfo::clone_from_repr_c(dst_parent_info)?;
+        let dst_name = String::clone_from_repr_c(dst_name)?;
+
+        send(app, user_data, o_cb, move |client, _| {
+            file_helper::move_file(
+                client.clone(),
+                src_parent_info,
+                src_name,
+                dst_parent_info,
+                dst_name,
+            )
+        })
+    })
+}
+
+/// Complete an interrupted `dir_move_file()`, so the file is only in the destination directory.
+#[no_mangle]
+pub unsafe extern "C" fn dir_finish_move_file(
+    app: *const App,
+    src_parent_info: *const MDataInfo,
+    src_name: *const c_char,
+    dst_parent_info: *const MDataInfo,
+    dst_name: *const c_char,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    recover_move_file(
+        app,
+        src_parent_info,
+        src_name,
+        dst_parent_info,
+        dst_name,
+        MoveRecovery::Finish,
+        user_data,
+        o_cb,
+    )
+}
+
+/// Undo an interrupted `dir_move_file()`, so the file is only in the source directory.
+#[no_mangle]
+pub unsafe extern "C" fn dir_rollback_move_file(
+    app: *const App,
+    src_parent_info: *const MDataInfo,
+    src_name: *const c_char,
+    dst_parent_info: *const MDataInfo,
+    dst_name: *const c_char,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    recover_move_file(
+        app,
+        src_parent_info,
+        src_name,
+        dst_parent_info,
+        dst_name,
+        MoveRecovery::RollBack,
+        user_data,
+        o_cb,
+    )
+}
+
+/// Enable version history for the file in the parent directory.
+///
+/// Once enabled, every update of the file records the replaced version in its history, which
+/// follows the file when it is renamed or moved.
+#[no_mangle]
+pub unsafe extern "C" fn dir_enable_file_history(
+    app: *const App,
+    parent_info: *const MDataInfo,
+    file_name: *const c_char,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = NativeMDataInfo::clone_from_repr_c(parent_info)?;
+        let file_name = String::clone_from_repr_c(file_name)?;
+
+        send(app, user_data, o_cb, move |client, _| {
+            file_history::enable(client.clone(), parent_info, file_name)
+        })
+    })
+}
+
+/// List the previous versions of the file in the parent directory, oldest first.
+///
+/// The returned files can be opened for reading with `file_open()`.
+#[no_mangle]
+pub unsafe extern "C" fn dir_list_file_versions(
+    app: *const App,
+    parent_info: *const MDataInfo,
+    file_name: *const c_char,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        versions: *const FileVersion,
+        versions_len: usize,
+    ),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = NativeMDataInfo::clone_from_repr_c(parent_info)?;
+        let file_name = String::clone_from_repr_c(file_name)?;
+        let user_data = OpaqueCtx(user_data);
+
+        (*app).send(move |client, _| {
+            file_history::list(client.clone(), parent_info, file_name)
+                .map(move |versions| {
+                    let versions: Vec<_> = versions
+                        .into_iter()
+                        .map(|(version, file)| FileVersion {
+                            version,
+                            file: file.into_repr_c(),
+                        })
+                        .collect();
+                    o_cb(
+                        user_data.0,
+                        FFI_RESULT_OK,
+                        versions.as_safe_ptr(),
+                        versions.len(),
+                    )
+                })
+                .map_err(AppError::from)
+                .map_err(move |err| {
+                    call_result_cb!(Err::<(), _>(err), user_data, o_cb);
+                })
+                .into_box()
+                .into()
+        })
+    })
+}
+
+/// Restore a previous version of the file in the parent directory.
+///
+/// `index` is the index of the version in the history, as listed by `dir_list_file_versions()`.
+/// The replaced version is recorded in the history.
+#[no_mangle]
+pub unsafe extern "C" fn dir_restore_file_version(
+    app: *const App,
+    parent_info: *const MDataInfo,
+    file_name: *const c_char,
+    index: u64,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult, new_version: u64),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = NativeMDataInfo::clone_from_repr_c(parent_info)?;
+        let file_name = String::clone_from_repr_c(file_name)?;
+
+        send(app, user_data, o_cb, move |client, _| {
+            file_history::restore(client.clone(), parent_info, file_name, index)
+        })
+    })
+}
+
+/// Insert a symbolic link into the parent directory, pointing to a path relative to it.
+///
+/// The target doesn't need to exist. Links are followed by `dir_fetch_file()` and
+/// `dir_resolve_path()`.
+#[no_mangle]
+pub unsafe extern "C" fn dir_insert_path_link(
+    app: *const App,
+    parent_info: *const MDataInfo,
+    link_name: *const c_char,
+    target_path: *const c_char,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = NativeMDataInfo::clone_from_repr_c(parent_info)?;
+        let link_name = String::clone_from_repr_c(link_name)?;
+        let target = Link::Path(String::clone_from_repr_c(target_path)?);
+
+        send(app, user_data, o_cb, move |client, _| {
+            link::symlink(client.clone(), parent_info, link_name, &target)
+        })
+    })
+}
+
+/// Insert a symbolic link into the parent directory, pointing to an entry in the target directory.
+///
+/// If `target_name` is null, the link points to the target directory itself. This allows linking
+/// to directories shared by other users without copying their content.
+#[no_mangle]
+pub unsafe extern "C" fn dir_insert_entry_link(
+    app: *const App,
+    parent_info: *const MDataInfo,
+    link_name: *const c_char,
+    target_info: *const MDataInfo,
+    target_name: *const c_char,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = NativeMDataInfo::clone_from_repr_c(parent_info)?;
+        let link_name = String::clone_from_repr_c(link_name)?;
+        let target = Link::Entry {
+            dir: NativeMDataInfo::clone_from_repr_c(target_info)?,
+            name: if target_name.is_null() {
+                None
+            } else {
+                Some(String::clone_from_repr_c(target_name)?)
+            },
+        };
+
+        send(app, user_data, o_cb, move |client, _| {
+            link::symlink(client.clone(), parent_info, link_name, &target)
+        })
+    })
+}
+
+/// Insert a hard link to the file into the destination directory.
+///
+/// Both directories must use the same encryption key. The data of an unpublished file is only
+/// deleted together with its last link.
+#[no_mangle]
+pub unsafe extern "C" fn dir_hard_link_file(
+    app: *const App,
+    src_parent_info: *const MDataInfo,
+    src_name: *const c_char,
+    dst_parent_info: *const MDataInfo,
+    dst_name: *const c_char,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let src_parent_info = NativeMDataInfo::clone_from_repr_c(src_parent_info)?;
+        let src_name = String::clone_from_repr_c(src_name)?;
+        let dst_parent_info = NativeMDataInfo::clone_from_repr_c(dst_parent_info)?;
+        let dst_name = String::clone_from_repr_c(dst_name)?;
+
+        send(app, user_data, o_cb, move |client, _| {
+            link::hard_link(
+                client.clone(),
+                src_parent_info,
+                src_name,
+                dst_parent_info,
+                dst_name,
+            )
+        })
+    })
+}
+
+/// Resolve a path relative to the parent directory, following any links on the way.
+///
+/// If the path points to a file, the callback receives the directory containing it and its entry.
+/// If it points to a directory, the callback receives that directory and a null entry.
+#[no_mangle]
+pub unsafe extern "C" fn dir_resolve_path(
+    app: *const App,
+    parent_info: *const MDataInfo,
+    path: *const c_char,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        dir_info: *const MDataInfo,
+        entry: *const FileEntry,
+    ),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = NativeMDataInfo::clone_from_repr_c(parent_info)?;
+        let path = String::clone_from_repr_c(path)?;
+        let user_data = OpaqueCtx(user_data);
+
+        (*app).send(move |client, _| {
+            link::resolve(client.clone(), parent_info, path)
+                .map_err(AppError::from)
+                .and_then(move |resolved| {
+                    match resolved {
+                        Resolved::Dir(dir) => {
+                            let dir = dir.into_repr_c();
+                            o_cb(user_data.0, FFI_RESULT_OK, &dir, ptr::null())
+                        }
+                        Resolved::File {
+                            parent,
+                            name,
+                            version,
+                            file,
+                        } => {
+                            let parent = parent.into_repr_c();
+                            let entries = file_entries_into_vec(Some((name, version, file)))?;
+                            o_cb(user_data.0, FFI_RESULT_OK, &parent, entries.as_ptr())
+                        }
+                    }
+                    Ok(())
+                })
+                .map_err(move |err| {
+                    call_result_cb!(Err::<(), _>(err), user_data, o_cb);
+                })
+                .into_box()
+                .into()
+        })
+    })
+}
+
+/// Publish the unpublished file in the parent directory, without uploading its content again.
+///
+/// If `delete_originals` is true, the unpublished data is deleted afterwards, unless it is shared
+/// with hard links. `o_cb` receives the new version of the entry and the cost of publishing.
+#[no_mangle]
+pub unsafe extern "C" fn dir_publish_file(
+    app: *const App,
+    parent_info: *const MDataInfo,
+    file_name: *const c_char,
+    delete_originals: bool,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        report: *const PublishReport,
+    ),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = NativeMDataInfo::clone_from_repr_c(parent_info)?;
+        let file_name = String::clone_from_repr_c(file_name)?;
+        let user_data = OpaqueCtx(user_data);
+
+        (*app).send(move |client, _| {
+            publish::publish(client.clone(), parent_info, file_name, delete_originals)
+                .map(move |report| {
+                    let report = report.into_repr_c();
+                    o_cb(user_data.0, FFI_RESULT_OK, &report);
+                })
+                .map_err(AppError::from)
+                .map_err(move |err| {
+                    call_result_cb!(Err::<(), _>(err), user_data, o_cb);
+                })
+                .into_box()
+                .into()
+        })
+    })
+}
+
+/// Move the file in the parent directory into the trash directory, normally the `_trash`
+/// container.
+///
+/// Expired items aren't purged here; use `trash_purge` for that. `o_cb` receives the ID of the new
+/// trash item, or null if the entry was a symbolic link, which is deleted immediately instead.
+#[no_mangle]
+pub unsafe extern "C" fn dir_trash_file(
+    app: *const App,
+    parent_info: *const MDataInfo,
+    file_name: *const c_char,
+    trash_info: *const MDataInfo,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult, item_id: *const c_char),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = NativeMDataInfo::clone_from_repr_c(parent_info)?;
+        let file_name = String::clone_from_repr_c(file_name)?;
+        let trash_info = NativeMDataInfo::clone_from_repr_c(trash_info)?;
+        let user_data = OpaqueCtx(user_data);
+
+        (*app).send(move |client, _| {
+            trash::soft_delete(client.clone(), parent_info, file_name, trash_info)
+                .map_err(AppError::from)
+                .and_then(move |id| {
+                    let id = id.map(CString::new).transpose()?;
+                    o_cb(
+                        user_data.0,
+                        FFI_RESULT_OK,
+                        id.as_ref().map_or(ptr::null(), |id| id.as_ptr()),
+                    );
+                    Ok(())
+                })
+                .map_err(move |err| {
+                    call_result_cb!(Err::<(), _>(err), user_data, o_cb);
+                })
+                .into_box()
+                .into()
+        })
+    })
+}
+
+/// List the items in the trash directory, sorted by the time of deletion, oldest first.
+#[no_mangle]
+pub unsafe extern "C" fn trash_list_items(
+    app: *const App,
+    trash_info: *const MDataInfo,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        items: *const TrashItem,
+        items_len: usize,
+    ),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let trash_info = NativeMDataInfo::clone_from_repr_c(trash_info)?;
+        let user_data = OpaqueCtx(user_data);
+
+        (*app).send(move |client, _| {
+            trash::list(client.clone(), trash_info)
+                .map_err(AppError::from)
+                .and_then(move |items| {
+                    let items = items
+                        .into_iter()
+                        .map(NativeTrashItem::into_repr_c)
+                        .collect::<Result<Vec<_>, _>>()?;
+                    o_cb(user_data.0, FFI_RESULT_OK, items.as_safe_ptr(), items.len());
+                    Ok(())
+                })
+                .map_err(move |err| {
+                    call_result_cb!(Err::<(), _>(err), user_data, o_cb);
+                })
+                .into_box()
+                .into()
+        })
+    })
+}
+
+/// Restore the item from the trash directory into the directory it was deleted from, under its
+/// original name.
+#[no_mangle]
+pub unsafe extern "C" fn trash_restore_item(
+    app: *const App,
+    trash_info: *const MDataInfo,
+    item_id: *const c_char,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let trash_info = NativeMDataInfo::clone_from_repr_c(trash_info)?;
+        let item_id = String::clone_from_repr_c(item_id)?;
+
+        send(app, user_data, o_cb, move |client, _| {
+            trash::restore(client.clone(), trash_info, item_id)
+        })
+    })
+}
+
+/// Permanently delete the items which have been in the trash directory for longer than
+/// `retention_secs` seconds. `o_cb` receives the number of deleted items.
+#[no_mangle]
+pub unsafe extern "C" fn trash_purge(
+    app: *const App,
+    trash_info: *const MDataInfo,
+    retention_secs: u64,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult, purged: u64),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let trash_info = NativeMDataInfo::clone_from_repr_c(trash_info)?;
+        let retention = Duration::from_secs(retention_secs);
+
+        send(app, user_data, o_cb, move |client, _| {
+            trash::purge(client.clone(), trash_info, retention).map(|purged| purged as u64)
+        })
+    })
+}
+
+/// Permanently delete all items from the trash directory. `o_cb` receives the number of deleted
+/// items.
+#[no_mangle]
+pub unsafe extern "C" fn trash_empty(
+    app: *const App,
+    trash_info: *const MDataInfo,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult, emptied: u64),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let trash_info = NativeMDataInfo::clone_from_repr_c(trash_info)?;
+
+        send(app, user_data, o_cb, move |client, _| {
+            trash::empty(client.clone(), trash_info).map(|emptied| emptied as u64)
+        })
+    })
+}
+
+/// Watch the parent directory for changes of its files.
+///
+/// The directory is polled every `min_interval_ms` milliseconds after a change, with the interval
+/// doubling while nothing changes, up to `max_interval_ms`. Intervals shorter than 10 milliseconds
+/// are raised to it, and `min_interval_ms` can't be greater than `max_interval_ms`. `o_cb`
+/// receives the handle of the watcher, and `o_event` is then called for every change until the
+/// watcher is stopped with `dir_watch_stop()`. If polling fails, the handle of the watcher is
+/// freed and `o_event` receives the error.
+#[no_mangle]
+pub unsafe extern "C" fn dir_watch(
+    app: *const App,
+    parent_info: *const MDataInfo,
+    min_interval_ms: u64,
+    max_interval_ms: u64,
+    user_data: *mut c_void,
+    o_event: extern "C" fn(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        event: *const DirEvent,
+    ),
+    o_cb: extern "C" fn(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        watcher_h: DirWatcherHandle,
+    ),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let parent_info = NativeMDataInfo::clone_from_repr_c(parent_info)?;
+        let options = WatchOptions {
+            min_interval: Duration::from_millis(min_interval_ms),
+            max_interval: Duration::from_millis(max_interval_ms),
+        }
+        .checked()?;
+        let user_data = OpaqueCtx(user_data);
+
+        (*app).send(move |client, context| {
+            let context = context.clone();
+            let (stop_tx, stop_rx) = oneshot::channel();
+            let handle = context
+                .object_cache()
+                .insert_dir_watcher(DirWatcher { _stop_tx: stop_tx });
+            o_cb(user_data.0, FFI_RESULT_OK, handle);
+
+            let events = watch::watch(client.clone(), parent_info, options)
+                .map_err(AppError::from)
+                .for_each(move |event| {
+                    let event = event.into_repr_c()?;
+                    o_event(user_data.0, FFI_RESULT_OK, &event);
+                    Ok(())
+                })
+                .map_err(move |err| {
+                    let _ = context.object_cache().remove_dir_watcher(handle);
+                    call_result_cb!(Err::<(), _>(err), user_data, o_event);
+                });
+            // The sender is dropped when the watcher is removed from the object cache.
+            let stopped = stop_rx.then(|_| Ok(()));
+
+            Some(events.select(stopped).then(|_| Ok(())).into_box())
+        })
+    })
+}
+
+/// Stop the directory watcher and free its handle.
+#[no_mangle]
+pub unsafe extern "C" fn dir_watch_stop(
+    app: *const App,
+    watcher_h: DirWatcherHandle,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        send_sync(app, user_data, o_cb, move |_, context| {
+            let _ = context.object_cache().remove_dir_watcher(watcher_h)?;
+            Ok(())
+        })
+    })
+}
+
 /// Open the file to read or write its contents.
 #[no_mangle]
 pub unsafe extern "C" fn file_open(
@@ -186,16 +977,23 @@ pub unsafe extern "C" fn file_open(
 
             // Initialise the reader if OPEN_MODE_READ is requested.
             let reader = if open_mode & OPEN_MODE_READ != 0 {
+                let read_ahead = read_ahead(open_mode);
                 let fut = file_helper::read(client.clone(), &file, parent_info.enc_key().cloned())
-                    .map(Some);
+                    .map(move |mut reader| {
+                        reader.set_read_ahead(read_ahead);
+                        Some(reader)
+                    });
                 Either::A(fut)
             } else {
                 Either::B(future::ok(None))
             };
 
             // Initialise the writer if one of write modes is requested.
-            let writer = if open_mode & (OPEN_MODE_OVERWRITE | OPEN_MODE_APPEND) != 0 {
-                let writer_mode = if open_mode & OPEN_MODE_APPEND != 0 {
+            let write_modes = OPEN_MODE_OVERWRITE | OPEN_MODE_APPEND | OPEN_MODE_MODIFY;
+            let writer = if open_mode & write_modes != 0 {
+                let writer_mode = if open_mode & OPEN_MODE_MODIFY != 0 {
+                    Mode::Modify
+                } else if open_mode & OPEN_MODE_APPEND != 0 {
                     Mode::Append
                 } else {
                     Mode::Overwrite
@@ -329,6 +1127,78 @@ pub unsafe extern "C" fn file_write(
     })
 }
 
+/// Write data to file starting at the given position.
+///
+/// Unless the file was opened with `OPEN_MODE_MODIFY`, the position must be at the end of the data
+/// written so far.
+#[no_mangle]
+pub unsafe extern "C" fn file_write_at(
+    app: *const App,
+    file_h: FileContextHandle,
+    position: u64,
+    data: *const u8,
+    data_len: usize,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let user_data = OpaqueCtx(user_data);
+        let data = vec_clone_from_raw_parts(data, data_len);
+
+        (*app).send(move |_client, context| {
+            let file_ctx = try_cb!(context.object_cache().get_file(file_h), user_data, o_cb);
+
+            if let Some(ref writer) = file_ctx.writer {
+                writer
+                    .write_at(&data, position)
+                    .then(move |res| {
+                        call_result_cb!(res.map_err(AppError::from), user_data, o_cb);
+                        Ok(())
+                    })
+                    .into_box()
+                    .into()
+            } else {
+                call_result_cb!(Err::<(), _>(AppError::InvalidFileMode), user_data, o_cb);
+                None
+            }
+        })
+    })
+}
+
+/// Truncate the file to the given size, or extend it with zeros if it is smaller.
+///
+/// The file must have been opened with `OPEN_MODE_MODIFY`.
+#[no_mangle]
+pub unsafe extern "C" fn file_truncate(
+    app: *const App,
+    file_h: FileContextHandle,
+    size: u64,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let user_data = OpaqueCtx(user_data);
+
+        (*app).send(move |_client, context| {
+            let file_ctx = try_cb!(context.object_cache().get_file(file_h), user_data, o_cb);
+
+            if let Some(ref writer) = file_ctx.writer {
+                writer
+                    .truncate(size)
+                    .then(move |res| {
+                        call_result_cb!(res.map_err(AppError::from), user_data, o_cb);
+                        Ok(())
+                    })
+                    .into_box()
+                    .into()
+            } else {
+                call_result_cb!(Err::<(), _>(AppError::InvalidFileMode), user_data, o_cb);
+                None
+            }
+        })
+    })
+}
+
 /// Close is invoked only after all the data is completely written. The
 /// file is saved only when `close` is invoked.
 ///
@@ -374,3 +1244,78 @@ pub unsafe extern "C" fn file_close(
         })
     })
 }
+
+// Convert the FFI listing options into their native representation.
+unsafe fn list_options_clone_from_repr_c(
+    options: *const ListOptions,
+) -> Result<NativeListOptions, AppError> {
+    let options = &*options;
+
+    let sort_by = match options.sort_by {
+        x if x == LIST_SORT_BY_NAME => SortBy::Name,
+        x if x == LIST_SORT_BY_SIZE => SortBy::Size,
+        x if x == LIST_SORT_BY_CREATED => SortBy::Created,
+        x if x == LIST_SORT_BY_MODIFIED => SortBy::Modified,
+        _ => return Err(AppError::from("Invalid sort order")),
+    };
+    let prefix = if options.prefix.is_null() {
+        None
+    } else {
+        Some(String::clone_from_repr_c(options.prefix)?)
+    };
+    let limit = if options.limit == LIST_NO_LIMIT {
+        None
+    } else {
+        Some(options.limit as usize)
+    };
+
+    Ok(NativeListOptions {
+        sort_by,
+        descending: options.descending,
+        prefix,
+        offset: options.offset as usize,
+        limit,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe fn recover_move_file(
+    app: *const App,
+    src_parent_info: *const MDataInfo,
+    src_name: *const c_char,
+    dst_parent_info: *const MDataInfo,
+    dst_name: *const c_char,
+    recovery: MoveRecovery,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    catch_unwind_cb(user_data, o_cb, || {
+        let src_parent_info = NativeMDataInfo::clone_from_repr_c(src_parent_info)?;
+        let src_name = String::clone_from_repr_c(src_name)?;
+        let dst_parent_info = NativeMDataInfo::clone_from_repr_c(dst_parent_info)?;
+        let dst_name = String::clone_from_repr_c(dst_name)?;
+
+        send(app, user_data, o_cb, move |client, _| {
+            file_helper::recover_move(
+                client.clone(),
+                src_parent_info,
+                src_name,
+                dst_parent_info,
+                dst_name,
+                recovery,
+            )
+        })
+    })
+}
+
+// Number of chunks to prefetch after each read, as given by the open mode.
+fn read_ahead(open_mode: u64) -> usize {
+    if open_mode & OPEN_MODE_NO_READ_AHEAD != 0 {
+        return 0;
+    }
+
+    match (open_mode & OPEN_MODE_READ_AHEAD_MASK) >> OPEN_MODE_READ_AHEAD_SHIFT {
+        0 => DEFAULT_READ_AHEAD,
+        chunks => chunks as usize,
+    }
+}
@@ -49,3 +49,7 @@ pub type PubKeyHandle = ObjectHandle;
 pub type SignSecKeyHandle = ObjectHandle;
 /// Disambiguating `ObjectHandle`
 pub type FileContextHandle = ObjectHandle;
+/// Disambiguating `ObjectHandle`
+pub type DirWatcherHandle = ObjectHandle;
+/// Disambiguating `ObjectHandle`
+pub type ADataTailerHandle = ObjectHandle;
@@ -0,0 +1,122 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use crate::ffi::append_only_data::*;
+use crate::ffi::object_cache::ADataTailerHandle;
+use crate::test_utils::create_app;
+use crate::{run, App, AppError};
+use ffi_utils::test_utils::{
+    call_0, send_via_user_data, send_via_user_data_custom, sender_as_user_data, SendWrapper,
+    UserData,
+};
+use ffi_utils::FfiResult;
+use futures::Future;
+use safe_core::ffi::ADataEntry as FfiADataEntry;
+use safe_core::Client;
+use safe_nd::{AData, ADataEntry, ADataOwner, AppendOnlyData, PubSeqAppendOnlyData, XorName};
+use std::os::raw::c_void;
+use std::slice;
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+type TailResult = Result<Option<(u64, Vec<u8>)>, i32>;
+
+// Test tailing append-only data through the FFI.
+// 1. Put data with two entries and tail it from the second one.
+// 2. Stop the tailer, which frees its handle.
+// 3. Tail data which doesn't exist, and check the error ends tailing and frees the handle.
+#[test]
+fn tail_entries() {
+    let app = create_app();
+    let name: XorName = rand::random();
+    let tag = 15_050;
+
+    unwrap!(run(&app, move |client, _| {
+        let mut data = PubSeqAppendOnlyData::new(name, tag);
+        unwrap!(data.append_owner(
+            ADataOwner {
+                public_key: client.owner_key(),
+                entries_index: 0,
+                permissions_index: 0,
+            },
+            0
+        ));
+        unwrap!(data.append_seq(
+            vec![
+                ADataEntry::new(vec![0], vec![0]),
+                ADataEntry::new(vec![1], vec![1])
+            ],
+            0
+        ));
+
+        client
+            .put_adata(AData::PubSeq(data))
+            .map_err(AppError::from)
+    }));
+
+    let (entry_tx, entry_rx) = mpsc::channel::<SendWrapper<TailResult>>();
+    let (handle_tx, handle_rx) = mpsc::channel::<ADataTailerHandle>();
+    let mut ud = UserData::default();
+    ud.custom = &handle_tx as *const _ as *mut c_void;
+    let user_data = sender_as_user_data(&entry_tx, &mut ud);
+
+    let handle = unsafe { tail(&app, name, tag, user_data, &handle_rx) };
+    assert_eq!(unwrap!(entry_rx.recv()).0, Ok(Some((1, vec![1]))));
+    unsafe { unwrap!(call_0(|ud, cb| adata_tail_stop(&app, handle, ud, cb))) };
+
+    let handle = unsafe { tail(&app, rand::random(), tag, user_data, &handle_rx) };
+    assert!(unwrap!(entry_rx.recv_timeout(Duration::from_secs(10)))
+        .0
+        .is_err());
+    let result = unsafe { call_0(|ud, cb| adata_tail_stop(&app, handle, ud, cb)) };
+    assert!(result.is_err());
+}
+
+// Start tailing the public sequenced data from its second entry, returning the tailer handle.
+unsafe fn tail(
+    app: &App,
+    name: XorName,
+    tag: u64,
+    user_data: *mut c_void,
+    handle_rx: &Receiver<ADataTailerHandle>,
+) -> ADataTailerHandle {
+    extern "C" fn entry_cb(
+        user_data: *mut c_void,
+        res: *const FfiResult,
+        entry: *const FfiADataEntry,
+    ) {
+        unsafe {
+            let result = if (*res).error_code != 0 {
+                Err((*res).error_code)
+            } else if entry.is_null() {
+                Ok(None)
+            } else {
+                let value = slice::from_raw_parts((*entry).value, (*entry).value_len).to_vec();
+                Ok(Some(((*entry).index, value)))
+            };
+            send_via_user_data(user_data, SendWrapper(result))
+        }
+    }
+
+    extern "C" fn handle_cb(
+        user_data: *mut c_void,
+        res: *const FfiResult,
+        handle: ADataTailerHandle,
+    ) {
+        unsafe {
+            assert_eq!((*res).error_code, 0);
+            send_via_user_data_custom(user_data, handle)
+        }
+    }
+
+    adata_tail(
+        app, &name.0, tag, true, true, 1, 10, 40, user_data, entry_cb, handle_cb,
+    );
+    unwrap!(handle_rx.recv())
+}
@@ -12,17 +12,25 @@ use crate::ffi::nfs::*;
 use crate::ffi::object_cache::FileContextHandle;
 use crate::test_utils::{create_app_by_req, create_auth_req_with_access};
 use crate::{run, App};
-use ffi_utils::test_utils::{call_0, call_1, call_2, call_vec_u8};
-use ffi_utils::ErrorCode;
+use ffi_utils::test_utils::{
+    call_0, call_1, call_2, call_vec, call_vec_u8, send_via_user_data, sender_as_user_data,
+    SendWrapper, UserData,
+};
+use ffi_utils::{ErrorCode, FfiResult, ReprC};
 use futures::Future;
-use safe_core::ffi::nfs::File;
+use safe_core::ffi::nfs::{File, FileEntry, FileVersion, ListOptions};
 use safe_core::ffi::MDataInfo;
 use safe_core::ipc::Permission;
-use safe_core::nfs::{File as NativeFile, NfsError};
+use safe_core::nfs::{create_dir, File as NativeFile, NfsError};
 use safe_core::utils;
+use safe_core::{MDataInfo as NativeMDataInfo, DIR_TAG};
+use safe_nd::MDataKind;
 use std;
 use std::collections::HashMap;
 use std::ffi::CString;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::mpsc;
 
 fn setup() -> (App, MDataInfo) {
     let mut container_permissions = HashMap::new();
@@ -1131,6 +1139,648 @@ fn file_write_chunks() {
     assert_eq!(retrieved_content, vec![0u8; 2 * GOAL_SIZE]);
 }
 
+// Test listing the files in a container.
+// 1. Insert several files with different sizes into a container.
+// 2. List all the files sorted by name.
+// 3. List the files sorted by size in descending order.
+// 4. List the files with a name prefix, an offset and a limit.
+#[test]
+fn list_files() {
+    let (app, container_info) = setup();
+
+    for &(name, size) in &[("b.txt", 10), ("a.txt", 30), ("ab.txt", 20)] {
+        let mut file = NativeFile::new(Vec::new(), true);
+        file.set_size(size);
+        let ffi_name = unwrap!(CString::new(name));
+
+        unsafe {
+            unwrap!(call_0(|ud, cb| dir_insert_file(
+                &app,
+                &container_info,
+                ffi_name.as_ptr(),
+                &file.into_repr_c(),
+                ud,
+                cb,
+            )))
+        }
+    }
+
+    let options = ListOptions {
+        sort_by: LIST_SORT_BY_NAME,
+        descending: false,
+        prefix: ptr::null(),
+        offset: 0,
+        limit: LIST_NO_LIMIT,
+    };
+    let files: Vec<ListedFile> = unsafe {
+        unwrap!(call_vec(|ud, cb| dir_list_files(
+            &app,
+            &container_info,
+            &options,
+            ud,
+            cb,
+        )))
+    };
+    let names: Vec<_> = files.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, vec!["a.txt", "ab.txt", "b.txt"]);
+    assert!(files.iter().all(|f| f.version == 0));
+
+    let options = ListOptions {
+        sort_by: LIST_SORT_BY_SIZE,
+        descending: true,
+        prefix: ptr::null(),
+        offset: 0,
+        limit: LIST_NO_LIMIT,
+    };
+    let files: Vec<ListedFile> = unsafe {
+        unwrap!(call_vec(|ud, cb| dir_list_files(
+            &app,
+            &container_info,
+            &options,
+            ud,
+            cb,
+        )))
+    };
+    let sizes: Vec<_> = files.iter().map(|f| f.file.size()).collect();
+    assert_eq!(sizes, vec![30, 20, 10]);
+
+    let prefix = unwrap!(CString::new("a"));
+    let options = ListOptions {
+        sort_by: LIST_SORT_BY_NAME,
+        descending: false,
+        prefix: prefix.as_ptr(),
+        offset: 1,
+        limit: 1,
+    };
+    let files: Vec<ListedFile> = unsafe {
+        unwrap!(call_vec(|ud, cb| dir_list_files(
+            &app,
+            &container_info,
+            &options,
+            ud,
+            cb,
+        )))
+    };
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].name, "ab.txt");
+}
+
+// Native counterpart of `FileEntry`, used to receive the results of `dir_list_files`.
+struct ListedFile {
+    name: String,
+    version: u64,
+    file: NativeFile,
+}
+
+impl ReprC for ListedFile {
+    type C = *const FileEntry;
+    type Error = AppError;
+
+    unsafe fn clone_from_repr_c(repr_c: Self::C) -> Result<Self, Self::Error> {
+        Ok(ListedFile {
+            name: String::clone_from_repr_c((*repr_c).name)?,
+            version: (*repr_c).version,
+            file: NativeFile::clone_from_repr_c(&(*repr_c).file)?,
+        })
+    }
+}
+
+// Test renaming, copying and moving files through the FFI.
+// 1. Rename a file and check that only the new name exists.
+// 2. Copy it and move the copy, checking both files are there.
+// 3. Copy a file as if a move was interrupted, then roll the move back.
+#[test]
+fn rename_move_and_copy() {
+    let (app, container_info) = setup();
+
+    let name_a = unwrap!(CString::new("a.txt"));
+    let name_b = unwrap!(CString::new("b.txt"));
+    let name_c = unwrap!(CString::new("c.txt"));
+    let name_d = unwrap!(CString::new("d.txt"));
+
+    let file = NativeFile::new(b"metadata".to_vec(), true);
+    unsafe {
+        unwrap!(call_0(|ud, cb| dir_insert_file(
+            &app,
+            &container_info,
+            name_a.as_ptr(),
+            &file.into_repr_c(),
+            ud,
+            cb,
+        )));
+        unwrap!(call_0(|ud, cb| dir_rename_file(
+            &app,
+            &container_info,
+            name_a.as_ptr(),
+            name_b.as_ptr(),
+            GET_NEXT_VERSION,
+            ud,
+            cb,
+        )));
+        unwrap!(call_0(|ud, cb| dir_copy_file(
+            &app,
+            &container_info,
+            name_b.as_ptr(),
+            &container_info,
+            name_c.as_ptr(),
+            ud,
+            cb,
+        )));
+        unwrap!(call_0(|ud, cb| dir_move_file(
+            &app,
+            &container_info,
+            name_c.as_ptr(),
+            &container_info,
+            name_d.as_ptr(),
+            ud,
+            cb,
+        )));
+    }
+    let names = list_file_names(&app, &container_info);
+    assert_eq!(names, vec!["b.txt", "d.txt"]);
+
+    unsafe {
+        unwrap!(call_0(|ud, cb| dir_copy_file(
+            &app,
+            &container_info,
+            name_b.as_ptr(),
+            &container_info,
+            name_a.as_ptr(),
+            ud,
+            cb,
+        )));
+        unwrap!(call_0(|ud, cb| dir_rollback_move_file(
+            &app,
+            &container_info,
+            name_b.as_ptr(),
+            &container_info,
+            name_a.as_ptr(),
+            ud,
+            cb,
+        )));
+    }
+    let names = list_file_names(&app, &container_info);
+    assert_eq!(names, vec!["b.txt", "d.txt"]);
+
+    unsafe {
+        unwrap!(call_0(|ud, cb| dir_finish_move_file(
+            &app,
+            &container_info,
+            name_b.as_ptr(),
+            &container_info,
+            name_a.as_ptr(),
+            ud,
+            cb,
+        )));
+    }
+    let names = list_file_names(&app, &container_info);
+    assert_eq!(names, vec!["a.txt", "d.txt"]);
+}
+
+// Test the version history of a file through the FFI.
+// 1. Insert a file, enable its history and update it.
+// 2. Check that the previous version is listed.
+// 3. Restore the previous version and fetch it back.
+#[test]
+fn file_history() {
+    let (app, container_info) = setup();
+
+    let file_name = unwrap!(CString::new("file.txt"));
+    let file = NativeFile::new(b"v0".to_vec(), true);
+    unsafe {
+        unwrap!(call_0(|ud, cb| dir_insert_file(
+            &app,
+            &container_info,
+            file_name.as_ptr(),
+            &file.into_repr_c(),
+            ud,
+            cb,
+        )));
+        unwrap!(call_0(|ud, cb| dir_enable_file_history(
+            &app,
+            &container_info,
+            file_name.as_ptr(),
+            ud,
+            cb,
+        )));
+    }
+
+    let file = NativeFile::new(b"v1".to_vec(), true);
+    let version: u64 = unsafe {
+        unwrap!(call_1(|ud, cb| dir_update_file(
+            &app,
+            &container_info,
+            file_name.as_ptr(),
+            &file.into_repr_c(),
+            GET_NEXT_VERSION,
+            ud,
+            cb,
+        )))
+    };
+    assert_eq!(version, 2);
+
+    let versions: Vec<HistoricFile> = unsafe {
+        unwrap!(call_vec(|ud, cb| dir_list_file_versions(
+            &app,
+            &container_info,
+            file_name.as_ptr(),
+            ud,
+            cb,
+        )))
+    };
+    assert_eq!(versions.len(), 1);
+    assert_eq!(versions[0].version, 0);
+    assert_eq!(versions[0].file.user_metadata(), b"v0");
+
+    let version: u64 = unsafe {
+        unwrap!(call_1(|ud, cb| dir_restore_file_version(
+            &app,
+            &container_info,
+            file_name.as_ptr(),
+            0,
+            ud,
+            cb,
+        )))
+    };
+    assert_eq!(version, 3);
+
+    let (file, version): (NativeFile, u64) = unsafe {
+        unwrap!(call_2(|ud, cb| dir_fetch_file(
+            &app,
+            &container_info,
+            file_name.as_ptr(),
+            ud,
+            cb,
+        )))
+    };
+    assert_eq!(version, 3);
+    assert_eq!(file.user_metadata(), b"v0");
+}
+
+// Native counterpart of `FileVersion`, used to receive the results of `dir_list_file_versions`.
+struct HistoricFile {
+    version: u64,
+    file: NativeFile,
+}
+
+impl ReprC for HistoricFile {
+    type C = *const FileVersion;
+    type Error = AppError;
+
+    unsafe fn clone_from_repr_c(repr_c: Self::C) -> Result<Self, Self::Error> {
+        Ok(HistoricFile {
+            version: (*repr_c).version,
+            file: NativeFile::clone_from_repr_c(&(*repr_c).file)?,
+        })
+    }
+}
+
+// Test resolving conflicting updates through the FFI.
+// 1. Insert a file and update it.
+// 2. Update it again based on the first version, keeping both versions.
+// 3. Update it again based on the first version, overwriting the newer one.
+// 4. Check that unknown policies are rejected.
+#[test]
+fn update_file_with_policy() {
+    let (app, container_info) = setup();
+
+    let file_name = unwrap!(CString::new("file.txt"));
+    let file = NativeFile::new(b"v0".to_vec(), true);
+    unsafe {
+        unwrap!(call_0(|ud, cb| dir_insert_file(
+            &app,
+            &container_info,
+            file_name.as_ptr(),
+            &file.into_repr_c(),
+            ud,
+            cb,
+        )));
+    }
+
+    let update = |user_metadata: &[u8], policy: u64| -> Result<(String, u64), i32> {
+        let file = NativeFile::new(user_metadata.to_vec(), true);
+        unsafe {
+            call_2(|ud, cb| {
+                dir_update_file_with_policy(
+                    &app,
+                    &container_info,
+                    file_name.as_ptr(),
+                    &file.into_repr_c(),
+                    1,
+                    policy,
+                    ud,
+                    cb,
+                )
+            })
+        }
+    };
+
+    let (name, version) = unwrap!(update(b"v1", MERGE_POLICY_LAST_WRITER_WINS));
+    assert_eq!(name, "file.txt");
+    assert_eq!(version, 1);
+
+    let (name, version) = unwrap!(update(b"v1 too", MERGE_POLICY_KEEP_BOTH));
+    assert_eq!(name, "file (conflict 2).txt");
+    assert_eq!(version, 0);
+
+    let (name, version) = unwrap!(update(b"v2", MERGE_POLICY_LAST_WRITER_WINS));
+    assert_eq!(name, "file.txt");
+    assert_eq!(version, 2);
+
+    let (file, _): (NativeFile, u64) = unsafe {
+        unwrap!(call_2(|ud, cb| dir_fetch_file(
+            &app,
+            &container_info,
+            file_name.as_ptr(),
+            ud,
+            cb,
+        )))
+    };
+    assert_eq!(file.user_metadata(), b"v2");
+
+    match update(b"v3", 42) {
+        Err(code) if code == AppError::Unexpected(String::new()).error_code() => (),
+        res => panic!("Unexpected result {:?}", res),
+    }
+}
+
+// Test moving a file into the trash, restoring it and emptying the trash through the FFI.
+#[test]
+fn trash_file() {
+    let (app, container_info) = setup();
+
+    let trash_info = unwrap!(run(&app, |client, _| {
+        let trash = unwrap!(NativeMDataInfo::random_private(MDataKind::Seq, DIR_TAG));
+        create_dir(client, &trash, btree_map![], btree_map![])
+            .map(move |()| trash)
+            .map_err(AppError::from)
+    }));
+    let trash_info = trash_info.into_repr_c();
+
+    let file_name = unwrap!(CString::new("file.txt"));
+    let file = NativeFile::new(Vec::new(), true);
+    let trash_file = || -> String {
+        unsafe {
+            unwrap!(call_0(|ud, cb| dir_insert_file(
+                &app,
+                &container_info,
+                file_name.as_ptr(),
+                &file.clone().into_repr_c(),
+                ud,
+                cb,
+            )));
+            unwrap!(call_1(|ud, cb| dir_trash_file(
+                &app,
+                &container_info,
+                file_name.as_ptr(),
+                &trash_info,
+                ud,
+                cb,
+            )))
+        }
+    };
+
+    let item_id = unwrap!(CString::new(trash_file()));
+    let res: Result<(NativeFile, u64), i32> = unsafe {
+        call_2(|ud, cb| dir_fetch_file(&app, &container_info, file_name.as_ptr(), ud, cb))
+    };
+    match res {
+        Err(code) if code == AppError::from(NfsError::FileNotFound).error_code() => (),
+        res => panic!("Unexpected result {:?}", res),
+    }
+
+    unsafe {
+        unwrap!(call_0(|ud, cb| trash_restore_item(
+            &app,
+            &trash_info,
+            item_id.as_ptr(),
+            ud,
+            cb,
+        )));
+        let _: (NativeFile, u64) = unwrap!(call_2(|ud, cb| dir_fetch_file(
+            &app,
+            &container_info,
+            file_name.as_ptr(),
+            ud,
+            cb,
+        )));
+
+        let _: u64 = unwrap!(call_1(|ud, cb| dir_delete_file(
+            &app,
+            &container_info,
+            file_name.as_ptr(),
+            true,
+            GET_NEXT_VERSION,
+            ud,
+            cb,
+        )));
+    }
+
+    let _ = trash_file();
+    let purged: u64 = unsafe {
+        unwrap!(call_1(|ud, cb| trash_purge(
+            &app,
+            &trash_info,
+            3600,
+            ud,
+            cb
+        )))
+    };
+    assert_eq!(purged, 0);
+    let emptied: u64 = unsafe { unwrap!(call_1(|ud, cb| trash_empty(&app, &trash_info, ud, cb))) };
+    assert_eq!(emptied, 1);
+}
+
+// Test modifying a file at arbitrary positions through the FFI.
+// 1. Write a file and open it in the modify mode.
+// 2. Overwrite a few bytes at its start and truncate it.
+// 3. Read it back and check the content.
+// 4. Check that truncation is rejected in the other write modes.
+#[test]
+fn modify_file() {
+    let (app, container_info) = setup();
+
+    let file = NativeFile::new(Vec::new(), true);
+    let content = b"hello world";
+
+    let write_h = unsafe {
+        unwrap!(call_1(|ud, cb| file_open(
+            &app,
+            &container_info,
+            &file.into_repr_c(),
+            OPEN_MODE_OVERWRITE,
+            ud,
+            cb,
+        )))
+    };
+    let written_file: NativeFile = unsafe {
+        unwrap!(call_0(|ud, cb| file_write(
+            &app,
+            write_h,
+            content.as_ptr(),
+            content.len(),
+            ud,
+            cb
+        )));
+        unwrap!(call_1(|ud, cb| file_close(&app, write_h, ud, cb)))
+    };
+
+    let modify_h = unsafe {
+        unwrap!(call_1(|ud, cb| file_open(
+            &app,
+            &container_info,
+            &written_file.into_repr_c(),
+            OPEN_MODE_MODIFY,
+            ud,
+            cb,
+        )))
+    };
+    let modified_file: NativeFile = unsafe {
+        let data = b"HELLO";
+        unwrap!(call_0(|ud, cb| file_write_at(
+            &app,
+            modify_h,
+            0,
+            data.as_ptr(),
+            data.len(),
+            ud,
+            cb
+        )));
+        unwrap!(call_0(|ud, cb| file_truncate(&app, modify_h, 8, ud, cb)));
+        unwrap!(call_1(|ud, cb| file_close(&app, modify_h, ud, cb)))
+    };
+    assert_eq!(modified_file.size(), 8);
+
+    let read_h = unsafe {
+        unwrap!(call_1(|ud, cb| file_open(
+            &app,
+            &container_info,
+            &modified_file.into_repr_c(),
+            OPEN_MODE_READ | OPEN_MODE_APPEND,
+            ud,
+            cb,
+        )))
+    };
+    let retrieved_content = unsafe {
+        unwrap!(call_vec_u8(|ud, cb| file_read(
+            &app,
+            read_h,
+            0,
+            FILE_READ_TO_END,
+            ud,
+            cb
+        )))
+    };
+    assert_eq!(retrieved_content, b"HELLO wo");
+
+    let res = unsafe { call_0(|ud, cb| file_truncate(&app, read_h, 0, ud, cb)) };
+    match res {
+        Err(code) if code == AppError::from(NfsError::Unexpected(String::new())).error_code() => (),
+        Err(x) => panic!("Unexpected: {:?}", x),
+        Ok(_) => panic!("Unexpected success"),
+    }
+}
+
+// Test symbolic and hard links.
+// 1. Insert an unpublished file, a link to it and a link to the container itself.
+// 2. Fetch the file through the link and resolve a path through both links.
+// 3. Hard link the file, delete the original and fetch the hard link.
+// 4. Check that only files are listed.
+#[test]
+fn links() {
+    let (app, container_info) = setup();
+
+    let file_name = unwrap!(CString::new("file.txt"));
+    let alias_name = unwrap!(CString::new("alias.txt"));
+    let self_name = unwrap!(CString::new("self"));
+    let hard_name = unwrap!(CString::new("hard.txt"));
+    let path = unwrap!(CString::new("self/alias.txt"));
+
+    let file = NativeFile::new(b"metadata".to_vec(), false);
+    unsafe {
+        unwrap!(call_0(|ud, cb| dir_insert_file(
+            &app,
+            &container_info,
+            file_name.as_ptr(),
+            &file.into_repr_c(),
+            ud,
+            cb,
+        )));
+        unwrap!(call_0(|ud, cb| dir_insert_path_link(
+            &app,
+            &container_info,
+            alias_name.as_ptr(),
+            file_name.as_ptr(),
+            ud,
+            cb,
+        )));
+        unwrap!(call_0(|ud, cb| dir_insert_entry_link(
+            &app,
+            &container_info,
+            self_name.as_ptr(),
+            &container_info,
+            ptr::null(),
+            ud,
+            cb,
+        )));
+    }
+
+    let (fetched, _version): (NativeFile, u64) = unsafe {
+        unwrap!(call_2(|ud, cb| dir_fetch_file(
+            &app,
+            &container_info,
+            alias_name.as_ptr(),
+            ud,
+            cb,
+        )))
+    };
+    assert_eq!(fetched.user_metadata(), b"metadata");
+
+    let (dir_info, resolved) = unwrap!(resolve_path(&app, &container_info, &path));
+    let resolved = unwrap!(resolved);
+    assert_eq!(resolved.name, "file.txt");
+    assert_eq!(resolved.file, fetched);
+    assert_eq!(
+        unsafe { unwrap!(NativeMDataInfo::clone_from_repr_c(&container_info)) },
+        dir_info
+    );
+
+    unsafe {
+        unwrap!(call_0(|ud, cb| dir_hard_link_file(
+            &app,
+            &container_info,
+            file_name.as_ptr(),
+            &container_info,
+            hard_name.as_ptr(),
+            ud,
+            cb,
+        )));
+        let _: u64 = unwrap!(call_1(|ud, cb| dir_delete_file(
+            &app,
+            &container_info,
+            file_name.as_ptr(),
+            false,
+            GET_NEXT_VERSION,
+            ud,
+            cb,
+        )));
+    }
+
+    let (hard_linked, _version): (NativeFile, u64) = unsafe {
+        unwrap!(call_2(|ud, cb| dir_fetch_file(
+            &app,
+            &container_info,
+            hard_name.as_ptr(),
+            ud,
+            cb,
+        )))
+    };
+    assert_eq!(hard_linked, fetched);
+
+    let names = list_file_names(&app, &container_info);
+    assert_eq!(names, vec!["hard.txt"]);
+}
+
 // Helper function for writing to a file in chunks.
 fn write_chunks(
     app: &App,
@@ -1177,3 +1827,67 @@ fn write_chunks(
         unwrap!(call_1(|ud, cb| file_close(app, write_h, ud, cb)))
     }
 }
+
+// List the names of all the files in the container.
+fn list_file_names(app: &App, container_info: &MDataInfo) -> Vec<String> {
+    let options = ListOptions {
+        sort_by: LIST_SORT_BY_NAME,
+        descending: false,
+        prefix: ptr::null(),
+        offset: 0,
+        limit: LIST_NO_LIMIT,
+    };
+    let files: Vec<ListedFile> = unsafe {
+        unwrap!(call_vec(|ud, cb| dir_list_files(
+            app,
+            container_info,
+            &options,
+            ud,
+            cb,
+        )))
+    };
+    files.into_iter().map(|f| f.name).collect()
+}
+
+// Resolve the path, returning the resolved directory and, if the path points to a file, its name,
+// version and the file.
+fn resolve_path(
+    app: &App,
+    container_info: &MDataInfo,
+    path: &CString,
+) -> Result<(NativeMDataInfo, Option<ListedFile>), i32> {
+    extern "C" fn resolved_cb(
+        user_data: *mut c_void,
+        res: *const FfiResult,
+        dir_info: *const MDataInfo,
+        entry: *const FileEntry,
+    ) {
+        unsafe {
+            let result = if (*res).error_code == 0 {
+                let dir_info = unwrap!(NativeMDataInfo::clone_from_repr_c(dir_info));
+                let entry = if entry.is_null() {
+                    None
+                } else {
+                    Some(unwrap!(ListedFile::clone_from_repr_c(entry)))
+                };
+                Ok((dir_info, entry))
+            } else {
+                Err((*res).error_code)
+            };
+            send_via_user_data(user_data, SendWrapper(result))
+        }
+    }
+
+    let (tx, rx) = mpsc::channel::<SendWrapper<_>>();
+    let mut ud = UserData::default();
+    unsafe {
+        dir_resolve_path(
+            app,
+            container_info,
+            path.as_ptr(),
+            sender_as_user_data(&tx, &mut ud),
+            resolved_cb,
+        )
+    };
+    unwrap!(rx.recv()).0
+}
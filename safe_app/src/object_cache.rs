@@ -13,7 +13,8 @@
 use super::errors::AppError;
 use crate::cipher_opt::CipherOpt;
 use crate::client::AppClient;
-use crate::ffi::nfs::FileContext;
+use crate::ffi::append_only_data::ADataTailer;
+use crate::ffi::nfs::{DirWatcher, FileContext};
 use crate::ffi::object_cache::*;
 use safe_core::{crypto::shared_box, SelfEncryptionStorage};
 use safe_nd::{
@@ -41,6 +42,8 @@ pub struct ObjectCache {
     pub_sign_key: Store<PublicKey>,
     sec_sign_key: Store<ClientFullId>,
     file: Store<FileContext>,
+    dir_watcher: Store<DirWatcher>,
+    adata_tailer: Store<ADataTailer>,
 }
 
 impl ObjectCache {
@@ -61,6 +64,8 @@ impl ObjectCache {
             pub_sign_key: Store::new(),
             sec_sign_key: Store::new(),
             file: Store::new(),
+            dir_watcher: Store::new(),
+            adata_tailer: Store::new(),
         }
     }
 
@@ -80,6 +85,8 @@ impl ObjectCache {
         self.pub_sign_key.clear();
         self.sec_sign_key.clear();
         self.file.clear();
+        self.dir_watcher.clear();
+        self.adata_tailer.clear();
     }
 }
 
@@ -221,6 +228,24 @@ impl_cache!(
     insert_file,
     remove_file
 );
+impl_cache!(
+    dir_watcher,
+    DirWatcher,
+    DirWatcherHandle,
+    InvalidDirWatcherHandle,
+    get_dir_watcher,
+    insert_dir_watcher,
+    remove_dir_watcher
+);
+impl_cache!(
+    adata_tailer,
+    ADataTailer,
+    ADataTailerHandle,
+    InvalidADataTailerHandle,
+    get_adata_tailer,
+    insert_adata_tailer,
+    remove_adata_tailer
+);
 
 impl Default for ObjectCache {
     fn default() -> Self {
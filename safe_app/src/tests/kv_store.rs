@@ -0,0 +1,52 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use crate::{run, test_utils::create_app, AppError};
+use futures::Future;
+use safe_core::kv_store::{KvStore, KvValue};
+use safe_core::{FutureExt, MDataInfo};
+use safe_nd::MDataKind;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Note(String);
+
+impl KvValue for Note {}
+
+// Test that an app can use a store it created, although the mutable data is owned by the owner of
+// the account.
+#[test]
+fn store_created_by_app() {
+    let app = create_app();
+
+    unwrap!(run(&app, |client, _context| {
+        let info = unwrap!(MDataInfo::random_private(MDataKind::Seq, 15_041));
+
+        KvStore::<_, u8, Note>::create(client.clone(), info)
+            .and_then(|store| {
+                store
+                    .insert(&1, &Note("first".to_string()))
+                    .and_then(move |()| store.update(&1, |_| Note("second".to_string())))
+                    .map(move |()| store)
+            })
+            .and_then(|store| {
+                store
+                    .get(&1)
+                    .join(store.remove(&1))
+                    .map(move |results| (store, results))
+            })
+            .and_then(|(store, (value, removed))| {
+                assert_eq!(value, Some(Note("second".to_string())));
+                assert!(removed);
+                store.get(&1)
+            })
+            .map(|value| assert_eq!(value, None))
+            .map_err(AppError::from)
+            .into_box()
+    }));
+}
@@ -9,6 +9,8 @@
 
 mod append_only_data;
 mod coins;
+mod kv_store;
+mod sharded_map;
 mod unpublished_mutable_data;
 
 use crate::ffi::test_utils::test_create_app_with_access;
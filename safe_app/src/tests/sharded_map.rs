@@ -0,0 +1,49 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use crate::{run, test_utils::create_app, AppError};
+use futures::{future, Future};
+use safe_core::sharded_map::ShardedMap;
+use safe_core::{CoreFuture, FutureExt, MDataInfo};
+use safe_nd::MDataKind;
+use std::rc::Rc;
+
+// Test that an app can use a map it created, including the shards created when splitting,
+// although the mutable data is owned by the owner of the account.
+#[test]
+fn map_created_by_app() {
+    let app = create_app();
+
+    unwrap!(run(&app, |client, _context| {
+        let root = unwrap!(MDataInfo::random_private(MDataKind::Seq, 15_043));
+
+        ShardedMap::create(client.clone(), root)
+            .and_then(|mut map| {
+                map.set_max_shard_entries(2);
+
+                let map = Rc::new(map);
+
+                // Insert one at a time, so every insert sees the splits before it.
+                let start: Box<CoreFuture<()>> = future::ok(()).into_box();
+                let inserts = (0..6u8).fold(start, |done, i| {
+                    let map = Rc::clone(&map);
+                    done.and_then(move |()| map.insert(&[i], &[i])).into_box()
+                });
+                inserts.map(move |()| map)
+            })
+            .and_then(|map| map.shards().join(map.entries()))
+            .map(|(shards, entries)| {
+                assert!(shards.len() > 1);
+                assert_eq!(entries.len(), 6);
+                assert_eq!(entries[&vec![3]], vec![3]);
+            })
+            .map_err(AppError::from)
+            .into_box()
+    }));
+}
@@ -48,7 +48,7 @@ use safe_core::ffi::ipc::resp::{
     AccessContInfo, AccessContainerEntry, AppAccess, AppKeys, AuthGranted, ContainerInfo,
     MDataEntry, MDataKey, MDataValue, MetadataResponse,
 };
-use safe_core::ffi::nfs::File;
+use safe_core::ffi::nfs::{File, FileEntry, FileVersion, ListOptions};
 use safe_core::ffi::*;
 use std::ffi::{CStr, CString};
 use std::os::raw::{c_char, c_void};
@@ -278,6 +278,8 @@ gen_object_array_converter!(
     ContainerPermissions,
     "net/maidsafe/safe_app/ContainerPermissions"
 );
+gen_object_array_converter!(find_class, FileEntry, "net/maidsafe/safe_app/FileEntry");
+gen_object_array_converter!(find_class, FileVersion, "net/maidsafe/safe_app/FileVersion");
 
 extern "C" fn call_app_disconnect_cb(ctx: *mut c_void) {
     unsafe {
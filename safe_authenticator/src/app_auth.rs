@@ -146,11 +146,10 @@ pub fn authenticate(client: &AuthClient, auth_req: AuthReq) -> Box<AuthFuture<Au
 
     config::list_apps(client)
         .join(check_revocation(client, app_id.clone()))
-        .and_then(move |((apps_version, apps), ())| {
-            app_state(&c2, &apps, &app_id)
-                .map(move |app_state| (apps_version, apps, app_state, app_id))
+        .and_then(move |(apps, ())| {
+            app_state(&c2, &apps, &app_id).map(move |app_state| (apps, app_state, app_id))
         })
-        .and_then(move |(apps_version, mut apps, app_state, app_id)| {
+        .and_then(move |(mut apps, app_state, app_id)| {
             // Determine an app state. If it's revoked we can reuse existing
             // keys stored in the config. And if it is authorised, we just
             // return the app info from the config.
@@ -163,8 +162,8 @@ pub fn authenticate(client: &AuthClient, auth_req: AuthReq) -> Box<AuthFuture<Au
                         info: auth_req.app,
                         keys,
                     };
-                    config::insert_app(&c3, apps, config::next_version(apps_version), app.clone())
-                        .map(move |_| (app, app_state, app_id))
+                    config::insert_app(&c3, app.clone())
+                        .map(move |()| (app, app_state, app_id))
                         .into_box()
                 }
                 AppState::Authenticated | AppState::Revoked => {
@@ -81,17 +81,13 @@ pub fn remove_revoked_app(client: &AuthClient, app_id: String) -> Box<AuthFuture
     let app_id3 = app_id.clone();
 
     config::list_apps(&client)
-        .and_then(move |(apps_version, apps)| {
-            app_state(&c2, &apps, &app_id).map(move |app_state| (app_state, apps, apps_version))
-        })
-        .and_then(move |(app_state, apps, apps_version)| match app_state {
-            AppState::Revoked => Ok((apps, apps_version)),
+        .and_then(move |apps| app_state(&c2, &apps, &app_id))
+        .and_then(move |app_state| match app_state {
+            AppState::Revoked => Ok(()),
             AppState::Authenticated => Err(AuthError::from("App is not revoked")),
             AppState::NotAuthenticated => Err(AuthError::IpcError(IpcError::UnknownApp)),
         })
-        .and_then(move |(apps, apps_version)| {
-            config::remove_app(&c3, apps, config::next_version(apps_version), &app_id2)
-        })
+        .and_then(move |()| config::remove_app(&c3, &app_id2))
         .and_then(move |_| app_container::remove(c4, &app_id3).map(move |_res| ()))
         .into_box()
 }
@@ -102,7 +98,7 @@ pub fn list_revoked(client: &AuthClient) -> Box<AuthFuture<Vec<AppExchangeInfo>>
     let c3 = client.clone();
 
     config::list_apps(client)
-        .map(move |(_, auth_cfg)| (c2.access_container(), auth_cfg))
+        .map(move |auth_cfg| (c2.access_container(), auth_cfg))
         .and_then(move |(access_container, auth_cfg)| {
             c3.list_seq_mdata_entries(access_container.name(), access_container.type_tag())
                 .map_err(From::from)
@@ -139,7 +135,7 @@ pub fn list_registered(client: &AuthClient) -> Box<AuthFuture<Vec<RegisteredApp>
     let c3 = client.clone();
 
     config::list_apps(client)
-        .map(move |(_, auth_cfg)| (c2.access_container(), auth_cfg))
+        .map(move |auth_cfg| (c2.access_container(), auth_cfg))
         .and_then(move |(access_container, auth_cfg)| {
             c3.list_seq_mdata_entries(access_container.name(), access_container.type_tag())
                 .map_err(From::from)
@@ -197,7 +193,7 @@ pub fn apps_accessing_mutable_data(
             tag: type_tag,
         })
         .map_err(AuthError::from)
-        .join(config::list_apps(&c2).map(|(_, apps)| {
+        .join(config::list_apps(&c2).map(|apps| {
             apps.into_iter()
                 .map(|(_, app_info)| (app_info.keys.public_key(), app_info.info))
                 .collect::<HashMap<_, _>>()
@@ -14,13 +14,18 @@ use crate::client::AuthClient;
 use bincode::{deserialize, serialize};
 use futures::future::{self, Either, Loop};
 use futures::Future;
+use safe_core::client::recovery;
+use safe_core::client::roles::DataItem;
 use safe_core::ipc::req::AppExchangeInfo;
 use safe_core::ipc::resp::AppKeys;
 use safe_core::ipc::IpcError;
-use safe_core::{Client, CoreError, FutureExt};
-use safe_nd::{EntryError, Error as SndError, MDataSeqEntryActions};
+use safe_core::nfs::trash;
+use safe_core::sharded_map::ShardedMap;
+use safe_core::{Client, CoreError, FutureExt, MDataInfo};
+use safe_nd::{EntryError, Error as SndError, MDataAddress, MDataSeqEntryActions, XorName};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::Duration;
 use tiny_keccak::sha3_256;
 
 /// App data stored in the authenticator configuration.
@@ -38,17 +43,26 @@ pub struct AppInfo {
     pub keys: AppKeys,
 }
 
-/// Config file key under which the list of registered apps is stored.
+/// Config file key under which the list of registered apps was stored before it was moved into a
+/// sharded map, see `list_apps`.
 pub const KEY_APPS: &[u8] = b"apps";
 
 /// Config file key under which the revocation queue is stored.
 pub const KEY_APP_REVOCATION_QUEUE: &[u8] = b"revocation-queue";
 
+/// Config file key under which the data items transferred to the user and accepted are stored.
+pub const KEY_ACCEPTED_ITEMS: &[u8] = b"accepted-items";
+
+/// Config file key under which the time after which items are purged from the trash is stored.
+pub const KEY_TRASH_RETENTION: &[u8] = b"trash-retention";
+
 /// Maps from a SHA-3 hash of an app ID to app info.
 pub type Apps = HashMap<[u8; 32], AppInfo>;
 /// Contains a queue of revocations that are currently running or have failed.
 /// String refers to `app_id`.
 pub type RevocationQueue = VecDeque<String>;
+/// Maps from the name under which a data item was accepted to the item.
+pub type AcceptedItems = BTreeMap<String, DataItem>;
 
 /// Bump the current version to obtain new version.
 pub fn next_version(version: Option<u64>) -> u64 {
@@ -56,49 +70,150 @@ pub fn next_version(version: Option<u64>) -> u64 {
 }
 
 /// Retrieves apps registered with the authenticator.
-pub fn list_apps(client: &AuthClient) -> Box<AuthFuture<(Option<u64>, Apps)>> {
-    get_entry(client, KEY_APPS)
+pub fn list_apps(client: &AuthClient) -> Box<AuthFuture<Apps>> {
+    apps_map(client)
+        .and_then(|map| map.entries().map_err(AuthError::from))
+        .and_then(|entries| {
+            entries
+                .into_iter()
+                .map(|(key, value)| Ok((app_hash(&key)?, deserialize(&value)?)))
+                .collect::<Result<_, AuthError>>()
+        })
+        .into_box()
 }
 
 /// Retrieves an app info by the given app ID.
 pub fn get_app(client: &AuthClient, app_id: &str) -> Box<AuthFuture<AppInfo>> {
     let app_id_hash = sha3_256(app_id.as_bytes());
-    list_apps(client)
-        .and_then(move |(_, config)| {
-            config
-                .get(&app_id_hash)
-                .cloned()
-                .ok_or_else(|| AuthError::IpcError(IpcError::UnknownApp))
+
+    apps_map(client)
+        .and_then(move |map| map.get(&app_id_hash).map_err(AuthError::from))
+        .and_then(|value| match value {
+            Some(value) => Ok(deserialize(&value)?),
+            None => Err(AuthError::IpcError(IpcError::UnknownApp)),
         })
         .into_box()
 }
 
-/// Register the given app with authenticator.
-pub fn insert_app(
-    client: &AuthClient,
-    apps: Apps,
-    new_version: u64,
-    app: AppInfo,
-) -> Box<AuthFuture<(u64, Apps)>> {
-    let client = client.clone();
+/// Register the given app with authenticator, replacing any app registered with the same ID.
+pub fn insert_app(client: &AuthClient, app: AppInfo) -> Box<AuthFuture<()>> {
     let hash = sha3_256(app.info.id.as_bytes());
+    let value = fry!(serialize(&app));
 
-    mutate_entry(&client, KEY_APPS, apps, new_version, move |apps| {
-        apps.insert(hash, app.clone()).is_none()
-    })
+    apps_map(client)
+        .and_then(move |map| map.insert(&hash, &value).map_err(AuthError::from))
+        .into_box()
 }
 
 /// Remove the given app from the list of registered apps.
-pub fn remove_app(
+pub fn remove_app(client: &AuthClient, app_id: &str) -> Box<AuthFuture<()>> {
+    let hash = sha3_256(app_id.as_bytes());
+
+    apps_map(client)
+        .and_then(move |map| map.remove(&hash).map_err(AuthError::from))
+        .map(|_| ())
+        .into_box()
+}
+
+/// Put the empty sharded map holding the registered apps on the network. Succeeds if the map
+/// already exists.
+pub(crate) fn create_apps_map(client: &AuthClient) -> Box<AuthFuture<ShardedMap<AuthClient>>> {
+    let client2 = client.clone();
+    let root = apps_root(client);
+    let root2 = root.clone();
+
+    ShardedMap::create(client.clone(), root)
+        .or_else(move |error| match error {
+            CoreError::DataError(SndError::DataExists) => Ok(ShardedMap::new(client2, root2)),
+            error => Err(AuthError::from(error)),
+        })
+        .into_box()
+}
+
+// Open the sharded map holding the registered apps, by the SHA-3 hashes of their IDs. Accounts
+// created before the apps were sharded keep them in the `KEY_APPS` entry of the config root dir
+// instead, from which they are moved into a new map when it's first opened.
+pub(crate) fn apps_map(client: &AuthClient) -> Box<AuthFuture<ShardedMap<AuthClient>>> {
+    let client = client.clone();
+
+    get_entry(&client, KEY_APPS)
+        .and_then(move |(version, apps): (_, Apps)| match version {
+            Some(version) => migrate_apps(&client, version, apps),
+            None => ok!(ShardedMap::new(client.clone(), apps_root(&client))),
+        })
+        .into_box()
+}
+
+// Move the apps read from the `KEY_APPS` entry at `version` into the sharded map and delete the
+// entry. If the entry was changed or deleted concurrently, it's left for the next call to move.
+fn migrate_apps(
     client: &AuthClient,
+    version: u64,
     apps: Apps,
-    new_version: u64,
-    app_id: &str,
-) -> Box<AuthFuture<(u64, Apps)>> {
-    let hash = sha3_256(app_id.as_bytes());
-    mutate_entry(client, KEY_APPS, apps, new_version, move |apps| {
-        apps.remove(&hash).is_some()
-    })
+) -> Box<AuthFuture<ShardedMap<AuthClient>>> {
+    trace!("Moving {} registered apps into a sharded map", apps.len());
+
+    let client2 = client.clone();
+
+    create_apps_map(client)
+        .and_then(move |map| {
+            let inserts = fry!(apps
+                .iter()
+                .map(|(hash, app)| Ok(map.insert(hash, &serialize(app)?)))
+                .collect::<Result<Vec<_>, AuthError>>());
+
+            future::join_all(inserts)
+                .map_err(AuthError::from)
+                .map(move |_| map)
+                .into_box()
+        })
+        .and_then(move |map| {
+            let parent = client2.config_root_dir();
+            let key = fry!(parent.enc_entry_key(KEY_APPS));
+
+            client2
+                .mutate_seq_mdata_entries(
+                    parent.name(),
+                    parent.type_tag(),
+                    MDataSeqEntryActions::new().del(key, version + 1),
+                )
+                .or_else(|error| {
+                    if recovery::is_entry_conflict(&error) {
+                        Ok(())
+                    } else {
+                        Err(AuthError::from(error))
+                    }
+                })
+                .map(move |()| map)
+                .into_box()
+        })
+        .into_box()
+}
+
+// Root of the sharded map holding the registered apps. It's derived from the config root dir and
+// shares its encryption info, so it doesn't need to be stored anywhere.
+fn apps_root(client: &AuthClient) -> MDataInfo {
+    let mut root = client.config_root_dir();
+    let mut seed = root.name().0.to_vec();
+    seed.extend_from_slice(KEY_APPS);
+
+    root.address = MDataAddress::Seq {
+        name: XorName(sha3_256(&seed)),
+        tag: root.type_tag(),
+    };
+    root.new_enc_info = None;
+    root
+}
+
+fn app_hash(key: &[u8]) -> Result<[u8; 32], AuthError> {
+    let mut hash = [0; 32];
+    if key.len() != hash.len() {
+        return Err(AuthError::Unexpected(
+            "Invalid key in the map of registered apps".to_string(),
+        ));
+    }
+    hash.copy_from_slice(key);
+    Ok(hash)
 }
 
 /// Get authenticator's revocation queue.
@@ -194,6 +309,60 @@ pub fn repush_to_app_revocation_queue(
     )
 }
 
+/// Retrieves the data items transferred to the user and accepted.
+pub fn list_accepted_items(client: &AuthClient) -> Box<AuthFuture<(Option<u64>, AcceptedItems)>> {
+    get_entry(client, KEY_ACCEPTED_ITEMS)
+}
+
+/// Store the accepted data item under the given name. Does nothing if the name already refers to
+/// the item, and replaces the item the name refers to otherwise.
+pub fn insert_accepted_item(
+    client: &AuthClient,
+    items: AcceptedItems,
+    new_version: u64,
+    name: &str,
+    item: DataItem,
+) -> Box<AuthFuture<(u64, AcceptedItems)>> {
+    let name = name.to_string();
+    mutate_entry(
+        client,
+        KEY_ACCEPTED_ITEMS,
+        items,
+        new_version,
+        move |items| items.insert(name.clone(), item) != Some(item),
+    )
+}
+
+/// Retrieves the time after which items are purged from the `_trash` container, which is
+/// `trash::DEFAULT_RETENTION` unless set with `set_trash_retention`.
+pub fn get_trash_retention(client: &AuthClient) -> Box<AuthFuture<Duration>> {
+    get_entry(client, KEY_TRASH_RETENTION)
+        .map(|(_, secs): (_, Option<u64>)| {
+            secs.map_or(trash::DEFAULT_RETENTION, Duration::from_secs)
+        })
+        .into_box()
+}
+
+/// Store the time after which items are purged from the `_trash` container, with a precision of
+/// seconds.
+pub fn set_trash_retention(client: &AuthClient, retention: Duration) -> Box<AuthFuture<()>> {
+    let client2 = client.clone();
+    let secs = retention.as_secs();
+
+    get_entry(client, KEY_TRASH_RETENTION)
+        .and_then(move |(version, current): (_, Option<u64>)| {
+            mutate_entry(
+                &client2,
+                KEY_TRASH_RETENTION,
+                current,
+                next_version(version),
+                move |current| current.replace(secs) != Some(secs),
+            )
+        })
+        .map(|_| ())
+        .into_box()
+}
+
 fn get_entry<T>(client: &AuthClient, key: &[u8]) -> Box<AuthFuture<(Option<u64>, T)>>
 where
     T: Default + DeserializeOwned + Serialize + 'static,
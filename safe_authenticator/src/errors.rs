@@ -70,11 +70,15 @@ mod codes {
     pub const ERR_SHARE_MDATA_DENIED: i32 = -206;
     pub const ERR_INVALID_OWNER: i32 = -207;
     pub const ERR_INCOMPATIBLE_MOCK_STATUS: i32 = -208;
+    pub const ERR_TRANS_OWNERSHIP_DENIED: i32 = -209;
+    pub const ERR_UNSUPPORTED_DATA_ITEM: i32 = -210;
 
     // NFS errors.
     pub const ERR_FILE_EXISTS: i32 = -300;
     pub const ERR_FILE_NOT_FOUND: i32 = -301;
     pub const ERR_INVALID_RANGE: i32 = -302;
+    pub const ERR_TOO_MANY_LINKS: i32 = -303;
+    pub const ERR_CONTENT_HASH_MISMATCH: i32 = -304;
 
     // Authenticator errors.
     pub const ERR_IO_ERROR: i32 = -1013;
@@ -274,12 +278,16 @@ impl ErrorCode for AuthError {
                 IpcError::ShareMDataDenied => ERR_SHARE_MDATA_DENIED,
                 IpcError::InvalidOwner(..) => ERR_INVALID_OWNER,
                 IpcError::IncompatibleMockStatus => ERR_INCOMPATIBLE_MOCK_STATUS,
+                IpcError::TransOwnershipDenied => ERR_TRANS_OWNERSHIP_DENIED,
+                IpcError::UnsupportedDataItem(..) => ERR_UNSUPPORTED_DATA_ITEM,
             },
             Self::NfsError(ref err) => match *err {
                 NfsError::CoreError(ref err) => core_error_code(err),
                 NfsError::FileExists => ERR_FILE_EXISTS,
                 NfsError::FileNotFound => ERR_FILE_NOT_FOUND,
                 NfsError::InvalidRange => ERR_INVALID_RANGE,
+                NfsError::TooManyLinks => ERR_TOO_MANY_LINKS,
+                NfsError::ContentHashMismatch => ERR_CONTENT_HASH_MISMATCH,
                 NfsError::EncodeDecodeError(_) => ERR_ENCODE_DECODE_ERROR,
                 NfsError::SelfEncryption(_) => ERR_SELF_ENCRYPTION,
                 NfsError::Unexpected(_) => ERR_UNEXPECTED,
@@ -267,7 +267,7 @@ mod tests {
             let c2 = client.clone();
 
             config::list_apps(client)
-                .and_then(move |(_, apps)| app_state(&c2, &apps, &app_id))
+                .and_then(move |apps| app_state(&c2, &apps, &app_id))
                 .and_then(move |res| match res {
                     AppState::Authenticated => Ok(()),
                     _ => panic!("App state changed after failed revocation"),
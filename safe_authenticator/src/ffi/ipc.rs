@@ -10,6 +10,7 @@ use crate::access_container;
 use crate::app_auth;
 use crate::config;
 use crate::ipc::{decode_ipc_msg, decode_share_mdata_req, encode_response, update_container_perms};
+use crate::ownership;
 use crate::revocation::{flush_app_revocation_queue, revoke_app};
 use crate::{AuthError, Authenticator};
 use ffi_utils::{
@@ -17,11 +18,11 @@ use ffi_utils::{
 };
 use futures::{stream, Future, Stream};
 use safe_core::client::Client;
-use safe_core::ffi::ipc::req::{AuthReq, ContainersReq, ShareMDataReq};
+use safe_core::ffi::ipc::req::{AuthReq, ContainersReq, ShareMDataReq, TransOwnershipReq};
 use safe_core::ffi::ipc::resp::MetadataResponse;
 use safe_core::ipc::req::{
     AuthReq as NativeAuthReq, ContainersReq as NativeContainersReq, IpcReq,
-    ShareMDataReq as NativeShareMDataReq,
+    ShareMDataReq as NativeShareMDataReq, TransOwnershipReq as NativeTransOwnershipReq,
 };
 use safe_core::ipc::resp::IpcResp;
 use safe_core::ipc::{decode_msg, IpcError, IpcMsg};
@@ -95,6 +96,11 @@ pub unsafe extern "C" fn auth_decode_ipc_msg(
         metadata: *const MetadataResponse,
         metadata_len: usize,
     ),
+    o_trans_ownership: extern "C" fn(
+        user_data: *mut c_void,
+        req_id: u32,
+        req: *const TransOwnershipReq,
+    ),
     o_err: extern "C" fn(user_data: *mut c_void, result: *const FfiResult, response: *const c_char),
 ) {
     let user_data = OpaqueCtx(user_data);
@@ -162,6 +168,16 @@ pub unsafe extern "C" fn auth_decode_ipc_msg(
                             Ok(())
                         })
                         .into_box(),
+                    Ok(IpcMsg::Req {
+                        req: IpcReq::TransOwnership(trans_ownership_req),
+                        req_id,
+                    }) => {
+                        let repr_c = fry!(trans_ownership_req
+                            .into_repr_c()
+                            .map_err(AuthError::IpcError));
+                        o_trans_ownership(user_data.0, req_id, &repr_c);
+                        ok!(())
+                    }
                     Err((error_code, description, err)) => {
                         let res = fry!(NativeResult {
                             error_code,
@@ -507,3 +523,65 @@ pub unsafe extern "C" fn encode_share_mdata_resp(
         Ok(())
     })
 }
+
+/// Transfers the ownership of the data items of the request if it was granted by the user, and
+/// encodes the response to the request, see `ownership::trans_ownership`. Requests including
+/// mutable data fail with `UnsupportedDataItem`, as the network can't change its owner.
+#[no_mangle]
+pub unsafe extern "C" fn encode_trans_ownership_resp(
+    auth: *const Authenticator,
+    req: *const TransOwnershipReq,
+    req_id: u32,
+    is_granted: bool,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult, response: *const c_char),
+) {
+    let user_data = OpaqueCtx(user_data);
+
+    catch_unwind_cb(user_data.0, o_cb, || -> Result<(), AuthError> {
+        let trans_ownership_req = NativeTransOwnershipReq::clone_from_repr_c(req)?;
+
+        if !is_granted {
+            let resp = encode_response(&IpcMsg::Resp {
+                req_id,
+                resp: IpcResp::TransOwnership(Err(IpcError::TransOwnershipDenied)),
+            })?;
+
+            o_cb(user_data.0, FFI_RESULT_OK, resp.as_ptr());
+        } else {
+            (*auth).send(move |client| {
+                ownership::trans_ownership(client, trans_ownership_req)
+                    .and_then(move |()| {
+                        let resp = encode_response(&IpcMsg::Resp {
+                            req_id,
+                            resp: IpcResp::TransOwnership(Ok(())),
+                        })?;
+
+                        o_cb(user_data.0, FFI_RESULT_OK, resp.as_ptr());
+                        Ok(())
+                    })
+                    .or_else(move |e| -> Result<(), AuthError> {
+                        let (error_code, description) = ffi_error!(e);
+                        let resp = encode_response(&IpcMsg::Resp {
+                            req_id,
+                            resp: IpcResp::TransOwnership(Err(e.into())),
+                        })?;
+                        let res = NativeResult {
+                            error_code,
+                            description: Some(description),
+                        }
+                        .into_repr_c()?;
+                        o_cb(user_data.0, &res, resp.as_ptr());
+                        Ok(())
+                    })
+                    .map_err(move |e| {
+                        call_result_cb!(Err::<(), _>(e), user_data, o_cb);
+                    })
+                    .into_box()
+                    .into()
+            })?;
+        }
+
+        Ok(())
+    })
+}
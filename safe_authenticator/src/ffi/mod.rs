@@ -16,16 +16,23 @@ pub mod apps;
 pub mod ipc;
 /// Logging utilities
 pub mod logging;
+/// Transfer of data ownership
+pub mod ownership;
+/// Storage usage of the account
+pub mod usage;
 
+use crate::config;
 use crate::errors::AuthError;
 use crate::Authenticator;
 use ffi_utils::{catch_unwind_cb, FfiResult, OpaqueCtx, ReprC, FFI_RESULT_OK};
+use futures::Future;
 use rand::thread_rng;
-use safe_core::{config_handler, test_create_balance, Client};
+use safe_core::{config_handler, test_create_balance, Client, FutureExt};
 use safe_nd::{ClientFullId, Coins};
 use std::ffi::{CStr, OsStr};
 use std::os::raw::{c_char, c_void};
 use std::str::FromStr;
+use std::time::Duration;
 
 /// Create a registered client. This or any one of the other companion
 /// functions to get an authenticator instance must be called before initiating any
@@ -131,6 +138,30 @@ pub unsafe extern "C" fn auth_reconnect(
     })
 }
 
+/// Set the time in seconds after which items are purged from the `_trash` container, which is
+/// done on every login. Defaults to `safe_core::nfs::trash::DEFAULT_RETENTION`.
+#[no_mangle]
+pub unsafe extern "C" fn auth_set_trash_retention(
+    auth: *const Authenticator,
+    retention_secs: u64,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    let user_data = OpaqueCtx(user_data);
+
+    catch_unwind_cb(user_data.0, o_cb, || -> Result<_, AuthError> {
+        (*auth).send(move |client| {
+            config::set_trash_retention(client, Duration::from_secs(retention_secs))
+                .then(move |res| {
+                    call_result_cb!(res, user_data, o_cb);
+                    Ok(())
+                })
+                .into_box()
+                .into()
+        })
+    })
+}
+
 /// Sets the path from which the `safe_core.config` file will be read.
 #[no_mangle]
 pub unsafe extern "C" fn auth_set_config_dir_path(
@@ -167,8 +198,7 @@ mod tests {
     use crate::ffi::auth_is_mock;
     use crate::run;
     use ffi_utils::test_utils::call_1;
-    use futures::Future;
-    use safe_core::{utils, FutureExt};
+    use safe_core::utils;
     use safe_nd::PubImmutableData;
     use std::ffi::CString;
     use std::os::raw::c_void;
@@ -0,0 +1,104 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::config::list_accepted_items;
+use crate::ownership::accept_ownership;
+use crate::{AuthError, Authenticator};
+use ffi_utils::{catch_unwind_cb, FfiResult, OpaqueCtx, ReprC, SafePtr, FFI_RESULT_OK};
+use futures::Future;
+use safe_core::client::roles::DataItem as NativeDataItem;
+use safe_core::ffi::ipc::req::DataItem;
+use safe_core::FutureExt;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_void};
+
+/// Data item transferred to the user and accepted.
+#[repr(C)]
+pub struct AcceptedItem {
+    /// UTF-8 encoded name under which the item was accepted.
+    pub name: *const c_char,
+    /// The data item.
+    pub item: DataItem,
+}
+
+impl Drop for AcceptedItem {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CString::from_raw(self.name as *mut _);
+        }
+    }
+}
+
+/// Accept a data item transferred to the user, storing it under `name` in the list of accepted
+/// items. Fails if the item isn't owned by the user yet, or if another item was already accepted
+/// under the name.
+#[no_mangle]
+pub unsafe extern "C" fn auth_accept_ownership(
+    auth: *const Authenticator,
+    name: *const c_char,
+    item: *const DataItem,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+) {
+    let user_data = OpaqueCtx(user_data);
+
+    catch_unwind_cb(user_data.0, o_cb, || -> Result<_, AuthError> {
+        let name = String::clone_from_repr_c(name)?;
+        let item = NativeDataItem::clone_from_repr_c(item)?;
+
+        (*auth).send(move |client| {
+            accept_ownership(client, &name, item)
+                .then(move |res| {
+                    call_result_cb!(res, user_data, o_cb);
+                    Ok(())
+                })
+                .into_box()
+                .into()
+        })
+    })
+}
+
+/// Get the list of data items transferred to the user and accepted.
+#[no_mangle]
+pub unsafe extern "C" fn auth_accepted_items(
+    auth: *const Authenticator,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        items: *const AcceptedItem,
+        items_len: usize,
+    ),
+) {
+    let user_data = OpaqueCtx(user_data);
+
+    catch_unwind_cb(user_data.0, o_cb, || -> Result<_, AuthError> {
+        (*auth).send(move |client| {
+            list_accepted_items(client)
+                .and_then(move |(_, items)| {
+                    let items: Vec<_> = items
+                        .into_iter()
+                        .map(|(name, item)| {
+                            Ok(AcceptedItem {
+                                name: CString::new(name)?.into_raw(),
+                                item: item.into_repr_c(),
+                            })
+                        })
+                        .collect::<Result<_, AuthError>>()?;
+                    o_cb(user_data.0, FFI_RESULT_OK, items.as_safe_ptr(), items.len());
+
+                    Ok(())
+                })
+                .map_err(move |e| {
+                    call_result_cb!(Err::<(), _>(e), user_data, o_cb);
+                })
+                .into_box()
+                .into()
+        })
+    })
+}
@@ -0,0 +1,93 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::usage::account_usage;
+use crate::{AuthError, Authenticator};
+use ffi_utils::{catch_unwind_cb, vec_from_raw_parts, FfiResult, OpaqueCtx, FFI_RESULT_OK};
+use futures::Future;
+use safe_core::ffi::nfs::Usage;
+use safe_core::FutureExt;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_void};
+
+/// Storage used by a container.
+#[repr(C)]
+pub struct ContainerUsage {
+    /// UTF-8 encoded name of the container, or ID of the app for dedicated app containers.
+    pub name: *const c_char,
+    /// Usage of the container.
+    pub usage: Usage,
+}
+
+impl Drop for ContainerUsage {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CString::from_raw(self.name as *mut _);
+        }
+    }
+}
+
+/// Storage used by the account, broken down by container.
+#[repr(C)]
+pub struct AccountUsage {
+    /// Usage of the containers in the access container, excluding the dedicated containers of
+    /// apps.
+    pub containers: *const ContainerUsage,
+    /// Length of the containers array.
+    pub containers_len: usize,
+    /// Usage of the dedicated containers of apps, named by app ID.
+    pub apps: *const ContainerUsage,
+    /// Length of the apps array.
+    pub apps_len: usize,
+    /// Usage of the config root directory and the sharded map of registered apps.
+    pub config: Usage,
+    /// Total usage of the account.
+    pub total: Usage,
+}
+
+impl Drop for AccountUsage {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = vec_from_raw_parts(self.containers as *mut ContainerUsage, self.containers_len);
+            let _ = vec_from_raw_parts(self.apps as *mut ContainerUsage, self.apps_len);
+        }
+    }
+}
+
+/// Get the storage usage of the account, broken down by container and by app.
+#[no_mangle]
+pub unsafe extern "C" fn auth_account_usage(
+    auth: *const Authenticator,
+    user_data: *mut c_void,
+    o_cb: extern "C" fn(
+        user_data: *mut c_void,
+        result: *const FfiResult,
+        usage: *const AccountUsage,
+    ),
+) {
+    let user_data = OpaqueCtx(user_data);
+
+    catch_unwind_cb(user_data.0, o_cb, || -> Result<_, AuthError> {
+        (*auth).send(move |client| {
+            account_usage(client)
+                .and_then(move |usage| {
+                    let usage = usage.into_repr_c()?;
+                    o_cb(user_data.0, FFI_RESULT_OK, &usage);
+
+                    Ok(())
+                })
+                .map_err(move |e| {
+                    call_result_cb!(Err::<(), _>(e), user_data, o_cb);
+                })
+                .into_box()
+                .into()
+        })?;
+
+        Ok(())
+    })
+}
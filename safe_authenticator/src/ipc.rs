@@ -13,6 +13,7 @@ use crate::access_container;
 use crate::app_auth::{app_state, AppState};
 use crate::client::AuthClient;
 use crate::config;
+use crate::ownership::validate_trans_ownership_req;
 use bincode::deserialize;
 use ffi_utils::StringError;
 use futures::future::{self, Either};
@@ -61,6 +62,32 @@ pub fn decode_ipc_msg(
             req_id,
             req: IpcReq::ShareMData(share_mdata_req),
         })),
+        IpcMsg::Req {
+            req: IpcReq::TransOwnership(trans_ownership_req),
+            req_id,
+        } => {
+            // Requests which can't be granted are rejected without asking the user.
+            validate_trans_ownership_req(client, &trans_ownership_req)
+                .then(move |res| match res {
+                    Ok(()) => Ok(Ok(IpcMsg::Req {
+                        req_id,
+                        req: IpcReq::TransOwnership(trans_ownership_req),
+                    })),
+                    Err(error @ AuthError::IpcError(_)) => {
+                        let (error_code, description) = ffi_error!(error);
+
+                        let resp = IpcMsg::Resp {
+                            resp: IpcResp::TransOwnership(Err(error.into())),
+                            req_id,
+                        };
+                        let resp = encode_response(&resp)?;
+
+                        Ok(Err((error_code, description, resp)))
+                    }
+                    Err(error) => Err(error),
+                })
+                .into_box()
+        }
         IpcMsg::Req {
             req: IpcReq::Containers(cont_req),
             req_id,
@@ -71,7 +98,7 @@ pub fn decode_ipc_msg(
             let c2 = client.clone();
 
             config::list_apps(client)
-                .and_then(move |(_config_version, config)| app_state(&c2, &config, &app_id))
+                .and_then(move |config| app_state(&c2, &config, &app_id))
                 .and_then(move |app_state| {
                     match app_state {
                         AppState::Authenticated => Ok(Ok(IpcMsg::Req {
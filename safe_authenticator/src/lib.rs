@@ -45,7 +45,9 @@ pub mod config;
 pub mod errors;
 pub mod ffi;
 pub mod ipc;
+pub mod ownership;
 pub mod revocation;
+pub mod usage;
 #[cfg(any(test, feature = "testing"))]
 #[macro_use]
 pub mod test_utils;
@@ -53,6 +55,7 @@ pub mod test_utils;
 pub use ffi::apps::*;
 pub use ffi::ipc::*;
 pub use ffi::logging::*;
+pub use ffi::usage::*;
 pub use ffi::*;
 
 mod client;
@@ -273,7 +276,37 @@ impl Authenticator {
                             .into()
                     })));
                 } else {
-                    unwrap!(tx.send(Ok(core_tx)));
+                    // Create the standard directories added to the defaults since the account
+                    // was registered, and then purge the expired items from the trash once the
+                    // login has completed. Failing to do either doesn't fail the login, as they're
+                    // tried again on the next one.
+                    let core_tx2 = core_tx.clone();
+
+                    unwrap!(core_tx.unbounded_send(CoreMsg::new(move |client, &()| {
+                        let client = client.clone();
+
+                        std_dirs::create_missing(&client)
+                            .then(move |res| {
+                                if let Err(error) = res {
+                                    warn!(
+                                        "Failed to create missing standard directories: {:?}",
+                                        error
+                                    );
+                                }
+                                unwrap!(tx.send(Ok(core_tx2)));
+
+                                std_dirs::purge_trash(&client)
+                            })
+                            .then(|res| {
+                                match res {
+                                    Ok(purged) => trace!("Purged {} items from the trash", purged),
+                                    Err(error) => warn!("Failed to purge the trash: {:?}", error),
+                                }
+                                Ok::<_, ()>(())
+                            })
+                            .into_box()
+                            .into()
+                    })));
                 }
 
                 event_loop::run(el, &client, &(), core_rx);
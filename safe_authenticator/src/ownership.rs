@@ -0,0 +1,217 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Transfer of the ownership of data items between users.
+//!
+//! The authenticator of the current owner handles the `TransOwnership` IPC request, and the
+//! authenticator of the new owner can then accept the item, keeping a reference to it in its
+//! configuration.
+//!
+//! Only append-only data can be transferred. The network doesn't support changing the owner of
+//! mutable data, which is set when the data is put, so requests including mutable data are
+//! rejected with `IpcError::UnsupportedDataItem` as a whole.
+
+use super::{AuthError, AuthFuture};
+use crate::client::AuthClient;
+use crate::config;
+use futures::future;
+use futures::Future;
+use safe_core::client::roles::{self, DataItem};
+use safe_core::event_loop::CoreFuture;
+use safe_core::ipc::req::TransOwnershipReq;
+use safe_core::ipc::IpcError;
+use safe_core::recovery;
+use safe_core::{Client, CoreError, FutureExt};
+use safe_nd::{
+    ADataAddress, ADataIndex, ADataOwner, Error as SndError, MDataKind, PublicKey, XorName,
+};
+
+/// Checks that all the data items of the request are append-only data owned by the user, or
+/// already transferred to the new owner. Fails with `IpcError::UnsupportedDataItem` listing the
+/// mutable data items, whose owner can't be changed, and with `IpcError::InvalidOwner` listing the
+/// items owned by someone else.
+pub fn validate_trans_ownership_req(
+    client: &AuthClient,
+    req: &TransOwnershipReq,
+) -> Box<AuthFuture<()>> {
+    let unsupported: Vec<_> = req
+        .items
+        .iter()
+        .filter(|item| match item {
+            DataItem::MData(_) => true,
+            DataItem::AData(_) => false,
+        })
+        .map(|item| name_and_tag(*item))
+        .collect();
+    if !unsupported.is_empty() {
+        return err!(AuthError::IpcError(IpcError::UnsupportedDataItem(
+            unsupported
+        )));
+    }
+
+    let user = client.public_key();
+    let new_owner = req.new_owner;
+
+    fetch_owners(client, &req.items)
+        .map_err(AuthError::from)
+        .and_then(move |owners| {
+            let invalids: Vec<_> = owners
+                .into_iter()
+                .filter(|(_, owner)| *owner != user && *owner != new_owner)
+                .map(|(item, _)| name_and_tag(item))
+                .collect();
+
+            if invalids.is_empty() {
+                Ok(())
+            } else {
+                Err(AuthError::IpcError(IpcError::InvalidOwner(invalids)))
+            }
+        })
+        .into_box()
+}
+
+/// Transfers the ownership of the data items of the request to the new owner, after validating
+/// the request, so nothing is changed if any of the items can't be transferred.
+///
+/// The permissions of the user on each item are revoked before its owner is changed, while the
+/// user can still change them. Permissions of other keys, such as those of the user's apps, are
+/// left to the new owner to manage.
+///
+/// Items already owned by the new owner are skipped, so if the transfer fails part way through,
+/// e.g. because of a network error, repeating the request transfers the remaining items.
+pub fn trans_ownership(client: &AuthClient, req: TransOwnershipReq) -> Box<AuthFuture<()>> {
+    trace!(
+        "Transferring ownership of {} items to {:?}",
+        req.items.len(),
+        req.new_owner
+    );
+
+    let c2 = client.clone();
+    let c3 = client.clone();
+    let new_owner = req.new_owner;
+    let items = req.items.clone();
+
+    validate_trans_ownership_req(client, &req)
+        .and_then(move |()| fetch_owners(&c2, &items).map_err(AuthError::from))
+        .and_then(move |owners| {
+            let transfers: Vec<_> = owners
+                .into_iter()
+                .filter(|(_, owner)| *owner != new_owner)
+                .filter_map(|(item, _)| match item {
+                    DataItem::AData(address) => Some(transfer_adata(&c3, address, new_owner)),
+                    DataItem::MData(_) => None,
+                })
+                .collect();
+
+            future::join_all(transfers).map(|_| ())
+        })
+        .into_box()
+}
+
+/// Accepts a data item transferred to the user by storing it under `name` in the list of
+/// accepted items, kept in the authenticator configuration. Accepting the item again under the same
+/// name does nothing.
+///
+/// Fails with `IpcError::InvalidOwner` if the item isn't owned by the user yet, and with
+/// `DataExists` if another item was already accepted under the name.
+pub fn accept_ownership(client: &AuthClient, name: &str, item: DataItem) -> Box<AuthFuture<()>> {
+    trace!("Accepting {:?} as '{}'", item, name);
+
+    let c2 = client.clone();
+    let c3 = client.clone();
+    let user = client.public_key();
+    let name = name.to_string();
+
+    fetch_owner(client, item)
+        .map_err(AuthError::from)
+        .and_then(move |owner| {
+            if owner != user {
+                return Err(AuthError::IpcError(IpcError::InvalidOwner(vec![
+                    name_and_tag(item),
+                ])));
+            }
+            Ok(())
+        })
+        .and_then(move |()| config::list_accepted_items(&c2))
+        .and_then(move |(version, items)| {
+            match items.get(&name) {
+                Some(existing) if *existing != item => {
+                    return err!(AuthError::from(CoreError::DataError(SndError::DataExists)));
+                }
+                _ => (),
+            }
+
+            config::insert_accepted_item(&c3, items, config::next_version(version), &name, item)
+                .map(|_| ())
+                .into_box()
+        })
+        .into_box()
+}
+
+fn transfer_adata(
+    client: &AuthClient,
+    address: ADataAddress,
+    new_owner: PublicKey,
+) -> Box<AuthFuture<()>> {
+    let c2 = client.clone();
+    let c3 = client.clone();
+
+    roles::revoke_role(client, vec![DataItem::AData(address)], client.public_key())
+        .and_then(move |()| c2.get_adata_indices(address))
+        .and_then(move |indices| {
+            let owner = ADataOwner {
+                public_key: new_owner,
+                entries_index: indices.entries_index(),
+                permissions_index: indices.permissions_index(),
+            };
+            recovery::set_adata_owners(&c3, address, owner, indices.owners_index())
+        })
+        .map_err(AuthError::from)
+        .into_box()
+}
+
+fn fetch_owners(
+    client: &AuthClient,
+    items: &[DataItem],
+) -> Box<CoreFuture<Vec<(DataItem, PublicKey)>>> {
+    let owners: Vec<_> = items
+        .iter()
+        .map(|item| {
+            let item = *item;
+            fetch_owner(client, item).map(move |owner| (item, owner))
+        })
+        .collect();
+
+    future::join_all(owners).into_box()
+}
+
+fn fetch_owner(client: &AuthClient, item: DataItem) -> Box<CoreFuture<PublicKey>> {
+    match item {
+        DataItem::MData(address) => match address.kind() {
+            MDataKind::Seq => client
+                .get_seq_mdata_shell(*address.name(), address.tag())
+                .map(|shell| *shell.owner())
+                .into_box(),
+            MDataKind::Unseq => client
+                .get_unseq_mdata_shell(*address.name(), address.tag())
+                .map(|shell| *shell.owner())
+                .into_box(),
+        },
+        DataItem::AData(address) => client
+            .get_adata_owners(address, ADataIndex::FromEnd(1))
+            .map(|owner| owner.public_key)
+            .into_box(),
+    }
+}
+
+fn name_and_tag(item: DataItem) -> (XorName, u64) {
+    match item {
+        DataItem::MData(address) => (*address.name(), address.tag()),
+        DataItem::AData(address) => (*address.name(), address.tag()),
+    }
+}
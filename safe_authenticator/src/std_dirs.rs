@@ -8,28 +8,31 @@
 
 use crate::access_container::{self, AUTHENTICATOR_ENTRY};
 use crate::client::AuthClient;
-use crate::config::KEY_APPS;
+use crate::config;
 use crate::{AuthError, AuthFuture};
 use bincode::serialize;
 use futures::{future, Future};
 use safe_core::ipc::access_container_enc_key;
-use safe_core::mdata_info;
-use safe_core::nfs::create_dir;
+use safe_core::nfs::{create_dir, trash};
 use safe_core::utils::symmetric_encrypt;
 use safe_core::{Client, CoreError, FutureExt, MDataInfo, DIR_TAG};
 use safe_nd::{Error as SndError, MDataKind, MDataSeqValue};
 use std::collections::HashMap;
 
 /// Default directories to be created at registration.
-pub static DEFAULT_PRIVATE_DIRS: [&str; 6] = [
+pub static DEFAULT_PRIVATE_DIRS: [&str; 7] = [
     "_documents",
     "_downloads",
     "_music",
     "_pictures",
     "_videos",
     "_publicNames",
+    TRASH_DIR,
 ];
 
+/// Default directory soft deleted files are moved into, see `safe_core::nfs::trash`.
+pub const TRASH_DIR: &str = "_trash";
+
 /// Publicly accessible default directories to be created upon registration.
 pub static DEFAULT_PUBLIC_DIRS: [&str; 1] = ["_public"];
 
@@ -81,14 +84,65 @@ pub fn create(client: &AuthClient) -> Box<AuthFuture<()>> {
         .into_box()
 }
 
-fn create_config_dir(client: &AuthClient, config_dir: &MDataInfo) -> Box<AuthFuture<()>> {
-    let config_dir_entries =
-        btree_map![KEY_APPS.to_vec() => MDataSeqValue { data: Vec::new(), version: 0 }];
+/// Create the standard directories missing from the authenticator entry of the access container,
+/// such as directories which were added to the defaults after the account was created, and record
+/// them in the entry.
+pub fn create_missing(client: &AuthClient) -> Box<AuthFuture<()>> {
+    let c2 = client.clone();
+    let c3 = client.clone();
 
-    let config_dir_entries = fry!(mdata_info::encrypt_entries(config_dir, &config_dir_entries));
+    access_container::fetch_authenticator_entry(client)
+        .and_then(move |(version, mut entries)| {
+            let missing: HashMap<_, _> = fry!(random_std_dirs())
+                .into_iter()
+                .filter(|(name, _)| !entries.contains_key(*name))
+                .map(|(name, md_info)| (String::from(name), md_info))
+                .collect();
+            if missing.is_empty() {
+                return ok!(());
+            }
 
-    create_dir(client, config_dir, config_dir_entries, btree_map![])
-        .map_err(From::from)
+            trace!(
+                "Creating missing standard directories: {:?}",
+                missing.keys()
+            );
+
+            create_std_dirs(&c2, &missing)
+                .and_then(move |()| {
+                    entries.extend(missing);
+                    access_container::put_authenticator_entry(&c3, &entries, version + 1)
+                })
+                .into_box()
+        })
+        .into_box()
+}
+
+/// Permanently delete the items which have been in the `_trash` container for longer than the
+/// retention set in the authenticator configuration, see `config::get_trash_retention`. Returns
+/// the number of deleted items.
+pub fn purge_trash(client: &AuthClient) -> Box<AuthFuture<usize>> {
+    let c2 = client.clone();
+
+    access_container::fetch_authenticator_entry(client)
+        .join(config::get_trash_retention(client))
+        .and_then(
+            move |((_, mut entries), retention)| match entries.remove(TRASH_DIR) {
+                Some(trash_dir) => trash::purge(c2, trash_dir, retention)
+                    .map_err(AuthError::from)
+                    .into_box(),
+                None => ok!(0),
+            },
+        )
+        .into_box()
+}
+
+fn create_config_dir(client: &AuthClient, config_dir: &MDataInfo) -> Box<AuthFuture<()>> {
+    let c2 = client.clone();
+
+    create_dir(client, config_dir, btree_map![], btree_map![])
+        .map_err(AuthError::from)
+        .and_then(move |()| config::create_apps_map(&c2))
+        .map(|_| ())
         .into_box()
 }
 
@@ -158,8 +212,11 @@ pub fn create_std_dirs(
 mod tests {
     use super::*;
     use crate::run;
-    use crate::test_utils::create_account_and_login;
+    use crate::test_utils::{create_account_and_login, create_authenticator};
+    use crate::Authenticator;
     use futures::Future;
+    use safe_core::nfs::{file_helper, File};
+    use std::time::Duration;
 
     // Test creation of default dirs.
     #[test]
@@ -200,4 +257,68 @@ mod tests {
             })
         }));
     }
+
+    // Test that default dirs missing from an existing account are created at login.
+    // 1. Remove `_trash` from the authenticator entry of the access container.
+    // 2. Log in again and check `_trash` has been added back to the entry.
+    #[test]
+    fn creates_missing_dirs_at_login() {
+        let (auth, locator, password) = create_authenticator();
+
+        unwrap!(run(&auth, |client| {
+            let client = client.clone();
+
+            access_container::fetch_authenticator_entry(&client).and_then(
+                move |(version, mut entries)| {
+                    assert!(entries.remove("_trash").is_some());
+                    access_container::put_authenticator_entry(&client, &entries, version + 1)
+                },
+            )
+        }));
+
+        let auth = unwrap!(Authenticator::login(locator, password, || ()));
+        let entries = unwrap!(run(&auth, |client| {
+            access_container::fetch_authenticator_entry(client).map(|(_, entries)| entries)
+        }));
+        assert!(entries.contains_key("_trash"));
+    }
+
+    // Test purging the trash with the retention set in the configuration.
+    // 1. Move a file into the trash.
+    // 2. Check it isn't purged with the default retention.
+    // 3. Set the retention to zero and check it's purged.
+    #[test]
+    fn purges_trash() {
+        let auth = create_account_and_login();
+
+        let purged = unwrap!(run(&auth, |client| {
+            let c2 = client.clone();
+            let c3 = client.clone();
+
+            access_container::fetch_authenticator_entry(client)
+                .and_then(move |(_, mut entries)| {
+                    let docs = unwrap!(entries.remove("_documents"));
+                    let trash = unwrap!(entries.remove(TRASH_DIR));
+
+                    file_helper::insert(
+                        c2.clone(),
+                        docs.clone(),
+                        "file.txt",
+                        &File::new(vec![], true),
+                    )
+                    .and_then(move |()| trash::soft_delete(c2, docs, "file.txt", trash))
+                    .map_err(AuthError::from)
+                })
+                .and_then(move |_| purge_trash(&c3))
+        }));
+        assert_eq!(purged, 0);
+
+        let purged = unwrap!(run(&auth, |client| {
+            let client = client.clone();
+
+            config::set_trash_retention(&client, Duration::from_secs(0))
+                .and_then(move |()| purge_trash(&client))
+        }));
+        assert_eq!(purged, 1);
+    }
 }
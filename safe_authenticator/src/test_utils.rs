@@ -23,6 +23,7 @@ use safe_core::client::{test_create_balance, Client};
 use safe_core::crypto::shared_secretbox;
 use safe_core::ffi::ipc::req::{
     AuthReq as FfiAuthReq, ContainersReq as FfiContainersReq, ShareMDataReq as FfiShareMDataReq,
+    TransOwnershipReq as FfiTransOwnershipReq,
 };
 use safe_core::ffi::ipc::resp::MetadataResponse as FfiUserMetadata;
 use safe_core::ipc::req::{container_perms_into_permission_set, ContainerPermissions};
@@ -30,6 +31,7 @@ use safe_core::ipc::resp::AccessContainerEntry;
 use safe_core::ipc::resp::UserMetadata;
 use safe_core::ipc::{
     self, AppExchangeInfo, AuthGranted, AuthReq, ContainersReq, IpcMsg, IpcReq, ShareMDataReq,
+    TransOwnershipReq,
 };
 use safe_core::nfs::file_helper::{self, Version};
 use safe_core::nfs::{File, Mode};
@@ -555,6 +557,26 @@ pub fn auth_decode_ipc_msg_helper(authenticator: &Authenticator, msg: &str) -> C
         }
     }
 
+    extern "C" fn trans_ownership_cb(
+        user_data: *mut c_void,
+        req_id: u32,
+        req: *const FfiTransOwnershipReq,
+    ) {
+        unsafe {
+            let req = match TransOwnershipReq::clone_from_repr_c(req) {
+                Ok(req) => req,
+                Err(_) => return send_via_user_data::<ChannelType>(user_data, Err((-2, None))),
+            };
+
+            let msg = IpcMsg::Req {
+                req_id,
+                req: IpcReq::TransOwnership(req),
+            };
+
+            send_via_user_data::<ChannelType>(user_data, Ok((msg, None)))
+        }
+    }
+
     let ffi_msg = unwrap!(CString::new(msg));
     let mut ud = Default::default();
 
@@ -567,6 +589,7 @@ pub fn auth_decode_ipc_msg_helper(authenticator: &Authenticator, msg: &str) -> C
             containers_cb,
             unregistered_cb,
             share_mdata_cb,
+            trans_ownership_cb,
             err_cb,
         );
     };
@@ -11,6 +11,7 @@
 mod revocation;
 mod serialisation;
 mod share_mdata;
+mod trans_ownership;
 mod utils;
 
 use crate::access_container as access_container_tools;
@@ -22,16 +23,18 @@ use crate::ffi::ipc::{
 };
 use crate::safe_core::ffi::ipc::req::AppExchangeInfo as FfiAppExchangeInfo;
 use crate::safe_core::ipc::{
-    self, AuthReq, ContainersReq, IpcError, IpcMsg, IpcReq, IpcResp, Permission,
+    self, AppKeys, AuthReq, ContainersReq, IpcError, IpcMsg, IpcReq, IpcResp, Permission,
 };
 use crate::std_dirs::{DEFAULT_PRIVATE_DIRS, DEFAULT_PUBLIC_DIRS};
 use crate::test_utils::{self, ChannelType};
-use crate::{app_container, run};
+use crate::{app_container, run, usage};
+use bincode::serialize;
 use ffi_utils::test_utils::{call_1, call_vec, sender_as_user_data};
 use ffi_utils::{ErrorCode, ReprC, StringError};
 use futures::{future, Future};
 use safe_core::config_handler::Config;
 use safe_core::{app_container_name, mdata_info, AuthActions, Client};
+use safe_nd::MDataSeqEntryActions;
 use std::collections::HashMap;
 use std::ffi::CString;
 use std::sync::mpsc;
@@ -431,9 +434,57 @@ fn config_root_dir() {
 
     let entries = unwrap!(mdata_info::decrypt_entries(&dir, &entries));
 
-    // Verify it contains the required entries.
-    let config = unwrap!(entries.get(KEY_APPS));
-    assert!(config.data.is_empty());
+    // The apps are kept in a sharded map instead of an entry of the dir.
+    assert!(!entries.contains_key(KEY_APPS));
+    let apps = unwrap!(run(&authenticator, |client| config::list_apps(client)));
+    assert!(apps.is_empty());
+}
+
+// Test moving the apps registered with an account created before they were sharded into the
+// sharded map.
+// 1. Store an app in the `KEY_APPS` entry of the config root dir, as older accounts do.
+// 2. List the apps and check the app has been moved into the map and the entry deleted.
+#[test]
+fn apps_migration() {
+    let authenticator = test_utils::create_account_and_login();
+    let app = test_utils::rand_app();
+    let app_id = app.id.clone();
+
+    unwrap!(run(&authenticator, move |client| {
+        let dir = client.config_root_dir();
+        let keys = AppKeys::new(unwrap!(client.public_id().client_public_id()).clone());
+        let apps: config::Apps = vec![(
+            sha3_256(app.id.as_bytes()),
+            config::AppInfo { info: app, keys },
+        )]
+        .into_iter()
+        .collect();
+
+        let key = unwrap!(dir.enc_entry_key(KEY_APPS));
+        let value = unwrap!(dir.enc_entry_value(&unwrap!(serialize(&apps))));
+
+        client
+            .mutate_seq_mdata_entries(
+                dir.name(),
+                dir.type_tag(),
+                MDataSeqEntryActions::new().ins(key, value, 0),
+            )
+            .map_err(AuthError::from)
+    }));
+
+    let apps = unwrap!(run(&authenticator, |client| config::list_apps(client)));
+    assert_eq!(apps.len(), 1);
+    assert_eq!(unwrap!(apps.values().next()).info.id, app_id);
+
+    let (dir, entries) = unwrap!(run(&authenticator, |client| {
+        let dir = client.config_root_dir();
+        client
+            .list_seq_mdata_entries(dir.name(), dir.type_tag())
+            .map(move |entries| (dir, entries))
+            .map_err(AuthError::from)
+    }));
+    let entries = unwrap!(mdata_info::decrypt_entries(&dir, &entries));
+    assert!(!entries.contains_key(KEY_APPS));
 }
 
 // Test app authentication.
@@ -545,9 +596,7 @@ fn app_authentication() {
     let (app_dir_info, _) = unwrap!(access_container.remove(&app_container_name(&app_id)));
 
     // Check the app info is present in the config file.
-    let apps = unwrap!(run(&authenticator, |client| {
-        config::list_apps(client).map(|(_, apps)| apps)
-    }));
+    let apps = unwrap!(run(&authenticator, |client| config::list_apps(client)));
 
     let app_config_key = sha3_256(app_id.as_bytes());
     let app_info = unwrap!(apps.get(&app_config_key));
@@ -1013,6 +1062,45 @@ fn lists_of_registered_and_revoked_apps() {
     assert_eq!(revoked.len(), 0);
 }
 
+// Test that the storage usage of the account is broken down by container and by app.
+#[test]
+fn account_usage() {
+    let authenticator = test_utils::create_account_and_login();
+    let (app_id, _) = unwrap!(test_utils::register_rand_app(
+        &authenticator,
+        true,
+        HashMap::new()
+    ));
+
+    let documents = unwrap!(test_utils::get_container_from_authenticator_entry(
+        &authenticator,
+        "_documents"
+    ));
+    unwrap!(test_utils::create_file(
+        &authenticator,
+        documents,
+        "notes/hello.txt",
+        vec![1; 5000],
+        false
+    ));
+
+    let usage = unwrap!(run(&authenticator, usage::account_usage));
+
+    let documents = usage.containers["_documents"];
+    assert_eq!(documents.entries, 1);
+    assert_eq!(documents.files, 1);
+    assert_eq!(documents.size, 5000);
+    assert!(documents.chunks > 0);
+
+    assert_eq!(usage.apps[&app_id].files, 0);
+    assert!(!usage.containers.contains_key(&app_container_name(&app_id)));
+    assert!(usage.config.entries > 0);
+
+    let total = usage.total();
+    assert_eq!(total.files, 1);
+    assert_eq!(total.size, 5000);
+}
+
 fn unregistered_decode_ipc_msg(msg: &str) -> ChannelType {
     let (tx, rx) = mpsc::channel::<ChannelType>();
 
@@ -42,7 +42,7 @@ fn verify_app_is_revoked(
     let c1 = client.clone();
 
     config::list_apps(client)
-        .and_then(move |(_, apps)| {
+        .and_then(move |apps| {
             let auth_keys = c0.list_auth_keys_and_version().map_err(AuthError::from);
             let state = app_state(&c0, &apps, &app_id);
 
@@ -86,7 +86,7 @@ fn verify_app_is_authenticated(client: &AuthClient, app_id: String) -> Box<AuthF
 
     config::list_apps(client)
         .then(move |res| {
-            let (_, mut apps) = unwrap!(res);
+            let mut apps = unwrap!(res);
 
             let app_hash = sha3_256(app_id.as_bytes());
             let app_keys = unwrap!(apps.remove(&app_hash)).keys;
@@ -351,7 +351,7 @@ mod mock_routing {
 
                 config::list_apps(&client)
                     .then(move |res| {
-                        let (_, apps) = unwrap!(res);
+                        let apps = unwrap!(res);
                         let f_0 = app_state(&client, &apps, &app_id_0);
                         let f_1 = app_state(&client, &apps, &app_id_1);
 
@@ -382,7 +382,7 @@ mod mock_routing {
 
             config::list_apps(client)
                 .then(move |res| {
-                    let (_, apps) = unwrap!(res);
+                    let apps = unwrap!(res);
                     let f_0 = app_state(&c2, &apps, &app_id_0);
                     let f_1 = app_state(&c2, &apps, &app_id_1);
 
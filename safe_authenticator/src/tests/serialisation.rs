@@ -140,7 +140,7 @@ fn serialisation_read_data() {
             })
             .then(move |res| {
                 let (client, stash) = unwrap!(res);
-                config::list_apps(&client).map(move |(_, apps)| (client, stash, apps))
+                config::list_apps(&client).map(move |apps| (client, stash, apps))
             })
             .then(move |res| {
                 let (client, stash, apps) = unwrap!(res);
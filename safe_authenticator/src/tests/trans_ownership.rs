@@ -0,0 +1,269 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::config;
+use crate::errors::{AuthError, ERR_UNSUPPORTED_DATA_ITEM};
+use crate::ffi;
+use crate::ownership::{accept_ownership, trans_ownership};
+use crate::test_utils;
+use crate::{run, Authenticator};
+use ffi_utils::test_utils::{call_0, call_1};
+use futures::Future;
+use safe_core::client::roles::{self, DataItem, Role};
+use safe_core::ipc::{self, IpcError, IpcMsg, IpcReq, IpcResp, TransOwnershipReq};
+use safe_core::{Client, CoreError};
+use safe_nd::{
+    AData, ADataIndex, ADataOwner, ADataUser, AppendOnlyData, Error as SndError, MDataAddress,
+    PubSeqAppendOnlyData, PublicKey, SeqMutableData,
+};
+use std::ffi::CString;
+
+fn put_adata(authenticator: &Authenticator) -> DataItem {
+    unwrap!(run(authenticator, |client| {
+        let mut data = PubSeqAppendOnlyData::new(rand::random(), 15_020);
+        unwrap!(data.append_owner(
+            ADataOwner {
+                public_key: client.public_key(),
+                entries_index: 0,
+                permissions_index: 0,
+            },
+            0
+        ));
+        let address = *data.address();
+
+        client
+            .put_adata(AData::PubSeq(data))
+            .map(move |()| DataItem::AData(address))
+            .map_err(AuthError::from)
+    }))
+}
+
+fn trans_ownership_req(new_owner: PublicKey, items: Vec<DataItem>) -> TransOwnershipReq {
+    TransOwnershipReq {
+        app: test_utils::rand_app(),
+        new_owner,
+        items,
+    }
+}
+
+// Test transferring append-only data to another user, who accepts it into a container.
+#[test]
+fn transfer_and_accept() {
+    let alice = test_utils::create_account_and_login();
+    let bob = test_utils::create_account_and_login();
+
+    let bob_key = unwrap!(run(&bob, |client| ok!(client.public_key())));
+    let item = put_adata(&alice);
+    let alice_key = unwrap!(run(&alice, move |client| {
+        let alice_key = client.public_key();
+        roles::grant_role(client, vec![item], alice_key, Role::Editor)
+            .map(move |()| alice_key)
+            .map_err(AuthError::from)
+    }));
+
+    let req = trans_ownership_req(bob_key, vec![item]);
+    unwrap!(run(&alice, move |client| trans_ownership(client, req)));
+
+    // The new owner is the last owner of the data.
+    let owner = unwrap!(run(&bob, move |client| {
+        let address = match item {
+            DataItem::AData(address) => address,
+            DataItem::MData(_) => unreachable!(),
+        };
+        client
+            .get_adata_owners(address, ADataIndex::FromEnd(1))
+            .map_err(AuthError::from)
+    }));
+    assert_eq!(owner.public_key, bob_key);
+
+    // The permissions of the previous owner were revoked.
+    let roles = unwrap!(run(&bob, move |client| {
+        roles::audit_roles(client, item).map_err(AuthError::from)
+    }));
+    assert!(!roles.contains_key(&ADataUser::Key(alice_key)));
+
+    // Repeating the transfer, e.g. after a partial failure, succeeds without changing anything.
+    let req = trans_ownership_req(bob_key, vec![item]);
+    unwrap!(run(&alice, move |client| trans_ownership(client, req)));
+
+    // The previous owner can't accept the item anymore.
+    match run(&alice, move |client| accept_ownership(client, "log", item)) {
+        Err(AuthError::IpcError(IpcError::InvalidOwner(_))) => (),
+        x => panic!("Unexpected {:?}", x),
+    }
+
+    // Accepting the item again under the same name does nothing.
+    unwrap!(run(&bob, move |client| accept_ownership(
+        client, "log", item
+    )));
+    unwrap!(run(&bob, move |client| accept_ownership(
+        client, "log", item
+    )));
+
+    // Another item can't be accepted under the same name.
+    let other_item = put_adata(&bob);
+    match run(&bob, move |client| {
+        accept_ownership(client, "log", other_item)
+    }) {
+        Err(AuthError::CoreError(CoreError::DataError(SndError::DataExists))) => (),
+        x => panic!("Unexpected {:?}", x),
+    }
+
+    let (_, accepted) = unwrap!(run(&bob, |client| config::list_accepted_items(client)));
+    assert_eq!(accepted, btree_map!["log".to_string() => item]);
+}
+
+// Test handling a transfer request over FFI, from decoding it to accepting the item.
+#[test]
+fn transfer_over_ffi() {
+    let alice = test_utils::create_account_and_login();
+    let bob = test_utils::create_account_and_login();
+
+    let bob_key = unwrap!(run(&bob, |client| ok!(client.public_key())));
+    let item = put_adata(&alice);
+
+    let req_id = ipc::gen_req_id();
+    let msg = IpcMsg::Req {
+        req_id,
+        req: IpcReq::TransOwnership(trans_ownership_req(bob_key, vec![item])),
+    };
+    let encoded_msg = unwrap!(ipc::encode_msg(&msg));
+
+    let req = match unwrap!(test_utils::auth_decode_ipc_msg_helper(&alice, &encoded_msg)) {
+        (
+            IpcMsg::Req {
+                req_id: received_req_id,
+                req: IpcReq::TransOwnership(req),
+            },
+            None,
+        ) => {
+            assert_eq!(received_req_id, req_id);
+            req
+        }
+        x => panic!("Unexpected {:?}", x),
+    };
+    assert_eq!(req.new_owner, bob_key);
+    assert_eq!(req.items, vec![item]);
+
+    let req_c = unwrap!(req.into_repr_c());
+    let resp: String = unsafe {
+        unwrap!(call_1(|ud, cb| ffi::ipc::encode_trans_ownership_resp(
+            &alice, &req_c, req_id, true, ud, cb,
+        )))
+    };
+    match unwrap!(ipc::decode_msg(&resp)) {
+        IpcMsg::Resp {
+            resp: IpcResp::TransOwnership(Ok(())),
+            ..
+        } => (),
+        x => panic!("Unexpected {:?}", x),
+    }
+
+    let name = unwrap!(CString::new("log"));
+    let item_c = item.into_repr_c();
+    unsafe {
+        unwrap!(call_0(|ud, cb| ffi::ownership::auth_accept_ownership(
+            &bob,
+            name.as_ptr(),
+            &item_c,
+            ud,
+            cb,
+        )))
+    };
+
+    let (_, accepted) = unwrap!(run(&bob, |client| config::list_accepted_items(client)));
+    assert_eq!(accepted, btree_map!["log".to_string() => item]);
+
+    // Requests including mutable data are rejected when decoded.
+    let mdata_item = DataItem::MData(MDataAddress::Seq {
+        name: rand::random(),
+        tag: 15_020,
+    });
+    let msg = IpcMsg::Req {
+        req_id,
+        req: IpcReq::TransOwnership(trans_ownership_req(bob_key, vec![mdata_item])),
+    };
+    let encoded_msg = unwrap!(ipc::encode_msg(&msg));
+
+    match test_utils::auth_decode_ipc_msg_helper(&alice, &encoded_msg) {
+        Err((
+            ERR_UNSUPPORTED_DATA_ITEM,
+            Some(IpcMsg::Resp {
+                resp: IpcResp::TransOwnership(Err(IpcError::UnsupportedDataItem(_))),
+                ..
+            }),
+        )) => (),
+        x => panic!("Unexpected {:?}", x),
+    }
+}
+
+// Test that transfers of data not owned by the user, denied transfers and transfers including
+// mutable data fail without changing anything.
+#[test]
+fn transfer_failures() {
+    let alice = test_utils::create_account_and_login();
+    let bob = test_utils::create_account_and_login();
+
+    let bob_key = unwrap!(run(&bob, |client| ok!(client.public_key())));
+    let bobs_item = put_adata(&bob);
+
+    let req = trans_ownership_req(bob_key, vec![bobs_item]);
+    match run(&alice, move |client| trans_ownership(client, req)) {
+        Err(AuthError::IpcError(IpcError::InvalidOwner(invalids))) => {
+            assert_eq!(invalids.len(), 1)
+        }
+        x => panic!("Unexpected {:?}", x),
+    }
+
+    let req_c = unwrap!(trans_ownership_req(bob_key, vec![bobs_item]).into_repr_c());
+    let resp: String = unsafe {
+        unwrap!(call_1(|ud, cb| ffi::ipc::encode_trans_ownership_resp(
+            &alice, &req_c, 2, false, ud, cb,
+        )))
+    };
+    match unwrap!(ipc::decode_msg(&resp)) {
+        IpcMsg::Resp {
+            req_id: 2,
+            resp: IpcResp::TransOwnership(Err(IpcError::TransOwnershipDenied)),
+        } => (),
+        x => panic!("Unexpected {:?}", x),
+    }
+
+    // The network doesn't support changing the owner of mutable data, so the append-only data
+    // transferred along with it keeps its owner.
+    let alice_key = unwrap!(run(&alice, |client| ok!(client.public_key())));
+    let alices_item = put_adata(&alice);
+    let mdata_item = unwrap!(run(&alice, |client| {
+        let data = SeqMutableData::new(rand::random(), 15_020, client.public_key());
+        let address = *data.address();
+
+        client
+            .put_seq_mutable_data(data)
+            .map(move |()| DataItem::MData(address))
+            .map_err(AuthError::from)
+    }));
+
+    let req = trans_ownership_req(bob_key, vec![alices_item, mdata_item]);
+    match run(&alice, move |client| trans_ownership(client, req)) {
+        Err(AuthError::IpcError(IpcError::UnsupportedDataItem(unsupported))) => {
+            assert_eq!(unsupported.len(), 1)
+        }
+        x => panic!("Unexpected {:?}", x),
+    }
+
+    let owner = unwrap!(run(&alice, move |client| {
+        let address = match alices_item {
+            DataItem::AData(address) => address,
+            DataItem::MData(_) => unreachable!(),
+        };
+        client
+            .get_adata_owners(address, ADataIndex::FromEnd(1))
+            .map_err(AuthError::from)
+    }));
+    assert_eq!(owner.public_key, alice_key);
+}
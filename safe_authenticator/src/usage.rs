@@ -0,0 +1,149 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Storage usage of the account.
+
+use crate::access_container;
+use crate::client::AuthClient;
+use crate::config;
+use crate::ffi::usage::{AccountUsage as FfiAccountUsage, ContainerUsage};
+use crate::{AuthError, AuthFuture};
+use ffi_utils::vec_into_raw_parts;
+use futures::future::{self, Future};
+use safe_core::nfs::usage::{self, Usage};
+use safe_core::{app_container_name, Client, FutureExt};
+use std::collections::BTreeMap;
+use std::ffi::{CString, NulError};
+
+/// Storage used by the account, broken down by container.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AccountUsage {
+    /// Usage of the containers in the access container, by name, excluding the dedicated
+    /// containers of apps.
+    pub containers: BTreeMap<String, Usage>,
+    /// Usage of the dedicated containers of apps, by app ID.
+    pub apps: BTreeMap<String, Usage>,
+    /// Usage of the config root directory and the sharded map of registered apps. They hold no
+    /// files, so only their entries and their size are counted.
+    pub config: Usage,
+}
+
+impl AccountUsage {
+    /// Total usage of the account.
+    pub fn total(&self) -> Usage {
+        let mut total = self.config;
+        for usage in self.containers.values().chain(self.apps.values()) {
+            total += *usage;
+        }
+        total
+    }
+
+    /// Construct FFI wrapper for the native Rust object, consuming self.
+    pub fn into_repr_c(self) -> Result<FfiAccountUsage, NulError> {
+        let total = self.total().into_repr_c();
+        let AccountUsage {
+            containers,
+            apps,
+            config,
+        } = self;
+
+        let (containers, containers_len) = vec_into_raw_parts(containers_into_vec(containers)?);
+        let (apps, apps_len) = vec_into_raw_parts(containers_into_vec(apps)?);
+
+        Ok(FfiAccountUsage {
+            containers,
+            containers_len,
+            apps,
+            apps_len,
+            config: config.into_repr_c(),
+            total,
+        })
+    }
+}
+
+/// Compute the storage usage of the account.
+///
+/// Every container in the access container is walked as an NFS directory, see
+/// `safe_core::nfs::usage::dir_usage`.
+pub fn account_usage(client: &AuthClient) -> Box<AuthFuture<AccountUsage>> {
+    trace!("Computing storage usage of the account.");
+
+    let c2 = client.clone();
+    let c3 = client.clone();
+    let config_root = client.config_root_dir();
+
+    let config = config::apps_map(client)
+        .and_then(move |map| {
+            let root = map.root().clone();
+            map.shards()
+                .map(move |mut dirs| {
+                    dirs.push(root);
+                    dirs.push(config_root);
+                    dirs
+                })
+                .map_err(AuthError::from)
+        })
+        .and_then(move |dirs| {
+            let lists = dirs.into_iter().map(move |dir| {
+                c3.list_seq_mdata_entries(dir.name(), dir.type_tag())
+                    .map_err(AuthError::from)
+            });
+            future::join_all(lists)
+        })
+        .map(|lists| Usage {
+            entries: lists.iter().map(|entries| entries.len() as u64).sum(),
+            size: lists
+                .iter()
+                .flat_map(|entries| entries.values())
+                .map(|value| value.data.len() as u64)
+                .sum(),
+            ..Usage::default()
+        });
+
+    access_container::fetch_authenticator_entry(client)
+        .and_then(move |(_, entries)| {
+            let usages = entries.into_iter().map(move |(name, dir)| {
+                usage::dir_usage(c2.clone(), dir)
+                    .map(move |usage| (name, usage.total))
+                    .map_err(AuthError::from)
+            });
+            future::join_all(usages)
+        })
+        .join(config)
+        .map(|(usages, config)| {
+            let apps_prefix = app_container_name("");
+            let mut account = AccountUsage {
+                config,
+                ..AccountUsage::default()
+            };
+
+            for (name, usage) in usages {
+                if name.starts_with(&apps_prefix) {
+                    let app_id = name[apps_prefix.len()..].to_string();
+                    let _ = account.apps.insert(app_id, usage);
+                } else {
+                    let _ = account.containers.insert(name, usage);
+                }
+            }
+
+            account
+        })
+        .into_box()
+}
+
+fn containers_into_vec(usages: BTreeMap<String, Usage>) -> Result<Vec<ContainerUsage>, NulError> {
+    usages
+        .into_iter()
+        .map(|(name, usage)| {
+            Ok(ContainerUsage {
+                name: CString::new(name)?.into_raw(),
+                usage: usage.into_repr_c(),
+            })
+        })
+        .collect()
+}
@@ -47,7 +47,7 @@ use safe_core::ffi::ipc::resp::{
     AccessContInfo, AccessContainerEntry, AppAccess, AppKeys, AuthGranted, ContainerInfo,
     MDataEntry, MDataKey, MDataValue, MetadataResponse,
 };
-use safe_core::ffi::nfs::File;
+use safe_core::ffi::nfs::{File, FileEntry, FileVersion, ListOptions};
 use safe_core::ffi::*;
 use std::ffi::{CStr, CString};
 use std::os::raw::{c_char, c_void};
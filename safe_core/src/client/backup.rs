@@ -0,0 +1,422 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::recovery;
+use super::{Client, MDataInfo};
+use crate::errors::CoreError;
+use crate::event_loop::CoreFuture;
+use crate::utils::FutureExt;
+use bincode::{deserialize, serialize};
+use futures::Future;
+use safe_nd::{
+    Error as SndError, MDataAddress, MDataKind, MDataPermissionSet, MDataSeqEntries, MDataSeqValue,
+    MDataUnseqEntries, PublicKey, SeqMutableData, UnseqMutableData,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Version of the format of serialised backups. Backups in other formats are rejected.
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+
+// Prefix of serialised backups.
+const BACKUP_MAGIC: [u8; 8] = *b"SAFEMDBK";
+
+/// Entries of backed up mutable data.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum BackupEntries {
+    /// Entries of sequenced mutable data.
+    Seq(MDataSeqEntries),
+    /// Entries of unsequenced mutable data.
+    Unseq(MDataUnseqEntries),
+}
+
+impl BackupEntries {
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        match *self {
+            BackupEntries::Seq(ref entries) => entries.len(),
+            BackupEntries::Unseq(ref entries) => entries.len(),
+        }
+    }
+
+    /// Returns whether there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Portable copy of mutable data.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct MDataBackup {
+    /// Address of the backed up data.
+    pub address: MDataAddress,
+    /// Version of the shell of the data.
+    pub version: u64,
+    /// Owner of the data.
+    pub owner: PublicKey,
+    /// Permissions of the data.
+    pub permissions: BTreeMap<PublicKey, MDataPermissionSet>,
+    /// Entries of the data, as they are stored.
+    pub entries: BackupEntries,
+    /// Entries decrypted with the `MDataInfo` given to `export`, if any. Entries which can't be
+    /// decrypted, such as unencrypted entries of private data, are included as they are stored.
+    pub plaintext: Option<BackupEntries>,
+    /// Content of the files of an NFS directory, by name (see `nfs::backup`).
+    pub files: BTreeMap<String, Vec<u8>>,
+    /// Backups of the sub-directories of an NFS directory, by name (see `nfs::backup`).
+    pub dirs: BTreeMap<String, MDataBackup>,
+}
+
+impl MDataBackup {
+    /// Serialise the backup, prefixed by a header holding `BACKUP_FORMAT_VERSION`.
+    pub fn serialise(&self) -> Result<Vec<u8>, CoreError> {
+        Ok(serialize(&(BACKUP_MAGIC, BACKUP_FORMAT_VERSION, self))?)
+    }
+
+    /// Deserialise a backup created by `serialise`. Fails if the backup is in a different format.
+    pub fn deserialise(data: &[u8]) -> Result<Self, CoreError> {
+        let (magic, format_version): ([u8; 8], u32) = deserialize(data)?;
+        if magic != BACKUP_MAGIC {
+            return Err(CoreError::Unexpected(
+                "Not a mutable data backup".to_string(),
+            ));
+        }
+        if format_version != BACKUP_FORMAT_VERSION {
+            return Err(CoreError::Unexpected(format!(
+                "Unsupported backup format version {}",
+                format_version
+            )));
+        }
+
+        let (_, _, backup): ([u8; 8], u32, Self) = deserialize(data)?;
+        Ok(backup)
+    }
+}
+
+/// Back up the mutable data with its shell, permissions, owner and all entries.
+///
+/// If `info` is given, the entries are also decrypted with it and stored in plain text, allowing
+/// them to be restored with different encryption. Note that the plain text is stored unencrypted
+/// in the backup.
+pub fn export(
+    client: &impl Client,
+    address: MDataAddress,
+    info: Option<&MDataInfo>,
+) -> Box<CoreFuture<MDataBackup>> {
+    trace!("Backing up mutable data {:?}", address);
+
+    let info = info.cloned();
+    let backup = match address.kind() {
+        MDataKind::Seq => client
+            .get_seq_mdata(*address.name(), address.tag())
+            .map(move |data| MDataBackup {
+                address,
+                version: data.version(),
+                owner: *data.owner(),
+                permissions: data.permissions(),
+                entries: BackupEntries::Seq(data.entries().clone()),
+                plaintext: None,
+                files: BTreeMap::new(),
+                dirs: BTreeMap::new(),
+            })
+            .into_box(),
+        MDataKind::Unseq => client
+            .get_unseq_mdata(*address.name(), address.tag())
+            .map(move |data| MDataBackup {
+                address,
+                version: data.version(),
+                owner: *data.owner(),
+                permissions: data.permissions(),
+                entries: BackupEntries::Unseq(data.entries().clone()),
+                plaintext: None,
+                files: BTreeMap::new(),
+                dirs: BTreeMap::new(),
+            })
+            .into_box(),
+    };
+
+    backup
+        .map(move |mut backup| {
+            backup.plaintext = info.map(|info| decrypt_entries(&info, &backup.entries));
+            backup
+        })
+        .into_box()
+}
+
+/// Restore the backed up mutable data, owned by the owner of the client, and return its address.
+///
+/// If `target` is given, the data is restored at its address instead of the backed up one, with
+/// the plain text entries encrypted with `target`. This fails if the backup doesn't contain plain
+/// text entries. Otherwise, the entries are restored as they were stored. The data is put using
+/// `recovery::put_mdata` or `recovery::put_unseq_mdata`, so existing data is updated to contain
+/// the backed up entries and permissions.
+pub fn restore(
+    client: &impl Client,
+    backup: &MDataBackup,
+    target: Option<&MDataInfo>,
+) -> Box<CoreFuture<MDataAddress>> {
+    let address = match target {
+        Some(info) => *info.address(),
+        None => backup.address,
+    };
+    trace!("Restoring mutable data backup at {:?}", address);
+
+    let entries = match (target, &backup.plaintext) {
+        (Some(info), Some(plaintext)) => fry!(encrypt_entries(info, &backup.entries, plaintext)),
+        (Some(_), None) => {
+            return err!(CoreError::Unexpected(
+                "Backup doesn't contain plain text entries".to_string()
+            ));
+        }
+        (None, _) => backup.entries.clone(),
+    };
+
+    let result = match (address, entries) {
+        (MDataAddress::Seq { name, tag }, BackupEntries::Seq(entries)) => {
+            let data = SeqMutableData::new_with_data(
+                name,
+                tag,
+                entries,
+                backup.permissions.clone(),
+                client.owner_key(),
+            );
+            recovery::put_mdata(client, data)
+        }
+        (MDataAddress::Unseq { name, tag }, BackupEntries::Unseq(entries)) => {
+            let data = UnseqMutableData::new_with_data(
+                name,
+                tag,
+                entries,
+                backup.permissions.clone(),
+                client.owner_key(),
+            );
+            recovery::put_unseq_mdata(client, data)
+        }
+        _ => return err!(CoreError::DataError(SndError::InvalidOperation)),
+    };
+
+    result.map(move |()| address).into_box()
+}
+
+// Decrypt the entries, keeping the ones which can't be decrypted as they are.
+fn decrypt_entries(info: &MDataInfo, entries: &BackupEntries) -> BackupEntries {
+    let decrypt = |data: &Vec<u8>| info.decrypt(data).unwrap_or_else(|_| data.clone());
+
+    match *entries {
+        BackupEntries::Seq(ref entries) => BackupEntries::Seq(
+            entries
+                .iter()
+                .map(|(key, value)| {
+                    let value = MDataSeqValue {
+                        data: decrypt(&value.data),
+                        version: value.version,
+                    };
+                    (decrypt(key), value)
+                })
+                .collect(),
+        ),
+        BackupEntries::Unseq(ref entries) => BackupEntries::Unseq(
+            entries
+                .iter()
+                .map(|(key, value)| (decrypt(key), decrypt(value)))
+                .collect(),
+        ),
+    }
+}
+
+// Encrypt the plain text entries with the `MDataInfo`. Entries which were stored unencrypted, so
+// their keys are the same in both `stored` and `plaintext`, are restored as they were stored.
+fn encrypt_entries(
+    info: &MDataInfo,
+    stored: &BackupEntries,
+    plaintext: &BackupEntries,
+) -> Result<BackupEntries, CoreError> {
+    match (stored, plaintext) {
+        (BackupEntries::Seq(stored), BackupEntries::Seq(plaintext)) => {
+            let mut output = BTreeMap::new();
+            for (key, value) in plaintext {
+                let _ = match stored.get(key) {
+                    Some(value) => output.insert(key.clone(), value.clone()),
+                    None => output.insert(
+                        info.enc_entry_key(key)?,
+                        MDataSeqValue {
+                            data: info.enc_entry_value(&value.data)?,
+                            version: value.version,
+                        },
+                    ),
+                };
+            }
+            Ok(BackupEntries::Seq(output))
+        }
+        (BackupEntries::Unseq(stored), BackupEntries::Unseq(plaintext)) => {
+            let mut output = BTreeMap::new();
+            for (key, value) in plaintext {
+                let _ = match stored.get(key) {
+                    Some(value) => output.insert(key.clone(), value.clone()),
+                    None => output.insert(info.enc_entry_key(key)?, info.enc_entry_value(value)?),
+                };
+            }
+            Ok(BackupEntries::Unseq(output))
+        }
+        _ => Err(CoreError::Unexpected(
+            "Plain text entries of a different kind".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::random_client;
+    use safe_nd::{MDataAction, MDataSeqEntryActions, MDataUnseqEntryActions};
+
+    // Test serialising backups and rejecting other formats.
+    #[test]
+    fn serialisation() {
+        let owner = PublicKey::from(threshold_crypto::SecretKey::random().public_key());
+        let backup = MDataBackup {
+            address: MDataAddress::Unseq {
+                name: rand::random(),
+                tag: 10_000,
+            },
+            version: 2,
+            owner,
+            permissions: btree_map![owner => MDataPermissionSet::new().allow(MDataAction::Read)],
+            entries: BackupEntries::Unseq(btree_map![vec![0] => vec![1]]),
+            plaintext: None,
+            files: BTreeMap::new(),
+            dirs: BTreeMap::new(),
+        };
+
+        let data = unwrap!(backup.serialise());
+        assert_eq!(unwrap!(MDataBackup::deserialise(&data)), backup);
+
+        let mut other = data.clone();
+        other[BACKUP_MAGIC.len()] += 1;
+        assert!(MDataBackup::deserialise(&other).is_err());
+        assert!(MDataBackup::deserialise(&data[1..]).is_err());
+    }
+
+    // Test backing up private mutable data and restoring it at a new address with new
+    // encryption.
+    #[test]
+    fn backup_and_restore() {
+        random_client(|client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+            let client4 = client.clone();
+
+            let info = unwrap!(MDataInfo::random_private(MDataKind::Seq, 10_000));
+            let target = unwrap!(MDataInfo::random_private(MDataKind::Seq, 10_000));
+            let target2 = target.clone();
+            let target3 = target.clone();
+            let info2 = info.clone();
+
+            let data = SeqMutableData::new_with_data(
+                info.name(),
+                info.type_tag(),
+                BTreeMap::new(),
+                BTreeMap::new(),
+                client.owner_key(),
+            );
+            let key = unwrap!(info.enc_entry_key(b"key"));
+            let value = unwrap!(info.enc_entry_value(b"value"));
+            let actions = MDataSeqEntryActions::new().ins(key, value, 0).ins(
+                b"plain".to_vec(),
+                b"text".to_vec(),
+                0,
+            );
+
+            client
+                .put_seq_mutable_data(data)
+                .and_then(move |()| {
+                    client2.mutate_seq_mdata_entries(info2.name(), info2.type_tag(), actions)
+                })
+                .and_then(move |()| export(&client3, *info.address(), Some(&info)))
+                .and_then(move |backup| {
+                    let backup = unwrap!(MDataBackup::deserialise(&unwrap!(backup.serialise())));
+                    assert_eq!(backup.entries.len(), 2);
+                    assert_eq!(
+                        backup.plaintext,
+                        Some(BackupEntries::Seq(btree_map![
+                            b"key".to_vec() => MDataSeqValue { data: b"value".to_vec(), version: 0 },
+                            b"plain".to_vec() => MDataSeqValue { data: b"text".to_vec(), version: 0 }
+                        ]))
+                    );
+
+                    restore(&client4, &backup, Some(&target)).map(move |address| (client4, address))
+                })
+                .and_then(move |(client, address)| {
+                    assert_eq!(address, *target2.address());
+                    client.list_seq_mdata_entries(*address.name(), address.tag())
+                })
+                .map(move |entries| {
+                    // The entry which was stored unencrypted is restored as it was.
+                    assert_eq!(entries[&b"plain".to_vec()].data, b"text".to_vec());
+
+                    let key = unwrap!(target3.enc_entry_key(b"key"));
+                    let value = unwrap!(target3.decrypt(&entries[&key].data));
+                    assert_eq!(value, b"value".to_vec());
+                })
+        });
+    }
+
+    // Test restoring unsequenced mutable data which still exists, and rejecting a restore at a
+    // new address from a backup without plain text entries.
+    #[test]
+    fn restore_existing_unseq() {
+        random_client(|client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+            let client4 = client.clone();
+            let client5 = client.clone();
+
+            let name = rand::random();
+            let tag = 10_000;
+            let address = MDataAddress::Unseq { name, tag };
+            let target = unwrap!(MDataInfo::random_private(MDataKind::Unseq, tag));
+            let owner = client.owner_key();
+
+            let data = UnseqMutableData::new_with_data(
+                name,
+                tag,
+                btree_map![vec![0] => vec![0], vec![1] => vec![1]],
+                btree_map![owner => MDataPermissionSet::new().allow(MDataAction::Insert)],
+                owner,
+            );
+            let actions = MDataUnseqEntryActions::new()
+                .update(vec![0], vec![2])
+                .del(vec![1]);
+
+            client
+                .put_unseq_mutable_data(data)
+                .and_then(move |()| export(&client2, address, None))
+                .and_then(move |backup| {
+                    client3
+                        .mutate_unseq_mdata_entries(name, tag, actions)
+                        .map(move |()| backup)
+                })
+                .and_then(move |backup| {
+                    restore(&client4, &backup, Some(&target)).then(move |result| {
+                        match result {
+                            Err(CoreError::Unexpected(_)) => (),
+                            result => panic!("Unexpected {:?}", result),
+                        }
+                        restore(&client4, &backup, None)
+                    })
+                })
+                .and_then(move |restored| {
+                    assert_eq!(restored, address);
+                    client5.list_unseq_mdata_entries(name, tag)
+                })
+                .map(|entries| {
+                    assert_eq!(entries, btree_map![vec![0] => vec![0], vec![1] => vec![1]]);
+                })
+        });
+    }
+}
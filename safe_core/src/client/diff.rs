@@ -0,0 +1,332 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::Client;
+use crate::event_loop::CoreFuture;
+use crate::utils::FutureExt;
+use futures::Future;
+use safe_nd::{MDataAddress, MDataSeqEntries, MDataSeqEntryActions, MDataSeqValue};
+use std::collections::BTreeMap;
+
+/// Change of a single entry between two snapshots of mutable data entries.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EntryChange {
+    /// The entry was inserted, with the given value.
+    Inserted(MDataSeqValue),
+    /// The value or version of the entry changed.
+    Updated {
+        /// Value in the old snapshot.
+        old: MDataSeqValue,
+        /// Value in the new snapshot.
+        new: MDataSeqValue,
+    },
+    /// The entry was deleted. Holds its value in the old snapshot.
+    Deleted(MDataSeqValue),
+}
+
+/// Changes of entries between two snapshots of sequenced mutable data entries.
+///
+/// Entries are compared as they are stored, so the keys and values of private mutable data are
+/// encrypted.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MDataDiff {
+    /// Changed entries, by key.
+    pub changes: BTreeMap<Vec<u8>, EntryChange>,
+}
+
+impl MDataDiff {
+    /// Compare the snapshots. Entries with a different value or version are considered updated.
+    pub fn new(old: &MDataSeqEntries, new: &MDataSeqEntries) -> Self {
+        let mut changes = BTreeMap::new();
+
+        for (key, old_value) in old {
+            match new.get(key) {
+                Some(new_value) if new_value == old_value => (),
+                Some(new_value) => {
+                    let _ = changes.insert(
+                        key.clone(),
+                        EntryChange::Updated {
+                            old: old_value.clone(),
+                            new: new_value.clone(),
+                        },
+                    );
+                }
+                None => {
+                    let _ = changes.insert(key.clone(), EntryChange::Deleted(old_value.clone()));
+                }
+            }
+        }
+
+        for (key, new_value) in new {
+            if !old.contains_key(key) {
+                let _ = changes.insert(key.clone(), EntryChange::Inserted(new_value.clone()));
+            }
+        }
+
+        Self { changes }
+    }
+
+    /// Returns whether the snapshots contain the same entries.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Returns the number of changed entries.
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Returns the inserted entries along with their values.
+    pub fn inserted(&self) -> impl Iterator<Item = (&Vec<u8>, &MDataSeqValue)> {
+        self.changes
+            .iter()
+            .filter_map(|(key, change)| match change {
+                EntryChange::Inserted(value) => Some((key, value)),
+                _ => None,
+            })
+    }
+
+    /// Returns the updated entries along with their new values.
+    pub fn updated(&self) -> impl Iterator<Item = (&Vec<u8>, &MDataSeqValue)> {
+        self.changes
+            .iter()
+            .filter_map(|(key, change)| match change {
+                EntryChange::Updated { new, .. } => Some((key, new)),
+                _ => None,
+            })
+    }
+
+    /// Returns the keys of the deleted entries.
+    pub fn deleted(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.changes
+            .iter()
+            .filter_map(|(key, change)| match change {
+                EntryChange::Deleted(_) => Some(key),
+                _ => None,
+            })
+    }
+
+    /// Apply the changes to the entries, turning the old snapshot into the new one. Useful to
+    /// keep a cached snapshot up to date.
+    pub fn apply(&self, entries: &mut MDataSeqEntries) {
+        for (key, change) in &self.changes {
+            match change {
+                EntryChange::Inserted(value) | EntryChange::Updated { new: value, .. } => {
+                    let _ = entries.insert(key.clone(), value.clone());
+                }
+                EntryChange::Deleted(_) => {
+                    let _ = entries.remove(key);
+                }
+            }
+        }
+    }
+
+    /// Convert the changes into entry actions replaying them onto mutable data whose entries
+    /// are at the versions of the old snapshot, such as a mirror of it.
+    ///
+    /// Inserted entries keep their versions, while updates and deletions use the successors of
+    /// the versions in the old snapshot. So the actions fail with `InvalidEntryActions` if the
+    /// target diverged from the old snapshot.
+    pub fn into_actions(self) -> MDataSeqEntryActions {
+        self.changes
+            .into_iter()
+            .fold(
+                MDataSeqEntryActions::new(),
+                |actions, (key, change)| match change {
+                    EntryChange::Inserted(value) => actions.ins(key, value.data, value.version),
+                    EntryChange::Updated { old, new } => {
+                        actions.update(key, new.data, old.version + 1)
+                    }
+                    EntryChange::Deleted(old) => actions.del(key, old.version + 1),
+                },
+            )
+    }
+
+    /// Convert the changes into entry actions replaying them onto mutable data with the given
+    /// current entries, overwriting any of its own changes of the same entries.
+    ///
+    /// Updates of entries the target doesn't contain become insertions, insertions of entries the
+    /// target already contains become updates and deletions of entries the target doesn't contain
+    /// are skipped.
+    pub fn into_actions_for(self, target: &MDataSeqEntries) -> MDataSeqEntryActions {
+        self.changes
+            .into_iter()
+            .fold(MDataSeqEntryActions::new(), |actions, (key, change)| {
+                let current = target.get(&key).map(|value| value.version);
+
+                match (change, current) {
+                    (EntryChange::Inserted(value), None)
+                    | (EntryChange::Updated { new: value, .. }, None) => {
+                        actions.ins(key, value.data, value.version)
+                    }
+                    (EntryChange::Inserted(value), Some(version))
+                    | (EntryChange::Updated { new: value, .. }, Some(version)) => {
+                        actions.update(key, value.data, version + 1)
+                    }
+                    (EntryChange::Deleted(_), Some(version)) => actions.del(key, version + 1),
+                    (EntryChange::Deleted(_), None) => actions,
+                }
+            })
+    }
+}
+
+/// Compare the cached snapshot of the entries of the sequenced mutable data with its current
+/// entries on the network. Returns the diff along with the current entries, to be cached for the
+/// next comparison.
+pub fn diff_with_network(
+    client: &impl Client,
+    address: MDataAddress,
+    snapshot: MDataSeqEntries,
+) -> Box<CoreFuture<(MDataDiff, MDataSeqEntries)>> {
+    client
+        .list_seq_mdata_entries(*address.name(), address.tag())
+        .map(move |entries| (MDataDiff::new(&snapshot, &entries), entries))
+        .into_box()
+}
+
+/// Replay the changes onto the sequenced mutable data, overwriting its own changes of the same
+/// entries (see `MDataDiff::into_actions_for`).
+pub fn replay(client: &impl Client, diff: MDataDiff, target: MDataAddress) -> Box<CoreFuture<()>> {
+    if diff.is_empty() {
+        return ok!(());
+    }
+
+    let client2 = client.clone();
+
+    client
+        .list_seq_mdata_entries(*target.name(), target.tag())
+        .and_then(move |entries| {
+            let actions = diff.into_actions_for(&entries);
+            if actions.actions().is_empty() {
+                return ok!(());
+            }
+            client2.mutate_seq_mdata_entries(*target.name(), target.tag(), actions)
+        })
+        .into_box()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::random_client;
+    use safe_nd::{MDataSeqEntryAction, SeqMutableData};
+
+    fn value(data: u8, version: u64) -> MDataSeqValue {
+        MDataSeqValue {
+            data: vec![data],
+            version,
+        }
+    }
+
+    // Test computing, applying and converting diffs.
+    #[test]
+    fn diff_snapshots() {
+        let old = btree_map![
+            vec![0] => value(0, 0),
+            vec![1] => value(1, 0),
+            vec![2] => value(2, 3)
+        ];
+        let new = btree_map![
+            vec![0] => value(0, 0),
+            vec![1] => value(10, 2),
+            vec![3] => value(3, 0)
+        ];
+
+        let diff = MDataDiff::new(&old, &new);
+        assert_eq!(diff.len(), 3);
+        assert_eq!(
+            diff.inserted().collect::<Vec<_>>(),
+            vec![(&vec![3], &value(3, 0))]
+        );
+        assert_eq!(
+            diff.updated().collect::<Vec<_>>(),
+            vec![(&vec![1], &value(10, 2))]
+        );
+        assert_eq!(diff.deleted().collect::<Vec<_>>(), vec![&vec![2]]);
+        assert!(MDataDiff::new(&new, &new).is_empty());
+
+        let mut cached = old.clone();
+        diff.apply(&mut cached);
+        assert_eq!(cached, new);
+
+        let actions = diff.clone().into_actions().into_actions();
+        assert_eq!(actions[&vec![1]], MDataSeqEntryAction::Update(value(10, 1)));
+        assert_eq!(actions[&vec![2]], MDataSeqEntryAction::Del(4));
+        assert_eq!(actions[&vec![3]], MDataSeqEntryAction::Ins(value(3, 0)));
+
+        // A target which already contains the inserted entry, but not the updated one.
+        let target = btree_map![
+            vec![2] => value(2, 7),
+            vec![3] => value(30, 1)
+        ];
+        let actions = diff.into_actions_for(&target).into_actions();
+        assert_eq!(actions[&vec![1]], MDataSeqEntryAction::Ins(value(10, 2)));
+        assert_eq!(actions[&vec![2]], MDataSeqEntryAction::Del(8));
+        assert_eq!(actions[&vec![3]], MDataSeqEntryAction::Update(value(3, 2)));
+    }
+
+    // Test mirroring changes of mutable data onto another one.
+    #[test]
+    fn mirror() {
+        random_client(|client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+            let client4 = client.clone();
+            let client5 = client.clone();
+
+            let entries = btree_map![
+                vec![0] => value(0, 0),
+                vec![1] => value(1, 0)
+            ];
+            let source = SeqMutableData::new_with_data(
+                rand::random(),
+                10_000,
+                entries.clone(),
+                Default::default(),
+                client.public_key(),
+            );
+            let mirror = SeqMutableData::new_with_data(
+                rand::random(),
+                10_000,
+                entries.clone(),
+                Default::default(),
+                client.public_key(),
+            );
+            let source_address = *source.address();
+            let mirror_address = *mirror.address();
+
+            client
+                .put_seq_mutable_data(source)
+                .join(client.put_seq_mutable_data(mirror))
+                .and_then(move |_| {
+                    let actions = MDataSeqEntryActions::new()
+                        .update(vec![0], vec![10], 1)
+                        .del(vec![1], 1)
+                        .ins(vec![2], vec![2], 0);
+                    client2.mutate_seq_mdata_entries(
+                        *source_address.name(),
+                        source_address.tag(),
+                        actions,
+                    )
+                })
+                .and_then(move |()| diff_with_network(&client3, source_address, entries))
+                .and_then(move |(diff, current)| {
+                    assert_eq!(diff.len(), 3);
+                    replay(&client4, diff, mirror_address).map(move |()| current)
+                })
+                .and_then(move |current| {
+                    client5
+                        .list_seq_mdata_entries(*mirror_address.name(), mirror_address.tag())
+                        .map(move |mirrored| (current, mirrored))
+                })
+                .map(|(current, mirrored)| {
+                    assert_eq!(mirrored, current);
+                })
+        });
+    }
+}
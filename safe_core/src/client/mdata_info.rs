@@ -274,7 +274,10 @@ fn encrypt_value(info: &MDataInfo, value: &MDataSeqValue) -> Result<MDataSeqValu
     })
 }
 
-fn decrypt_value(info: &MDataInfo, value: &MDataSeqValue) -> Result<MDataSeqValue, CoreError> {
+pub(crate) fn decrypt_value(
+    info: &MDataInfo,
+    value: &MDataSeqValue,
+) -> Result<MDataSeqValue, CoreError> {
     Ok(MDataSeqValue {
         data: info.decrypt(&value.data)?,
         version: value.version,
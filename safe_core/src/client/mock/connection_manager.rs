@@ -8,11 +8,13 @@
 
 use super::vault::{self, Vault};
 use crate::config_handler::{get_config, Config};
+use crate::utils::FutureExt;
 use crate::{
-    client::SafeKey,
+    client::{MDataEntriesPage, SafeKey},
     event::{NetworkEvent, NetworkTx},
     CoreError, CoreFuture,
 };
+use futures::future;
 use quic_p2p::{self, Config as QuicP2pConfig};
 use safe_nd::{Coins, Message, PublicId, PublicKey, Request, RequestType, Response, XorName};
 use std::collections::HashSet;
@@ -101,6 +103,32 @@ impl ConnectionManager {
         }
     }
 
+    /// Send a `ListMDataEntries` request `msg`, receiving only the page of entries following the
+    /// key `cursor`, which is taken from the entries by the vault.
+    pub fn list_mdata_entries_page(
+        &mut self,
+        pub_id: &PublicId,
+        msg: &Message,
+        cursor: Option<Vec<u8>>,
+        limit: usize,
+    ) -> Box<CoreFuture<MDataEntriesPage>> {
+        let cursor = cursor.as_ref().map(Vec::as_slice);
+
+        #[cfg(any(feature = "testing", test))]
+        {
+            if let Some(resp) = self.intercept_request(msg.clone()) {
+                return future::result(MDataEntriesPage::from_response(resp, cursor, limit))
+                    .into_box();
+            }
+        }
+
+        let mut vault = vault::lock(&self.vault, false);
+        let result = vault
+            .list_mdata_entries_page(pub_id.clone(), msg, cursor, limit)
+            .map_err(CoreError::from);
+        future::result(result).into_box()
+    }
+
     /// Bootstrap to any known contact.
     pub fn bootstrap(&mut self, full_id: SafeKey) -> Box<CoreFuture<()>> {
         let _ = unwrap!(self.groups.lock()).insert(full_id.public_id());
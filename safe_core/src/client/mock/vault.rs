@@ -9,15 +9,15 @@
 use super::DataId;
 use super::{Account, CoinBalance};
 use crate::client::mock::connection_manager::unlimited_coins;
-use crate::client::COST_OF_PUT;
+use crate::client::{MDataEntriesPage, COST_OF_PUT};
 use crate::config_handler::{Config, DevConfig};
 use bincode::{deserialize, serialize};
 use fs2::FileExt;
 use safe_nd::{
     verify_signature, AData, ADataAction, ADataAddress, ADataIndex, AppPermissions, AppendOnlyData,
     Coins, Data, Error as SndError, IData, IDataAddress, LoginPacket, MData, MDataAction,
-    MDataAddress, MDataKind, Message, PublicId, PublicKey, Request, RequestType, Response,
-    Result as SndResult, SeqAppendOnly, Transaction, UnseqAppendOnly, XorName,
+    MDataAddress, MDataKind, Message, MessageId, PublicId, PublicKey, Request, RequestType,
+    Response, Result as SndResult, SeqAppendOnly, Signature, Transaction, UnseqAppendOnly, XorName,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap};
@@ -394,40 +394,7 @@ impl Vault {
             return Err(SndError::from("Unexpected Message type"));
         };
 
-        // Get the requester's public key.
-        let result = match requester.clone() {
-            PublicId::App(pk) => Ok((true, *pk.public_key(), *pk.owner().public_key())),
-            PublicId::Client(pk) => Ok((false, *pk.public_key(), *pk.public_key())),
-            PublicId::Node(_) => Err(SndError::AccessDenied),
-        }
-        .and_then(|(is_app, requester_pk, owner_pk)| {
-            let request_type = request.get_type();
-
-            match request_type {
-                RequestType::PrivateGet | RequestType::Mutation | RequestType::Transaction => {
-                    // For apps, check if its public key is listed as an auth key.
-                    if is_app {
-                        let auth_keys = self
-                            .get_account(&requester.name())
-                            .map(|account| (account.auth_keys().clone()))
-                            .unwrap_or_else(Default::default);
-
-                        if !auth_keys.contains_key(&requester_pk) {
-                            return Err(SndError::AccessDenied);
-                        }
-                    }
-
-                    // Verify signature if the request is not a GET for public data.
-                    match signature {
-                        Some(sig) => verify_signature(&sig, &requester_pk, &request, &message_id)?,
-                        None => return Err(SndError::InvalidSignature),
-                    }
-                }
-                RequestType::PublicGet => (),
-            }
-
-            Ok((requester_pk, owner_pk))
-        });
+        let result = self.authorise(&requester, request, message_id, signature.as_ref());
 
         // Return errors as a response message corresponding to the incoming request message.
         let (requester_pk, owner_pk) = match result {
@@ -1264,6 +1231,90 @@ impl Vault {
         }
     }
 
+    /// Process a `ListMDataEntries` request, returning only the page of at most `limit` entries
+    /// following the key `cursor`.
+    pub fn list_mdata_entries_page(
+        &mut self,
+        requester: PublicId,
+        message: &Message,
+        cursor: Option<&[u8]>,
+        limit: usize,
+    ) -> SndResult<MDataEntriesPage> {
+        let (request, message_id, signature) = if let Message::Request {
+            request,
+            message_id,
+            signature,
+        } = message
+        {
+            (request, *message_id, signature)
+        } else {
+            return Err(SndError::from("Unexpected Message type"));
+        };
+        let address = if let Request::ListMDataEntries(address) = *request {
+            address
+        } else {
+            return Err(SndError::from("Unexpected Request type"));
+        };
+
+        let (requester_pk, _) =
+            self.authorise(&requester, request, message_id, signature.as_ref())?;
+
+        match (
+            address.kind(),
+            self.get_mdata(address, requester_pk, request)?,
+        ) {
+            (MDataKind::Seq, MData::Seq(mdata)) => {
+                Ok(MDataEntriesPage::seq(mdata.entries(), cursor, limit))
+            }
+            (MDataKind::Unseq, MData::Unseq(mdata)) => {
+                Ok(MDataEntriesPage::unseq(mdata.entries(), cursor, limit))
+            }
+            _ => Err(SndError::NoSuchData),
+        }
+    }
+
+    // Check that the requester may make the request, returning its public key and the public key
+    // of its owner.
+    fn authorise(
+        &self,
+        requester: &PublicId,
+        request: &Request,
+        message_id: MessageId,
+        signature: Option<&Signature>,
+    ) -> SndResult<(PublicKey, PublicKey)> {
+        // Get the requester's public key.
+        let (is_app, requester_pk, owner_pk) = match requester.clone() {
+            PublicId::App(pk) => (true, *pk.public_key(), *pk.owner().public_key()),
+            PublicId::Client(pk) => (false, *pk.public_key(), *pk.public_key()),
+            PublicId::Node(_) => return Err(SndError::AccessDenied),
+        };
+
+        match request.get_type() {
+            RequestType::PrivateGet | RequestType::Mutation | RequestType::Transaction => {
+                // For apps, check if its public key is listed as an auth key.
+                if is_app {
+                    let auth_keys = self
+                        .get_account(&requester.name())
+                        .map(|account| (account.auth_keys().clone()))
+                        .unwrap_or_else(Default::default);
+
+                    if !auth_keys.contains_key(&requester_pk) {
+                        return Err(SndError::AccessDenied);
+                    }
+                }
+
+                // Verify signature if the request is not a GET for public data.
+                match signature {
+                    Some(sig) => verify_signature(sig, &requester_pk, request, &message_id)?,
+                    None => return Err(SndError::InvalidSignature),
+                }
+            }
+            RequestType::PublicGet => (),
+        }
+
+        Ok((requester_pk, owner_pk))
+    }
+
     pub fn get_mdata(
         &mut self,
         address: MDataAddress,
@@ -8,19 +8,33 @@
 
 /// User Account information.
 pub mod account;
+/// Backup and restore of mutable data.
+pub mod backup;
 /// Core client used for testing purposes.
 #[cfg(any(test, feature = "testing"))]
 pub mod core_client;
+/// Differences between snapshots of mutable data entries.
+pub mod diff;
 /// `MDataInfo` utilities.
 pub mod mdata_info;
+/// Paged listing of mutable data.
+pub mod paging;
 /// Operations with recovery.
 pub mod recovery;
+/// Role-based permissions of mutable and append-only data.
+pub mod roles;
+/// Tailing append-only data for new entries.
+pub mod tail;
+/// Multi-entry transactions on mutable data.
+pub mod transaction;
 
 mod id;
 #[cfg(feature = "mock-network")]
 mod mock;
 
 pub use self::account::ClientKeys;
+pub use self::backup::MDataBackup;
+pub use self::diff::MDataDiff;
 pub use self::id::SafeKey;
 pub use self::mdata_info::MDataInfo;
 #[cfg(feature = "mock-network")]
@@ -29,6 +43,7 @@ pub use self::mock::vault::mock_vault_path;
 pub use self::mock::ConnectionManager as MockConnectionManager;
 #[cfg(feature = "mock-network")]
 use self::mock::ConnectionManager;
+pub use self::paging::MDataEntriesPage;
 use crate::config_handler::Config;
 #[cfg(not(feature = "mock-network"))]
 use crate::connection_manager::ConnectionManager;
@@ -625,6 +640,23 @@ pub trait Client: Clone + 'static {
         .into_box()
     }
 
+    /// Return a page of at most `limit` entries of `MutableData`, in the order of their keys,
+    /// following the key `cursor`, or starting with the first entry if `cursor` is `None`. Pass
+    /// the `next` cursor of the page to get the following page.
+    fn list_mdata_entries_page(
+        &self,
+        address: MDataAddress,
+        cursor: Option<Vec<u8>>,
+        limit: usize,
+    ) -> Box<CoreFuture<MDataEntriesPage>> {
+        trace!("ListMDataEntries page for {:?}", address);
+
+        let request = self.compose_message(Request::ListMDataEntries(address), true);
+        let inner = self.inner();
+        let cm = &mut inner.borrow_mut().connection_manager;
+        cm.list_mdata_entries_page(&self.public_id(), &request, cursor, limit)
+    }
+
     /// Return the permissions set for a particular user
     fn list_mdata_user_permissions(
         &self,
@@ -1031,6 +1063,9 @@ pub trait Client: Clone + 'static {
     }
 
     /// Sends an ownership transfer request.
+    ///
+    /// The network has no request to change the owner of mutable data yet, so this always fails
+    /// with `OperationForbidden`.
     #[allow(unused)]
     fn change_mdata_owner(
         &self,
@@ -1039,7 +1074,9 @@ pub trait Client: Clone + 'static {
         new_owner: PublicKey,
         version: u64,
     ) -> Box<CoreFuture<()>> {
-        unimplemented!();
+        trace!("Change owner of MData {:?}", name);
+
+        err!(CoreError::OperationForbidden)
     }
 
     #[cfg(any(
@@ -0,0 +1,311 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::client::mdata_info::{self, MDataInfo};
+use crate::client::Client;
+use crate::errors::CoreError;
+#[cfg(not(feature = "mock-network"))]
+use futures::future;
+use futures::stream::{self, Stream};
+use futures::Future;
+use safe_nd::{MDataEntries, MDataSeqEntries, MDataSeqValue, Response};
+use std::cmp;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+/// Page of the entries of a `MutableData`, in the order of their keys.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MDataEntriesPage {
+    /// Entries in the page.
+    pub entries: MDataEntries,
+    /// Cursor to pass to get the next page, which is the last key in this page, or `None` if this
+    /// is the last page.
+    pub next: Option<Vec<u8>>,
+}
+
+impl MDataEntriesPage {
+    /// Get the page of at most `limit` entries following the key `cursor`, or starting with the
+    /// first entry if `cursor` is `None`. A `limit` of zero is treated as one.
+    pub fn new(entries: &MDataEntries, cursor: Option<&[u8]>, limit: usize) -> Self {
+        match *entries {
+            MDataEntries::Seq(ref entries) => Self::seq(entries, cursor, limit),
+            MDataEntries::Unseq(ref entries) => Self::unseq(entries, cursor, limit),
+        }
+    }
+
+    /// Get the page of the sequenced entries, see `new`.
+    pub fn seq(entries: &MDataSeqEntries, cursor: Option<&[u8]>, limit: usize) -> Self {
+        let (entries, next) = page(entries, cursor, limit);
+        Self {
+            entries: MDataEntries::Seq(entries),
+            next,
+        }
+    }
+
+    /// Get the page of the unsequenced entries, see `new`.
+    pub fn unseq(
+        entries: &BTreeMap<Vec<u8>, Vec<u8>>,
+        cursor: Option<&[u8]>,
+        limit: usize,
+    ) -> Self {
+        let (entries, next) = page(entries, cursor, limit);
+        Self {
+            entries: MDataEntries::Unseq(entries),
+            next,
+        }
+    }
+
+    /// Get the page from the response to a `ListMDataEntries` request holding all the entries.
+    pub fn from_response(
+        response: Response,
+        cursor: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Self, CoreError> {
+        match response {
+            Response::ListMDataEntries(res) => res
+                .map(|entries| Self::new(&entries, cursor, limit))
+                .map_err(CoreError::from),
+            _ => Err(CoreError::ReceivedUnexpectedEvent),
+        }
+    }
+
+    /// Number of entries in the page.
+    pub fn len(&self) -> usize {
+        match self.entries {
+            MDataEntries::Seq(ref entries) => entries.len(),
+            MDataEntries::Unseq(ref entries) => entries.len(),
+        }
+    }
+
+    /// Returns `true` if the page holds no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Iterate the entries of the sequenced `MutableData` in the order of their keys, fetching
+/// `page_size` entries at a time. The entries of each page are decrypted with the `MDataInfo` once
+/// the page is fetched, and the next page is only fetched once all the entries of the previous one
+/// have been consumed.
+///
+/// The network protocol doesn't support paging yet, so outside of the mock network all the
+/// entries are fetched once, with the first page, and the following pages are taken from them.
+pub fn seq_entries_stream(
+    client: impl Client,
+    info: MDataInfo,
+    page_size: usize,
+) -> Box<dyn Stream<Item = (Vec<u8>, MDataSeqValue), Error = CoreError>> {
+    // `None` once the last page has been fetched, otherwise the cursor of the next page.
+    #[cfg(feature = "mock-network")]
+    let pages = stream::unfold(Some(None), move |cursor: Option<Option<Vec<u8>>>| {
+        let cursor = cursor?;
+        let info = info.clone();
+
+        Some(
+            client
+                .list_mdata_entries_page(*info.address(), cursor, page_size)
+                .and_then(move |page| decrypt_page(&info, page)),
+        )
+    });
+
+    #[cfg(not(feature = "mock-network"))]
+    let pages = client
+        .list_seq_mdata_entries(info.name(), info.type_tag())
+        .map(move |entries| {
+            stream::unfold(Some(None), move |cursor: Option<Option<Vec<u8>>>| {
+                let cursor = cursor?;
+                let page =
+                    MDataEntriesPage::seq(&entries, cursor.as_ref().map(Vec::as_slice), page_size);
+                Some(future::result(decrypt_page(&info, page)))
+            })
+        })
+        .flatten_stream();
+
+    Box::new(pages.map(stream::iter_ok::<_, CoreError>).flatten())
+}
+
+/// Iterate the decrypted keys of the sequenced `MutableData` in the order of their entries, fetching
+/// `page_size` keys at a time, see `seq_entries_stream`.
+///
+/// Outside of the mock network all the keys are fetched once, without their values, and each key
+/// is only decrypted once it's consumed.
+pub fn seq_keys_stream(
+    client: impl Client,
+    info: MDataInfo,
+    page_size: usize,
+) -> Box<dyn Stream<Item = Vec<u8>, Error = CoreError>> {
+    #[cfg(feature = "mock-network")]
+    let keys = seq_entries_stream(client, info, page_size).map(|(key, _)| key);
+
+    #[cfg(not(feature = "mock-network"))]
+    let keys = {
+        let _ = page_size;
+        client
+            .list_mdata_keys(*info.address())
+            .map(move |keys| stream::iter_ok(keys).and_then(move |key| info.decrypt(&key)))
+            .flatten_stream()
+    };
+
+    Box::new(keys)
+}
+
+/// Iterate the decrypted values of the sequenced `MutableData` in the order of their entries,
+/// fetching `page_size` values at a time, see `seq_entries_stream`.
+///
+/// Outside of the mock network all the values are fetched once, without their keys, and each value
+/// is only decrypted once it's consumed.
+pub fn seq_values_stream(
+    client: impl Client,
+    info: MDataInfo,
+    page_size: usize,
+) -> Box<dyn Stream<Item = MDataSeqValue, Error = CoreError>> {
+    #[cfg(feature = "mock-network")]
+    let values = seq_entries_stream(client, info, page_size).map(|(_, value)| value);
+
+    #[cfg(not(feature = "mock-network"))]
+    let values = {
+        let _ = page_size;
+        client
+            .list_seq_mdata_values(info.name(), info.type_tag())
+            .map(move |values| {
+                stream::iter_ok(values)
+                    .and_then(move |value| mdata_info::decrypt_value(&info, &value))
+            })
+            .flatten_stream()
+    };
+
+    Box::new(values)
+}
+
+// Decrypt the entries of the page, returning them along with the cursor of the next page.
+fn decrypt_page(
+    info: &MDataInfo,
+    page: MDataEntriesPage,
+) -> Result<(MDataSeqEntries, Option<Option<Vec<u8>>>), CoreError> {
+    let entries = match page.entries {
+        MDataEntries::Seq(ref entries) => mdata_info::decrypt_entries(info, entries)?,
+        MDataEntries::Unseq(_) => return Err(CoreError::ReceivedUnexpectedData),
+    };
+    Ok((entries, page.next.map(Some)))
+}
+
+// Clone the page of at most `limit` entries following the cursor, returning it along with the
+// cursor of the next page.
+fn page<V: Clone>(
+    entries: &BTreeMap<Vec<u8>, V>,
+    cursor: Option<&[u8]>,
+    limit: usize,
+) -> (BTreeMap<Vec<u8>, V>, Option<Vec<u8>>) {
+    let start = cursor.map_or(Bound::Unbounded, |cursor| Bound::Excluded(cursor.to_vec()));
+    let mut range = entries.range((start, Bound::Unbounded));
+
+    let page: BTreeMap<_, _> = range
+        .by_ref()
+        .take(cmp::max(limit, 1))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    let next = if range.next().is_some() {
+        page.keys().next_back().cloned()
+    } else {
+        None
+    };
+
+    (page, next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::random_client;
+    use crate::DIR_TAG;
+    use safe_nd::{MDataKind, SeqMutableData};
+
+    fn test_entries(count: u8) -> MDataSeqEntries {
+        (0..count)
+            .map(|i| {
+                let value = MDataSeqValue {
+                    data: vec![i],
+                    version: 0,
+                };
+                (vec![i], value)
+            })
+            .collect()
+    }
+
+    // Test splitting entries into pages.
+    #[test]
+    fn pages() {
+        let entries = MDataEntries::Seq(test_entries(5));
+
+        let page = MDataEntriesPage::new(&entries, None, 2);
+        assert_eq!(page.len(), 2);
+        assert_eq!(page.next, Some(vec![1]));
+
+        let page = MDataEntriesPage::new(&entries, Some(&[1]), 2);
+        assert_eq!(
+            page.entries,
+            MDataEntries::Seq(test_entries(4).split_off(&vec![2]))
+        );
+        assert_eq!(page.next, Some(vec![3]));
+
+        let page = MDataEntriesPage::new(&entries, Some(&[3]), 2);
+        assert_eq!(page.len(), 1);
+        assert_eq!(page.next, None);
+
+        // The cursor doesn't need to be an existing key.
+        let page = MDataEntriesPage::new(&entries, Some(&[4, 0]), 2);
+        assert!(page.is_empty());
+        assert_eq!(page.next, None);
+    }
+
+    // Test streaming the decrypted entries, keys and values of a private mutable data page by page.
+    #[test]
+    fn stream_entries() {
+        random_client(|client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+            let info = unwrap!(MDataInfo::random_private(MDataKind::Seq, DIR_TAG));
+            let info2 = info.clone();
+            let entries = test_entries(10);
+            let encrypted = unwrap!(mdata_info::encrypt_entries(&info, &entries));
+
+            let data = SeqMutableData::new_with_data(
+                info.name(),
+                info.type_tag(),
+                encrypted,
+                Default::default(),
+                client.public_key(),
+            );
+
+            client
+                .put_seq_mutable_data(data)
+                .and_then(move |()| client2.list_mdata_entries_page(*info.address(), None, 4))
+                .and_then(move |page| {
+                    assert_eq!(page.len(), 4);
+                    assert!(page.next.is_some());
+
+                    seq_entries_stream(client3.clone(), info2.clone(), 4)
+                        .collect()
+                        .join3(
+                            seq_keys_stream(client3.clone(), info2.clone(), 4).collect(),
+                            seq_values_stream(client3, info2, 4).collect(),
+                        )
+                })
+                .map(move |(streamed, keys, values)| {
+                    let streamed: MDataSeqEntries = streamed.into_iter().collect();
+                    assert_eq!(streamed, entries);
+
+                    // Keys and values come in the order of the encrypted keys.
+                    let mut pairs: Vec<_> = keys.into_iter().zip(values).collect();
+                    assert_eq!(pairs.len(), entries.len());
+                    pairs.sort();
+                    assert_eq!(pairs, entries.into_iter().collect::<Vec<_>>());
+                })
+        });
+    }
+}
@@ -14,13 +14,17 @@ use crate::utils::FutureExt;
 use futures::future::{self, Either, Loop};
 use futures::Future;
 use safe_nd::{
-    AppPermissions, EntryError, Error as SndError, MDataAction, MDataAddress, MDataPermissionSet,
-    MDataSeqEntries, MDataSeqEntryAction, MDataSeqEntryActions, MDataSeqValue, PublicKey,
-    SeqMutableData,
+    ADataAddress, ADataAppendOperation, ADataIndex, ADataIndices, ADataOwner, ADataPubPermissions,
+    ADataUnpubPermissions, AppPermissions, EntryError, Error as SndError, MDataAction,
+    MDataAddress, MDataPermissionSet, MDataSeqEntries, MDataSeqEntryAction, MDataSeqEntryActions,
+    MDataSeqValue, MDataUnseqEntries, MDataUnseqEntryActions, PublicKey, SeqMutableData,
+    UnseqMutableData,
 };
 use std::collections::BTreeMap;
+use std::rc::Rc;
 
-const MAX_ATTEMPTS: usize = 10;
+/// Number of attempts made to recover from errors before giving up.
+pub const MAX_ATTEMPTS: usize = 10;
 
 /// Puts mutable data on the network and tries to recover from errors.
 ///
@@ -39,7 +43,29 @@ pub fn put_mdata(client: &impl Client, data: SeqMutableData) -> Box<CoreFuture<(
         .into_box()
 }
 
+/// Puts unsequenced mutable data on the network and tries to recover from errors.
+///
+/// If the data already exists, it tries to mutate it like `put_mdata` does. Entries whose values
+/// differ are overwritten, as unsequenced entries have no versions to compare.
+pub fn put_unseq_mdata(client: &impl Client, data: UnseqMutableData) -> Box<CoreFuture<()>> {
+    let client2 = client.clone();
+
+    client
+        .put_unseq_mutable_data(data.clone())
+        .or_else(move |error| match error {
+            CoreError::DataError(SndError::DataExists) => {
+                Either::A(update_unseq_mdata(&client2, data))
+            }
+            error => Either::B(future::err(error)),
+        })
+        .into_box()
+}
+
 /// Mutates mutable data entries and tries to recover from errors.
+///
+/// Entry errors are recovered from by changing the actions to apply to the current state of the
+/// entries, overwriting any concurrent changes. Use `transaction::run` to mutate entries only if
+/// they haven't changed since they were read.
 pub fn mutate_mdata_entries(
     client: &impl Client,
     address: MDataAddress,
@@ -146,6 +172,290 @@ pub fn del_mdata_user_permissions(
     .into_box()
 }
 
+/// Appends entries to sequenced append-only data and tries to recover from errors.
+///
+/// If other entries were appended concurrently, the entries are appended after them. If the
+/// entries already exist with the same values, e.g. because a timed out request was applied, the
+/// append is considered done.
+pub fn append_seq_adata(
+    client: &impl Client,
+    append: ADataAppendOperation,
+    index: u64,
+) -> Box<CoreFuture<()>> {
+    let state = (0, index);
+    let client = client.clone();
+
+    future::loop_fn(state, move |(attempts, index)| {
+        let client2 = client.clone();
+        let append2 = append.clone();
+
+        client
+            .append_seq_adata(append.clone(), index)
+            .map(|_| Loop::Break(()))
+            .or_else(move |error| match error {
+                CoreError::DataError(SndError::InvalidSuccessor(current_index))
+                    if attempts < MAX_ATTEMPTS =>
+                {
+                    ok!(Loop::Continue((attempts + 1, current_index)))
+                }
+                CoreError::DataError(SndError::KeysExist(_)) => is_appended(&client2, append2)
+                    .and_then(move |appended| {
+                        if appended {
+                            Ok(Loop::Break(()))
+                        } else {
+                            Err(error)
+                        }
+                    })
+                    .into_box(),
+                CoreError::RequestTimeout if attempts < MAX_ATTEMPTS => {
+                    ok!(Loop::Continue((attempts + 1, index)))
+                }
+                error => err!(error),
+            })
+    })
+    .into_box()
+}
+
+/// Adds permissions to unpublished append-only data and tries to recover from errors.
+///
+/// If the data was changed concurrently, the permissions are resubmitted with the current indices.
+/// If the latest permissions are already the same, e.g. because a timed out request was applied,
+/// nothing is added.
+pub fn add_unpub_adata_permissions(
+    client: &impl Client,
+    address: ADataAddress,
+    permissions: ADataUnpubPermissions,
+    permissions_index: u64,
+) -> Box<CoreFuture<()>> {
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let indices = ADataIndices::new(
+        permissions.entries_index,
+        permissions.owners_index,
+        permissions_index,
+    );
+    let permissions = permissions.permissions;
+    let permissions2 = permissions.clone();
+
+    change_adata(
+        client,
+        address,
+        indices,
+        move |indices| {
+            let permissions = ADataUnpubPermissions {
+                permissions: permissions.clone(),
+                entries_index: indices.entries_index(),
+                owners_index: indices.owners_index(),
+            };
+            client2.add_unpub_adata_permissions(address, permissions, indices.permissions_index())
+        },
+        move |indices| {
+            if indices.permissions_index() == 0 {
+                return ok!(false);
+            }
+            let permissions = permissions2.clone();
+
+            client3
+                .get_unpub_adata_permissions_at_index(address, ADataIndex::FromEnd(1))
+                .map(move |current| current.permissions == permissions)
+                .into_box()
+        },
+    )
+}
+
+/// Adds permissions to published append-only data and tries to recover from errors.
+///
+/// If the data was changed concurrently, the permissions are resubmitted with the current indices.
+/// If the latest permissions are already the same, e.g. because a timed out request was applied,
+/// nothing is added.
+pub fn add_pub_adata_permissions(
+    client: &impl Client,
+    address: ADataAddress,
+    permissions: ADataPubPermissions,
+    permissions_index: u64,
+) -> Box<CoreFuture<()>> {
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let indices = ADataIndices::new(
+        permissions.entries_index,
+        permissions.owners_index,
+        permissions_index,
+    );
+    let permissions = permissions.permissions;
+    let permissions2 = permissions.clone();
+
+    change_adata(
+        client,
+        address,
+        indices,
+        move |indices| {
+            let permissions = ADataPubPermissions {
+                permissions: permissions.clone(),
+                entries_index: indices.entries_index(),
+                owners_index: indices.owners_index(),
+            };
+            client2.add_pub_adata_permissions(address, permissions, indices.permissions_index())
+        },
+        move |indices| {
+            if indices.permissions_index() == 0 {
+                return ok!(false);
+            }
+            let permissions = permissions2.clone();
+
+            client3
+                .get_pub_adata_permissions_at_index(address, ADataIndex::FromEnd(1))
+                .map(move |current| current.permissions == permissions)
+                .into_box()
+        },
+    )
+}
+
+/// Sets the owner of append-only data and tries to recover from errors.
+///
+/// If the data was changed concurrently, the owner is resubmitted with the current indices. If the
+/// latest owner is already the same, e.g. because a timed out request was applied, nothing is set.
+pub fn set_adata_owners(
+    client: &impl Client,
+    address: ADataAddress,
+    owner: ADataOwner,
+    owners_index: u64,
+) -> Box<CoreFuture<()>> {
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let indices = ADataIndices::new(owner.entries_index, owners_index, owner.permissions_index);
+    let public_key = owner.public_key;
+
+    change_adata(
+        client,
+        address,
+        indices,
+        move |indices| {
+            let owner = ADataOwner {
+                public_key,
+                entries_index: indices.entries_index(),
+                permissions_index: indices.permissions_index(),
+            };
+            client2.set_adata_owners(address, owner, indices.owners_index())
+        },
+        move |indices| {
+            if indices.owners_index() == 0 {
+                return ok!(false);
+            }
+
+            client3
+                .get_adata_owners(address, ADataIndex::FromEnd(1))
+                .map(move |current| current.public_key == public_key)
+                .into_box()
+        },
+    )
+}
+
+/// Returns whether the error is caused by a stale entry version, i.e. by the entry having been
+/// mutated concurrently.
+pub fn is_version_conflict(error: &CoreError) -> bool {
+    match *error {
+        CoreError::DataError(SndError::InvalidSuccessor(_)) => true,
+        CoreError::DataError(SndError::InvalidEntryActions(ref errors)) => {
+            errors.values().any(|error| match *error {
+                EntryError::InvalidSuccessor(_) => true,
+                _ => false,
+            })
+        }
+        _ => false,
+    }
+}
+
+/// Returns whether the entry actions failed because of the current state of the entries, i.e. a
+/// stale version, an inserted entry which already exists or a mutated entry which doesn't exist,
+/// which suggests the entries were mutated concurrently.
+pub fn is_entry_conflict(error: &CoreError) -> bool {
+    if is_version_conflict(error) {
+        return true;
+    }
+
+    match *error {
+        CoreError::DataError(SndError::InvalidEntryActions(ref errors)) => {
+            errors.values().any(|error| match *error {
+                EntryError::EntryExists(_) | EntryError::NoSuchEntry => true,
+                _ => false,
+            })
+        }
+        _ => false,
+    }
+}
+
+// Submits a change of the permissions or owners of append-only data with the given indices. On
+// index mismatches and timeouts, the current indices are read and, unless `is_applied` says the
+// change is already applied, the change is resubmitted with them.
+fn change_adata<S, A>(
+    client: &impl Client,
+    address: ADataAddress,
+    indices: ADataIndices,
+    submit: S,
+    is_applied: A,
+) -> Box<CoreFuture<()>>
+where
+    S: Fn(ADataIndices) -> Box<CoreFuture<()>> + 'static,
+    A: Fn(ADataIndices) -> Box<CoreFuture<bool>> + 'static,
+{
+    let state = (0, indices);
+    let client = client.clone();
+    let is_applied = Rc::new(is_applied);
+
+    future::loop_fn(state, move |(attempts, indices)| {
+        let client2 = client.clone();
+        let is_applied = Rc::clone(&is_applied);
+
+        submit(indices)
+            .map(|_| Loop::Break(()))
+            .or_else(move |error| match error {
+                CoreError::DataError(SndError::InvalidSuccessor(_))
+                | CoreError::DataError(SndError::InvalidOwnersSuccessor(_))
+                | CoreError::DataError(SndError::InvalidPermissionsSuccessor(_))
+                | CoreError::RequestTimeout
+                    if attempts < MAX_ATTEMPTS =>
+                {
+                    client2
+                        .get_adata_indices(address)
+                        .and_then(move |indices| {
+                            (*is_applied)(indices).map(move |applied| {
+                                if applied {
+                                    Loop::Break(())
+                                } else {
+                                    Loop::Continue((attempts + 1, indices))
+                                }
+                            })
+                        })
+                        .into_box()
+                }
+                error => err!(error),
+            })
+    })
+    .into_box()
+}
+
+// Returns whether all the entries of the append operation exist with the same values.
+fn is_appended(client: &impl Client, append: ADataAppendOperation) -> Box<CoreFuture<bool>> {
+    let address = append.address;
+    let values: Vec<_> = append
+        .values
+        .into_iter()
+        .map(|entry| {
+            client
+                .get_adata_value(address, entry.key)
+                .then(move |result| match result {
+                    Ok(value) => Ok(value == entry.value),
+                    Err(CoreError::DataError(SndError::NoSuchEntry)) => Ok(false),
+                    Err(error) => Err(error),
+                })
+        })
+        .collect();
+
+    future::join_all(values)
+        .map(|appended| appended.into_iter().all(|appended| appended))
+        .into_box()
+}
+
 fn update_mdata(client: &impl Client, data: SeqMutableData) -> Box<CoreFuture<()>> {
     let client2 = client.clone();
     let client3 = client.clone();
@@ -172,6 +482,75 @@ fn update_mdata(client: &impl Client, data: SeqMutableData) -> Box<CoreFuture<()
         .into_box()
 }
 
+fn update_unseq_mdata(client: &impl Client, data: UnseqMutableData) -> Box<CoreFuture<()>> {
+    let client2 = client.clone();
+    let client3 = client.clone();
+
+    let address = *data.address();
+    let f0 = client.list_mdata_permissions(address);
+    let f1 = client.get_mdata_version(address);
+
+    f0.join(f1)
+        .and_then(move |(permissions, version)| {
+            update_mdata_permissions(
+                &client2,
+                address,
+                &permissions,
+                data.permissions().clone(),
+                version + 1,
+            )
+            .map(move |_| data)
+        })
+        .and_then(move |data| update_unseq_mdata_entries(&client3, address, data.entries().clone()))
+        .into_box()
+}
+
+// Update the unsequenced mutable data on the network so it has all the `desired_entries`. The
+// current entries are read again if the actions conflict with concurrent changes.
+fn update_unseq_mdata_entries(
+    client: &impl Client,
+    address: MDataAddress,
+    desired_entries: MDataUnseqEntries,
+) -> Box<CoreFuture<()>> {
+    let client = client.clone();
+
+    future::loop_fn(0, move |attempts| {
+        let client2 = client.clone();
+        let desired_entries = desired_entries.clone();
+
+        client
+            .list_unseq_mdata_entries(*address.name(), address.tag())
+            .and_then(move |current_entries| {
+                let actions = desired_entries.into_iter().fold(
+                    MDataUnseqEntryActions::new(),
+                    |actions, (key, value)| match current_entries.get(&key) {
+                        Some(current_value) if *current_value == value => actions,
+                        Some(_) => actions.update(key, value),
+                        None => actions.ins(key, value),
+                    },
+                );
+                if actions.actions().is_empty() {
+                    return ok!(Loop::Break(()));
+                }
+
+                client2
+                    .mutate_unseq_mdata_entries(*address.name(), address.tag(), actions)
+                    .map(|_| Loop::Break(()))
+                    .or_else(move |error| match error {
+                        CoreError::DataError(SndError::InvalidEntryActions(_))
+                        | CoreError::RequestTimeout
+                            if attempts < MAX_ATTEMPTS =>
+                        {
+                            Ok(Loop::Continue(attempts + 1))
+                        }
+                        error => Err(error),
+                    })
+                    .into_box()
+            })
+    })
+    .into_box()
+}
+
 // Update the mutable data on the network so it has all the `desired_entries`.
 fn update_mdata_entries(
     client: &impl Client,
@@ -442,7 +821,10 @@ mod tests {
 mod tests_with_mock_routing {
     use super::*;
     use crate::utils::test_utils::random_client;
-    use safe_nd::{MDataSeqValue, XorName};
+    use safe_nd::{
+        AData, ADataEntry, ADataUnpubPermissionSet, AppendOnlyData, MDataSeqValue,
+        UnpubSeqAppendOnlyData, XorName,
+    };
 
     // Test putting mdata and recovering from errors
     #[test]
@@ -723,4 +1105,109 @@ mod tests_with_mock_routing {
                 })
         })
     }
+
+    // Test appending to adata, adding permissions and setting owners, and recovering from errors
+    #[test]
+    fn adata_mutations_with_recovery() {
+        random_client(|client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+            let client4 = client.clone();
+            let client5 = client.clone();
+            let client6 = client.clone();
+            let client7 = client.clone();
+            let client8 = client.clone();
+            let client9 = client.clone();
+
+            let mut data = UnpubSeqAppendOnlyData::new(rand::random(), 10_000);
+            unwrap!(data.append_owner(
+                ADataOwner {
+                    public_key: client.public_key(),
+                    entries_index: 0,
+                    permissions_index: 0,
+                },
+                0
+            ));
+            let address = *data.address();
+
+            let entry = |index: u8| ADataEntry::new(vec![index], vec![index]);
+            let append = move |entries| ADataAppendOperation {
+                address,
+                values: entries,
+            };
+
+            let bls_sk = threshold_crypto::SecretKey::random();
+            let user = PublicKey::from(bls_sk.public_key());
+            let permissions = ADataUnpubPermissions {
+                permissions: btree_map![user => ADataUnpubPermissionSet::new(true, true, false)],
+                entries_index: 0,
+                owners_index: 0,
+            };
+            let permissions2 = permissions.clone();
+
+            let bls_sk = threshold_crypto::SecretKey::random();
+            let new_owner = ADataOwner {
+                public_key: PublicKey::from(bls_sk.public_key()),
+                entries_index: 0,
+                permissions_index: 0,
+            };
+
+            client
+                .put_adata(AData::UnpubSeq(data))
+                .then(move |res| {
+                    unwrap!(res);
+                    client2.append_seq_adata(append(vec![entry(0)]), 0)
+                })
+                .then(move |res| {
+                    unwrap!(res);
+                    // append with invalid index
+                    append_seq_adata(&client3, append(vec![entry(1)]), 0)
+                })
+                .then(move |res| {
+                    unwrap!(res);
+                    // append of already appended entries
+                    append_seq_adata(&client4, append(vec![entry(1)]), 1)
+                })
+                .then(move |res| {
+                    unwrap!(res);
+                    // append of an existing key with a different value
+                    let entries = vec![ADataEntry::new(vec![1], vec![2])];
+                    append_seq_adata(&client5, append(entries), 2)
+                })
+                .then(move |res| {
+                    match res {
+                        Err(CoreError::DataError(SndError::KeysExist(_))) => (),
+                        x => panic!("Unexpected {:?}", x),
+                    }
+
+                    // add permissions with invalid indices
+                    add_unpub_adata_permissions(&client6, address, permissions, 0)
+                })
+                .then(move |res| {
+                    unwrap!(res);
+                    // add permissions which were already added
+                    add_unpub_adata_permissions(&client7, address, permissions2, 0)
+                })
+                .then(move |res| {
+                    unwrap!(res);
+                    // set owner with invalid indices
+                    set_adata_owners(&client8, address, new_owner, 0)
+                })
+                .then(move |res| {
+                    unwrap!(res);
+                    client9
+                        .get_adata_indices(address)
+                        .join(client9.get_adata_owners(address, ADataIndex::FromEnd(1)))
+                })
+                .then(move |res| {
+                    let (indices, owner) = unwrap!(res);
+                    assert_eq!(indices.entries_index(), 2);
+                    assert_eq!(indices.permissions_index(), 1);
+                    assert_eq!(indices.owners_index(), 2);
+                    assert_eq!(owner.public_key, new_owner.public_key);
+
+                    Ok::<_, CoreError>(())
+                })
+        })
+    }
 }
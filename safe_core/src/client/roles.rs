@@ -0,0 +1,443 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::recovery;
+use super::Client;
+use crate::event_loop::CoreFuture;
+use crate::utils::FutureExt;
+use futures::future;
+use futures::Future;
+use safe_nd::{
+    ADataAction, ADataAddress, ADataIndex, ADataIndices, ADataPubPermissionSet,
+    ADataPubPermissions, ADataUnpubPermissionSet, ADataUnpubPermissions, ADataUser, MDataAction,
+    MDataAddress, MDataPermissionSet, PublicKey,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const MDATA_ACTIONS: [MDataAction; 5] = [
+    MDataAction::Read,
+    MDataAction::Insert,
+    MDataAction::Update,
+    MDataAction::Delete,
+    MDataAction::ManagePermissions,
+];
+
+/// Named set of permissions, which can be granted on both mutable and append-only data.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Role {
+    /// Can read the data.
+    Reader,
+    /// Can read the data and add new entries to it.
+    Contributor,
+    /// Can read the data and add, update and delete its entries. Append-only data can't be
+    /// updated, so on append-only data this is the same as `Contributor`.
+    Editor,
+    /// Can do everything an editor can, and manage the permissions of the data.
+    Admin,
+}
+
+impl Role {
+    /// All roles, from the least to the most privileged.
+    pub const ALL: [Role; 4] = [Role::Reader, Role::Contributor, Role::Editor, Role::Admin];
+
+    /// Returns the permission set of the role for mutable data.
+    pub fn mdata_permissions(self) -> MDataPermissionSet {
+        let set = MDataPermissionSet::new().allow(MDataAction::Read);
+        match self {
+            Role::Reader => set,
+            Role::Contributor => set.allow(MDataAction::Insert),
+            Role::Editor => set
+                .allow(MDataAction::Insert)
+                .allow(MDataAction::Update)
+                .allow(MDataAction::Delete),
+            Role::Admin => set
+                .allow(MDataAction::Insert)
+                .allow(MDataAction::Update)
+                .allow(MDataAction::Delete)
+                .allow(MDataAction::ManagePermissions),
+        }
+    }
+
+    /// Returns the permission set of the role for unpublished append-only data.
+    pub fn unpub_adata_permissions(self) -> ADataUnpubPermissionSet {
+        match self {
+            Role::Reader => ADataUnpubPermissionSet::new(true, false, false),
+            Role::Contributor | Role::Editor => ADataUnpubPermissionSet::new(true, true, false),
+            Role::Admin => ADataUnpubPermissionSet::new(true, true, true),
+        }
+    }
+
+    /// Returns the permission set of the role for published append-only data, which can be read
+    /// by anyone.
+    pub fn pub_adata_permissions(self) -> ADataPubPermissionSet {
+        match self {
+            Role::Reader => ADataPubPermissionSet::new(false, false),
+            Role::Contributor | Role::Editor => ADataPubPermissionSet::new(true, false),
+            Role::Admin => ADataPubPermissionSet::new(true, true),
+        }
+    }
+
+    /// Returns the role with exactly the allowed actions of the permission set, if any.
+    pub fn from_mdata_permissions(set: &MDataPermissionSet) -> Option<Self> {
+        Self::ALL.iter().cloned().find(|role| {
+            let role_set = role.mdata_permissions();
+            MDATA_ACTIONS
+                .iter()
+                .all(|&action| set.is_allowed(action) == role_set.is_allowed(action))
+        })
+    }
+
+    /// Returns the least privileged role with exactly the allowed actions of the permission set,
+    /// if any.
+    pub fn from_unpub_adata_permissions(set: ADataUnpubPermissionSet) -> Option<Self> {
+        Self::ALL.iter().cloned().find(|role| {
+            let role_set = role.unpub_adata_permissions();
+            [
+                ADataAction::Read,
+                ADataAction::Append,
+                ADataAction::ManagePermissions,
+            ]
+            .iter()
+            .all(|&action| set.is_allowed(action) == role_set.is_allowed(action))
+        })
+    }
+
+    /// Returns the least privileged role with exactly the allowed actions of the permission set,
+    /// if any. Actions which are not set are considered not allowed.
+    pub fn from_pub_adata_permissions(set: ADataPubPermissionSet) -> Option<Self> {
+        Self::ALL.iter().cloned().find(|role| {
+            let role_set = role.pub_adata_permissions();
+            [ADataAction::Append, ADataAction::ManagePermissions]
+                .iter()
+                .all(|&action| {
+                    set.is_allowed(action).unwrap_or(false)
+                        == role_set.is_allowed(action).unwrap_or(false)
+                })
+        })
+    }
+}
+
+/// Reference to either mutable or append-only data.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub enum DataItem {
+    /// Mutable data.
+    MData(MDataAddress),
+    /// Append-only data.
+    AData(ADataAddress),
+}
+
+/// Grant the role to the user on all the data items, replacing any permissions the user had
+/// before. Conflicting concurrent permission changes are retried.
+pub fn grant_role(
+    client: &impl Client,
+    items: Vec<DataItem>,
+    user: PublicKey,
+    role: Role,
+) -> Box<CoreFuture<()>> {
+    trace!(
+        "Granting {:?} role to {:?} on {} items",
+        role,
+        user,
+        items.len()
+    );
+    set_role(client, items, user, Some(role))
+}
+
+/// Revoke any role or permissions of the user on all the data items. Items the user has no
+/// permissions on are left untouched.
+pub fn revoke_role(
+    client: &impl Client,
+    items: Vec<DataItem>,
+    user: PublicKey,
+) -> Box<CoreFuture<()>> {
+    trace!("Revoking roles of {:?} on {} items", user, items.len());
+    set_role(client, items, user, None)
+}
+
+/// Returns the users with permissions on the data item, along with their roles. Users whose
+/// permissions don't match any role exactly have `None` as their role. Permissions of mutable
+/// data are always granted to keys, so `ADataUser::Anyone` can only be returned for published
+/// append-only data.
+pub fn audit_roles(
+    client: &impl Client,
+    item: DataItem,
+) -> Box<CoreFuture<BTreeMap<ADataUser, Option<Role>>>> {
+    match item {
+        DataItem::MData(address) => client
+            .list_mdata_permissions(address)
+            .map(|permissions| {
+                permissions
+                    .iter()
+                    .map(|(key, set)| (ADataUser::Key(*key), Role::from_mdata_permissions(set)))
+                    .collect()
+            })
+            .into_box(),
+        DataItem::AData(address) if address.is_pub() => {
+            fetch_pub_adata_permissions(client, address)
+                .map(|(_, permissions)| {
+                    permissions
+                        .iter()
+                        .map(|(user, set)| (*user, Role::from_pub_adata_permissions(*set)))
+                        .collect()
+                })
+                .into_box()
+        }
+        DataItem::AData(address) => fetch_unpub_adata_permissions(client, address)
+            .map(|(_, permissions)| {
+                permissions
+                    .iter()
+                    .map(|(key, set)| {
+                        (
+                            ADataUser::Key(*key),
+                            Role::from_unpub_adata_permissions(*set),
+                        )
+                    })
+                    .collect()
+            })
+            .into_box(),
+    }
+}
+
+fn set_role(
+    client: &impl Client,
+    items: Vec<DataItem>,
+    user: PublicKey,
+    role: Option<Role>,
+) -> Box<CoreFuture<()>> {
+    let changes: Vec<_> = items
+        .into_iter()
+        .map(|item| match item {
+            DataItem::MData(address) => set_mdata_role(client, address, user, role),
+            DataItem::AData(address) => set_adata_role(client, address, user, role),
+        })
+        .collect();
+
+    future::join_all(changes).map(|_| ()).into_box()
+}
+
+fn set_mdata_role(
+    client: &impl Client,
+    address: MDataAddress,
+    user: PublicKey,
+    role: Option<Role>,
+) -> Box<CoreFuture<()>> {
+    let client2 = client.clone();
+
+    client
+        .get_mdata_version(address)
+        .and_then(move |version| match role {
+            Some(role) => recovery::set_mdata_user_permissions(
+                &client2,
+                address,
+                user,
+                role.mdata_permissions(),
+                version + 1,
+            ),
+            None => recovery::del_mdata_user_permissions(&client2, address, user, version + 1),
+        })
+        .into_box()
+}
+
+// Append new permissions of the append-only data, with the permissions of the user replaced.
+// Concurrent changes and timeouts are recovered from by `recovery`, which resubmits the
+// permissions with the current indices unless the latest ones are already the same.
+fn set_adata_role(
+    client: &impl Client,
+    address: ADataAddress,
+    user: PublicKey,
+    role: Option<Role>,
+) -> Box<CoreFuture<()>> {
+    let client2 = client.clone();
+
+    if address.is_pub() {
+        fetch_pub_adata_permissions(client, address)
+            .and_then(move |(indices, mut permissions)| {
+                let changed = match role {
+                    Some(role) => {
+                        let set = role.pub_adata_permissions();
+                        permissions.insert(ADataUser::Key(user), set) != Some(set)
+                    }
+                    None => permissions.remove(&ADataUser::Key(user)).is_some(),
+                };
+                if !changed {
+                    return ok!(());
+                }
+
+                let permissions = ADataPubPermissions {
+                    permissions,
+                    entries_index: indices.entries_index(),
+                    owners_index: indices.owners_index(),
+                };
+                recovery::add_pub_adata_permissions(
+                    &client2,
+                    address,
+                    permissions,
+                    indices.permissions_index(),
+                )
+            })
+            .into_box()
+    } else {
+        fetch_unpub_adata_permissions(client, address)
+            .and_then(move |(indices, mut permissions)| {
+                let changed = match role {
+                    Some(role) => {
+                        let set = role.unpub_adata_permissions();
+                        permissions.insert(user, set) != Some(set)
+                    }
+                    None => permissions.remove(&user).is_some(),
+                };
+                if !changed {
+                    return ok!(());
+                }
+
+                let permissions = ADataUnpubPermissions {
+                    permissions,
+                    entries_index: indices.entries_index(),
+                    owners_index: indices.owners_index(),
+                };
+                recovery::add_unpub_adata_permissions(
+                    &client2,
+                    address,
+                    permissions,
+                    indices.permissions_index(),
+                )
+            })
+            .into_box()
+    }
+}
+
+// Fetch the indices and the latest permissions of the published append-only data.
+fn fetch_pub_adata_permissions(
+    client: &impl Client,
+    address: ADataAddress,
+) -> Box<CoreFuture<(ADataIndices, BTreeMap<ADataUser, ADataPubPermissionSet>)>> {
+    let client2 = client.clone();
+
+    client
+        .get_adata_indices(address)
+        .and_then(move |indices| {
+            if indices.permissions_index() == 0 {
+                return ok!((indices, BTreeMap::new()));
+            }
+            client2
+                .get_pub_adata_permissions_at_index(address, ADataIndex::FromEnd(1))
+                .map(move |permissions| (indices, permissions.permissions))
+                .into_box()
+        })
+        .into_box()
+}
+
+// Fetch the indices and the latest permissions of the unpublished append-only data.
+fn fetch_unpub_adata_permissions(
+    client: &impl Client,
+    address: ADataAddress,
+) -> Box<CoreFuture<(ADataIndices, BTreeMap<PublicKey, ADataUnpubPermissionSet>)>> {
+    let client2 = client.clone();
+
+    client
+        .get_adata_indices(address)
+        .and_then(move |indices| {
+            if indices.permissions_index() == 0 {
+                return ok!((indices, BTreeMap::new()));
+            }
+            client2
+                .get_unpub_adata_permissions_at_index(address, ADataIndex::FromEnd(1))
+                .map(move |permissions| (indices, permissions.permissions))
+                .into_box()
+        })
+        .into_box()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::random_client;
+    use safe_nd::{ADataOwner, AppendOnlyData, SeqMutableData, UnpubSeqAppendOnlyData};
+
+    // Test that the permission sets of the roles map back to the roles.
+    #[test]
+    fn role_permissions() {
+        for &role in &Role::ALL {
+            assert_eq!(
+                Role::from_mdata_permissions(&role.mdata_permissions()),
+                Some(role)
+            );
+        }
+
+        // Append-only data can't be updated, so contributors and editors are the same.
+        assert_eq!(
+            Role::from_unpub_adata_permissions(Role::Editor.unpub_adata_permissions()),
+            Some(Role::Contributor)
+        );
+        assert_eq!(
+            Role::from_pub_adata_permissions(Role::Admin.pub_adata_permissions()),
+            Some(Role::Admin)
+        );
+
+        let custom = MDataPermissionSet::new().allow(MDataAction::Insert);
+        assert_eq!(Role::from_mdata_permissions(&custom), None);
+    }
+
+    // Test granting, auditing and revoking roles on mutable and append-only data.
+    #[test]
+    fn grant_audit_revoke() {
+        random_client(|client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+            let client4 = client.clone();
+            let client5 = client.clone();
+
+            let user = PublicKey::from(threshold_crypto::SecretKey::random().public_key());
+            let owner = client.public_key();
+
+            let mdata = SeqMutableData::new(rand::random(), 15_010, owner);
+            let mdata_address = *mdata.address();
+
+            let mut adata = UnpubSeqAppendOnlyData::new(rand::random(), 15_010);
+            unwrap!(adata.append_owner(
+                ADataOwner {
+                    public_key: owner,
+                    entries_index: 0,
+                    permissions_index: 0,
+                },
+                0
+            ));
+            let adata_address = *adata.address();
+
+            let items = vec![
+                DataItem::MData(mdata_address),
+                DataItem::AData(adata_address),
+            ];
+            let items2 = items.clone();
+
+            client
+                .put_seq_mutable_data(mdata)
+                .join(client.put_adata(adata.into()))
+                .and_then(move |_| grant_role(&client2, items, user, Role::Editor))
+                .and_then(move |()| {
+                    audit_roles(&client3, DataItem::MData(mdata_address))
+                        .join(audit_roles(&client3, DataItem::AData(adata_address)))
+                })
+                .and_then(move |(mdata_roles, adata_roles)| {
+                    assert_eq!(mdata_roles[&ADataUser::Key(user)], Some(Role::Editor));
+                    assert_eq!(adata_roles[&ADataUser::Key(user)], Some(Role::Contributor));
+
+                    revoke_role(&client4, items2, user)
+                })
+                .and_then(move |()| {
+                    audit_roles(&client5, DataItem::MData(mdata_address))
+                        .join(audit_roles(&client5, DataItem::AData(adata_address)))
+                })
+                .map(move |(mdata_roles, adata_roles)| {
+                    assert!(!mdata_roles.contains_key(&ADataUser::Key(user)));
+                    assert!(!adata_roles.contains_key(&ADataUser::Key(user)));
+                })
+        });
+    }
+}
@@ -0,0 +1,271 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Tailing append-only data for new entries.
+//!
+//! The network doesn't notify clients of appended entries, so the data is polled for its entries
+//! index, and the entries past the last one yielded are fetched when it grows. As with watching
+//! directories, the polling interval doubles while nothing is appended, up to a maximum, and drops
+//! back to the minimum as soon as something is.
+
+use super::Client;
+use crate::errors::CoreError;
+use crate::event_loop::CoreFuture;
+use crate::utils::FutureExt;
+use futures::future::{self, Loop};
+use futures::stream::{self, Stream};
+use futures::Future;
+use safe_nd::{ADataAddress, ADataEntry, ADataIndex};
+use std::cmp;
+use std::time::{Duration, Instant};
+use tokio::timer::Delay;
+
+/// Shortest interval between polls. Shorter intervals are raised to it, so that the network isn't
+/// polled continuously.
+pub const MIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Options controlling how often append-only data is polled.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct TailOptions {
+    /// Interval between polls right after new entries were appended.
+    pub min_interval: Duration,
+    /// Longest interval between polls, reached while nothing is appended.
+    pub max_interval: Duration,
+}
+
+impl Default for TailOptions {
+    fn default() -> Self {
+        TailOptions {
+            min_interval: Duration::from_secs(1),
+            max_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+impl TailOptions {
+    /// Check the options, raising intervals shorter than `MIN_POLL_INTERVAL` to it. Fails if the
+    /// minimum interval is longer than the maximum one.
+    pub fn checked(self) -> Result<Self, CoreError> {
+        if self.min_interval > self.max_interval {
+            return Err(CoreError::Unexpected(
+                "Minimum polling interval is longer than the maximum one".to_string(),
+            ));
+        }
+
+        Ok(TailOptions {
+            min_interval: cmp::max(self.min_interval, MIN_POLL_INTERVAL),
+            max_interval: cmp::max(self.max_interval, MIN_POLL_INTERVAL),
+        })
+    }
+}
+
+/// Tail the append-only data, returning a stream of its entries along with their indices,
+/// starting with the entry at index `from`.
+///
+/// Entries already appended at the time of the first poll, which happens immediately, are yielded
+/// first, so passing the index following the last entry seen resumes tailing where it stopped.
+///
+/// The stream ends when the requests to the network are aborted, e.g. because the client was
+/// dropped, and stops with an error if polling fails for another reason than a timeout, or right
+/// away if the options are invalid (see `TailOptions::checked`). Dropping the stream, e.g.
+/// together with the event loop running it, stops tailing.
+pub fn tail(
+    client: impl Client,
+    address: ADataAddress,
+    from: u64,
+    options: TailOptions,
+) -> Box<dyn Stream<Item = (u64, ADataEntry), Error = CoreError>> {
+    trace!("Tailing {:?} from {}", address, from);
+
+    let options = match options.checked() {
+        Ok(options) => options,
+        Err(error) => return Box::new(stream::once(Err(error))),
+    };
+    let state = Tailer {
+        client,
+        address,
+        options,
+        next: from,
+        interval: options.min_interval,
+        polled: false,
+    };
+
+    Box::new(
+        stream::unfold(state, |tailer| Some(tailer.next_entries()))
+            .take_while(|entries| Ok(entries.is_some()))
+            .map(|entries| stream::iter_ok(entries.unwrap_or_default()))
+            .flatten(),
+    )
+}
+
+struct Tailer<C> {
+    client: C,
+    address: ADataAddress,
+    options: TailOptions,
+    // Index of the next entry to yield.
+    next: u64,
+    interval: Duration,
+    polled: bool,
+}
+
+impl<C: Client> Tailer<C> {
+    // Poll the data until new entries are appended. Returns `None` if the requests were aborted.
+    #[allow(clippy::type_complexity)]
+    fn next_entries(self) -> Box<CoreFuture<(Option<Vec<(u64, ADataEntry)>>, Self)>> {
+        future::loop_fn(self, |tailer| {
+            // The first poll happens without waiting.
+            let delay = if tailer.polled {
+                Delay::new(Instant::now() + tailer.interval)
+                    .map_err(|error| CoreError::Unexpected(format!("Timer error: {}", error)))
+                    .into_box()
+            } else {
+                ok!(())
+            };
+
+            delay
+                .and_then(move |()| tailer.fetch().then(move |result| Ok((tailer, result))))
+                .and_then(|(tailer, result)| tailer.update(result))
+        })
+        .into_box()
+    }
+
+    // Fetch the entries past the next index, if any.
+    fn fetch(&self) -> Box<CoreFuture<Vec<ADataEntry>>> {
+        let client = self.client.clone();
+        let address = self.address;
+        let next = self.next;
+
+        self.client
+            .get_adata_indices(address)
+            .and_then(move |indices| {
+                let end = indices.entries_index();
+                if end <= next {
+                    return ok!(Vec::new());
+                }
+                client.get_adata_range(
+                    address,
+                    (ADataIndex::FromStart(next), ADataIndex::FromStart(end)),
+                )
+            })
+            .into_box()
+    }
+
+    // Yield the new entries, or back off if there are none.
+    #[allow(clippy::type_complexity)]
+    fn update(
+        mut self,
+        result: Result<Vec<ADataEntry>, CoreError>,
+    ) -> Result<Loop<(Option<Vec<(u64, ADataEntry)>>, Self), Self>, CoreError> {
+        self.polled = true;
+
+        let entries = match result {
+            Ok(entries) => entries,
+            Err(CoreError::RequestTimeout) => {
+                self.back_off();
+                return Ok(Loop::Continue(self));
+            }
+            Err(CoreError::OperationAborted) => return Ok(Loop::Break((None, self))),
+            Err(error) => return Err(error),
+        };
+
+        if entries.is_empty() {
+            self.back_off();
+            return Ok(Loop::Continue(self));
+        }
+
+        let start = self.next;
+        self.next += entries.len() as u64;
+        self.interval = self.options.min_interval;
+
+        let entries = (start..).zip(entries).collect();
+        Ok(Loop::Break((Some(entries), self)))
+    }
+
+    fn back_off(&mut self) {
+        self.interval = cmp::min(self.interval * 2, self.options.max_interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::random_client;
+    use safe_nd::{AData, ADataAppendOperation, ADataOwner, AppendOnlyData, PubSeqAppendOnlyData};
+
+    fn append(client: &impl Client, address: ADataAddress, index: u64) -> Box<CoreFuture<()>> {
+        let append = ADataAppendOperation {
+            address,
+            values: vec![ADataEntry::new(vec![index as u8], vec![index as u8])],
+        };
+        client.append_seq_adata(append, index)
+    }
+
+    // Test raising short polling intervals and rejecting a minimum longer than the maximum.
+    #[test]
+    fn options_checked() {
+        let options = unwrap!(TailOptions {
+            min_interval: Duration::from_millis(0),
+            max_interval: Duration::from_millis(0),
+        }
+        .checked());
+        assert_eq!(options.min_interval, MIN_POLL_INTERVAL);
+        assert_eq!(options.max_interval, MIN_POLL_INTERVAL);
+
+        let options = TailOptions {
+            min_interval: Duration::from_secs(2),
+            max_interval: Duration::from_secs(1),
+        };
+        assert!(options.checked().is_err());
+    }
+
+    // Test tailing entries appended before and after tailing started, resuming from an index.
+    #[test]
+    fn tail_entries() {
+        random_client(|client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+            let client4 = client.clone();
+            let client5 = client.clone();
+
+            let mut data = PubSeqAppendOnlyData::new(rand::random(), 15_040);
+            unwrap!(data.append_owner(
+                ADataOwner {
+                    public_key: client.public_key(),
+                    entries_index: 0,
+                    permissions_index: 0,
+                },
+                0
+            ));
+            let address = *data.address();
+
+            let options = TailOptions {
+                min_interval: Duration::from_millis(10),
+                max_interval: Duration::from_millis(40),
+            };
+
+            client
+                .put_adata(AData::PubSeq(data))
+                .and_then(move |()| append(&client2, address, 0))
+                .and_then(move |()| append(&client3, address, 1))
+                .and_then(move |()| {
+                    // Start from the second entry, and append the third while tailing.
+                    let tailed = tail(client4.clone(), address, 1, options).take(2).collect();
+                    let appended = Delay::new(Instant::now() + Duration::from_millis(50))
+                        .map_err(|error| CoreError::Unexpected(error.to_string()))
+                        .and_then(move |()| append(&client5, address, 2));
+
+                    tailed.join(appended)
+                })
+                .map(|(entries, ())| {
+                    let indices: Vec<_> = entries.iter().map(|(index, _)| *index).collect();
+                    assert_eq!(indices, vec![1, 2]);
+                    assert_eq!(entries[1].1.value, vec![2]);
+                })
+        });
+    }
+}
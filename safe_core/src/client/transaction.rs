@@ -0,0 +1,465 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::recovery::{self, MAX_ATTEMPTS};
+use super::Client;
+use crate::errors::CoreError;
+use crate::event_loop::CoreFuture;
+use crate::utils::FutureExt;
+use futures::future::{self, Loop};
+use futures::Future;
+use safe_nd::{
+    MDataAddress, MDataSeqEntries, MDataSeqEntryAction, MDataSeqEntryActions, MDataSeqValue,
+};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+
+/// Options of a transaction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TransactionOptions {
+    /// Maximum number of times the transaction is run before giving up.
+    pub max_attempts: usize,
+    /// If set, the entry actions are applied with exactly the versions given to them. Otherwise,
+    /// the versions of updates and deletions of entries in the snapshot are set to the successors
+    /// of their versions in the snapshot.
+    pub strict: bool,
+    /// If set, entries in the snapshot which the actions don't mutate are updated with their
+    /// current values, at the successors of their versions in the snapshot. The transaction then
+    /// also fails and is retried if any entry it only read has changed, instead of only if an
+    /// entry it mutates has. This bumps the versions of the read entries. Entries which were read
+    /// but don't exist are not checked.
+    pub validate_reads: bool,
+}
+
+impl Default for TransactionOptions {
+    fn default() -> Self {
+        Self {
+            max_attempts: MAX_ATTEMPTS,
+            strict: false,
+            validate_reads: false,
+        }
+    }
+}
+
+/// Consistent snapshot of entries of a mutable data, read by a transaction.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Snapshot {
+    entries: MDataSeqEntries,
+    attempt: usize,
+}
+
+impl Snapshot {
+    /// Get the value of the entry, or `None` if the mutable data contains no such entry.
+    pub fn get(&self, key: &[u8]) -> Option<&MDataSeqValue> {
+        self.entries.get(key)
+    }
+
+    /// Returns the entries of the snapshot, which are the read entries contained in the mutable
+    /// data.
+    pub fn entries(&self) -> &MDataSeqEntries {
+        &self.entries
+    }
+
+    /// Returns the number of the attempt to run the transaction, starting with 1.
+    pub fn attempt(&self) -> usize {
+        self.attempt
+    }
+}
+
+/// Run a transaction over the entries of the sequenced mutable data with the given keys.
+///
+/// The entries are read in a single request, giving a consistent snapshot, which is passed to `f`.
+/// `f` returns the entry actions to apply along with the result of the transaction. The actions
+/// are applied atomically and only if none of the entries they mutate changed since the snapshot
+/// was read. Otherwise, the entries are read again and `f` is run on the new snapshot, up to
+/// `options.max_attempts` times, after which the last error is returned. If applying the actions
+/// times out, the same actions are resubmitted instead, so an applied transaction is never
+/// applied again. Unlike
+/// `recovery::mutate_mdata_entries`, the actions are never changed to apply to entries which
+/// changed since they were read. If `f` returns no actions, nothing is mutated, even if
+/// `options.validate_reads` is set, as the snapshot is consistent already.
+///
+/// There is no request for reading a set of entries at once, so all the entries of the mutable
+/// data are listed on every attempt and then filtered down to `keys`. This makes transactions
+/// expensive over mutable data with many or large entries.
+pub fn run<F, T>(
+    client: &impl Client,
+    address: MDataAddress,
+    keys: BTreeSet<Vec<u8>>,
+    options: TransactionOptions,
+    f: F,
+) -> Box<CoreFuture<T>>
+where
+    F: FnMut(&Snapshot) -> Result<(MDataSeqEntryActions, T), CoreError> + 'static,
+    T: 'static,
+{
+    let client = client.clone();
+    let f = Rc::new(RefCell::new(f));
+
+    future::loop_fn(1, move |attempt| {
+        let client2 = client.clone();
+        let keys = keys.clone();
+        let f = Rc::clone(&f);
+
+        client
+            .list_seq_mdata_entries(*address.name(), address.tag())
+            .and_then(move |entries| {
+                let snapshot = Snapshot {
+                    entries: entries
+                        .into_iter()
+                        .filter(|(key, _)| keys.contains(key))
+                        .collect(),
+                    attempt,
+                };
+                let (actions, result) = (&mut *f.borrow_mut())(&snapshot)?;
+
+                let actions = if options.strict {
+                    actions
+                } else {
+                    set_versions(actions, &snapshot.entries)
+                };
+                let actions = if options.validate_reads && !actions.actions().is_empty() {
+                    validate_reads(actions, &snapshot.entries)
+                } else {
+                    actions
+                };
+                Ok((actions, result))
+            })
+            .and_then(move |(actions, result)| {
+                if actions.actions().is_empty() {
+                    return ok!(result);
+                }
+                apply(&client2, address, actions)
+                    .map(move |()| result)
+                    .into_box()
+            })
+            .map(Loop::Break)
+            .or_else(move |error| {
+                if recovery::is_entry_conflict(&error) && attempt < options.max_attempts {
+                    trace!("Retrying transaction after {:?}", error);
+                    Ok(Loop::Continue(attempt + 1))
+                } else {
+                    Err(error)
+                }
+            })
+    })
+    .into_box()
+}
+
+// Apply the actions of a transaction. A request which timed out may still have been applied, so
+// it's resubmitted unchanged rather than running the transaction again, which would apply it
+// twice. A conflict following a timeout means the actions were applied if the entries are now as
+// they leave them. Other conflicts are returned, so the transaction is run again.
+fn apply(
+    client: &impl Client,
+    address: MDataAddress,
+    actions: MDataSeqEntryActions,
+) -> Box<CoreFuture<()>> {
+    let client = client.clone();
+
+    future::loop_fn(0, move |attempts| {
+        let client2 = client.clone();
+        let actions2 = actions.clone();
+
+        client
+            .mutate_seq_mdata_entries(*address.name(), address.tag(), actions.clone())
+            .then(move |result| match result {
+                Ok(()) => ok!(Loop::Break(())),
+                Err(CoreError::RequestTimeout) if attempts < MAX_ATTEMPTS => {
+                    ok!(Loop::Continue(attempts + 1))
+                }
+                Err(error) if attempts > 0 && recovery::is_entry_conflict(&error) => client2
+                    .list_seq_mdata_entries(*address.name(), address.tag())
+                    .and_then(move |entries| {
+                        if is_applied(&actions2, &entries) {
+                            Ok(Loop::Break(()))
+                        } else {
+                            Err(error)
+                        }
+                    })
+                    .into_box(),
+                Err(error) => err!(error),
+            })
+    })
+    .into_box()
+}
+
+// Whether the entries are as the actions leave them.
+fn is_applied(actions: &MDataSeqEntryActions, entries: &MDataSeqEntries) -> bool {
+    actions
+        .actions()
+        .iter()
+        .all(|(key, action)| match (action, entries.get(key)) {
+            (MDataSeqEntryAction::Ins(value), Some(current))
+            | (MDataSeqEntryAction::Update(value), Some(current)) => current == value,
+            (MDataSeqEntryAction::Del(_), None) => true,
+            _ => false,
+        })
+}
+
+// Set the versions of updates and deletions of entries in the snapshot to the successors of their
+// versions in the snapshot.
+fn set_versions(actions: MDataSeqEntryActions, snapshot: &MDataSeqEntries) -> MDataSeqEntryActions {
+    actions
+        .into_actions()
+        .into_iter()
+        .map(|(key, action)| {
+            let action = match (action, snapshot.get(&key)) {
+                (MDataSeqEntryAction::Update(value), Some(current)) => {
+                    MDataSeqEntryAction::Update(MDataSeqValue {
+                        data: value.data,
+                        version: current.version + 1,
+                    })
+                }
+                (MDataSeqEntryAction::Del(_), Some(current)) => {
+                    MDataSeqEntryAction::Del(current.version + 1)
+                }
+                (action, _) => action,
+            };
+            (key, action)
+        })
+        .collect::<BTreeMap<_, _>>()
+        .into()
+}
+
+// Add updates of the entries in the snapshot which aren't mutated by the actions, keeping their
+// values, so the actions only apply if none of the read entries changed.
+fn validate_reads(
+    actions: MDataSeqEntryActions,
+    snapshot: &MDataSeqEntries,
+) -> MDataSeqEntryActions {
+    let mut actions = actions.into_actions();
+    for (key, current) in snapshot {
+        if !actions.contains_key(key) {
+            let _ = actions.insert(
+                key.clone(),
+                MDataSeqEntryAction::Update(MDataSeqValue {
+                    data: current.data.clone(),
+                    version: current.version + 1,
+                }),
+            );
+        }
+    }
+    actions.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test setting the versions of entry actions from the snapshot.
+    #[test]
+    fn test_set_versions() {
+        let snapshot = btree_map![
+            vec![0] => MDataSeqValue { data: vec![0], version: 3 },
+            vec![1] => MDataSeqValue { data: vec![1], version: 5 }
+        ];
+        let actions = MDataSeqEntryActions::new()
+            .update(vec![0], vec![10], 0)
+            .del(vec![1], 0)
+            .ins(vec![2], vec![12], 0)
+            .update(vec![3], vec![13], 7);
+
+        let actions = set_versions(actions, &snapshot).into_actions();
+
+        assert_eq!(
+            actions[&vec![0]],
+            MDataSeqEntryAction::Update(MDataSeqValue {
+                data: vec![10],
+                version: 4,
+            })
+        );
+        assert_eq!(actions[&vec![1]], MDataSeqEntryAction::Del(6));
+        assert_eq!(
+            actions[&vec![2]],
+            MDataSeqEntryAction::Ins(MDataSeqValue {
+                data: vec![12],
+                version: 0,
+            })
+        );
+        // Entries not in the snapshot are left untouched.
+        assert_eq!(
+            actions[&vec![3]],
+            MDataSeqEntryAction::Update(MDataSeqValue {
+                data: vec![13],
+                version: 7,
+            })
+        );
+    }
+
+    // Test adding updates of read entries which the actions don't mutate.
+    #[test]
+    fn test_validate_reads() {
+        let snapshot = btree_map![
+            vec![0] => MDataSeqValue { data: vec![0], version: 3 },
+            vec![1] => MDataSeqValue { data: vec![1], version: 5 }
+        ];
+        let actions = MDataSeqEntryActions::new().del(vec![1], 6);
+
+        let actions = validate_reads(actions, &snapshot).into_actions();
+
+        assert_eq!(actions.len(), 2);
+        assert_eq!(
+            actions[&vec![0]],
+            MDataSeqEntryAction::Update(MDataSeqValue {
+                data: vec![0],
+                version: 4,
+            })
+        );
+        assert_eq!(actions[&vec![1]], MDataSeqEntryAction::Del(6));
+    }
+
+    // Test checking whether the entries are as the actions leave them.
+    #[test]
+    fn test_is_applied() {
+        let actions = MDataSeqEntryActions::new()
+            .ins(vec![0], vec![10], 0)
+            .update(vec![1], vec![11], 4)
+            .del(vec![2], 2);
+
+        let entries = btree_map![
+            vec![0] => MDataSeqValue { data: vec![10], version: 0 },
+            vec![1] => MDataSeqValue { data: vec![11], version: 4 }
+        ];
+        assert!(is_applied(&actions, &entries));
+
+        // Updated by someone else to the same value.
+        let entries = btree_map![
+            vec![0] => MDataSeqValue { data: vec![10], version: 0 },
+            vec![1] => MDataSeqValue { data: vec![11], version: 5 }
+        ];
+        assert!(!is_applied(&actions, &entries));
+
+        // Not deleted.
+        let entries = btree_map![
+            vec![0] => MDataSeqValue { data: vec![10], version: 0 },
+            vec![1] => MDataSeqValue { data: vec![11], version: 4 },
+            vec![2] => MDataSeqValue { data: vec![2], version: 1 }
+        ];
+        assert!(!is_applied(&actions, &entries));
+    }
+}
+
+#[cfg(all(test, feature = "mock-network"))]
+mod tests_with_mock_routing {
+    use super::*;
+    use crate::utils::test_utils::random_client;
+    use safe_nd::{Error as SndError, SeqMutableData};
+
+    fn put_data(client: &impl Client) -> Box<CoreFuture<MDataAddress>> {
+        let entries = btree_map![
+            vec![0] => MDataSeqValue { data: vec![10], version: 0 },
+            vec![1] => MDataSeqValue { data: vec![20], version: 0 }
+        ];
+        let data = SeqMutableData::new_with_data(
+            rand::random(),
+            10_000,
+            entries,
+            Default::default(),
+            client.public_key(),
+        );
+        let address = *data.address();
+
+        client
+            .put_seq_mutable_data(data)
+            .map(move |()| address)
+            .into_box()
+    }
+
+    // Test moving a value between two entries, with a concurrent change of one of them on the
+    // first attempt.
+    #[test]
+    fn transfer_with_conflict() {
+        random_client(|client| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+
+            put_data(client)
+                .then(move |res| {
+                    let address = unwrap!(res);
+                    let keys = btree_set![vec![0], vec![1]];
+                    let options = TransactionOptions {
+                        strict: true,
+                        ..Default::default()
+                    };
+
+                    run(&client2, address, keys, options, |snapshot| {
+                        let from = unwrap!(snapshot.get(&[0]));
+                        let to = unwrap!(snapshot.get(&[1]));
+                        // Pretend the first entry was changed concurrently on the first attempt,
+                        // by using a stale version.
+                        let version = if snapshot.attempt() == 1 {
+                            from.version
+                        } else {
+                            from.version + 1
+                        };
+
+                        let actions = MDataSeqEntryActions::new()
+                            .update(vec![0], vec![from.data[0] - 5], version)
+                            .update(vec![1], vec![to.data[0] + 5], to.version + 1);
+                        Ok((actions, snapshot.attempt()))
+                    })
+                    .map(move |attempts| (address, attempts))
+                })
+                .then(move |res| {
+                    let (address, attempts) = unwrap!(res);
+                    assert_eq!(attempts, 2);
+
+                    client3.list_seq_mdata_entries(*address.name(), address.tag())
+                })
+                .map(|entries| {
+                    // Both entries were changed together.
+                    assert_eq!(entries[&vec![0]].data, vec![5]);
+                    assert_eq!(entries[&vec![1]].data, vec![25]);
+                    assert_eq!(entries[&vec![0]].version, 1);
+                    assert_eq!(entries[&vec![1]].version, 1);
+                })
+        });
+    }
+
+    // Test that a transaction gives up after the maximum number of attempts, without changing
+    // the versions of its actions in strict mode.
+    #[test]
+    fn strict_attempts_bounded() {
+        random_client(|client| {
+            let client2 = client.clone();
+
+            put_data(client).then(move |res| {
+                let address = unwrap!(res);
+                let options = TransactionOptions {
+                    max_attempts: 3,
+                    strict: true,
+                    ..Default::default()
+                };
+                let mut runs = 0;
+
+                run(
+                    &client2,
+                    address,
+                    btree_set![vec![0]],
+                    options,
+                    move |snapshot| {
+                        runs += 1;
+                        assert_eq!(snapshot.attempt(), runs);
+
+                        // The version is never the successor of the current version.
+                        let actions = MDataSeqEntryActions::new().update(vec![0], vec![0], 5);
+                        Ok((actions, ()))
+                    },
+                )
+                .then(|res| -> Result<_, CoreError> {
+                    match res {
+                        Err(CoreError::DataError(SndError::InvalidEntryActions(_))) => Ok(()),
+                        res => panic!("Unexpected result {:?}", res),
+                    }
+                })
+            })
+        });
+    }
+}
@@ -8,7 +8,12 @@
 
 mod connection_group;
 
-use crate::{client::SafeKey, event::NetworkEvent, event::NetworkTx, CoreError, CoreFuture};
+use crate::{
+    client::{MDataEntriesPage, SafeKey},
+    event::NetworkEvent,
+    event::NetworkTx,
+    CoreError, CoreFuture,
+};
 use connection_group::ConnectionGroup;
 use futures::{future, Future};
 use quic_p2p::Config as QuicP2pConfig;
@@ -56,6 +61,24 @@ impl ConnectionManager {
         self.inner.borrow_mut().send(pub_id, msg)
     }
 
+    /// Send a `ListMDataEntries` request `msg`, receiving only the page of entries following the
+    /// key `cursor`.
+    ///
+    /// The network protocol doesn't support paging yet, so all the entries are received and the
+    /// page is taken from them locally. Iterating all the pages this way fetches all the entries
+    /// for every page, which is why `paging::seq_entries_stream` only fetches them once.
+    pub fn list_mdata_entries_page(
+        &mut self,
+        pub_id: &PublicId,
+        msg: &Message,
+        cursor: Option<Vec<u8>>,
+        limit: usize,
+    ) -> Box<CoreFuture<MDataEntriesPage>> {
+        Box::new(self.send(pub_id, msg).and_then(move |response| {
+            MDataEntriesPage::from_response(response, cursor.as_ref().map(Vec::as_slice), limit)
+        }))
+    }
+
     /// Connect to Client Handlers that manage the provided ID.
     pub fn bootstrap(&mut self, full_id: SafeKey) -> Box<CoreFuture<()>> {
         self.inner.borrow_mut().bootstrap(full_id)
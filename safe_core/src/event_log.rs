@@ -0,0 +1,300 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Encrypted append-only event log over unpublished sequenced append-only data.
+//!
+//! Every event is stored as a single entry, keyed by its index in the log (as big-endian bytes) so
+//! that keys are unique, with the event encrypted with the key of the log, if any. Appends read
+//! the current number of entries and are retried with the reported index when another client
+//! appends concurrently. As the key depends on the index, a timed out append is not simply
+//! resubmitted: it may have been applied, so the entry at its index is checked first.
+
+use crate::client::recovery::MAX_ATTEMPTS;
+use crate::client::Client;
+use crate::crypto::shared_secretbox;
+use crate::errors::CoreError;
+use crate::event_loop::CoreFuture;
+use crate::utils::{symmetric_decrypt, symmetric_encrypt, FutureExt};
+use futures::future::{self, Loop};
+use futures::Future;
+use safe_nd::{
+    AData, ADataAddress, ADataAppendOperation, ADataEntry, ADataIndex, ADataOwner,
+    ADataUnpubPermissionSet, ADataUnpubPermissions, AppendOnlyData, Error as SndError,
+    UnpubSeqAppendOnlyData, XorName,
+};
+use std::collections::BTreeMap;
+
+/// Append-only log of encrypted events.
+pub struct EventLog<C: Client> {
+    client: C,
+    address: ADataAddress,
+    enc_key: Option<shared_secretbox::Key>,
+}
+
+impl<C: Client> EventLog<C> {
+    /// Create a log over the existing append-only data. Events are encrypted with `enc_key`, or
+    /// stored in plain text if it's `None`.
+    pub fn new(client: C, name: XorName, tag: u64, enc_key: Option<shared_secretbox::Key>) -> Self {
+        Self {
+            client,
+            address: ADataAddress::UnpubSeq { name, tag },
+            enc_key,
+        }
+    }
+
+    /// Put an empty append-only data owned by the owner of the client on the network, with the
+    /// key of the client allowed to read and append, and create a log over it.
+    pub fn create(
+        client: C,
+        name: XorName,
+        tag: u64,
+        enc_key: Option<shared_secretbox::Key>,
+    ) -> Box<CoreFuture<Self>> {
+        let mut data = UnpubSeqAppendOnlyData::new(name, tag);
+        let mut permissions = BTreeMap::new();
+        let _ = permissions.insert(
+            client.public_key(),
+            ADataUnpubPermissionSet::new(true, true, false),
+        );
+        let permissions = ADataUnpubPermissions {
+            permissions,
+            entries_index: 0,
+            owners_index: 0,
+        };
+        let owner = ADataOwner {
+            public_key: client.owner_key(),
+            entries_index: 0,
+            permissions_index: 1,
+        };
+        fry!(data.append_permissions(permissions, 0));
+        fry!(data.append_owner(owner, 0));
+
+        client
+            .put_adata(AData::UnpubSeq(data))
+            .map(move |()| Self::new(client, name, tag, enc_key))
+            .into_box()
+    }
+
+    /// Returns the address of the append-only data holding the log.
+    pub fn address(&self) -> &ADataAddress {
+        &self.address
+    }
+
+    /// Returns the number of events in the log, which is also the index of the next event.
+    pub fn count(&self) -> Box<CoreFuture<u64>> {
+        self.client
+            .get_adata_indices(self.address)
+            .map(|indices| indices.entries_index())
+            .into_box()
+    }
+
+    /// Append the event, returning its index. If other events are appended concurrently, the
+    /// event is appended after them.
+    ///
+    /// If an append times out, the event is looked up at the index it was appended at before
+    /// trying again, so it's not appended twice. Encrypted events are unique, but without an
+    /// encryption key, an identical event appended at that index by another client can't be told
+    /// apart, in which case the event is considered appended.
+    pub fn append(&self, event: &[u8]) -> Box<CoreFuture<u64>> {
+        let value = fry!(encrypt(self.enc_key.as_ref(), event));
+        let client = self.client.clone();
+        let address = self.address;
+
+        // The state holds the index to append at, if known, and the index at which the previous
+        // attempt timed out, if it did.
+        future::loop_fn((0, None, None), move |(attempts, index, timed_out)| {
+            let client2 = client.clone();
+            let client3 = client.clone();
+            let value = value.clone();
+
+            let appended = match timed_out {
+                Some(timed_out) => {
+                    let value = value.clone();
+                    get_value(&client, address, timed_out)
+                        .map(move |current| match current {
+                            Some(ref current) if *current == value => Some(timed_out),
+                            _ => None,
+                        })
+                        .into_box()
+                }
+                None => ok!(None),
+            };
+
+            appended.and_then(move |appended| {
+                if let Some(index) = appended {
+                    return ok!(Loop::Break(index));
+                }
+
+                let index = match index {
+                    Some(index) => ok!(index),
+                    None => client2
+                        .get_adata_indices(address)
+                        .map(|indices| indices.entries_index())
+                        .into_box(),
+                };
+
+                index
+                    .and_then(move |index| {
+                        let append = ADataAppendOperation {
+                            address,
+                            values: vec![ADataEntry::new(index.to_be_bytes().to_vec(), value)],
+                        };
+
+                        client3
+                            .append_seq_adata(append, index)
+                            .map(move |()| Loop::Break(index))
+                            .or_else(move |error| match error {
+                                CoreError::DataError(SndError::InvalidSuccessor(current))
+                                    if attempts < MAX_ATTEMPTS =>
+                                {
+                                    Ok(Loop::Continue((attempts + 1, Some(current), None)))
+                                }
+                                CoreError::RequestTimeout if attempts < MAX_ATTEMPTS => {
+                                    Ok(Loop::Continue((attempts + 1, Some(index), Some(index))))
+                                }
+                                error => Err(error),
+                            })
+                    })
+                    .into_box()
+            })
+        })
+        .into_box()
+    }
+
+    /// Read the decrypted events with indices from `start` (inclusive) to `end` (exclusive).
+    /// Fails with `NoSuchEntry` if the log has fewer than `end` events.
+    pub fn read_range(&self, start: u64, end: u64) -> Box<CoreFuture<Vec<Vec<u8>>>> {
+        read_range(&self.client, self.address, self.enc_key.clone(), start, end)
+    }
+
+    /// Read the decrypted events appended at or after index `from`, along with their indices.
+    /// Returns an empty list if there are none, so this can be polled with the index following
+    /// the last event read.
+    pub fn tail(&self, from: u64) -> Box<CoreFuture<Vec<(u64, Vec<u8>)>>> {
+        let client = self.client.clone();
+        let address = self.address;
+        let enc_key = self.enc_key.clone();
+
+        self.count()
+            .and_then(move |count| {
+                read_range(&client, address, enc_key, from, count)
+                    .map(move |events| (from..).zip(events).collect())
+            })
+            .into_box()
+    }
+}
+
+fn read_range(
+    client: &impl Client,
+    address: ADataAddress,
+    enc_key: Option<shared_secretbox::Key>,
+    start: u64,
+    end: u64,
+) -> Box<CoreFuture<Vec<Vec<u8>>>> {
+    if start >= end {
+        return ok!(Vec::new());
+    }
+
+    client
+        .get_adata_range(
+            address,
+            (ADataIndex::FromStart(start), ADataIndex::FromStart(end)),
+        )
+        .and_then(move |entries| {
+            entries
+                .iter()
+                .map(|entry| decrypt(enc_key.as_ref(), &entry.value))
+                .collect()
+        })
+        .into_box()
+}
+
+// Get the value of the entry with the given index, or `None` if there is no such entry yet.
+fn get_value(
+    client: &impl Client,
+    address: ADataAddress,
+    index: u64,
+) -> Box<CoreFuture<Option<Vec<u8>>>> {
+    client
+        .get_adata_value(address, index.to_be_bytes().to_vec())
+        .then(|result| match result {
+            Ok(value) => Ok(Some(value)),
+            Err(CoreError::DataError(SndError::NoSuchEntry)) => Ok(None),
+            Err(error) => Err(error),
+        })
+        .into_box()
+}
+
+fn encrypt(enc_key: Option<&shared_secretbox::Key>, event: &[u8]) -> Result<Vec<u8>, CoreError> {
+    match enc_key {
+        Some(key) => symmetric_encrypt(event, key, None),
+        None => Ok(event.to_vec()),
+    }
+}
+
+fn decrypt(enc_key: Option<&shared_secretbox::Key>, value: &[u8]) -> Result<Vec<u8>, CoreError> {
+    match enc_key {
+        Some(key) => symmetric_decrypt(value, key),
+        None => Ok(value.to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::random_client;
+
+    // Test appending events, including concurrently, and reading them back.
+    #[test]
+    fn append_and_read() {
+        random_client(|client| {
+            let client2 = client.clone();
+            let name: XorName = rand::random();
+
+            EventLog::create(
+                client.clone(),
+                name,
+                15_030,
+                Some(shared_secretbox::gen_key()),
+            )
+            .and_then(|log| log.append(b"first").map(move |index| (log, index)))
+            .and_then(|(log, index)| {
+                assert_eq!(index, 0);
+
+                // Both appends expect index 1, so one of them is retried.
+                log.append(b"second")
+                    .join(log.append(b"third"))
+                    .map(move |indices| (log, indices))
+            })
+            .and_then(|(log, (second, third))| {
+                let mut indices = vec![second, third];
+                indices.sort();
+                assert_eq!(indices, vec![1, 2]);
+
+                log.read_range(0, 3)
+                    .join(log.tail(2))
+                    .join(log.tail(3))
+                    .map(move |results| (log, results))
+            })
+            .and_then(move |(log, ((events, tail), empty))| {
+                assert_eq!(events.len(), 3);
+                assert_eq!(events[0], b"first".to_vec());
+                assert_eq!(tail, vec![(2, events[2].clone())]);
+                assert!(empty.is_empty());
+
+                // The stored events are encrypted.
+                client2.get_adata_last_entry(*log.address())
+            })
+            .map(|entry| {
+                assert_eq!(entry.key, 2u64.to_be_bytes().to_vec());
+                assert_ne!(entry.value, b"second".to_vec());
+                assert_ne!(entry.value, b"third".to_vec());
+            })
+        });
+    }
+}
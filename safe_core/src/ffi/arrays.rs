@@ -23,5 +23,8 @@ pub type BlsPublicKey = [u8; BLS_PUBLIC_KEY_LEN];
 /// Array containing a BLS Signature.
 pub type Signature = [u8; SIG_SIZE];
 
+/// Array containing a SHA3-256 hash.
+pub type Sha3Hash = [u8; 32];
+
 /// Array containing `XorName` bytes.
 pub type XorNameArray = [u8; XOR_NAME_LEN];
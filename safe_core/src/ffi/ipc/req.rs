@@ -183,3 +183,42 @@ impl Drop for ShareMDataReq {
         }
     }
 }
+
+#[repr(C)]
+/// Represents a mutable or append-only data item.
+pub struct DataItem {
+    /// The data name.
+    pub name: XorNameArray,
+    /// The data type.
+    pub type_tag: u64,
+    /// Whether the data is mutable data, or append-only data otherwise.
+    pub is_mdata: bool,
+    /// Whether the append-only data is published. Always `false` for mutable data.
+    pub is_pub: bool,
+    /// Whether the data is sequenced.
+    pub is_seq: bool,
+}
+
+#[repr(C)]
+/// Represents a request to transfer the ownership of data items to another key.
+pub struct TransOwnershipReq {
+    /// Info about the app requesting the transfer
+    pub app: AppExchangeInfo,
+    /// Serialised public key of the new owner
+    pub new_owner: *const u8,
+    /// Length of the serialised public key
+    pub new_owner_len: usize,
+    /// List of data items being transferred
+    pub items: *const DataItem,
+    /// Length of the items array
+    pub items_len: usize,
+}
+
+impl Drop for TransOwnershipReq {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = vec_from_raw_parts(self.new_owner as *mut u8, self.new_owner_len);
+            let _ = vec_from_raw_parts(self.items as *mut DataItem, self.items_len);
+        }
+    }
+}
@@ -49,6 +49,23 @@ pub struct MDataInfo {
     pub new_enc_nonce: SymNonce,
 }
 
+/// FFI wrapper for an entry of append-only data, along with its index.
+///
+/// The key and value are only valid for the duration of the callback receiving the entry.
+#[repr(C)]
+pub struct ADataEntry {
+    /// Index of the entry.
+    pub index: u64,
+    /// Key of the entry.
+    pub key: *const u8,
+    /// Length of the key.
+    pub key_len: usize,
+    /// Value of the entry.
+    pub value: *const u8,
+    /// Length of the value.
+    pub value_len: usize,
+}
+
 // TODO: Implement `into_repr_c` for MDataKind once we move FfiMDataKind to safe-nd.
 /// Convert from native to FFI representation for MDataKind.
 pub fn md_kind_into_repr_c(kind: NativeMDataKind) -> bool {
@@ -6,8 +6,12 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use crate::arrays::XorNameArray;
+use crate::arrays::{Sha3Hash, XorNameArray};
+use crate::ffi::MDataInfo;
 use ffi_utils::vec_from_raw_parts;
+use std::ffi::CString;
+use std::os::raw::c_char;
+
 /// FFI-wrapper for `File`.
 #[repr(C)]
 pub struct File {
@@ -29,11 +33,193 @@ pub struct File {
     pub data_map_name: XorNameArray,
     /// Published status of the file
     pub published: bool,
+    /// UTF-8 encoded MIME type of the content.
+    ///
+    /// null if not known.
+    pub mime_type: *const c_char,
+    /// SHA3-256 hash of the content. Only valid if `has_content_hash` is set.
+    pub content_hash: Sha3Hash,
+    /// Whether the hash of the content is known.
+    pub has_content_hash: bool,
+    /// Pointer to the extended attributes, sorted by name.
+    pub xattrs: *const FileXattr,
+    /// Number of the extended attributes.
+    pub xattrs_len: usize,
 }
 
 impl Drop for File {
     fn drop(&mut self) {
-        let _ =
-            unsafe { vec_from_raw_parts(self.user_metadata as *mut u8, self.user_metadata_len) };
+        unsafe {
+            let _ = vec_from_raw_parts(self.user_metadata as *mut u8, self.user_metadata_len);
+            if !self.mime_type.is_null() {
+                let _ = CString::from_raw(self.mime_type as *mut _);
+            }
+            let _ = vec_from_raw_parts(self.xattrs as *mut FileXattr, self.xattrs_len);
+        }
+    }
+}
+
+/// Extended attribute holding a boolean, encoded as a single byte which is either 0 or 1.
+pub const XATTR_KIND_BOOL: u64 = 0;
+/// Extended attribute holding a signed 64-bit integer, encoded in 8 big-endian bytes.
+pub const XATTR_KIND_INT: u64 = 1;
+/// Extended attribute holding UTF-8 encoded text.
+pub const XATTR_KIND_TEXT: u64 = 2;
+/// Extended attribute holding arbitrary bytes.
+pub const XATTR_KIND_BYTES: u64 = 3;
+
+/// FFI-wrapper for an extended attribute of a file.
+#[repr(C)]
+pub struct FileXattr {
+    /// UTF-8 encoded name of the attribute.
+    pub name: *const c_char,
+    /// Kind of the value, one of the `XATTR_KIND_*` constants.
+    pub kind: u64,
+    /// Pointer to the encoded value.
+    pub value: *const u8,
+    /// Size of the encoded value.
+    pub value_len: usize,
+}
+
+impl Drop for FileXattr {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CString::from_raw(self.name as *mut _);
+            let _ = vec_from_raw_parts(self.value as *mut u8, self.value_len);
+        }
+    }
+}
+
+/// Options controlling which files are listed in a directory and in which order.
+#[repr(C)]
+pub struct ListOptions {
+    /// Field to sort the files by.
+    pub sort_by: u64,
+    /// Sort in descending instead of ascending order.
+    pub descending: bool,
+    /// UTF-8 encoded name prefix the listed files must start with.
+    ///
+    /// null if all files should be listed.
+    pub prefix: *const c_char,
+    /// Number of matching files to skip.
+    pub offset: u64,
+    /// Maximum number of files to return, or 0 to return all of them.
+    pub limit: u64,
+}
+
+/// FFI-wrapper for a file listed in a directory.
+#[repr(C)]
+pub struct FileEntry {
+    /// UTF-8 encoded name of the file.
+    pub name: *const c_char,
+    /// Version of the directory entry holding the file.
+    pub version: u64,
+    /// The file itself.
+    pub file: File,
+}
+
+impl Drop for FileEntry {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CString::from_raw(self.name as *mut _);
+        }
+    }
+}
+
+/// FFI-wrapper for a previous version of a file, from its version history.
+#[repr(C)]
+pub struct FileVersion {
+    /// Index of this version in the history of the file, starting from zero for the oldest one.
+    pub version: u64,
+    /// The file itself.
+    pub file: File,
+}
+
+/// A file was added to the watched directory.
+pub const DIR_EVENT_ADDED: u64 = 0;
+/// A file in the watched directory was updated.
+pub const DIR_EVENT_UPDATED: u64 = 1;
+/// A file was removed from the watched directory.
+pub const DIR_EVENT_REMOVED: u64 = 2;
+
+/// FFI-wrapper for a change of a file in a watched directory.
+#[repr(C)]
+pub struct DirEvent {
+    /// Kind of the change, one of the `DIR_EVENT_*` constants.
+    pub kind: u64,
+    /// UTF-8 encoded name of the file.
+    pub name: *const c_char,
+    /// Version of the directory entry holding the file. 0 if the file was removed.
+    pub version: u64,
+    /// The file itself.
+    ///
+    /// null if the file was removed.
+    pub file: *const File,
+}
+
+impl Drop for DirEvent {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CString::from_raw(self.name as *mut _);
+            if !self.file.is_null() {
+                let _ = Box::from_raw(self.file as *mut File);
+            }
+        }
+    }
+}
+
+/// FFI-wrapper for the storage used by a set of directory entries.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Usage {
+    /// Number of entries, including symbolic links.
+    pub entries: u64,
+    /// Number of files.
+    pub files: u64,
+    /// Total size of the file contents, in bytes.
+    pub size: u64,
+    /// Number of chunks holding the file contents.
+    pub chunks: u64,
+}
+
+/// FFI-wrapper for a file in the trash.
+#[repr(C)]
+pub struct TrashItem {
+    /// UTF-8 encoded ID of the item.
+    pub id: *const c_char,
+    /// Directory the file was deleted from.
+    pub dir: MDataInfo,
+    /// UTF-8 encoded name of the file in the directory it was deleted from.
+    pub name: *const c_char,
+    /// Time the file was deleted, seconds part.
+    pub deleted_sec: i64,
+    /// Time the file was deleted, nanoseconds part.
+    pub deleted_nsec: u32,
+    /// The file itself.
+    pub file: File,
+}
+
+impl Drop for TrashItem {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CString::from_raw(self.id as *mut _);
+            let _ = CString::from_raw(self.name as *mut _);
+        }
     }
 }
+
+/// FFI-wrapper for the outcome of publishing a file.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct PublishReport {
+    /// Version of the directory entry holding the published file.
+    pub version: u64,
+    /// Number of chunks copied into published immutable data.
+    pub chunks: u64,
+    /// Number of mutations performed.
+    pub mutations: u64,
+    /// Cost of the mutations, in nano coins.
+    pub cost: u64,
+    /// Number of unpublished chunks and data maps deleted afterwards.
+    pub deleted: u64,
+}
@@ -36,8 +36,12 @@ pub enum IpcError {
     UnknownApp,
     /// User denied request for shared access to MD.
     ShareMDataDenied,
+    /// User denied request to transfer the ownership of data.
+    TransOwnershipDenied,
     /// Requested shared access to non-owned MD.
     InvalidOwner(Vec<(XorName, u64)>),
+    /// Requested the transfer of data items whose owner can't be changed.
+    UnsupportedDataItem(Vec<(XorName, u64)>),
     /// Message sent between mock and non-mock versions.
     IncompatibleMockStatus,
 
@@ -16,6 +16,7 @@ mod errors;
 pub use self::errors::IpcError;
 pub use self::req::{
     AppExchangeInfo, AuthReq, ContainersReq, IpcReq, Permission, ShareMData, ShareMDataReq,
+    TransOwnershipReq,
 };
 pub use self::resp::{
     access_container_enc_key, AccessContInfo, AccessContainerEntry, AppKeys, AuthGranted, IpcResp,
@@ -11,10 +11,12 @@
 mod auth;
 mod containers;
 mod share_mdata;
+mod trans_ownership;
 
 pub use self::auth::AuthReq;
 pub use self::containers::ContainersReq;
 pub use self::share_mdata::{ShareMData, ShareMDataReq};
+pub use self::trans_ownership::TransOwnershipReq;
 
 use crate::ffi::ipc::req::{
     AppExchangeInfo as FfiAppExchangeInfo, ContainerPermissions as FfiContainerPermissions,
@@ -48,7 +50,6 @@ pub enum Permission {
 pub type ContainerPermissions = BTreeSet<Permission>;
 
 /// IPC request.
-// TODO: `TransOwnership` variant
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub enum IpcReq {
     /// Authentication request.
@@ -60,6 +61,8 @@ pub enum IpcReq {
     Unregistered(Vec<u8>),
     /// Share mutable data.
     ShareMData(ShareMDataReq),
+    /// Transfer the ownership of data.
+    TransOwnership(TransOwnershipReq),
 }
 
 /// Consumes the object and returns the wrapped raw pointer.
@@ -300,9 +303,10 @@ impl ReprC for AppExchangeInfo {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::client::roles::DataItem;
     use crate::ffi::ipc::req::PermissionSet as FfiPermissionSet;
     use ffi_utils::ReprC;
-    use safe_nd::MDataAction;
+    use safe_nd::{ADataAddress, MDataAction, MDataAddress, PublicKey};
     use std::collections::HashMap;
     use std::ffi::CStr;
 
@@ -438,6 +442,48 @@ mod tests {
         assert_eq!(a.containers.len(), 0);
     }
 
+    // Test converting a `TransOwnershipReq` object to its FFI representation and back again.
+    #[test]
+    fn trans_ownership_req() {
+        let app = AppExchangeInfo {
+            id: "1".to_string(),
+            scope: None,
+            name: "3".to_string(),
+            vendor: "4".to_string(),
+        };
+        let new_owner = PublicKey::from(threshold_crypto::SecretKey::random().public_key());
+        let items = vec![
+            DataItem::MData(MDataAddress::Unseq {
+                name: rand::random(),
+                tag: 15_000,
+            }),
+            DataItem::AData(ADataAddress::PubSeq {
+                name: rand::random(),
+                tag: 15_001,
+            }),
+            DataItem::AData(ADataAddress::UnpubUnseq {
+                name: rand::random(),
+                tag: 15_002,
+            }),
+        ];
+
+        let a = TransOwnershipReq {
+            app,
+            new_owner,
+            items: items.clone(),
+        };
+
+        let ffi = unwrap!(a.into_repr_c());
+
+        assert_eq!(ffi.items_len, 3);
+
+        let a = unsafe { unwrap!(TransOwnershipReq::clone_from_repr_c(&ffi)) };
+
+        assert_eq!(a.app.id, "1");
+        assert_eq!(a.new_owner, new_owner);
+        assert_eq!(a.items, items);
+    }
+
     // Test converting a `ContainersReq` object to its FFI representation and back again.
     #[test]
     fn containers_req() {
@@ -0,0 +1,114 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::AppExchangeInfo;
+use crate::client::roles::DataItem;
+use crate::ffi::ipc::req as ffi;
+use crate::ipc::errors::IpcError;
+use bincode::{deserialize, serialize};
+use ffi_utils::{vec_clone_from_raw_parts, vec_into_raw_parts, ReprC};
+use safe_nd::{ADataAddress, MDataAddress, PublicKey, XorName};
+use serde::{Deserialize, Serialize};
+use std::slice;
+
+/// Represents a request to transfer the ownership of data items of the user to another key.
+///
+/// Only append-only data can be transferred, as the network has no request to change the owner
+/// of mutable data. Requests including mutable data are rejected as a whole.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TransOwnershipReq {
+    /// Info about the app requesting the transfer.
+    pub app: AppExchangeInfo,
+    /// Key of the new owner. Can be another user, or the user's own key to move data created
+    /// with an app key under the user's account.
+    pub new_owner: PublicKey,
+    /// List of data items being transferred.
+    pub items: Vec<DataItem>,
+}
+
+impl TransOwnershipReq {
+    /// Construct FFI wrapper for the native Rust object, consuming self.
+    pub fn into_repr_c(self) -> Result<ffi::TransOwnershipReq, IpcError> {
+        let (new_owner, new_owner_len) = vec_into_raw_parts(serialize(&self.new_owner)?);
+        let items_repr_c: Vec<_> = self.items.into_iter().map(DataItem::into_repr_c).collect();
+        let (items, items_len) = vec_into_raw_parts(items_repr_c);
+
+        Ok(ffi::TransOwnershipReq {
+            app: self.app.into_repr_c()?,
+            new_owner,
+            new_owner_len,
+            items,
+            items_len,
+        })
+    }
+}
+
+impl ReprC for TransOwnershipReq {
+    type C = *const ffi::TransOwnershipReq;
+    type Error = IpcError;
+
+    unsafe fn clone_from_repr_c(repr_c: Self::C) -> Result<Self, Self::Error> {
+        Ok(Self {
+            app: AppExchangeInfo::clone_from_repr_c(&(*repr_c).app)?,
+            new_owner: deserialize(&vec_clone_from_raw_parts(
+                (*repr_c).new_owner,
+                (*repr_c).new_owner_len,
+            ))?,
+            items: {
+                let items = slice::from_raw_parts((*repr_c).items, (*repr_c).items_len);
+                items
+                    .iter()
+                    .map(|c| DataItem::clone_from_repr_c(c))
+                    .collect::<Result<_, _>>()?
+            },
+        })
+    }
+}
+
+impl DataItem {
+    /// Construct FFI wrapper for the native Rust object, consuming self.
+    pub fn into_repr_c(self) -> ffi::DataItem {
+        match self {
+            DataItem::MData(address) => ffi::DataItem {
+                name: address.name().0,
+                type_tag: address.tag(),
+                is_mdata: true,
+                is_pub: false,
+                is_seq: address.is_seq(),
+            },
+            DataItem::AData(address) => ffi::DataItem {
+                name: address.name().0,
+                type_tag: address.tag(),
+                is_mdata: false,
+                is_pub: address.is_pub(),
+                is_seq: address.is_seq(),
+            },
+        }
+    }
+}
+
+impl ReprC for DataItem {
+    type C = *const ffi::DataItem;
+    type Error = IpcError;
+
+    unsafe fn clone_from_repr_c(repr_c: Self::C) -> Result<Self, Self::Error> {
+        let name = XorName((*repr_c).name);
+        let tag = (*repr_c).type_tag;
+
+        Ok(
+            match ((*repr_c).is_mdata, (*repr_c).is_pub, (*repr_c).is_seq) {
+                (true, _, true) => DataItem::MData(MDataAddress::Seq { name, tag }),
+                (true, _, false) => DataItem::MData(MDataAddress::Unseq { name, tag }),
+                (false, true, true) => DataItem::AData(ADataAddress::PubSeq { name, tag }),
+                (false, true, false) => DataItem::AData(ADataAddress::PubUnseq { name, tag }),
+                (false, false, true) => DataItem::AData(ADataAddress::UnpubSeq { name, tag }),
+                (false, false, false) => DataItem::AData(ADataAddress::UnpubUnseq { name, tag }),
+            },
+        )
+    }
+}
@@ -42,7 +42,6 @@ pub static METADATA_KEY: &[u8] = b"_metadata";
 pub static METADATA_KEY_LEN: usize = 9;
 
 /// IPC response.
-// TODO: `TransOwnership` variant
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum IpcResp {
@@ -54,6 +53,8 @@ pub enum IpcResp {
     Unregistered(Result<BootstrapConfig, IpcError>),
     /// Share mutable data.
     ShareMData(Result<(), IpcError>),
+    /// Transfer the ownership of data.
+    TransOwnership(Result<(), IpcError>),
 }
 
 /// It represents the authentication response.
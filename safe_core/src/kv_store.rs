@@ -0,0 +1,485 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Typed key-value store over sequenced mutable data.
+//!
+//! Keys and values are serialised with bincode and, if the `MDataInfo` of the store is private,
+//! encrypted with it. Every value is stored along with the version of its schema (see
+//! `KvValue::SCHEMA_VERSION`), so values stored by older versions of an app can be migrated when
+//! they are read. Entry versions are tracked by the store: mutations read the current version of
+//! the entry and are retried when the entry is mutated concurrently.
+
+use crate::client::paging;
+use crate::client::recovery::{self, MAX_ATTEMPTS};
+use crate::client::{Client, MDataInfo};
+use crate::errors::CoreError;
+use crate::event_loop::CoreFuture;
+use crate::utils::FutureExt;
+use bincode::{deserialize, serialize};
+use futures::future::{self, Loop};
+use futures::{Future, Stream};
+use safe_nd::{
+    Error as SndError, MDataAction, MDataPermissionSet, MDataSeqEntryActions, SeqMutableData,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+/// Value which can be stored in a `KvStore`.
+pub trait KvValue: Serialize + DeserializeOwned {
+    /// Version of the schema of the value, stored along with it. It should be increased whenever
+    /// the serialised form of the value changes.
+    const SCHEMA_VERSION: u32 = 0;
+
+    /// Decode a value stored with an older or newer schema version. Fails by default.
+    fn migrate(schema_version: u32, data: &[u8]) -> Result<Self, CoreError> {
+        let _ = data;
+        Err(CoreError::Unexpected(format!(
+            "Unsupported schema version {} of stored value",
+            schema_version
+        )))
+    }
+}
+
+// Serialised value along with the version of its schema.
+#[derive(Serialize, Deserialize)]
+struct Tagged {
+    schema_version: u32,
+    data: Vec<u8>,
+}
+
+// Mutation of an entry, with the value serialised but not yet encrypted.
+enum Write {
+    Keep,
+    Put(Vec<u8>),
+    Remove,
+}
+
+/// Typed key-value store over sequenced mutable data.
+pub struct KvStore<C: Client, K, V> {
+    client: C,
+    info: MDataInfo,
+    _types: PhantomData<(K, V)>,
+}
+
+impl<C, K, V> KvStore<C, K, V>
+where
+    C: Client,
+    K: Serialize + DeserializeOwned + 'static,
+    V: KvValue + 'static,
+{
+    /// Create a store over the existing mutable data.
+    pub fn new(client: C, info: MDataInfo) -> Self {
+        Self {
+            client,
+            info,
+            _types: PhantomData,
+        }
+    }
+
+    /// Put an empty mutable data owned by the owner of the client on the network and create a
+    /// store over it. The key of the client is allowed to read and mutate the entries, so apps
+    /// can use stores they create. Succeeds if the data already exists, leaving it unchanged.
+    pub fn create(client: C, info: MDataInfo) -> Box<CoreFuture<Self>> {
+        let permissions = btree_map![
+            client.public_key() => MDataPermissionSet::new()
+                .allow(MDataAction::Read)
+                .allow(MDataAction::Insert)
+                .allow(MDataAction::Update)
+                .allow(MDataAction::Delete)
+        ];
+        let data = SeqMutableData::new_with_data(
+            info.name(),
+            info.type_tag(),
+            BTreeMap::new(),
+            permissions,
+            client.owner_key(),
+        );
+
+        // Updating the permissions of existing data requires permission to manage them, which
+        // apps don't have, so existing data is used as it is.
+        client
+            .put_seq_mutable_data(data)
+            .or_else(|error| match error {
+                CoreError::DataError(SndError::DataExists) => Ok(()),
+                error => Err(error),
+            })
+            .map(move |()| Self::new(client, info))
+            .into_box()
+    }
+
+    /// Returns the `MDataInfo` of the mutable data holding the store.
+    pub fn info(&self) -> &MDataInfo {
+        &self.info
+    }
+
+    /// Get the value of the key, or `None` if the store doesn't contain the key.
+    pub fn get(&self, key: &K) -> Box<CoreFuture<Option<V>>> {
+        let key = fry!(encode_key(&self.info, key));
+
+        fetch(&self.client, &self.info, key)
+            .and_then(|entry| match entry {
+                Some((data, _)) => decode_value(&data).map(Some),
+                None => Ok(None),
+            })
+            .into_box()
+    }
+
+    /// Insert the value, replacing any existing value of the key.
+    pub fn insert(&self, key: &K, value: &V) -> Box<CoreFuture<()>> {
+        let key = fry!(encode_key(&self.info, key));
+        let value = fry!(encode_value(value).and_then(|data| self.info.enc_entry_value(&data)));
+        let actions = MDataSeqEntryActions::new().ins(key, value, 0);
+
+        recovery::mutate_mdata_entries(&self.client, *self.info.address(), actions)
+    }
+
+    /// Replace the existing value of the key with the value returned by `f`. If the value is
+    /// mutated concurrently, `f` is called again with the new value. Fails with `NoSuchEntry` if
+    /// the store doesn't contain the key.
+    pub fn update<F>(&self, key: &K, f: F) -> Box<CoreFuture<()>>
+    where
+        F: Fn(V) -> V + 'static,
+    {
+        self.modify(key, move |current| match current {
+            Some(value) => Ok((Write::Put(encode_value(&f(value))?), ())),
+            None => Err(CoreError::DataError(SndError::NoSuchEntry)),
+        })
+    }
+
+    /// Remove the key from the store, returning whether it was contained in the store.
+    pub fn remove(&self, key: &K) -> Box<CoreFuture<bool>> {
+        self.modify(key, |current| match current {
+            Some(_) => Ok((Write::Remove, true)),
+            None => Ok((Write::Keep, false)),
+        })
+    }
+
+    /// Replace the value of the key with `new` if its current value is `current`, where `None`
+    /// stands for the key not being contained in the store. Returns whether the value was
+    /// replaced.
+    pub fn compare_and_swap(
+        &self,
+        key: &K,
+        current: Option<V>,
+        new: Option<V>,
+    ) -> Box<CoreFuture<bool>>
+    where
+        V: PartialEq,
+    {
+        let new = match new {
+            Some(value) => Some(fry!(encode_value(&value))),
+            None => None,
+        };
+
+        self.modify(key, move |value| {
+            if value != current {
+                return Ok((Write::Keep, false));
+            }
+
+            match new {
+                Some(ref data) => Ok((Write::Put(data.clone()), true)),
+                None => Ok((Write::Remove, true)),
+            }
+        })
+    }
+
+    /// Iterate the entries of the store, fetching `page_size` entries at a time. The entries are
+    /// ordered by their encoded keys, which for private stores is unrelated to the order of the
+    /// keys themselves.
+    pub fn entries(&self, page_size: usize) -> Box<dyn Stream<Item = (K, V), Error = CoreError>> {
+        Box::new(
+            paging::seq_entries_stream(self.client.clone(), self.info.clone(), page_size).and_then(
+                |(key, value)| {
+                    let key = deserialize(&key)?;
+                    let value = decode_value(&value.data)?;
+                    Ok((key, value))
+                },
+            ),
+        )
+    }
+
+    // Read the current value of the key and apply the write returned by `change`, conditioned on
+    // the version read. If the entry is mutated concurrently, it is read again and `change` is
+    // called again with the new value, up to `MAX_ATTEMPTS` times. A write which timed out is
+    // resubmitted as it is instead, see `write_entry`.
+    fn modify<F, T>(&self, key: &K, change: F) -> Box<CoreFuture<T>>
+    where
+        F: Fn(Option<V>) -> Result<(Write, T), CoreError> + 'static,
+        T: 'static,
+    {
+        let key = fry!(encode_key(&self.info, key));
+        let client = self.client.clone();
+        let info = self.info.clone();
+        let change = Rc::new(change);
+
+        future::loop_fn(0, move |attempts| {
+            let client2 = client.clone();
+            let info2 = info.clone();
+            let info3 = info.clone();
+            let key2 = key.clone();
+            let key3 = key.clone();
+            let change = Rc::clone(&change);
+
+            let mutation = fetch(&client, &info, key.clone()).and_then(move |entry| {
+                let (current, version) = match entry {
+                    Some((data, version)) => (Some(decode_value(&data)?), Some(version)),
+                    None => (None, None),
+                };
+                let (write, result) = (*change)(current)?;
+
+                let write = match (write, version) {
+                    (Write::Put(data), Some(version)) => {
+                        let value = info2.enc_entry_value(&data)?;
+                        let actions = MDataSeqEntryActions::new().update(key2, value, version + 1);
+                        (actions, Some((data, version + 1)))
+                    }
+                    (Write::Put(data), None) => {
+                        let value = info2.enc_entry_value(&data)?;
+                        (
+                            MDataSeqEntryActions::new().ins(key2, value, 0),
+                            Some((data, 0)),
+                        )
+                    }
+                    (Write::Remove, Some(version)) => {
+                        (MDataSeqEntryActions::new().del(key2, version + 1), None)
+                    }
+                    (Write::Remove, None) | (Write::Keep, _) => return Ok((None, result)),
+                };
+                Ok((Some(write), result))
+            });
+
+            mutation
+                .and_then(move |(write, result)| match write {
+                    Some((actions, written)) => {
+                        write_entry(&client2, &info3, key3, actions, written)
+                            .map(move |()| result)
+                            .into_box()
+                    }
+                    None => ok!(result),
+                })
+                .map(Loop::Break)
+                .or_else(move |error| {
+                    if recovery::is_entry_conflict(&error) && attempts < MAX_ATTEMPTS {
+                        Ok(Loop::Continue(attempts + 1))
+                    } else {
+                        Err(error)
+                    }
+                })
+        })
+        .into_box()
+    }
+}
+
+// Apply the actions to the entry with the encoded key, which leave it with the serialised value
+// and version `written`, or remove it if that's `None`. A request which timed out may still have
+// been applied, so it's resubmitted unchanged rather than computing the value again, which would
+// apply the change twice. A conflict following a timeout means the actions were applied if the
+// entry is now as written. Other conflicts are returned, so the entry is read again.
+fn write_entry(
+    client: &impl Client,
+    info: &MDataInfo,
+    key: Vec<u8>,
+    actions: MDataSeqEntryActions,
+    written: Option<(Vec<u8>, u64)>,
+) -> Box<CoreFuture<()>> {
+    let client = client.clone();
+    let info = info.clone();
+
+    future::loop_fn(0, move |attempts| {
+        let client2 = client.clone();
+        let info2 = info.clone();
+        let key = key.clone();
+        let written = written.clone();
+
+        client
+            .mutate_seq_mdata_entries(info.name(), info.type_tag(), actions.clone())
+            .then(move |result| match result {
+                Ok(()) => ok!(Loop::Break(())),
+                Err(CoreError::RequestTimeout) if attempts < MAX_ATTEMPTS => {
+                    ok!(Loop::Continue(attempts + 1))
+                }
+                Err(error) if attempts > 0 && recovery::is_entry_conflict(&error) => {
+                    fetch(&client2, &info2, key)
+                        .and_then(move |current| {
+                            if current == written {
+                                Ok(Loop::Break(()))
+                            } else {
+                                Err(error)
+                            }
+                        })
+                        .into_box()
+                }
+                Err(error) => err!(error),
+            })
+    })
+    .into_box()
+}
+
+// Fetch the decrypted value of the encoded key along with its version, or `None` if the mutable
+// data doesn't contain the key.
+fn fetch(
+    client: &impl Client,
+    info: &MDataInfo,
+    key: Vec<u8>,
+) -> Box<CoreFuture<Option<(Vec<u8>, u64)>>> {
+    let info = info.clone();
+
+    client
+        .get_seq_mdata_value(info.name(), info.type_tag(), key)
+        .then(move |result| match result {
+            Ok(value) => Ok(Some((info.decrypt(&value.data)?, value.version))),
+            Err(CoreError::DataError(SndError::NoSuchEntry)) => Ok(None),
+            Err(error) => Err(error),
+        })
+        .into_box()
+}
+
+fn encode_key<K: Serialize>(info: &MDataInfo, key: &K) -> Result<Vec<u8>, CoreError> {
+    info.enc_entry_key(&serialize(key)?)
+}
+
+fn encode_value<V: KvValue>(value: &V) -> Result<Vec<u8>, CoreError> {
+    Ok(serialize(&Tagged {
+        schema_version: V::SCHEMA_VERSION,
+        data: serialize(value)?,
+    })?)
+}
+
+fn decode_value<V: KvValue>(data: &[u8]) -> Result<V, CoreError> {
+    let tagged: Tagged = deserialize(data)?;
+    if tagged.schema_version == V::SCHEMA_VERSION {
+        Ok(deserialize(&tagged.data)?)
+    } else {
+        V::migrate(tagged.schema_version, &tagged.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::random_client;
+    use safe_nd::MDataKind;
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct Counter {
+        count: u64,
+    }
+
+    impl KvValue for Counter {
+        const SCHEMA_VERSION: u32 = 1;
+
+        fn migrate(schema_version: u32, data: &[u8]) -> Result<Self, CoreError> {
+            match schema_version {
+                // The count used to be stored as `u32`.
+                0 => Ok(Counter {
+                    count: u64::from(deserialize::<u32>(data)?),
+                }),
+                _ => Err(CoreError::Unexpected("Unsupported schema".to_string())),
+            }
+        }
+    }
+
+    impl KvValue for u32 {}
+
+    // Test the basic operations of a private store.
+    #[test]
+    fn basics() {
+        random_client(|client| {
+            let info = unwrap!(MDataInfo::random_private(MDataKind::Seq, 15_001));
+            let key = "hits".to_string();
+
+            // A second store over the same data, as used by another instance of the app.
+            let store2 = KvStore::<_, String, Counter>::new(client.clone(), info.clone());
+
+            KvStore::<_, String, Counter>::create(client.clone(), info).then(move |res| {
+                let store = unwrap!(res);
+                let key2 = key.clone();
+                let key3 = key.clone();
+                let key4 = key.clone();
+
+                store
+                    .get(&key)
+                    .and_then(move |value| {
+                        assert_eq!(value, None);
+                        store.insert(&key, &Counter { count: 1 }).map(|()| store)
+                    })
+                    .and_then(move |store| {
+                        store
+                            .update(&key2, |counter| Counter {
+                                count: counter.count + 1,
+                            })
+                            .map(|()| store)
+                    })
+                    .and_then(move |store| {
+                        // A stale expectation doesn't swap the value.
+                        let stale = Some(Counter { count: 1 });
+                        let new = Some(Counter { count: 10 });
+                        store2
+                            .compare_and_swap(&key3, stale, new)
+                            .map(move |swapped| {
+                                assert!(!swapped);
+                                (store, store2)
+                            })
+                    })
+                    .and_then(move |(store, store2)| {
+                        let current = Some(Counter { count: 2 });
+                        let new = Some(Counter { count: 3 });
+                        store2
+                            .compare_and_swap(&key4, current, new)
+                            .map(move |swapped| {
+                                assert!(swapped);
+                                store
+                            })
+                    })
+                    .and_then(|store| {
+                        store
+                            .entries(10)
+                            .collect()
+                            .map(move |entries| (store, entries))
+                    })
+                    .and_then(|(store, entries)| {
+                        assert_eq!(entries, vec![("hits".to_string(), Counter { count: 3 })]);
+                        let key = "hits".to_string();
+                        store.remove(&key).map(move |removed| (store, key, removed))
+                    })
+                    .and_then(|(store, key, removed)| {
+                        assert!(removed);
+                        store.update(&key, |counter| counter).then(move |res| {
+                            match res {
+                                Err(CoreError::DataError(SndError::NoSuchEntry)) => (),
+                                res => panic!("Unexpected result {:?}", res),
+                            }
+                            store.remove(&key)
+                        })
+                    })
+                    .map(|removed| assert!(!removed))
+            })
+        });
+    }
+
+    // Test that values stored with an older schema are migrated when read.
+    #[test]
+    fn schema_migration() {
+        random_client(|client| {
+            let info = unwrap!(MDataInfo::random_private(MDataKind::Seq, 15_001));
+            let client2 = client.clone();
+            let info2 = info.clone();
+
+            KvStore::<_, u8, u32>::create(client.clone(), info)
+                .and_then(|old| old.insert(&0, &7))
+                .and_then(move |()| {
+                    let store = KvStore::<_, u8, Counter>::new(client2, info2);
+                    store.get(&0)
+                })
+                .map(|value| assert_eq!(value, Some(Counter { count: 7 })))
+        });
+    }
+}
@@ -56,16 +56,22 @@ pub mod client;
 pub mod config_handler;
 /// Cryptographic utilities.
 pub mod crypto;
+/// Encrypted append-only event log over append-only data.
+pub mod event_log;
 /// Event loop handling.
 pub mod event_loop;
 /// Utilities for handling `ImmutableData`.
 pub mod immutable_data;
 /// Inter-Process Communication utilities.
 pub mod ipc;
+/// Typed key-value store over mutable data.
+pub mod kv_store;
 /// NFS utilities.
 pub mod nfs;
 /// Implements the Self Encryption storage trait.
 pub mod self_encryption_storage;
+/// Map sharded across several mutable data.
+pub mod sharded_map;
 
 #[cfg(not(feature = "mock-network"))]
 mod connection_manager;
@@ -73,7 +79,8 @@ mod errors;
 mod event;
 
 pub use self::client::{
-    mdata_info, recovery, test_create_balance, AuthActions, Client, ClientKeys, MDataInfo,
+    backup, diff, mdata_info, paging, recovery, roles, test_create_balance, AuthActions, Client,
+    ClientKeys, MDataBackup, MDataDiff, MDataEntriesPage, MDataInfo,
 };
 #[cfg(feature = "mock-network")]
 pub use self::client::{mock_vault_path, MockConnectionManager as ConnectionManager};
@@ -92,6 +99,10 @@ pub use quic_p2p::Config as QuicP2pConfig;
 pub const MAIDSAFE_TAG: u64 = 5_483_000;
 /// `MutableData` type tag for a directory.
 pub const DIR_TAG: u64 = 15_000;
+/// `AppendOnlyData` type tag for the version history of a file.
+pub const FILE_HISTORY_TAG: u64 = 15_001;
+/// `MutableData` type tag for the number of hard links to a file.
+pub const FILE_LINK_COUNT_TAG: u64 = 15_002;
 
 /// Gets name of the dedicated container of the given app.
 pub fn app_container_name(app_id: &str) -> String {
@@ -0,0 +1,582 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Export and import of NFS directories as tar and zip archives.
+//!
+//! Every file is stored in the archive under its name in the directory, with its modification
+//! time. The remaining metadata of the files (creation time, user metadata, published status and
+//! structured metadata) is stored in a JSON manifest named `MANIFEST_NAME`, which is the first
+//! entry of the archive. Archives without a manifest, e.g. created by other tools, can be imported
+//! as well.
+//!
+//! Archives are encoded and decoded by the `tar` and `zip` crates on a separate thread, as they
+//! only support blocking I/O. Content is streamed between that thread and the network one chunk at
+//! a time through a bounded channel, so archives of any size can be exported and imported.
+
+mod tar;
+mod zip;
+
+use crate::client::{Client, MDataInfo};
+use crate::crypto::shared_secretbox;
+use crate::errors::CoreError;
+use crate::nfs::file_helper::{self, ListOptions, Version};
+use crate::nfs::{File, Metadata, Mode, NfsError, NfsFuture};
+use crate::utils::FutureExt;
+use chrono::{DateTime, Utc};
+use futures::future::{self, Loop};
+use futures::sync::{mpsc, oneshot};
+use futures::{sink, stream, Future, Sink, Stream};
+use self_encryption::MAX_CHUNK_SIZE;
+use serde::{Deserialize, Serialize};
+use std::cmp;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, Write};
+use std::thread;
+
+/// Name of the manifest entry holding the metadata of the archived files.
+pub const MANIFEST_NAME: &str = ".safe-nfs-manifest.json";
+
+/// Format of an archive.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ArchiveFormat {
+    /// POSIX tar archive, using GNU long name entries for names longer than 100 bytes.
+    Tar,
+    /// Zip archive with uncompressed entries. Archives are limited to 4 GiB and 65535 entries.
+    Zip,
+}
+
+/// Export the files in the directory to an archive written to `writer`, and return the writer.
+///
+/// If `prefix` is given, only the files in the subtree under it are exported, i.e. files whose
+/// names start with the prefix followed by `/`, and they are stored in the archive under the rest
+/// of their names.
+pub fn export<W>(
+    client: impl Client,
+    dir: MDataInfo,
+    prefix: Option<&str>,
+    format: ArchiveFormat,
+    writer: W,
+) -> Box<NfsFuture<W>>
+where
+    W: Write + Seek + Send + 'static,
+{
+    let prefix = prefix.map(|prefix| format!("{}/", prefix.trim_end_matches('/')));
+    trace!("Exporting {:?} with prefix {:?}", dir.address(), prefix);
+
+    let options = ListOptions {
+        prefix: prefix.clone(),
+        ..Default::default()
+    };
+    let enc_key = dir.enc_key().cloned();
+    let client2 = client.clone();
+
+    let (parts, encoded) = match format {
+        ArchiveFormat::Tar => spawn_encoder(move |parts| tar::encode(writer, parts)),
+        ArchiveFormat::Zip => spawn_encoder(move |parts| zip::encode(writer, parts)),
+    };
+
+    let exported = file_helper::list(client, dir, options)
+        .and_then(move |files| {
+            let prefix_len = prefix.map_or(0, |prefix| prefix.len());
+            let files: Vec<_> = files
+                .into_iter()
+                .map(|(name, _, file)| (name[prefix_len..].to_owned(), file))
+                .filter(|(name, _)| !name.is_empty())
+                .collect();
+
+            let manifest: Manifest = files
+                .iter()
+                .map(|(name, file)| (name.clone(), ManifestEntry::new(file)))
+                .collect();
+            let manifest = fry!(serde_json::to_vec(&manifest).map_err(CoreError::from));
+
+            let entry = Part::Entry {
+                name: MANIFEST_NAME.to_string(),
+                modified: Utc::now(),
+                size: manifest.len() as u64,
+            };
+            send_part(parts, entry)
+                .and_then(move |parts| send_part(parts, Part::Data(manifest)))
+                .and_then(|parts| send_part(parts, Part::EndOfEntry))
+                .map(move |parts| (parts, files))
+                .into_box()
+        })
+        .and_then(move |(parts, files)| {
+            future::loop_fn(
+                (parts, files.into_iter()),
+                move |(parts, mut files)| match files.next() {
+                    Some((name, file)) => {
+                        export_file(&client2, enc_key.clone(), parts, name, &file)
+                            .map(move |parts| Loop::Continue((parts, files)))
+                            .into_box()
+                    }
+                    None => ok!(Loop::Break(())),
+                },
+            )
+        });
+
+    // Closing the channel finishes the archive. If sending to the encoder failed, it stopped
+    // because of an error, which is reported rather than the failure to send.
+    exported
+        .then(move |result| match result {
+            Ok(()) => encoded,
+            Err(error) => encoded
+                .then(move |result| match result {
+                    Err(error) => Err(error),
+                    Ok(_) => Err(error),
+                })
+                .into_box(),
+        })
+        .into_box()
+}
+
+/// Import the files in a tar archive read from `reader` into the directory, which can be either
+/// new or already containing files. Returns the names of the imported files.
+///
+/// The archive is read sequentially. Directories and other special entries are skipped. If
+/// `replace` is set, existing files are replaced, otherwise importing a file which already exists
+/// fails with `NfsError::FileExists`.
+pub fn import_tar<R>(
+    client: impl Client,
+    dir: MDataInfo,
+    reader: R,
+    replace: bool,
+) -> Box<NfsFuture<Vec<String>>>
+where
+    R: Read + Send + 'static,
+{
+    trace!("Importing tar archive into {:?}", dir.address());
+    let parts = spawn_decoder(move |parts| tar::decode(reader, parts));
+    import(client, dir, parts, replace)
+}
+
+/// Import the files in a zip archive read from `reader` into the directory, which can be either
+/// new or already containing files. Returns the names of the imported files.
+///
+/// Entries can be either stored or deflated. Directories are skipped. If `replace` is set,
+/// existing files are replaced, otherwise importing a file which already exists fails with
+/// `NfsError::FileExists`.
+pub fn import_zip<R>(
+    client: impl Client,
+    dir: MDataInfo,
+    reader: R,
+    replace: bool,
+) -> Box<NfsFuture<Vec<String>>>
+where
+    R: Read + Seek + Send + 'static,
+{
+    trace!("Importing zip archive into {:?}", dir.address());
+    let parts = spawn_decoder(move |parts| zip::decode(reader, parts));
+    import(client, dir, parts, replace)
+}
+
+// Metadata of the archived files, by name.
+type Manifest = BTreeMap<String, ManifestEntry>;
+
+// Metadata of an archived file, which can't be stored in the archive entry itself.
+#[derive(Serialize, Deserialize)]
+struct ManifestEntry {
+    created: DateTime<Utc>,
+    modified: DateTime<Utc>,
+    user_metadata: Vec<u8>,
+    published: bool,
+    metadata: Metadata,
+}
+
+impl ManifestEntry {
+    // The location and key of the version history of the file are left out, as the archive may
+    // be shared, and the history can't be used by another account anyway.
+    fn new(file: &File) -> Self {
+        let mut metadata = file.metadata().clone();
+        metadata.set_history(None);
+
+        ManifestEntry {
+            created: *file.created_time(),
+            modified: *file.modified_time(),
+            user_metadata: file.user_metadata().to_vec(),
+            published: file.published(),
+            metadata,
+        }
+    }
+}
+
+// Part of an archive, passed between the event loop and the thread encoding or decoding the
+// archive. Every entry is followed by its content, in chunks, and `EndOfEntry`.
+enum Part {
+    Entry {
+        name: String,
+        modified: DateTime<Utc>,
+        size: u64,
+    },
+    Data(Vec<u8>),
+    EndOfEntry,
+}
+
+// Parts received by the encoding thread.
+type Parts = stream::Wait<mpsc::Receiver<Part>>;
+// Parts sent by the decoding thread, or the error which stopped it.
+type PartSink = sink::Wait<mpsc::Sender<io::Result<Part>>>;
+
+// Content of the current entry, read by the encoding thread from the parts sent to it.
+struct Content<'a> {
+    parts: &'a mut Parts,
+    chunk: Vec<u8>,
+    position: usize,
+    // Size of the rest of the content, which hasn't been received yet.
+    remaining: u64,
+}
+
+impl<'a> Content<'a> {
+    fn new(parts: &'a mut Parts, size: u64) -> Self {
+        Content {
+            parts,
+            chunk: Vec::new(),
+            position: 0,
+            remaining: size,
+        }
+    }
+}
+
+impl<'a> Read for Content<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.chunk.len() {
+            match self.parts.next() {
+                Some(Ok(Part::Data(ref data))) if data.len() as u64 > self.remaining => {
+                    return Err(size_mismatch());
+                }
+                Some(Ok(Part::Data(data))) => {
+                    self.remaining -= data.len() as u64;
+                    self.chunk = data;
+                    self.position = 0;
+                }
+                Some(Ok(Part::EndOfEntry)) if self.remaining == 0 => return Ok(0),
+                _ => return Err(size_mismatch()),
+            }
+        }
+
+        let len = cmp::min(buf.len(), self.chunk.len() - self.position);
+        buf[..len].copy_from_slice(&self.chunk[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+// Spawn a thread encoding an archive from the parts sent to the returned sender. The archive is
+// finished once the sender is dropped.
+fn spawn_encoder<W, F>(encode: F) -> (mpsc::Sender<Part>, Box<NfsFuture<W>>)
+where
+    W: Send + 'static,
+    F: FnOnce(&mut Parts) -> io::Result<W> + Send + 'static,
+{
+    let (parts_tx, parts_rx) = mpsc::channel(1);
+    let (result_tx, result_rx) = oneshot::channel();
+
+    let _ = thread::spawn(move || {
+        let result = encode(&mut parts_rx.wait());
+        let _ = result_tx.send(result);
+    });
+
+    let encoded = result_rx
+        .map_err(|_| NfsError::Unexpected("Archive encoder stopped".to_string()))
+        .and_then(|result| result.map_err(NfsError::from))
+        .into_box();
+
+    (parts_tx, encoded)
+}
+
+// Spawn a thread decoding an archive into the parts received from the returned receiver. If the
+// archive is invalid, the error is received last.
+fn spawn_decoder<F>(decode: F) -> mpsc::Receiver<io::Result<Part>>
+where
+    F: FnOnce(&mut PartSink) -> io::Result<()> + Send + 'static,
+{
+    let (parts_tx, parts_rx) = mpsc::channel(1);
+
+    let _ = thread::spawn(move || {
+        let mut parts = parts_tx.wait();
+        if let Err(error) = decode(&mut parts) {
+            let _ = parts.send(Err(error));
+        }
+    });
+
+    parts_rx
+}
+
+// Send the part to the encoding thread.
+fn send_part(parts: mpsc::Sender<Part>, part: Part) -> Box<NfsFuture<mpsc::Sender<Part>>> {
+    parts
+        .send(part)
+        .map_err(|_| NfsError::Unexpected("Archive encoder stopped".to_string()))
+        .into_box()
+}
+
+// Receive the next part from the decoding thread, or `None` at the end of the archive.
+fn receive_part(
+    parts: mpsc::Receiver<io::Result<Part>>,
+) -> Box<NfsFuture<(Option<Part>, mpsc::Receiver<io::Result<Part>>)>> {
+    parts
+        .into_future()
+        .map_err(|_| NfsError::Unexpected("Archive decoder stopped".to_string()))
+        .and_then(|(part, parts)| match part {
+            Some(Ok(part)) => Ok((Some(part), parts)),
+            Some(Err(error)) => Err(NfsError::from(error)),
+            None => Ok((None, parts)),
+        })
+        .into_box()
+}
+
+// Send the entry to the event loop, followed by its content read one chunk at a time.
+fn send_entry(
+    parts: &mut PartSink,
+    name: String,
+    modified: DateTime<Utc>,
+    size: u64,
+    content: &mut dyn Read,
+) -> io::Result<()> {
+    send(
+        parts,
+        Part::Entry {
+            name,
+            modified,
+            size,
+        },
+    )?;
+
+    let mut read = 0;
+    loop {
+        let mut buffer = vec![0; MAX_CHUNK_SIZE as usize];
+        let len = read_chunk(content, &mut buffer)?;
+        if len == 0 {
+            break;
+        }
+        read += len as u64;
+        buffer.truncate(len);
+        send(parts, Part::Data(buffer))?;
+    }
+
+    if read != size {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "Unexpected end of the archive entry",
+        ));
+    }
+    send(parts, Part::EndOfEntry)
+}
+
+fn send(parts: &mut PartSink, part: Part) -> io::Result<()> {
+    parts
+        .send(Ok(part))
+        .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Import was abandoned"))
+}
+
+// Write the content of the file to a new archive entry.
+fn export_file(
+    client: &impl Client,
+    enc_key: Option<shared_secretbox::Key>,
+    parts: mpsc::Sender<Part>,
+    name: String,
+    file: &File,
+) -> Box<NfsFuture<mpsc::Sender<Part>>> {
+    let modified = *file.modified_time();
+
+    file_helper::read(client.clone(), file, enc_key)
+        .and_then(move |reader| {
+            let size = reader.size();
+            let entry = Part::Entry {
+                name,
+                modified,
+                size,
+            };
+            send_part(parts, entry).map(move |parts| (reader, parts, size))
+        })
+        .and_then(|(reader, parts, size)| {
+            future::loop_fn((reader, parts, 0), move |(reader, parts, position)| {
+                if position >= size {
+                    return send_part(parts, Part::EndOfEntry)
+                        .map(Loop::Break)
+                        .into_box();
+                }
+
+                let length = cmp::min(u64::from(MAX_CHUNK_SIZE), size - position);
+                reader
+                    .read(position, length)
+                    .and_then(move |data| send_part(parts, Part::Data(data)))
+                    .map(move |parts| Loop::Continue((reader, parts, position + length)))
+                    .into_box()
+            })
+        })
+        .into_box()
+}
+
+// Import the files received from the decoding thread.
+fn import(
+    client: impl Client,
+    dir: MDataInfo,
+    parts: mpsc::Receiver<io::Result<Part>>,
+    replace: bool,
+) -> Box<NfsFuture<Vec<String>>> {
+    let state = (parts, None, Vec::new());
+
+    future::loop_fn(state, move |(parts, manifest, mut imported)| {
+        let client = client.clone();
+        let dir = dir.clone();
+
+        receive_part(parts).and_then(move |(part, parts)| {
+            let (name, modified) = match part {
+                Some(Part::Entry { name, modified, .. }) => (name, modified),
+                Some(_) => return err!(unexpected_part()),
+                None => return ok!(Loop::Break(imported)),
+            };
+
+            // The manifest is only recognised as the first entry.
+            let mut manifest: Manifest = match manifest {
+                Some(manifest) => manifest,
+                None if name == MANIFEST_NAME => {
+                    return receive_content(parts)
+                        .and_then(move |(content, parts)| {
+                            let manifest: Manifest =
+                                serde_json::from_slice(&content).map_err(CoreError::from)?;
+                            Ok(Loop::Continue((parts, Some(manifest), imported)))
+                        })
+                        .into_box();
+                }
+                None => Manifest::new(),
+            };
+
+            let file = match manifest.remove(&name) {
+                Some(manifest_entry) => {
+                    let mut file =
+                        File::new(manifest_entry.user_metadata, manifest_entry.published);
+                    file.set_created_time(manifest_entry.created);
+                    file.set_modified_time(manifest_entry.modified);
+                    *file.metadata_mut() = manifest_entry.metadata;
+                    // Archives written before the history was left out may still refer to it.
+                    file.metadata_mut().set_history(None);
+                    file
+                }
+                None => {
+                    let mut file = File::new(Vec::new(), false);
+                    file.set_created_time(modified);
+                    file.set_modified_time(modified);
+                    file
+                }
+            };
+
+            import_file(&client, &dir, name, parts, file, replace)
+                .map(move |(name, parts)| {
+                    imported.push(name);
+                    Loop::Continue((parts, Some(manifest), imported))
+                })
+                .into_box()
+        })
+    })
+    .into_box()
+}
+
+// Receive the whole content of the current entry.
+fn receive_content(
+    parts: mpsc::Receiver<io::Result<Part>>,
+) -> Box<NfsFuture<(Vec<u8>, mpsc::Receiver<io::Result<Part>>)>> {
+    future::loop_fn((Vec::new(), parts), |(mut content, parts)| {
+        receive_part(parts).and_then(move |(part, parts)| match part {
+            Some(Part::Data(data)) => {
+                content.extend_from_slice(&data);
+                Ok(Loop::Continue((content, parts)))
+            }
+            Some(Part::EndOfEntry) => Ok(Loop::Break((content, parts))),
+            _ => Err(unexpected_part()),
+        })
+    })
+    .into_box()
+}
+
+// Store the content of the current entry as the file, and insert it into the directory.
+fn import_file(
+    client: &impl Client,
+    dir: &MDataInfo,
+    name: String,
+    parts: mpsc::Receiver<io::Result<Part>>,
+    file: File,
+    replace: bool,
+) -> Box<NfsFuture<(String, mpsc::Receiver<io::Result<Part>>)>> {
+    trace!("Importing '{}'", name);
+
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let dir2 = dir.clone();
+    let dir3 = dir.clone();
+    let created = *file.created_time();
+    let modified = *file.modified_time();
+
+    file_helper::write(
+        client.clone(),
+        file,
+        Mode::Overwrite,
+        dir.enc_key().cloned(),
+    )
+    .and_then(move |writer| {
+        future::loop_fn((writer, parts), |(writer, parts)| {
+            receive_part(parts).and_then(move |(part, parts)| match part {
+                Some(Part::Data(data)) => writer
+                    .write(&data)
+                    .map(move |()| Loop::Continue((writer, parts)))
+                    .into_box(),
+                Some(Part::EndOfEntry) => ok!(Loop::Break((writer, parts))),
+                _ => err!(unexpected_part()),
+            })
+        })
+    })
+    .and_then(|(writer, parts)| writer.close().map(move |file| (file, parts)))
+    .and_then(move |(mut file, parts)| {
+        // Keep the times from the archive rather than the time of the import.
+        file.set_created_time(created);
+        file.set_modified_time(modified);
+
+        file_helper::insert(client2, dir2, name.clone(), &file)
+            .map(|()| None)
+            .or_else(move |error| match error {
+                NfsError::FileExists if replace => Ok(Some(file)),
+                error => Err(error),
+            })
+            .map(move |replaced| (name, replaced, parts))
+    })
+    .and_then(move |(name, replaced, parts)| match replaced {
+        Some(file) => file_helper::update(client3, dir3, &name, &file, Version::GetNext)
+            .map(move |_| (name, parts))
+            .into_box(),
+        None => ok!((name, parts)),
+    })
+    .into_box()
+}
+
+// Read as much as fits into the buffer, stopping short only at the end of the content.
+fn read_chunk(content: &mut dyn Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+
+    while len < buffer.len() {
+        match content.read(&mut buffer[len..]) {
+            Ok(0) => break,
+            Ok(read) => len += read,
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted => (),
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(len)
+}
+
+fn unexpected_part() -> NfsError {
+    NfsError::Unexpected("Unexpected part of the archive".to_string())
+}
+
+fn size_mismatch() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "Data doesn't match the size of the entry",
+    )
+}
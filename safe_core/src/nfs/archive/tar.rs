@@ -0,0 +1,139 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{send_entry, Content, Part, PartSink, Parts};
+use chrono::{TimeZone, Utc};
+use std::io::{self, Read, Write};
+use tar::{Archive, Builder, EntryType, Header};
+
+/// Write a tar archive with the entries received from the parts.
+pub fn encode<W: Write>(writer: W, parts: &mut Parts) -> io::Result<W> {
+    let mut builder = Builder::new(writer);
+
+    while let Some(part) = parts.next() {
+        let (name, modified, size) = match part {
+            Ok(Part::Entry {
+                name,
+                modified,
+                size,
+            }) => (name, modified, size),
+            _ => return Err(invalid_input("expected the start of an entry")),
+        };
+
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Regular);
+        header.set_mode(0o644);
+        header.set_mtime(modified.timestamp().max(0) as u64);
+        header.set_size(size);
+
+        // Names which don't fit into the header are stored in a GNU long name entry.
+        builder.append_data(&mut header, &name, Content::new(parts, size))?;
+    }
+
+    builder.into_inner()
+}
+
+/// Read the regular files in a tar archive sequentially, and send them as parts.
+pub fn decode<R: Read>(reader: R, parts: &mut PartSink) -> io::Result<()> {
+    let mut archive = Archive::new(reader);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        match entry.header().entry_type() {
+            EntryType::Regular | EntryType::Continuous => (),
+            // Directories, links and other special entries aren't imported.
+            _ => continue,
+        }
+
+        let name = String::from_utf8(entry.path_bytes().into_owned())
+            .map_err(|_| invalid_data("name is not valid UTF-8"))?;
+        let name = name.trim_start_matches("./");
+        if name.is_empty() || name.ends_with('/') {
+            continue;
+        }
+
+        let modified = Utc
+            .timestamp_opt(entry.header().mtime()? as i64, 0)
+            .single()
+            .unwrap_or_else(|| Utc.timestamp(0, 0));
+        let size = entry.size();
+
+        send_entry(parts, name.to_owned(), modified, size, &mut entry)?;
+    }
+
+    Ok(())
+}
+
+fn invalid_data(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid tar archive: {}", reason),
+    )
+}
+
+fn invalid_input(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Invalid tar entry: {}", reason),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{spawn_decoder, spawn_encoder};
+    use super::*;
+    use futures::{Future, Sink, Stream};
+
+    // Test that entries with long names are written and read back, and that a truncated archive
+    // is detected.
+    #[test]
+    fn long_name_round_trip() {
+        let name = format!("{}/file.txt", "dir".repeat(50));
+        let modified = Utc.timestamp(1_500_000_000, 0);
+
+        let (parts, encoded) = spawn_encoder(|parts| encode(Vec::new(), parts));
+        let mut parts = parts.wait();
+        unwrap!(parts.send(Part::Entry {
+            name: name.clone(),
+            modified,
+            size: 5,
+        }));
+        unwrap!(parts.send(Part::Data(b"hello".to_vec())));
+        unwrap!(parts.send(Part::EndOfEntry));
+        drop(parts);
+        let archive = unwrap!(encoded.wait());
+
+        let received: Vec<_> = spawn_decoder({
+            let archive = archive.clone();
+            move |parts| decode(&archive[..], parts)
+        })
+        .wait()
+        .map(|part| unwrap!(unwrap!(part)))
+        .collect();
+
+        match received[..] {
+            [Part::Entry {
+                name: ref received_name,
+                modified: received_modified,
+                size: 5,
+            }, Part::Data(ref data), Part::EndOfEntry] => {
+                assert_eq!(*received_name, name);
+                assert_eq!(received_modified, modified);
+                assert_eq!(data[..], b"hello"[..]);
+            }
+            _ => panic!("Unexpected parts"),
+        }
+
+        let truncated = archive[..archive.len() / 2].to_vec();
+        let result = spawn_decoder(move |parts| decode(&truncated[..], parts))
+            .wait()
+            .map(|part| unwrap!(part))
+            .collect::<io::Result<Vec<_>>>();
+        assert!(result.is_err());
+    }
+}
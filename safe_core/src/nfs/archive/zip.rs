@@ -0,0 +1,178 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{send_entry, Content, Part, PartSink, Parts};
+use chrono::{DateTime, Datelike, TimeZone, Timelike, Utc};
+use std::cmp;
+use std::io::{self, Read, Seek, Write};
+use zip::result::ZipError;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Write a zip archive with the entries received from the parts, without compression.
+pub fn encode<W: Write + Seek>(writer: W, parts: &mut Parts) -> io::Result<W> {
+    let mut zip = ZipWriter::new(writer);
+    let mut count = 0usize;
+
+    while let Some(part) = parts.next() {
+        let (name, modified, size) = match part {
+            Ok(Part::Entry {
+                name,
+                modified,
+                size,
+            }) => (name, modified, size),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Invalid zip entry: expected the start of an entry",
+                ))
+            }
+        };
+
+        count += 1;
+        if size > u64::from(u32::max_value()) || count > usize::from(u16::max_value()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Too much data for a zip archive",
+            ));
+        }
+
+        let options = FileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .last_modified_time(dos_date_time(&modified))
+            .unix_permissions(0o644);
+        zip.start_file(name, options).map_err(zip_error)?;
+        let _ = io::copy(&mut Content::new(parts, size), &mut zip)?;
+    }
+
+    zip.finish().map_err(zip_error)
+}
+
+/// Read the files in a zip archive in the order of its central directory, and send them as parts.
+pub fn decode<R: Read + Seek>(reader: R, parts: &mut PartSink) -> io::Result<()> {
+    let mut archive = ZipArchive::new(reader).map_err(zip_error)?;
+
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index).map_err(zip_error)?;
+
+        // Directories aren't imported.
+        if file.name().ends_with('/') {
+            continue;
+        }
+
+        let name = file.name().to_owned();
+        let modified = from_dos_date_time(&file.last_modified());
+        let size = file.size();
+
+        send_entry(parts, name, modified, size, &mut file)?;
+    }
+
+    Ok(())
+}
+
+// Convert to MS-DOS time and date, which only cover the years 1980 to 2107 with a precision of two
+// seconds.
+fn dos_date_time(time: &DateTime<Utc>) -> zip::DateTime {
+    if time.year() < 1980 {
+        return zip::DateTime::default();
+    }
+
+    zip::DateTime::from_date_and_time(
+        cmp::min(time.year(), 2107) as u16,
+        time.month() as u8,
+        time.day() as u8,
+        time.hour() as u8,
+        time.minute() as u8,
+        time.second() as u8,
+    )
+    .unwrap_or_default()
+}
+
+fn from_dos_date_time(time: &zip::DateTime) -> DateTime<Utc> {
+    Utc.ymd_opt(
+        i32::from(time.year()),
+        u32::from(time.month()),
+        u32::from(time.day()),
+    )
+    .single()
+    .and_then(|date| {
+        date.and_hms_opt(
+            u32::from(time.hour()),
+            u32::from(time.minute()),
+            u32::from(time.second()),
+        )
+    })
+    .unwrap_or_else(|| Utc.timestamp(0, 0))
+}
+
+fn zip_error(error: ZipError) -> io::Error {
+    match error {
+        ZipError::Io(error) => error,
+        error => io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid zip archive: {}", error),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{spawn_decoder, spawn_encoder};
+    use super::*;
+    use futures::{Future, Sink, Stream};
+    use std::io::Cursor;
+
+    // Test that an archive with a stored entry is read back, and corruption of its content is
+    // detected.
+    #[test]
+    fn stored_round_trip() {
+        let modified = Utc.ymd(2019, 6, 1).and_hms(12, 30, 10);
+
+        let (parts, encoded) = spawn_encoder(|parts| encode(Cursor::new(Vec::new()), parts));
+        let mut parts = parts.wait();
+        unwrap!(parts.send(Part::Entry {
+            name: "dir/hello.txt".to_string(),
+            modified,
+            size: 5,
+        }));
+        unwrap!(parts.send(Part::Data(b"hello".to_vec())));
+        unwrap!(parts.send(Part::EndOfEntry));
+        drop(parts);
+        let mut archive = unwrap!(encoded.wait()).into_inner();
+
+        let received: Vec<_> = spawn_decoder({
+            let archive = archive.clone();
+            move |parts| decode(Cursor::new(archive), parts)
+        })
+        .wait()
+        .map(|part| unwrap!(unwrap!(part)))
+        .collect();
+
+        match received[..] {
+            [Part::Entry {
+                ref name,
+                modified: received_modified,
+                size: 5,
+            }, Part::Data(ref data), Part::EndOfEntry] => {
+                assert_eq!(name, "dir/hello.txt");
+                assert_eq!(received_modified, modified);
+                assert_eq!(data[..], b"hello"[..]);
+            }
+            _ => panic!("Unexpected parts"),
+        }
+
+        // Corrupt the content, which is stored as is.
+        let position = unwrap!(archive.windows(5).position(|window| window == b"hello"));
+        archive[position] = b'j';
+        let result = spawn_decoder(move |parts| decode(Cursor::new(archive), parts))
+            .wait()
+            .map(|part| unwrap!(part))
+            .collect::<io::Result<Vec<_>>>();
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,230 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Backup of directories along with the content of their files.
+//!
+//! A directory is backed up as an `MDataBackup` holding its entries both as they are stored and
+//! decrypted, along with the content of its files in `MDataBackup::files` and the backups of its
+//! sub-directories in `MDataBackup::dirs`. When it is restored, files whose data maps can't be
+//! fetched with the encryption key of the restored directory, e.g. because they were deleted or
+//! the directory is restored with different encryption, are stored again from the backed up
+//! content.
+
+use crate::client::backup::{self, BackupEntries, MDataBackup};
+use crate::client::{Client, MDataInfo};
+use crate::crypto::shared_secretbox;
+use crate::nfs::file_helper;
+use crate::nfs::link::{Entry, Link};
+use crate::nfs::{data_map, File, Mode, NfsError, NfsFuture};
+use crate::utils::FutureExt;
+use futures::future::{self, Loop};
+use futures::stream::{self, Stream};
+use futures::Future;
+use safe_nd::{MDataAddress, MDataSeqEntries};
+use self_encryption::MAX_CHUNK_SIZE;
+use std::cmp;
+use std::collections::HashSet;
+
+/// Back up the directory, including its decrypted entries, the content of its files and its
+/// sub-directories.
+///
+/// Sub-directories are directories linked to by entries of `Link::Entry` kind with no name, and
+/// are only included if they are owned by the owner of the client. Other symbolic links are
+/// backed up as entries only. Note that the decrypted entries and the content are stored
+/// unencrypted in the backup.
+pub fn export_dir(client: impl Client, dir: MDataInfo) -> Box<NfsFuture<MDataBackup>> {
+    export_tree(client, dir, HashSet::new())
+}
+
+/// Restore the directory from a backup created by `export_dir` into `dir`, which can be either
+/// the backed up directory or a new one.
+///
+/// Sub-directories are restored first, in place, into the directories their links point to.
+/// Files whose data maps can't be fetched are stored again from their backed up content before
+/// the entries are restored (see `client::backup::restore`).
+pub fn restore_dir(client: impl Client, backup: MDataBackup, dir: MDataInfo) -> Box<NfsFuture<()>> {
+    trace!("Restoring directory backup into {:?}", dir.address());
+
+    let plaintext = match backup.plaintext {
+        Some(BackupEntries::Seq(ref entries)) => entries.clone(),
+        _ => {
+            return err!(NfsError::Unexpected(
+                "Backup doesn't contain decrypted directory entries".to_string()
+            ));
+        }
+    };
+
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let dir2 = dir.clone();
+    let enc_key = dir.enc_key().cloned();
+    let files = backup.files.clone();
+    let sub_dirs: Vec<_> = sub_dirs(&plaintext)
+        .into_iter()
+        .filter_map(|(name, dir)| Some((backup.dirs.get(&name)?.clone(), dir)))
+        .collect();
+
+    stream::iter_ok(sub_dirs)
+        .for_each(move |(backup, dir)| restore_dir(client3.clone(), backup, dir))
+        .and_then(move |()| {
+            stream::iter_ok(plaintext.clone())
+                .filter_map(move |(key, value)| {
+                    let name = String::from_utf8(key.clone()).ok()?;
+                    let content = files.get(&name)?.clone();
+                    match Entry::decode(&value.data).ok()? {
+                        Entry::File(file) => Some((key, file, content)),
+                        Entry::Link(_) => None,
+                    }
+                })
+                .and_then(move |(key, file, content)| {
+                    restore_file(client.clone(), file, content, enc_key.clone())
+                        .map(move |file| (key, file))
+                })
+                .fold(plaintext, |mut plaintext, (key, file)| {
+                    if let Some(file) = file {
+                        if let Some(value) = plaintext.get_mut(&key) {
+                            value.data = Entry::File(file).encode()?;
+                        }
+                    }
+                    Ok::<_, NfsError>(plaintext)
+                })
+        })
+        .and_then(move |plaintext: MDataSeqEntries| {
+            let backup = MDataBackup {
+                plaintext: Some(BackupEntries::Seq(plaintext)),
+                ..backup
+            };
+            backup::restore(&client2, &backup, Some(&dir2))
+                .map(|_| ())
+                .map_err(NfsError::from)
+        })
+        .into_box()
+}
+
+// Back up the directory and, recursively, its sub-directories which aren't among `ancestors`.
+fn export_tree<C: Client>(
+    client: C,
+    dir: MDataInfo,
+    mut ancestors: HashSet<MDataAddress>,
+) -> Box<NfsFuture<MDataBackup>> {
+    trace!("Backing up directory {:?}", dir.address());
+
+    let _ = ancestors.insert(*dir.address());
+
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let client4 = client.clone();
+    let enc_key = dir.enc_key().cloned();
+
+    backup::export(&client, *dir.address(), Some(&dir))
+        .map_err(NfsError::from)
+        .and_then(move |backup| {
+            file_helper::list(client2, dir, Default::default()).map(move |files| (backup, files))
+        })
+        .and_then(move |(backup, files)| {
+            stream::iter_ok(files)
+                .and_then(move |(name, _, file)| {
+                    read_content(client3.clone(), &file, enc_key.clone())
+                        .map(move |content| (name, content))
+                })
+                .collect()
+                .map(move |files| MDataBackup {
+                    files: files.into_iter().collect(),
+                    ..backup
+                })
+        })
+        .and_then(move |backup| {
+            let sub_dirs: Vec<_> = match backup.plaintext {
+                Some(BackupEntries::Seq(ref entries)) => sub_dirs(entries)
+                    .into_iter()
+                    .filter(|(_, dir)| !ancestors.contains(dir.address()))
+                    .collect(),
+                _ => Vec::new(),
+            };
+            let owner = client4.owner_key();
+
+            stream::iter_ok(sub_dirs)
+                .and_then(move |(name, dir)| {
+                    export_tree(client4.clone(), dir, ancestors.clone())
+                        .map(move |backup| (name, backup))
+                })
+                .filter(move |(_, backup)| backup.owner == owner)
+                .collect()
+                .map(move |dirs| MDataBackup {
+                    dirs: dirs.into_iter().collect(),
+                    ..backup
+                })
+        })
+        .into_box()
+}
+
+// Names of the entries linking to sub-directories, along with the linked directories.
+fn sub_dirs(plaintext: &MDataSeqEntries) -> Vec<(String, MDataInfo)> {
+    plaintext
+        .iter()
+        .filter_map(|(key, value)| {
+            let name = String::from_utf8(key.clone()).ok()?;
+            match Entry::decode(&value.data).ok()? {
+                Entry::Link(Link::Entry { dir, name: None }) => Some((name, dir)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+// Store the file again from its content if its data map can't be fetched. Returns the new file,
+// or `None` if the file is intact.
+fn restore_file<C: Client>(
+    client: C,
+    file: File,
+    content: Vec<u8>,
+    enc_key: Option<shared_secretbox::Key>,
+) -> Box<NfsFuture<Option<File>>> {
+    data_map::get(&client, file.data_address(), enc_key.clone())
+        .then(move |result| match result {
+            Ok(_) => ok!(None),
+            Err(_) => file_helper::write(client, file, Mode::Overwrite, enc_key)
+                .and_then(move |writer| writer.write(&content).map(move |()| writer))
+                .and_then(|writer| writer.close())
+                .map(Some)
+                .into_box(),
+        })
+        .into_box()
+}
+
+// Read the content of the file, one chunk at a time.
+fn read_content<C: Client>(
+    client: C,
+    file: &File,
+    enc_key: Option<shared_secretbox::Key>,
+) -> Box<NfsFuture<Vec<u8>>> {
+    file_helper::read(client, file, enc_key)
+        .and_then(|reader| {
+            let size = reader.size();
+
+            future::loop_fn(
+                (reader, Vec::new()),
+                move |(reader, mut content): (_, Vec<u8>)| {
+                    let position = content.len() as u64;
+                    if position >= size {
+                        return ok!(Loop::Break(content));
+                    }
+
+                    let length = cmp::min(u64::from(MAX_CHUNK_SIZE), size - position);
+                    reader
+                        .read(position, length)
+                        .map(move |data| {
+                            content.extend_from_slice(&data);
+                            Loop::Continue((reader, content))
+                        })
+                        .into_box()
+                },
+            )
+        })
+        .into_box()
+}
@@ -0,0 +1,212 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Resolution of conflicts between concurrent updates of a file.
+//!
+//! Updates of a directory entry are versioned, so when two clients update the same file starting
+//! from the same version, the second update fails. `update` detects such a conflict and resolves
+//! it according to a `MergeStrategy`, retrying as in `client::recovery`.
+
+use crate::client::recovery::{self, MAX_ATTEMPTS};
+use crate::client::{Client, MDataInfo};
+use crate::errors::CoreError;
+use crate::nfs::file_helper::{self, Version};
+use crate::nfs::{File, NfsError, NfsFuture};
+use crate::utils::FutureExt;
+use futures::future::{self, Loop};
+use futures::Future;
+use std::rc::Rc;
+
+/// Function merging the file being stored with the file currently stored, which is passed second.
+pub type MergeFn = dyn Fn(&File, &File) -> Result<File, NfsError>;
+
+/// How to resolve a conflict with a concurrent update of a file.
+#[derive(Clone)]
+pub enum MergeStrategy {
+    /// Overwrite the concurrent update.
+    LastWriterWins,
+    /// Keep the concurrent update, and store the file under a new name, e.g. `name (conflict 2)`.
+    KeepBoth,
+    /// Store the result of merging the file with the concurrent update. If the file is updated
+    /// concurrently again, the merge is repeated against the newer update.
+    Custom(Rc<MergeFn>),
+}
+
+/// Outcome of an update.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Resolution {
+    /// The file was updated without conflicts, to the given version.
+    Updated(u64),
+    /// A conflict was resolved by overwriting the concurrent update, or merging with it. The
+    /// entry has the given version.
+    Merged(u64),
+    /// A conflict was resolved by storing the file under the given name.
+    KeptBoth(String),
+}
+
+/// Update the file, resolving conflicts with concurrent updates using the given strategy.
+///
+/// If `version` is `Version::GetNext`, the current version is first retrieved from the network, and
+/// that version incremented by one is then used as the actual version. Otherwise the version should
+/// be the successor of the version the changes to the file are based on, so concurrent updates are
+/// detected.
+pub fn update<S>(
+    client: impl Client,
+    parent: MDataInfo,
+    name: S,
+    file: &File,
+    version: Version,
+    strategy: MergeStrategy,
+) -> Box<NfsFuture<Resolution>>
+where
+    S: Into<String>,
+{
+    let name = name.into();
+    trace!("Updating file '{}' with conflict resolution", name);
+
+    let version = match version {
+        Version::GetNext => file_helper::fetch(client.clone(), parent.clone(), name.clone())
+            .map(|(version, _)| version + 1)
+            .into_box(),
+        Version::Custom(version) => ok!(version),
+    };
+    let file = file.clone();
+
+    version
+        .and_then(move |version| {
+            future::loop_fn(
+                (0, file, version, false),
+                move |(attempts, file, version, merged)| {
+                    let client = client.clone();
+                    let parent = parent.clone();
+                    let name = name.clone();
+                    let strategy = strategy.clone();
+
+                    file_helper::update(
+                        client.clone(),
+                        parent.clone(),
+                        &name,
+                        &file,
+                        Version::Custom(version),
+                    )
+                    .then(move |result| match result {
+                        Ok(version) if merged => ok!(Loop::Break(Resolution::Merged(version))),
+                        Ok(version) => ok!(Loop::Break(Resolution::Updated(version))),
+                        Err(NfsError::CoreError(ref error))
+                            if attempts < MAX_ATTEMPTS && recovery::is_version_conflict(error) =>
+                        {
+                            resolve(&client, parent, name, file, &strategy)
+                                .map(move |step| match step {
+                                    Step::Retry(file, version) => {
+                                        Loop::Continue((attempts + 1, file, version, true))
+                                    }
+                                    Step::Done(resolution) => Loop::Break(resolution),
+                                })
+                                .into_box()
+                        }
+                        Err(NfsError::CoreError(CoreError::RequestTimeout))
+                            if attempts < MAX_ATTEMPTS =>
+                        {
+                            ok!(Loop::Continue((attempts + 1, file, version, merged)))
+                        }
+                        Err(error) => err!(error),
+                    })
+                },
+            )
+        })
+        .into_box()
+}
+
+// Next step after a conflict.
+enum Step {
+    // Retry the update with the file and version.
+    Retry(File, u64),
+    Done(Resolution),
+}
+
+// Resolve a conflict of the file with the currently stored one.
+fn resolve(
+    client: &impl Client,
+    parent: MDataInfo,
+    name: String,
+    file: File,
+    strategy: &MergeStrategy,
+) -> Box<NfsFuture<Step>> {
+    debug!("Resolving conflicting update of file '{}'", name);
+
+    match *strategy {
+        MergeStrategy::LastWriterWins => file_helper::fetch(client.clone(), parent, name)
+            .map(move |(version, _)| Step::Retry(file, version + 1))
+            .into_box(),
+        MergeStrategy::KeepBoth => keep_both(client, parent, name, file)
+            .map(|name| Step::Done(Resolution::KeptBoth(name)))
+            .into_box(),
+        MergeStrategy::Custom(ref merge) => {
+            let merge = Rc::clone(merge);
+            file_helper::fetch(client.clone(), parent, name)
+                .and_then(move |(version, current)| {
+                    let merged = merge(&file, &current)?;
+                    Ok(Step::Retry(merged, version + 1))
+                })
+                .into_box()
+        }
+    }
+}
+
+// Insert the file under the first free conflict name.
+fn keep_both(
+    client: &impl Client,
+    parent: MDataInfo,
+    name: String,
+    file: File,
+) -> Box<NfsFuture<String>> {
+    let client = client.clone();
+
+    future::loop_fn(2, move |number| {
+        let conflict_name = conflict_name(&name, number);
+
+        file_helper::insert(client.clone(), parent.clone(), &conflict_name, &file).then(
+            move |result| match result {
+                Ok(()) => Ok(Loop::Break(conflict_name)),
+                Err(NfsError::FileExists) if number < MAX_ATTEMPTS + 2 => {
+                    Ok(Loop::Continue(number + 1))
+                }
+                Err(error) => Err(error),
+            },
+        )
+    })
+    .into_box()
+}
+
+// Name for the conflicting copy of the file, keeping its extension.
+fn conflict_name(name: &str, number: usize) -> String {
+    let start = name.rfind('/').map_or(0, |index| index + 1);
+
+    match name[start..].rfind('.') {
+        Some(index) if index > 0 => {
+            let (stem, extension) = name.split_at(start + index);
+            format!("{} (conflict {}){}", stem, number, extension)
+        }
+        _ => format!("{} (conflict {})", name, number),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that conflict names keep the extension of the last path component.
+    #[test]
+    fn conflict_names() {
+        assert_eq!(conflict_name("notes", 2), "notes (conflict 2)");
+        assert_eq!(conflict_name("notes.txt", 3), "notes (conflict 3).txt");
+        assert_eq!(conflict_name(".profile", 2), ".profile (conflict 2)");
+        assert_eq!(conflict_name("a.d/notes", 2), "a.d/notes (conflict 2)");
+        assert_eq!(conflict_name("a/b.tar.gz", 2), "a/b.tar (conflict 2).gz");
+    }
+}
@@ -10,12 +10,13 @@
 
 use crate::client::Client;
 use crate::crypto::shared_secretbox;
+use crate::errors::CoreError;
 use crate::immutable_data;
-use crate::nfs::NfsFuture;
+use crate::nfs::{NfsError, NfsFuture};
 use crate::utils::FutureExt;
 use bincode::{deserialize, serialize};
 use futures::{future, Future};
-use safe_nd::{IDataAddress, XorName};
+use safe_nd::{Error as SndError, IDataAddress, XorName};
 use self_encryption::DataMap;
 
 // Get `DataMap` from the network.
@@ -33,12 +34,38 @@ pub fn get(
 
 // Put `DataMap` on the network.
 // If `encryption_key` is passed in, the `DataMap` will be encrypted.
+// Storing a `DataMap` which is already on the network is only accepted where that can't make two
+// files share it unknowingly: published data maps are never deleted, and encrypted ones are
+// stored with a random nonce, so only a retry of the same request can find them already stored.
+// Otherwise the existing data map belongs to another file, and `DataExists` is returned.
 pub fn put(
     client: &impl Client,
     data_map: &DataMap,
     published: bool,
     encryption_key: Option<shared_secretbox::Key>,
 ) -> Box<NfsFuture<XorName>> {
+    let shareable = published || encryption_key.is_some();
+
+    put_or_share(client, data_map, published, encryption_key)
+        .and_then(move |(name, existing)| {
+            if existing && !shareable {
+                Err(NfsError::from(CoreError::DataError(SndError::DataExists)))
+            } else {
+                Ok(name)
+            }
+        })
+        .into_box()
+}
+
+// Put `DataMap` on the network like `put`, but accept it being already stored in any case,
+// returning its name and whether it was already stored. The caller is responsible for counting
+// the file as a hard link to an existing unpublished data map.
+pub fn put_or_share(
+    client: &impl Client,
+    data_map: &DataMap,
+    published: bool,
+    encryption_key: Option<shared_secretbox::Key>,
+) -> Box<NfsFuture<(XorName, bool)>> {
     let client = client.clone();
     let client2 = client.clone();
 
@@ -49,7 +76,11 @@ pub fn put(
         })
         .and_then(move |data| {
             let name = *data.name();
-            client2.put_idata(data).map(move |_| name)
+            client2.put_idata(data).then(move |result| match result {
+                Ok(()) => Ok((name, false)),
+                Err(CoreError::DataError(SndError::DataExists)) => Ok((name, true)),
+                Err(error) => Err(error),
+            })
         })
         .map_err(From::from)
         .into_box()
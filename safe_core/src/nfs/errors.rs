@@ -11,6 +11,7 @@ use crate::self_encryption_storage::SelfEncryptionStorageError;
 use bincode::Error as SerialisationError;
 use self_encryption::SelfEncryptionError;
 use std::fmt;
+use std::io;
 
 /// NFS Errors
 #[allow(clippy::large_enum_variant)]
@@ -23,6 +24,10 @@ pub enum NfsError {
     FileNotFound,
     /// Invalid byte range specified
     InvalidRange,
+    /// Too many links followed, or a loop of links found, while resolving a name
+    TooManyLinks,
+    /// Content of a file doesn't match its hash
+    ContentHashMismatch,
     /// Unexpected error
     Unexpected(String),
     /// Unsuccessful Serialisation or Deserialisation
@@ -43,6 +48,12 @@ impl From<SerialisationError> for NfsError {
     }
 }
 
+impl From<io::Error> for NfsError {
+    fn from(error: io::Error) -> Self {
+        NfsError::CoreError(CoreError::IoError(error))
+    }
+}
+
 impl<'a> From<&'a str> for NfsError {
     fn from(error: &'a str) -> Self {
         NfsError::Unexpected(error.to_string())
@@ -65,6 +76,10 @@ impl fmt::Display for NfsError {
             NfsError::FileNotFound => write!(f, "File not found"),
 
             NfsError::InvalidRange => write!(f, "Invalid byte range specified"),
+            NfsError::TooManyLinks => write!(f, "Too many levels of links"),
+            NfsError::ContentHashMismatch => {
+                write!(f, "Content of the file doesn't match its hash")
+            }
             NfsError::Unexpected(ref error) => write!(f, "Unexpected error - {:?}", error),
             NfsError::EncodeDecodeError(ref error) => write!(
                 f,
@@ -87,6 +102,8 @@ impl fmt::Debug for NfsError {
             NfsError::FileExists => write!(f, "NfsError::FileExists"),
             NfsError::FileNotFound => write!(f, "NfsError::FileNotFound"),
             NfsError::InvalidRange => write!(f, "NfsError::InvalidRange"),
+            NfsError::TooManyLinks => write!(f, "NfsError::TooManyLinks"),
+            NfsError::ContentHashMismatch => write!(f, "NfsError::ContentHashMismatch"),
             NfsError::Unexpected(ref error) => write!(f, "NfsError::Unexpected -> {:?}", error),
             NfsError::EncodeDecodeError(ref error) => {
                 write!(f, "NfsError::EncodeDecodeError -> {:?}", error)
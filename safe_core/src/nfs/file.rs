@@ -6,12 +6,21 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use crate::ffi::nfs::File as FfiFile;
+use crate::ffi::nfs::{
+    File as FfiFile, FileEntry as FfiFileEntry, FileXattr as FfiFileXattr, XATTR_KIND_BOOL,
+    XATTR_KIND_BYTES, XATTR_KIND_INT, XATTR_KIND_TEXT,
+};
 use crate::nfs::errors::NfsError;
+use crate::nfs::metadata::{AttrValue, Metadata};
+use bincode::deserialize;
 use chrono::{DateTime, NaiveDateTime, Utc};
 use ffi_utils::{vec_clone_from_raw_parts, vec_into_raw_parts, ReprC};
 use safe_nd::{IDataAddress, IDataKind, XorName};
 use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+use std::ffi::{CStr, CString, NulError};
+use std::os::raw::c_char;
+use std::{ptr, slice};
 
 /// Representation of a File to be put into the network. Could be any kind of
 /// file: text, music, video, etc.
@@ -23,6 +32,18 @@ pub struct File {
     user_metadata: Vec<u8>,
     data_map_name: XorName,
     published: bool,
+    metadata: Metadata,
+}
+
+// Format of files stored before the structured metadata was added.
+#[derive(Deserialize)]
+struct LegacyFile {
+    size: u64,
+    created: DateTime<Utc>,
+    modified: DateTime<Utc>,
+    user_metadata: Vec<u8>,
+    data_map_name: XorName,
+    published: bool,
 }
 
 impl File {
@@ -35,7 +56,28 @@ impl File {
             user_metadata,
             data_map_name: XorName::default(),
             published,
+            metadata: Metadata::default(),
+        }
+    }
+
+    /// Decode a serialised file, including files stored without structured metadata.
+    pub fn decode(encoded: &[u8]) -> Result<File, NfsError> {
+        // A file in the legacy format ends where the metadata would start, so it can't be
+        // mistaken for a file in the current format.
+        if let Ok(file) = deserialize(encoded) {
+            return Ok(file);
         }
+
+        let legacy: LegacyFile = deserialize(encoded)?;
+        Ok(File {
+            size: legacy.size,
+            created: legacy.created,
+            modified: legacy.modified,
+            user_metadata: legacy.user_metadata,
+            data_map_name: legacy.data_map_name,
+            published: legacy.published,
+            metadata: Metadata::default(),
+        })
     }
 
     /// Construct FFI wrapper for the native Rust object, consuming self.
@@ -44,6 +86,25 @@ impl File {
         let user_metadata = self.user_metadata().to_vec();
         let (user_metadata, user_metadata_len) = vec_into_raw_parts(user_metadata);
 
+        // Names and MIME types containing nul characters are rejected when set, so any that still
+        // do (e.g. having been stored by another client) are left out.
+        let mime_type = self
+            .metadata
+            .mime_type()
+            .and_then(|mime_type| CString::new(mime_type).ok())
+            .map_or(ptr::null(), |mime_type| mime_type.into_raw() as *const _);
+        let (content_hash, has_content_hash) = match self.metadata.content_hash() {
+            Some(hash) => (*hash, true),
+            None => (Default::default(), false),
+        };
+        let xattrs: Vec<_> = self
+            .metadata
+            .xattrs()
+            .iter()
+            .filter_map(|(name, value)| xattr_into_repr_c(name, value))
+            .collect();
+        let (xattrs, xattrs_len) = vec_into_raw_parts(xattrs);
+
         FfiFile {
             size: self.size(),
             created_sec: self.created_time().timestamp(),
@@ -54,6 +115,11 @@ impl File {
             user_metadata_len,
             data_map_name: self.data_map_name().0,
             published: self.published(),
+            mime_type,
+            content_hash,
+            has_content_hash,
+            xattrs,
+            xattrs_len,
         }
     }
 
@@ -87,6 +153,16 @@ impl File {
         self.published
     }
 
+    /// Get the structured metadata
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Get the structured metadata for modification
+    pub fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+
     /// Get the Immutable Data address of the file
     pub fn data_address(&self) -> IDataAddress {
         let kind = IDataKind::from_flag(self.published());
@@ -98,6 +174,12 @@ impl File {
         self.data_map_name = datamap_name;
     }
 
+    /// Set published status of the file. Publishing also requires the data map to be stored as
+    /// published immutable data, see `nfs::publish`.
+    pub(crate) fn set_published(&mut self, published: bool) {
+        self.published = published;
+    }
+
     /// Set the size of file
     pub fn set_size(&mut self, size: u64) {
         self.size = size;
@@ -137,10 +219,97 @@ impl ReprC for File {
         file.set_modified_time(modified);
         file.set_data_map_name(XorName((*repr_c).data_map_name));
 
+        let metadata = file.metadata_mut();
+        if !(*repr_c).mime_type.is_null() {
+            let mime_type = decode_c_str((*repr_c).mime_type)?;
+            metadata.set_mime_type(Some(mime_type))?;
+        }
+        if (*repr_c).has_content_hash {
+            metadata.set_content_hash(Some((*repr_c).content_hash));
+        }
+        if (*repr_c).xattrs_len > 0 {
+            let xattrs = slice::from_raw_parts((*repr_c).xattrs, (*repr_c).xattrs_len);
+            for xattr in xattrs {
+                let name = decode_c_str(xattr.name)?;
+                let value = vec_clone_from_raw_parts(xattr.value, xattr.value_len);
+                let _ = metadata.set_xattr(name, xattr_value_from_repr_c(xattr.kind, value)?)?;
+            }
+        }
+
         Ok(file)
     }
 }
 
+/// Converts files listed in a directory, together with their names and versions, into their FFI
+/// representation.
+///
+/// The `ffi::nfs::FileEntry` struct has a `Drop` impl which frees the allocated names and
+/// metadata once it goes out of scope.
+pub fn file_entries_into_vec<I>(entries: I) -> Result<Vec<FfiFileEntry>, NulError>
+where
+    I: IntoIterator<Item = (String, u64, File)>,
+{
+    entries
+        .into_iter()
+        .map(|(name, version, file)| {
+            Ok(FfiFileEntry {
+                name: CString::new(name)?.into_raw(),
+                version,
+                file: file.into_repr_c(),
+            })
+        })
+        .collect()
+}
+
+fn xattr_into_repr_c(name: &str, value: &AttrValue) -> Option<FfiFileXattr> {
+    let (kind, value) = match *value {
+        AttrValue::Bool(value) => (XATTR_KIND_BOOL, vec![value as u8]),
+        AttrValue::Int(value) => (XATTR_KIND_INT, value.to_be_bytes().to_vec()),
+        AttrValue::Text(ref value) => (XATTR_KIND_TEXT, value.clone().into_bytes()),
+        AttrValue::Bytes(ref value) => (XATTR_KIND_BYTES, value.clone()),
+    };
+    let name = CString::new(name).ok()?.into_raw();
+    let (value, value_len) = vec_into_raw_parts(value);
+
+    Some(FfiFileXattr {
+        name,
+        kind,
+        value,
+        value_len,
+    })
+}
+
+fn xattr_value_from_repr_c(kind: u64, value: Vec<u8>) -> Result<AttrValue, NfsError> {
+    let invalid = || NfsError::Unexpected("Invalid extended attribute value".to_string());
+
+    match kind {
+        XATTR_KIND_BOOL => match value.as_slice() {
+            [0] => Ok(AttrValue::Bool(false)),
+            [1] => Ok(AttrValue::Bool(true)),
+            _ => Err(invalid()),
+        },
+        XATTR_KIND_INT => {
+            let bytes = value.as_slice().try_into().map_err(|_| invalid())?;
+            Ok(AttrValue::Int(i64::from_be_bytes(bytes)))
+        }
+        XATTR_KIND_TEXT => Ok(AttrValue::Text(
+            String::from_utf8(value).map_err(|_| invalid())?,
+        )),
+        XATTR_KIND_BYTES => Ok(AttrValue::Bytes(value)),
+        _ => Err(NfsError::Unexpected(
+            "Invalid extended attribute kind".to_string(),
+        )),
+    }
+}
+
+#[allow(unsafe_code)]
+unsafe fn decode_c_str(ptr: *const c_char) -> Result<String, NfsError> {
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map(str::to_owned)
+        .map_err(|_| NfsError::Unexpected("Invalid UTF-8 string".to_string()))
+}
+
 #[inline]
 fn convert_date_time(sec: i64, nsec: u32) -> Result<DateTime<Utc>, NfsError> {
     let naive = NaiveDateTime::from_timestamp_opt(sec, nsec)
@@ -161,4 +330,47 @@ mod tests {
         let obj_after = unwrap!(deserialize(&serialised_data));
         assert_eq!(obj_before, obj_after);
     }
+
+    // Test that files serialised before the structured metadata was added can still be decoded,
+    // and that the structured metadata survives a round trip.
+    #[test]
+    #[allow(unsafe_code)]
+    fn decode_with_metadata() {
+        let mut file = File::new(b"user metadata".to_vec(), false);
+        let legacy = unwrap!(serialize(&(
+            file.size(),
+            file.created_time(),
+            file.modified_time(),
+            file.user_metadata(),
+            file.data_map_name(),
+            file.published(),
+        )));
+        assert_eq!(unwrap!(File::decode(&legacy)), file);
+
+        unwrap!(file
+            .metadata_mut()
+            .set_mime_type(Some("text/plain".to_string())));
+        file.metadata_mut().set_content_hash(Some([1; 32]));
+        let _ = unwrap!(file.metadata_mut().set_xattr("answer", AttrValue::Int(42)));
+        let _ = unwrap!(file
+            .metadata_mut()
+            .set_xattr("flagged", AttrValue::Bool(true)));
+
+        let encoded = unwrap!(serialize(&file));
+        assert_eq!(unwrap!(File::decode(&encoded)), file);
+
+        let ffi_file = file.clone().into_repr_c();
+        assert_eq!(ffi_file.xattrs_len, 2);
+        let decoded = unwrap!(unsafe { File::clone_from_repr_c(&ffi_file) });
+        assert_eq!(decoded, file);
+
+        assert!(file
+            .metadata_mut()
+            .set_mime_type(Some(String::new()))
+            .is_err());
+        assert!(file
+            .metadata_mut()
+            .set_xattr("bad\0name", AttrValue::Bool(false))
+            .is_err());
+    }
 }
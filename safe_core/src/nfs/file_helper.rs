@@ -6,16 +6,20 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use crate::client::{Client, MDataInfo};
+use crate::client::{recovery, Client, MDataInfo};
 use crate::crypto::shared_secretbox;
 use crate::errors::CoreError;
-use crate::nfs::{File, Mode, NfsError, NfsFuture, Reader, Writer};
+use crate::ipc::resp::METADATA_KEY;
+use crate::nfs::file_history;
+use crate::nfs::link::{self, Entry, Resolved};
+use crate::nfs::{data_map, File, Mode, NfsError, NfsFuture, Reader, Writer};
 use crate::self_encryption_storage::SelfEncryptionStorage;
 use crate::utils::FutureExt;
-use bincode::{deserialize, serialize};
+use bincode::serialize;
 use futures::{Future, IntoFuture};
-use safe_nd::{Error as SndError, MDataSeqEntryActions};
+use safe_nd::{EntryError, Error as SndError, MDataSeqEntryActions};
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 
 /// Enum specifying which version should be used in places where a version is required.
 #[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
@@ -26,7 +30,49 @@ pub enum Version {
     Custom(u64),
 }
 
+/// Field by which the files returned from `list` are sorted.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SortBy {
+    /// Sort by file name.
+    Name,
+    /// Sort by file size.
+    Size,
+    /// Sort by time of creation.
+    Created,
+    /// Sort by time of modification.
+    Modified,
+}
+
+/// Options controlling which files are returned from `list` and in which order.
+#[derive(Clone, Debug)]
+pub struct ListOptions {
+    /// Field to sort the files by. Ties are broken by file name.
+    pub sort_by: SortBy,
+    /// Sort in descending instead of ascending order.
+    pub descending: bool,
+    /// Only include files whose names start with this prefix.
+    pub prefix: Option<String>,
+    /// Number of matching files to skip.
+    pub offset: usize,
+    /// Maximum number of files to return, or `None` to return all of them.
+    pub limit: Option<usize>,
+}
+
+impl Default for ListOptions {
+    fn default() -> Self {
+        ListOptions {
+            sort_by: SortBy::Name,
+            descending: false,
+            prefix: None,
+            offset: 0,
+            limit: None,
+        }
+    }
+}
+
 /// Insert the file into the directory.
+///
+/// Fails with `NfsError::FileExists` if the directory already contains a file with the same name.
 pub fn insert<S>(client: impl Client, parent: MDataInfo, name: S, file: &File) -> Box<NfsFuture<()>>
 where
     S: AsRef<str>,
@@ -34,45 +80,69 @@ where
     let name = name.as_ref();
     trace!("Inserting file with name '{}'", name);
 
-    serialize(&file)
-        .map_err(From::from)
-        .and_then(|encoded| {
-            let key = parent.enc_entry_key(name.as_bytes())?;
-            let value = parent.enc_entry_value(&encoded)?;
-
-            Ok((key, value))
-        })
-        .into_future()
-        .and_then(move |(key, value)| {
-            client.mutate_seq_mdata_entries(
-                parent.name(),
-                parent.type_tag(),
-                MDataSeqEntryActions::new().ins(key, value, 0),
-            )
-        })
-        .map_err(From::from)
-        .into_box()
+    insert_entry(client, parent, name, &Entry::File(file.clone()))
 }
 
 /// Get a file and its version from the directory.
+///
+/// Symbolic links are followed, in which case the version of the entry the file was found under
+/// is returned. Fails with `NfsError::TooManyLinks` if the links form a loop.
 pub fn fetch<S>(client: impl Client, parent: MDataInfo, name: S) -> Box<NfsFuture<(u64, File)>>
 where
     S: AsRef<str>,
 {
-    parent
-        .enc_entry_key(name.as_ref().as_bytes())
-        .into_future()
-        .and_then(move |key| {
-            client
-                .get_seq_mdata_value(parent.name(), parent.type_tag(), key)
-                .map(move |value| (value, parent))
+    link::follow(client, parent, name.as_ref().to_owned(), false)
+        .and_then(|resolved| match resolved {
+            Resolved::File { version, file, .. } => Ok((version, file)),
+            Resolved::Dir(_) => Err(NfsError::FileNotFound),
         })
-        .and_then(move |(value, parent)| {
-            let plaintext = parent.decrypt(&value.data)?;
-            let file = deserialize(&plaintext)?;
-            Ok((value.version, file))
+        .into_box()
+}
+
+/// List the files in the directory, returning their names, versions and decoded metadata.
+///
+/// Entries which are not files (such as the metadata entry and symbolic links) are skipped.
+/// Filtering by prefix is applied before sorting, and `offset` and `limit` are applied to the
+/// sorted result.
+pub fn list(
+    client: impl Client,
+    parent: MDataInfo,
+    options: ListOptions,
+) -> Box<NfsFuture<Vec<(String, u64, File)>>> {
+    trace!("Listing files in directory {:?}", parent.address());
+
+    client
+        .list_seq_mdata_entries(parent.name(), parent.type_tag())
+        .map_err(NfsError::from)
+        .map(move |entries| {
+            let mut files: Vec<_> = entries
+                .into_iter()
+                .filter(|(key, _)| key.as_slice() != METADATA_KEY)
+                .filter_map(|(key, value)| {
+                    let name = String::from_utf8(parent.decrypt(&key).ok()?).ok()?;
+                    match Entry::decode(&parent.decrypt(&value.data).ok()?).ok()? {
+                        Entry::File(file) => Some((name, value.version, file)),
+                        Entry::Link(_) => None,
+                    }
+                })
+                .filter(|(name, _, _)| match options.prefix {
+                    Some(ref prefix) => name.starts_with(prefix.as_str()),
+                    None => true,
+                })
+                .collect();
+
+            files.sort_by(|a, b| {
+                let ordering = compare_files(options.sort_by, a, b);
+                if options.descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            });
+
+            let limit = options.limit.unwrap_or_else(usize::max_value);
+            files.into_iter().skip(options.offset).take(limit).collect()
         })
-        .map_err(convert_error)
         .into_box()
 }
 
@@ -93,6 +163,10 @@ pub fn read<C: Client>(
 
 /// Delete a file from the directory.
 ///
+/// The data map of an unpublished file is deleted as well, unless it is shared with hard links or
+/// history is enabled for the file, in which case previous versions may still refer to it. The
+/// history itself is kept. Deleting a symbolic link doesn't affect the file it points to.
+///
 /// If `version` is `Version::GetNext`, the current version is first retrieved from the network, and
 /// that version incremented by one is then used as the actual version.
 // Allow pass by value for consistency with other functions.
@@ -128,13 +202,12 @@ where
     version_fut
         .and_then(move |version| {
             if !published {
-                fetch(client, parent2, name2)
-                    .and_then(move |(_, file)| {
-                        client2
-                            .del_unpub_idata(*file.data_map_name())
-                            .map(move |_| version)
-                            .map_err(NfsError::from)
+                link::fetch_entry(&client, &parent2, &name2)
+                    .and_then(move |(_, entry)| match entry {
+                        Entry::File(file) => link::release(&client2, &file),
+                        Entry::Link(_) => ok!(()),
                     })
+                    .map(move |()| version)
                     .into_box()
             } else {
                 ok!(version)
@@ -155,8 +228,12 @@ where
 
 /// Update the file.
 ///
-/// If `version` is `Version::GetNext`, the current version is first retrieved from the network, and
-/// that version incremented by one is then used as the actual version.
+/// Symbolic links are followed, in which case the file they point to is updated, like `fetch`
+/// returns it. The current entry is first retrieved from the network. If history is enabled for
+/// the current file, it is recorded in the history, and `file` inherits the history unless it has
+/// its own. If the current file is a hard link and `file` has a different data map, it's no longer
+/// counted as a link. If `version` is `Version::GetNext`, the current version incremented by one
+/// is used as the actual version.
 pub fn update<S>(
     client: impl Client,
     parent: MDataInfo,
@@ -167,40 +244,282 @@ pub fn update<S>(
 where
     S: AsRef<str>,
 {
-    let name = name.as_ref();
+    let name = name.as_ref().to_owned();
     trace!("Updating file with name '{}'", name);
 
     let client2 = client.clone();
+    let client3 = client.clone();
+    let mut file = file.clone();
 
-    serialize(&file)
-        .map_err(From::from)
-        .and_then(|encoded| {
-            let key = parent.enc_entry_key(name.as_bytes())?;
-            let content = parent.enc_entry_value(&encoded)?;
+    link::follow(client, parent, name, false)
+        .and_then(move |resolved| {
+            let (parent, name, current_version, current) = match resolved {
+                Resolved::File {
+                    parent,
+                    name,
+                    version,
+                    file,
+                } => (parent, name, version, file),
+                Resolved::Dir(_) => return err!(NfsError::FileNotFound),
+            };
 
-            Ok((key, content))
-        })
-        .into_future()
-        .and_then(move |(key, content)| match version {
-            Version::GetNext => client
-                .get_seq_mdata_value(parent.name(), parent.type_tag(), key.clone())
-                .map(move |value| (key, content, value.version + 1, parent))
-                .into_box(),
-            Version::Custom(version) => ok!((key, content, version, parent)),
-        })
-        .and_then(move |(key, content, version, parent)| {
-            client2
-                .mutate_seq_mdata_entries(
-                    parent.name(),
-                    parent.type_tag(),
-                    MDataSeqEntryActions::new().update(key, content, version),
-                )
+            if !file.metadata().has_history() {
+                file.metadata_mut()
+                    .set_history(current.metadata().history().cloned());
+            }
+            let version = match version {
+                Version::GetNext => current_version + 1,
+                Version::Custom(version) => version,
+            };
+            let key = fry!(parent.enc_entry_key(name.as_bytes()));
+            let content = fry!(serialize(&file)
+                .map_err(CoreError::from)
+                .and_then(|encoded| parent.enc_entry_value(&encoded)));
+            let replaced = file.data_map_name() != current.data_map_name();
+
+            file_history::record(&client2, &parent, &current)
+                .and_then(move |()| {
+                    client2
+                        .mutate_seq_mdata_entries(
+                            parent.name(),
+                            parent.type_tag(),
+                            MDataSeqEntryActions::new().update(key, content, version),
+                        )
+                        .map_err(convert_error)
+                })
+                .and_then(move |()| {
+                    if replaced {
+                        link::unlink(&client3, &current)
+                    } else {
+                        ok!(())
+                    }
+                })
                 .map(move |()| version)
+                .into_box()
+        })
+        .into_box()
+}
+
+/// Rename a file within the directory.
+///
+/// The new entry is inserted and the old one deleted in a single mutation, so the file is never
+/// duplicated or lost on failure. If `version` is `Version::GetNext`, the current version of the
+/// old entry is first retrieved from the network, and that version incremented by one is then used
+/// to delete it. Renaming a file to its own name does nothing.
+pub fn rename<S, T>(
+    client: impl Client,
+    parent: MDataInfo,
+    old_name: S,
+    new_name: T,
+    version: Version,
+) -> Box<NfsFuture<()>>
+where
+    S: AsRef<str>,
+    T: AsRef<str>,
+{
+    let old_name = old_name.as_ref();
+    let new_name = new_name.as_ref();
+    trace!("Renaming file '{}' to '{}'", old_name, new_name);
+
+    let old_key = fry!(parent.enc_entry_key(old_name.as_bytes()));
+    let new_key = fry!(parent.enc_entry_key(new_name.as_bytes()));
+    let client2 = client.clone();
+    let same_name = old_key == new_key;
+
+    client
+        .get_seq_mdata_value(parent.name(), parent.type_tag(), old_key.clone())
+        .and_then(move |value| {
+            // Both actions would have the same key, leaving only the deletion.
+            if same_name {
+                return ok!(());
+            }
+
+            let version = match version {
+                Version::GetNext => value.version + 1,
+                Version::Custom(version) => version,
+            };
+            // The encrypted value doesn't depend on the key, so it can be moved as is.
+            let actions = MDataSeqEntryActions::new()
+                .ins(new_key, value.data, 0)
+                .del(old_key, version);
+
+            client2.mutate_seq_mdata_entries(parent.name(), parent.type_tag(), actions)
         })
         .map_err(convert_error)
         .into_box()
 }
 
+/// Copy a file into the destination directory, which may also be the source directory.
+///
+/// The content of the file is not uploaded again. Instead, the data map is stored under the
+/// encryption key of the destination directory, pointing to the existing chunks. A published file
+/// copied between directories sharing the same key reuses the original data map. An unpublished
+/// file copied into a directory without encryption key has the same data map as any identical
+/// file stored there, so it's counted as a hard link to it, see `link::hard_link`. The version
+/// history of the file isn't copied.
+pub fn copy<S, T>(
+    client: impl Client,
+    src_parent: MDataInfo,
+    src_name: S,
+    dst_parent: MDataInfo,
+    dst_name: T,
+) -> Box<NfsFuture<()>>
+where
+    S: AsRef<str>,
+    T: AsRef<str>,
+{
+    let src_name = src_name.as_ref();
+    let dst_name = dst_name.as_ref().to_owned();
+    trace!("Copying file '{}' to '{}'", src_name, dst_name);
+
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let src_key = src_parent.enc_key().cloned();
+    let dst_parent2 = dst_parent.clone();
+
+    fetch(client, src_parent, src_name)
+        .and_then(move |(_, file)| {
+            // Unpublished data maps are deleted together with their file, so can't be shared.
+            let share = file.published();
+            copy_data_map(&client2, file, src_key, &dst_parent2, share)
+        })
+        .and_then(move |mut file| {
+            // The copy has its own history, if enabled, rather than sharing the original's.
+            file.metadata_mut().set_history(None);
+            insert(client3, dst_parent, dst_name, &file)
+        })
+        .into_box()
+}
+
+/// Move a file into the destination directory.
+///
+/// The file is first inserted into the destination directory, with its data map re-encrypted if
+/// the directories use different keys, and only then removed from the source directory. If this
+/// is interrupted, the file ends up in both directories and `recover_move` can be used to finish
+/// or roll back the move. Moving a file within a single directory is the same as `rename`.
+///
+/// Symbolic links are moved as they are, without following them.
+pub fn move_file<S, T>(
+    client: impl Client,
+    src_parent: MDataInfo,
+    src_name: S,
+    dst_parent: MDataInfo,
+    dst_name: T,
+) -> Box<NfsFuture<()>>
+where
+    S: AsRef<str>,
+    T: AsRef<str>,
+{
+    let src_name = src_name.as_ref().to_owned();
+    let dst_name = dst_name.as_ref().to_owned();
+    trace!("Moving file '{}' to '{}'", src_name, dst_name);
+
+    if src_parent.address() == dst_parent.address() {
+        return rename(client, src_parent, src_name, dst_name, Version::GetNext);
+    }
+
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let client4 = client.clone();
+    let src_parent2 = src_parent.clone();
+    let src_name2 = src_name.clone();
+    let src_key = src_parent.enc_key().cloned();
+    let dst_parent2 = dst_parent.clone();
+
+    link::fetch_entry(&client, &src_parent, &src_name)
+        .and_then(move |(version, entry)| {
+            let moved = match entry {
+                Entry::File(ref file) => {
+                    copy_data_map(&client2, file.clone(), src_key, &dst_parent2, true)
+                        .map(Entry::File)
+                        .into_box()
+                }
+                Entry::Link(ref link) => ok!(Entry::Link(link.clone())),
+            };
+            moved.map(move |moved| (version, entry, moved))
+        })
+        .and_then(move |(version, entry, moved)| {
+            insert_entry(client3, dst_parent, &dst_name, &moved)
+                .map(move |()| (version, entry, moved))
+        })
+        .and_then(move |(version, entry, moved)| {
+            remove_moved(
+                &client4,
+                &src_parent2,
+                &src_name2,
+                version + 1,
+                &entry,
+                &moved,
+            )
+        })
+        .into_box()
+}
+
+/// How `recover_move` should resolve an interrupted move.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MoveRecovery {
+    /// Complete the move, so the file is only in the destination directory.
+    Finish,
+    /// Undo the move, so the file is only in the source directory.
+    RollBack,
+}
+
+/// Finish or roll back a `move_file` which didn't complete, e.g. because the app was closed.
+///
+/// Both directories are inspected and only the missing steps are performed, so this can be called
+/// even if the move has actually completed or never started. Fails with `NfsError::FileExists` if
+/// the destination contains a different file, and with `NfsError::FileNotFound` if neither
+/// directory contains the file.
+pub fn recover_move<S, T>(
+    client: impl Client,
+    src_parent: MDataInfo,
+    src_name: S,
+    dst_parent: MDataInfo,
+    dst_name: T,
+    recovery: MoveRecovery,
+) -> Box<NfsFuture<()>>
+where
+    S: AsRef<str>,
+    T: AsRef<str>,
+{
+    let src_name = src_name.as_ref().to_owned();
+    let dst_name = dst_name.as_ref().to_owned();
+    trace!(
+        "Recovering move of file '{}' to '{}': {:?}",
+        src_name,
+        dst_name,
+        recovery
+    );
+
+    let src_fut = link::fetch_entry_if_exists(&client, &src_parent, &src_name);
+    let dst_fut = link::fetch_entry_if_exists(&client, &dst_parent, &dst_name);
+
+    src_fut
+        .join(dst_fut)
+        .and_then(move |(src, dst)| match (src, dst, recovery) {
+            (None, None, _) => err!(NfsError::FileNotFound),
+            (Some((_, ref src)), Some((_, ref dst)), _) if !same_entry(src, dst) => {
+                err!(NfsError::FileExists)
+            }
+            (Some(_), None, MoveRecovery::Finish) => {
+                move_file(client, src_parent, src_name, dst_parent, dst_name)
+            }
+            (Some((version, src)), Some((_, dst)), MoveRecovery::Finish) => {
+                remove_moved(&client, &src_parent, &src_name, version + 1, &src, &dst)
+            }
+            (None, Some(_), MoveRecovery::RollBack) => {
+                move_file(client, dst_parent, dst_name, src_parent, src_name)
+            }
+            (Some((_, src)), Some((version, dst)), MoveRecovery::RollBack) => {
+                remove_moved(&client, &dst_parent, &dst_name, version + 1, &dst, &src)
+            }
+            (Some(_), None, MoveRecovery::RollBack) | (None, Some(_), MoveRecovery::Finish) => {
+                ok!(())
+            }
+        })
+        .into_box()
+}
+
 /// Helper function to update content of a file in a directory. A Writer
 /// object is returned, through which the data for the file can be written to
 /// the network. The file is actually saved in the directory listing only after
@@ -222,12 +541,147 @@ pub fn write<C: Client>(
     )
 }
 
+// Compare two listed files by the given field, falling back to their names.
+fn compare_files(sort_by: SortBy, a: &(String, u64, File), b: &(String, u64, File)) -> Ordering {
+    let (ref a_name, _, ref a_file) = *a;
+    let (ref b_name, _, ref b_file) = *b;
+
+    let ordering = match sort_by {
+        SortBy::Name => Ordering::Equal,
+        SortBy::Size => a_file.size().cmp(&b_file.size()),
+        SortBy::Created => a_file.created_time().cmp(b_file.created_time()),
+        SortBy::Modified => a_file.modified_time().cmp(b_file.modified_time()),
+    };
+
+    ordering.then_with(|| a_name.cmp(b_name))
+}
+
+// Insert the encoded entry into the directory, failing if an entry with the same name exists.
+pub(crate) fn insert_entry(
+    client: impl Client,
+    parent: MDataInfo,
+    name: &str,
+    entry: &Entry,
+) -> Box<NfsFuture<()>> {
+    entry
+        .encode()
+        .and_then(|encoded| {
+            let key = parent.enc_entry_key(name.as_bytes())?;
+            let value = parent.enc_entry_value(&encoded)?;
+
+            Ok((key, value))
+        })
+        .into_future()
+        .and_then(move |(key, value)| {
+            client
+                .mutate_seq_mdata_entries(
+                    parent.name(),
+                    parent.type_tag(),
+                    MDataSeqEntryActions::new().ins(key, value, 0),
+                )
+                .map_err(convert_error)
+        })
+        .into_box()
+}
+
+// Return a copy of `file` whose data map is stored under the encryption key of `dst` instead of
+// `src_key`. The chunks are not touched. If `share` is set and the keys are the same, the original
+// data map is reused instead of being stored again. An unpublished data map which can't be stored
+// again under a different name, because `dst` has no encryption key and the same data map is
+// already stored, is shared with the files already using it and counted as a hard link.
+pub(crate) fn copy_data_map(
+    client: &impl Client,
+    mut file: File,
+    src_key: Option<shared_secretbox::Key>,
+    dst: &MDataInfo,
+    share: bool,
+) -> Box<NfsFuture<File>> {
+    let dst_key = dst.enc_key().cloned();
+    if share && src_key == dst_key {
+        return ok!(file);
+    }
+
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let dst = dst.clone();
+    let published = file.published();
+
+    data_map::get(client, file.data_address(), src_key)
+        .and_then(move |data_map| data_map::put_or_share(&client2, &data_map, published, dst_key))
+        .and_then(move |(data_map_name, existing)| {
+            file.set_data_map_name(data_map_name);
+            if existing && !published && dst.enc_key().is_none() {
+                link::add_link(&client3, &dst, data_map_name)
+                    .map(move |()| file)
+                    .into_box()
+            } else {
+                ok!(file)
+            }
+        })
+        .into_box()
+}
+
+// Delete an entry which has been moved elsewhere as `moved`, releasing the data map of the file
+// if it was not carried over to `moved`.
+pub(crate) fn remove_moved(
+    client: &impl Client,
+    parent: &MDataInfo,
+    name: &str,
+    version: u64,
+    entry: &Entry,
+    moved: &Entry,
+) -> Box<NfsFuture<()>> {
+    let key = fry!(parent.enc_entry_key(name.as_bytes()));
+    let client2 = client.clone();
+    let stale = match (entry, moved) {
+        (Entry::File(file), Entry::File(moved))
+            if file.data_map_name() != moved.data_map_name() =>
+        {
+            Some(file.clone())
+        }
+        _ => None,
+    };
+
+    recovery::mutate_mdata_entries(
+        client,
+        *parent.address(),
+        MDataSeqEntryActions::new().del(key, version),
+    )
+    .map_err(convert_error)
+    .and_then(move |()| match stale {
+        Some(file) => link::release(&client2, &file),
+        None => ok!(()),
+    })
+    .into_box()
+}
+
+// Check whether the two entries are the same, except for where the data maps of files are stored.
+fn same_entry(a: &Entry, b: &Entry) -> bool {
+    match (a, b) {
+        (Entry::File(a), Entry::File(b)) => {
+            let mut b = b.clone();
+            b.set_data_map_name(*a.data_map_name());
+            *a == b
+        }
+        (Entry::Link(a), Entry::Link(b)) => a == b,
+        _ => false,
+    }
+}
+
 // This is different from `impl From<CoreError> for NfsError`, because it maps
-// `NoSuchEntry` to `FileNotFound`.
+// `NoSuchEntry` to `FileNotFound` and existing entries to `FileExists`.
 // TODO:  consider performing such conversion directly in the mentioned `impl From`.
-fn convert_error(err: CoreError) -> NfsError {
+pub(crate) fn convert_error(err: CoreError) -> NfsError {
     match err {
         CoreError::DataError(SndError::NoSuchEntry) => NfsError::FileNotFound,
+        CoreError::DataError(SndError::InvalidEntryActions(ref errors))
+            if errors.values().any(|error| match *error {
+                EntryError::EntryExists(_) => true,
+                _ => false,
+            }) =>
+        {
+            NfsError::FileExists
+        }
         _ => NfsError::from(err),
     }
 }
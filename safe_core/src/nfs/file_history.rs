@@ -0,0 +1,345 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Version history of files.
+//!
+//! The history of a file is an append-only log of its previous `File` records. The log is created
+//! by `enable`, and its address and encryption key are stored in the metadata of the file, so the
+//! history follows the file when it is renamed or moved to another directory. Once enabled,
+//! `file_helper::update` records the replaced version of the file every time it is updated.
+//! Everyone allowed to modify the directory of the file at the time history is enabled is allowed
+//! to append to the log.
+//!
+//! The data maps of files with history are never deleted, as previous versions may still refer to
+//! them.
+
+use crate::client::{Client, MDataInfo};
+use crate::crypto::shared_secretbox;
+use crate::errors::CoreError;
+use crate::nfs::file_helper::{self, Version};
+use crate::nfs::link::{self, Resolved};
+use crate::nfs::metadata::History;
+use crate::nfs::{File, NfsError, NfsFuture, Reader};
+use crate::utils::{self, FutureExt};
+use crate::FILE_HISTORY_TAG;
+use bincode::{deserialize, serialize};
+use chrono::Utc;
+use futures::Future;
+use safe_nd::{
+    ADataAddress, ADataAppendOperation, ADataEntry, ADataIndex, ADataOwner,
+    ADataUnpubPermissionSet, ADataUnpubPermissions, AppendOnlyData, Error as SndError, PublicKey,
+    UnpubUnseqAppendOnlyData,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use tiny_keccak::sha3_256;
+
+// A previous version of a file, together with the key its data map is encrypted with. That is the
+// key of the directory the file was in at the time, which may differ from its current directory.
+#[derive(Serialize, Deserialize)]
+struct Record {
+    file: File,
+    encryption_key: Option<shared_secretbox::Key>,
+}
+
+/// Enable version history for the file with the given name, updating its directory entry.
+///
+/// Symbolic links are followed, in which case history is enabled for the file they point to.
+/// Enabling history for a file which already has it is not an error.
+pub fn enable<S>(client: impl Client, parent: MDataInfo, name: S) -> Box<NfsFuture<()>>
+where
+    S: AsRef<str>,
+{
+    let name = name.as_ref().to_owned();
+    trace!("Enabling history for file with name '{}'", name);
+
+    let client2 = client.clone();
+
+    link::follow(client, parent, name, false)
+        .and_then(move |resolved| match resolved {
+            Resolved::File {
+                parent,
+                name,
+                version,
+                file,
+            } => {
+                if file.metadata().has_history() {
+                    return ok!(());
+                }
+                create_log(client2, parent, name, version, file)
+            }
+            Resolved::Dir(_) => err!(NfsError::FileNotFound),
+        })
+        .into_box()
+}
+
+/// List the previous versions of the file, oldest first, together with their index in the history.
+///
+/// Symbolic links are followed. Returns an empty list if history is not enabled for the file.
+pub fn list<S>(client: impl Client, parent: MDataInfo, name: S) -> Box<NfsFuture<Vec<(u64, File)>>>
+where
+    S: AsRef<str>,
+{
+    let client2 = client.clone();
+
+    file_helper::fetch(client, parent, name)
+        .and_then(move |(_, file)| {
+            let history = match file.metadata().history() {
+                Some(history) => history.clone(),
+                None => return ok!(Vec::new()),
+            };
+
+            client2
+                .get_adata_range(
+                    address(&history),
+                    (ADataIndex::FromStart(0), ADataIndex::FromEnd(0)),
+                )
+                .map_err(NfsError::from)
+                .and_then(move |entries| {
+                    entries
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, entry)| {
+                            let record = decode_record(&history, &entry.value)?;
+                            Ok((index as u64, record.file))
+                        })
+                        .collect::<Result<_, NfsError>>()
+                })
+                .into_box()
+        })
+        .into_box()
+}
+
+/// Get the previous version of the file with the given index in its history.
+pub fn fetch<S>(client: impl Client, parent: MDataInfo, name: S, index: u64) -> Box<NfsFuture<File>>
+where
+    S: AsRef<str>,
+{
+    fetch_record(client, parent, name.as_ref(), index)
+        .map(|record| record.file)
+        .into_box()
+}
+
+/// Return a Reader for reading the contents of the previous version of the file with the given
+/// index in its history.
+pub fn read<C: Client, S>(
+    client: C,
+    parent: MDataInfo,
+    name: S,
+    index: u64,
+) -> Box<NfsFuture<Reader<C>>>
+where
+    S: AsRef<str>,
+{
+    fetch_record(client.clone(), parent, name.as_ref(), index)
+        .and_then(move |record| file_helper::read(client, &record.file, record.encryption_key))
+        .into_box()
+}
+
+/// Restore the previous version of the file with the given index in its history, recording the
+/// current version in the history.
+///
+/// Symbolic links are followed. Returns the new version of the directory entry.
+pub fn restore<S>(
+    client: impl Client,
+    parent: MDataInfo,
+    name: S,
+    index: u64,
+) -> Box<NfsFuture<u64>>
+where
+    S: AsRef<str>,
+{
+    let name = name.as_ref().to_owned();
+    trace!("Restoring version {} of file with name '{}'", index, name);
+
+    let client2 = client.clone();
+    let client3 = client.clone();
+
+    link::follow(client, parent, name, false)
+        .and_then(move |resolved| match resolved {
+            Resolved::File {
+                parent,
+                name,
+                version,
+                file,
+            } => fetch_from(&client2, &file, index)
+                .map(move |record| (parent, name, version, record))
+                .into_box(),
+            Resolved::Dir(_) => err!(NfsError::FileNotFound),
+        })
+        .and_then(move |(parent, name, version, record)| {
+            let mut file = record.file;
+            file.set_modified_time(Utc::now());
+
+            // A version of the file stored in another directory has its data map encrypted with
+            // the key of that directory, so it is re-encrypted for this one.
+            file_helper::copy_data_map(&client3, file, record.encryption_key, &parent, true)
+                .and_then(move |file| {
+                    file_helper::update(client3, parent, name, &file, Version::Custom(version + 1))
+                })
+        })
+        .into_box()
+}
+
+// Append the file, which is about to be replaced in the given directory, to its history. Does
+// nothing if history is not enabled for the file, or if the same record has already been appended.
+pub(crate) fn record(client: &impl Client, parent: &MDataInfo, file: &File) -> Box<NfsFuture<()>> {
+    let history = match file.metadata().history() {
+        Some(history) => history,
+        None => return ok!(()),
+    };
+
+    let record = Record {
+        file: file.clone(),
+        encryption_key: parent.enc_key().cloned(),
+    };
+    let encoded = fry!(serialize(&record).map_err(CoreError::from));
+    let value = fry!(utils::symmetric_encrypt(&encoded, &history.enc_key, None));
+
+    // The key is derived from the record, so retrying an update which failed after the record
+    // was appended doesn't record the same version twice.
+    let mut seed = history.enc_key.to_vec();
+    seed.extend_from_slice(&encoded);
+
+    let append = ADataAppendOperation {
+        address: address(history),
+        values: vec![ADataEntry {
+            key: sha3_256(&seed).to_vec(),
+            value,
+        }],
+    };
+
+    client
+        .append_unseq_adata(append)
+        .or_else(|error| match error {
+            CoreError::DataError(SndError::KeysExist(_)) => Ok(()),
+            error => Err(error),
+        })
+        .map_err(NfsError::from)
+        .into_box()
+}
+
+// Create the log for the history of the file and store its location in the file metadata.
+fn create_log(
+    client: impl Client,
+    parent: MDataInfo,
+    name: String,
+    version: u64,
+    mut file: File,
+) -> Box<NfsFuture<()>> {
+    let history = History {
+        name: rand::random(),
+        enc_key: utils::generate_sym_enc_key(),
+    };
+    let client2 = client.clone();
+    let client3 = client.clone();
+
+    link::dir_writers(&client, &parent)
+        .and_then(move |writers| {
+            let data = fry!(new_log(&client2, &history, writers));
+            file.metadata_mut().set_history(Some(history));
+
+            client2
+                .put_adata(data.into())
+                .map_err(NfsError::from)
+                .map(move |()| file)
+                .into_box()
+        })
+        .and_then(move |file| {
+            file_helper::update(client3, parent, name, &file, Version::Custom(version + 1))
+        })
+        .map(|_| ())
+        .into_box()
+}
+
+// New log for a history. `file_helper::update` records the history of the file before updating
+// it, so everyone allowed to modify the directory of the file is allowed to append to the log,
+// and not only the client creating it.
+fn new_log(
+    client: &impl Client,
+    history: &History,
+    writers: BTreeSet<PublicKey>,
+) -> Result<UnpubUnseqAppendOnlyData, CoreError> {
+    let own_key = client.public_key();
+    let mut data = UnpubUnseqAppendOnlyData::new(history.name, FILE_HISTORY_TAG);
+
+    let permissions: BTreeMap<_, _> = writers
+        .into_iter()
+        .map(|key| {
+            let manage = key == own_key;
+            (key, ADataUnpubPermissionSet::new(true, true, manage))
+        })
+        .collect();
+    data.append_permissions(
+        ADataUnpubPermissions {
+            permissions,
+            entries_index: 0,
+            owners_index: 0,
+        },
+        0,
+    )?;
+    data.append_owner(
+        ADataOwner {
+            public_key: client.owner_key(),
+            entries_index: 0,
+            permissions_index: 1,
+        },
+        0,
+    )?;
+
+    Ok(data)
+}
+
+fn fetch_record(
+    client: impl Client,
+    parent: MDataInfo,
+    name: &str,
+    index: u64,
+) -> Box<NfsFuture<Record>> {
+    let client2 = client.clone();
+
+    file_helper::fetch(client, parent, name)
+        .and_then(move |(_, file)| fetch_from(&client2, &file, index))
+        .into_box()
+}
+
+// Get the record with the given index from the history of the file.
+fn fetch_from(client: &impl Client, file: &File, index: u64) -> Box<NfsFuture<Record>> {
+    let history = match file.metadata().history() {
+        Some(history) => history.clone(),
+        None => return err!(NfsError::FileNotFound),
+    };
+    let range = (
+        ADataIndex::FromStart(index),
+        ADataIndex::FromStart(index + 1),
+    );
+
+    client
+        .get_adata_range(address(&history), range)
+        .then(move |result| match result {
+            Ok(entries) => match entries.first() {
+                Some(entry) => decode_record(&history, &entry.value),
+                None => Err(NfsError::FileNotFound),
+            },
+            Err(CoreError::DataError(SndError::NoSuchEntry)) => Err(NfsError::FileNotFound),
+            Err(error) => Err(NfsError::from(error)),
+        })
+        .into_box()
+}
+
+fn decode_record(history: &History, value: &[u8]) -> Result<Record, NfsError> {
+    let plaintext = utils::symmetric_decrypt(value, &history.enc_key)?;
+    Ok(deserialize(&plaintext)?)
+}
+
+fn address(history: &History) -> ADataAddress {
+    ADataAddress::UnpubUnseq {
+        name: history.name,
+        tag: FILE_HISTORY_TAG,
+    }
+}
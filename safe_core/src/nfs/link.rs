@@ -0,0 +1,698 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Symbolic and hard links.
+//!
+//! A symbolic link is a directory entry which, instead of a `File`, holds a `Link` pointing to a
+//! path or to an entry in another directory. `file_helper::fetch` and `resolve` follow such links.
+//!
+//! A hard link is a directory entry holding a `File` which shares its data map with other
+//! entries. The number of entries sharing an unpublished data map is counted on the network, so
+//! the data map is only deleted together with its last entry. The count is decremented when one of
+//! the entries is deleted or updated to a different data map, and deleted together with the data
+//! map. Everyone allowed to modify the directory a hard link is first inserted into is allowed to
+//! update the count.
+
+use crate::client::{Client, MDataInfo};
+use crate::errors::CoreError;
+use crate::ipc::resp::METADATA_KEY;
+use crate::nfs::file_helper::{self, convert_error};
+use crate::nfs::{File, NfsError, NfsFuture};
+use crate::utils::FutureExt;
+use crate::FILE_LINK_COUNT_TAG;
+use bincode::{deserialize, serialize};
+use futures::future::{self, Loop};
+use futures::Future;
+use safe_nd::{
+    EntryError, Error as SndError, MDataAction, MDataAddress, MDataPermissionSet,
+    MDataSeqEntryActions, MDataSeqValue, PublicKey, SeqMutableData, XorName,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::convert::TryInto;
+use tiny_keccak::sha3_256;
+
+/// Maximum number of links followed while resolving a single name or path.
+pub const MAX_LINK_HOPS: usize = 40;
+
+// Prefix marking an encoded `Link`. A serialised `File` starts with its size, which can't be
+// `u64::max_value()`, so the two can't be confused.
+const LINK_PREFIX: [u8; 8] = [0xff; 8];
+// Key of the entry holding the number of links to a data map.
+const LINK_COUNT_KEY: &[u8] = b"count";
+const MAX_ATTEMPTS: usize = 10;
+
+/// Target of a symbolic link.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Link {
+    /// Path relative to the directory containing the link. See `resolve` for how paths are
+    /// interpreted.
+    Path(String),
+    /// Entry in the given directory, which may belong to someone else. If `name` is `None`, the
+    /// link points to the directory itself.
+    Entry {
+        /// Directory containing the target.
+        dir: MDataInfo,
+        /// Name of the target within the directory.
+        name: Option<String>,
+    },
+}
+
+/// Result of resolving a path.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Resolved {
+    /// The path points to a directory.
+    Dir(MDataInfo),
+    /// The path points to a file.
+    File {
+        /// Directory containing the file entry.
+        parent: MDataInfo,
+        /// Name of the file entry.
+        name: String,
+        /// Version of the file entry.
+        version: u64,
+        /// The file.
+        file: File,
+    },
+}
+
+// Decoded value of a directory entry.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Entry {
+    File(File),
+    Link(Link),
+}
+
+impl Entry {
+    pub(crate) fn decode(plaintext: &[u8]) -> Result<Self, NfsError> {
+        if plaintext.starts_with(&LINK_PREFIX) {
+            Ok(Entry::Link(deserialize(&plaintext[LINK_PREFIX.len()..])?))
+        } else {
+            Ok(Entry::File(File::decode(plaintext)?))
+        }
+    }
+
+    pub(crate) fn encode(&self) -> Result<Vec<u8>, NfsError> {
+        match *self {
+            Entry::File(ref file) => Ok(serialize(file)?),
+            Entry::Link(ref link) => {
+                let mut encoded = LINK_PREFIX.to_vec();
+                encoded.extend_from_slice(&serialize(link)?);
+                Ok(encoded)
+            }
+        }
+    }
+}
+
+/// Insert a symbolic link with the given name into the directory.
+///
+/// The target doesn't need to exist. Fails with `NfsError::FileExists` if the directory already
+/// contains an entry with the same name.
+pub fn symlink<S>(
+    client: impl Client,
+    parent: MDataInfo,
+    name: S,
+    link: &Link,
+) -> Box<NfsFuture<()>>
+where
+    S: AsRef<str>,
+{
+    let name = name.as_ref();
+    trace!("Inserting link with name '{}' to {:?}", name, link);
+
+    file_helper::insert_entry(client, parent, name, &Entry::Link(link.clone()))
+}
+
+/// Get the symbolic link with the given name and its version, without following it.
+pub fn read_link<S>(client: impl Client, parent: MDataInfo, name: S) -> Box<NfsFuture<(u64, Link)>>
+where
+    S: AsRef<str>,
+{
+    fetch_entry(&client, &parent, name.as_ref())
+        .and_then(|(version, entry)| match entry {
+            Entry::Link(link) => Ok((version, link)),
+            Entry::File(_) => Err(NfsError::Unexpected("Not a link".to_string())),
+        })
+        .into_box()
+}
+
+/// List the symbolic links in the directory, returning their names, versions and targets.
+pub fn list(client: impl Client, parent: MDataInfo) -> Box<NfsFuture<Vec<(String, u64, Link)>>> {
+    trace!("Listing links in directory {:?}", parent.address());
+
+    client
+        .list_seq_mdata_entries(parent.name(), parent.type_tag())
+        .map_err(NfsError::from)
+        .map(move |entries| {
+            entries
+                .into_iter()
+                .filter(|(key, _)| key.as_slice() != METADATA_KEY)
+                .filter_map(|(key, value)| {
+                    let name = String::from_utf8(parent.decrypt(&key).ok()?).ok()?;
+                    match Entry::decode(&parent.decrypt(&value.data).ok()?).ok()? {
+                        Entry::Link(link) => Some((name, value.version, link)),
+                        Entry::File(_) => None,
+                    }
+                })
+                .collect()
+        })
+        .into_box()
+}
+
+/// Resolve a path relative to the directory, following any links on the way.
+///
+/// Files are commonly stored under names containing `/`, so at each step the remaining path is
+/// first looked up as a whole. Only if there is no such entry is its first component looked up,
+/// which then has to be a link to a directory. Empty components are ignored, and `.` and `..` have
+/// no special meaning.
+///
+/// Fails with `NfsError::FileNotFound` if the path can't be resolved, and with
+/// `NfsError::TooManyLinks` if more than `MAX_LINK_HOPS` links are followed or a loop is found.
+pub fn resolve<S>(client: impl Client, dir: MDataInfo, path: S) -> Box<NfsFuture<Resolved>>
+where
+    S: AsRef<str>,
+{
+    trace!("Resolving path '{}'", path.as_ref());
+    follow(client, dir, path.as_ref().to_owned(), true)
+}
+
+/// Insert a hard link to a file into the destination directory, which may also be the source
+/// directory.
+///
+/// The new entry shares the data map of the file, so both directories must use the same
+/// encryption key. If the source is a symbolic link, the file it points to is linked. Later
+/// updates to either entry are not reflected in the other one.
+pub fn hard_link<S, T>(
+    client: impl Client,
+    src_parent: MDataInfo,
+    src_name: S,
+    dst_parent: MDataInfo,
+    dst_name: T,
+) -> Box<NfsFuture<()>>
+where
+    S: AsRef<str>,
+    T: AsRef<str>,
+{
+    let src_name = src_name.as_ref();
+    let dst_name = dst_name.as_ref().to_owned();
+    trace!("Linking file '{}' as '{}'", src_name, dst_name);
+
+    if src_parent.enc_key() != dst_parent.enc_key() {
+        return err!(NfsError::Unexpected(
+            "Hard links require directories with the same encryption key".to_string()
+        ));
+    }
+
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let dst_parent2 = dst_parent.clone();
+
+    file_helper::fetch(client, src_parent, src_name)
+        .and_then(move |(_, file)| {
+            if file.published() {
+                ok!(file)
+            } else {
+                add_link(&client2, &dst_parent2, *file.data_map_name())
+                    .map(move |()| file)
+                    .into_box()
+            }
+        })
+        .and_then(move |file| {
+            // Roll back the count if the insertion fails, so the data map can still be deleted.
+            let client4 = client3.clone();
+            let data_map_name = *file.data_map_name();
+            let published = file.published();
+
+            file_helper::insert(client3, dst_parent, dst_name, &file).or_else(move |error| {
+                let rollback = if published {
+                    ok!(())
+                } else {
+                    remove_link(&client4, data_map_name).map(|_| ()).into_box()
+                };
+                rollback.then(move |_| Err(error))
+            })
+        })
+        .into_box()
+}
+
+/// Get the number of directory entries sharing the data map of the file.
+///
+/// Published files are never deleted, so their links are not counted and this returns 1.
+pub fn link_count(client: &impl Client, file: &File) -> Box<NfsFuture<u64>> {
+    if file.published() {
+        return ok!(1);
+    }
+
+    let address = link_count_address(*file.data_map_name());
+
+    client
+        .get_seq_mdata_value(*address.name(), address.tag(), LINK_COUNT_KEY.to_vec())
+        .then(|result| match result {
+            Ok(value) => decode_count(&value.data),
+            Err(CoreError::DataError(SndError::NoSuchData))
+            | Err(CoreError::DataError(SndError::NoSuchEntry)) => Ok(1),
+            Err(error) => Err(NfsError::from(error)),
+        })
+        .into_box()
+}
+
+// Delete the data map of an unpublished file whose entry has been removed, unless other entries
+// still share it. Data maps of files with history are kept, as the history may refer to them.
+pub(crate) fn release(client: &impl Client, file: &File) -> Box<NfsFuture<()>> {
+    if file.published() || file.metadata().has_history() {
+        return ok!(());
+    }
+
+    let client2 = client.clone();
+    let data_map_name = *file.data_map_name();
+
+    remove_link(client, data_map_name)
+        .and_then(move |remaining| {
+            if remaining > 0 {
+                return ok!(());
+            }
+
+            client2
+                .del_unpub_idata(data_map_name)
+                .or_else(|error| match error {
+                    CoreError::DataError(SndError::NoSuchData) => Ok(()),
+                    error => Err(error),
+                })
+                .map_err(NfsError::from)
+                .into_box()
+        })
+        .into_box()
+}
+
+// Stop counting the entry holding `file`, which has been updated to a different data map, as a
+// link to its old data map. Unlike `release`, the data map is kept even if no other entry shares
+// it, as it's not deleted when the entry isn't shared either.
+pub(crate) fn unlink(client: &impl Client, file: &File) -> Box<NfsFuture<()>> {
+    if file.published() || file.metadata().has_history() {
+        return ok!(());
+    }
+
+    remove_link(client, *file.data_map_name())
+        .map(|_| ())
+        .into_box()
+}
+
+// Get the decoded entry with the given name and its version, without following links.
+pub(crate) fn fetch_entry(
+    client: &impl Client,
+    parent: &MDataInfo,
+    name: &str,
+) -> Box<NfsFuture<(u64, Entry)>> {
+    let key = fry!(parent.enc_entry_key(name.as_bytes()));
+    let parent = parent.clone();
+
+    client
+        .get_seq_mdata_value(parent.name(), parent.type_tag(), key)
+        .map_err(convert_error)
+        .and_then(move |value| {
+            let entry = Entry::decode(&parent.decrypt(&value.data)?)?;
+            Ok((value.version, entry))
+        })
+        .into_box()
+}
+
+// Resolve `path` in `dir`, following links. Unless `split` is set, `path` is taken to be a single
+// name, although the paths of any links followed are still split.
+pub(crate) fn follow(
+    client: impl Client,
+    dir: MDataInfo,
+    path: String,
+    split: bool,
+) -> Box<NfsFuture<Resolved>> {
+    let state = (dir, path, split, HashSet::new());
+
+    future::loop_fn(state, move |(dir, path, split, mut visited)| {
+        let path = if split {
+            path.trim_start_matches('/').to_owned()
+        } else {
+            path
+        };
+        if split && path.is_empty() {
+            return ok!(Loop::Break(Resolved::Dir(dir)));
+        }
+        if visited.len() > MAX_LINK_HOPS || !visited.insert((*dir.address(), path.clone())) {
+            return err!(NfsError::TooManyLinks);
+        }
+
+        let client2 = client.clone();
+        let dir2 = dir.clone();
+        let path2 = path.clone();
+
+        fetch_entry_if_exists(&client, &dir, &path)
+            .and_then(move |entry| match entry {
+                Some((version, Entry::File(file))) => ok!(Loop::Break(Resolved::File {
+                    parent: dir,
+                    name: path,
+                    version,
+                    file,
+                })),
+                Some((_, Entry::Link(link))) => {
+                    let (dir, path) = link_target(link, dir, "");
+                    ok!(Loop::Continue((dir, path, true, visited)))
+                }
+                None => {
+                    let (first, rest) = match path2.find('/') {
+                        Some(index) if split => (&path2[..index], path2[index + 1..].to_owned()),
+                        _ => return err!(NfsError::FileNotFound),
+                    };
+
+                    fetch_entry_if_exists(&client2, &dir2, first)
+                        .and_then(move |entry| match entry {
+                            Some((_, Entry::Link(link))) => {
+                                let (dir, path) = link_target(link, dir2, &rest);
+                                Ok(Loop::Continue((dir, path, true, visited)))
+                            }
+                            _ => Err(NfsError::FileNotFound),
+                        })
+                        .into_box()
+                }
+            })
+            .into_box()
+    })
+    .into_box()
+}
+
+// Get the decoded entry with the given name and its version, or `None` if there is no such entry.
+pub(crate) fn fetch_entry_if_exists(
+    client: &impl Client,
+    parent: &MDataInfo,
+    name: &str,
+) -> Box<NfsFuture<Option<(u64, Entry)>>> {
+    fetch_entry(client, parent, name)
+        .map(Some)
+        .or_else(|error| match error {
+            NfsError::FileNotFound => Ok(None),
+            error => Err(error),
+        })
+        .into_box()
+}
+
+// Directory and path to continue resolving from after following the link found in `dir`, with
+// `rest` being the remainder of the path being resolved.
+fn link_target(link: Link, dir: MDataInfo, rest: &str) -> (MDataInfo, String) {
+    let join = |path: String| {
+        if rest.is_empty() {
+            path
+        } else {
+            format!("{}/{}", path, rest)
+        }
+    };
+
+    match link {
+        Link::Path(path) => (dir, join(path)),
+        Link::Entry { dir, name: None } => (dir, rest.to_owned()),
+        Link::Entry {
+            dir,
+            name: Some(name),
+        } => (dir, join(name)),
+    }
+}
+
+// Increment the number of entries sharing the data map, which is being linked into `dir`. A data
+// map without a count is shared by a single entry.
+pub(crate) fn add_link(
+    client: &impl Client,
+    dir: &MDataInfo,
+    data_map_name: XorName,
+) -> Box<NfsFuture<()>> {
+    let client = client.clone();
+    let dir = dir.clone();
+    let address = link_count_address(data_map_name);
+
+    future::loop_fn(0, move |attempts| {
+        let client2 = client.clone();
+        let dir = dir.clone();
+
+        get_count(&client, address)
+            .and_then(move |current| match current {
+                Some((count, version)) => write_count(&client2, address, count + 1, version),
+                None => {
+                    let client3 = client2.clone();
+                    count_permissions(&client2, &dir)
+                        .and_then(move |permissions| put_count(&client3, address, 2, permissions))
+                        .into_box()
+                }
+            })
+            .then(move |result| retry_on_conflict(result, attempts))
+    })
+    .into_box()
+}
+
+// Decrement the number of entries sharing the data map, returning the number of remaining ones.
+// When none remain, the count itself is deleted.
+fn remove_link(client: &impl Client, data_map_name: XorName) -> Box<NfsFuture<u64>> {
+    let client = client.clone();
+    let address = link_count_address(data_map_name);
+
+    future::loop_fn(0, move |attempts| {
+        let client2 = client.clone();
+
+        get_count(&client, address)
+            .and_then(move |current| match current {
+                Some((count, version)) if count > 1 => {
+                    write_count(&client2, address, count - 1, version)
+                        .map(move |()| count - 1)
+                        .into_box()
+                }
+                Some(_) => delete_count(&client2, address).map(|()| 0).into_box(),
+                None => ok!(0),
+            })
+            .then(move |result| retry_on_conflict(result, attempts))
+    })
+    .into_box()
+}
+
+// Delete the count of links to a data map which is no longer shared. Only the owner of the account
+// can delete mutable data, so apps leave it in place. A count of one is the same as no count, so
+// that's harmless apart from the space it takes up.
+fn delete_count(client: &impl Client, address: MDataAddress) -> Box<NfsFuture<()>> {
+    client
+        .delete_mdata(address)
+        .or_else(|error| match error {
+            CoreError::DataError(SndError::NoSuchData)
+            | CoreError::DataError(SndError::AccessDenied) => Ok(()),
+            error => Err(error),
+        })
+        .map_err(NfsError::from)
+        .into_box()
+}
+
+// Permissions for the count of links to a data map being linked into `dir`. Hard links may later
+// be inserted or deleted by anyone allowed to modify the directory, so they are all allowed to
+// update the count, and not only the client creating it.
+fn count_permissions(
+    client: &impl Client,
+    dir: &MDataInfo,
+) -> Box<NfsFuture<BTreeMap<PublicKey, MDataPermissionSet>>> {
+    let full = || {
+        MDataPermissionSet::new()
+            .allow(MDataAction::Read)
+            .allow(MDataAction::Insert)
+            .allow(MDataAction::Update)
+            .allow(MDataAction::Delete)
+    };
+
+    dir_writers(client, dir)
+        .map(move |writers| writers.into_iter().map(|key| (key, full())).collect())
+        .into_box()
+}
+
+// Keys allowed to modify the entries of the directory, including the client's own key.
+pub(crate) fn dir_writers(
+    client: &impl Client,
+    dir: &MDataInfo,
+) -> Box<NfsFuture<BTreeSet<PublicKey>>> {
+    let own_key = client.public_key();
+
+    client
+        .list_mdata_permissions(*dir.address())
+        .map_err(NfsError::from)
+        .map(move |permissions| {
+            let mut writers: BTreeSet<_> = permissions
+                .into_iter()
+                .filter(|(_, set)| {
+                    set.is_allowed(MDataAction::Insert)
+                        || set.is_allowed(MDataAction::Update)
+                        || set.is_allowed(MDataAction::Delete)
+                })
+                .map(|(key, _)| key)
+                .collect();
+            let _ = writers.insert(own_key);
+            writers
+        })
+        .into_box()
+}
+
+// Get the number of links to a data map, and the version of that number, if it's been counted.
+fn get_count(client: &impl Client, address: MDataAddress) -> Box<NfsFuture<Option<(u64, u64)>>> {
+    client
+        .get_seq_mdata_value(*address.name(), address.tag(), LINK_COUNT_KEY.to_vec())
+        .then(|result| match result {
+            Ok(value) => Ok(Some((decode_count(&value.data)?, value.version))),
+            Err(CoreError::DataError(SndError::NoSuchData)) => Ok(None),
+            Err(error) => Err(NfsError::from(error)),
+        })
+        .into_box()
+}
+
+// Write the new number of links, read at `version`. A request which timed out may still have been
+// applied, so it's resubmitted unchanged rather than recomputed from the count read again, which
+// would count the change twice. A conflict following a timeout means the change was applied if
+// the count is now the one written. Other conflicts are returned, so the caller reads the count
+// again.
+fn write_count(
+    client: &impl Client,
+    address: MDataAddress,
+    count: u64,
+    version: u64,
+) -> Box<NfsFuture<()>> {
+    let client = client.clone();
+
+    future::loop_fn(0, move |attempts| {
+        let client2 = client.clone();
+
+        client
+            .mutate_seq_mdata_entries(
+                *address.name(),
+                address.tag(),
+                MDataSeqEntryActions::new().update(
+                    LINK_COUNT_KEY.to_vec(),
+                    count.to_be_bytes().to_vec(),
+                    version + 1,
+                ),
+            )
+            .map_err(NfsError::from)
+            .then(move |result| match result {
+                Ok(()) => ok!(Loop::Break(())),
+                Err(error) => if_applied(&client2, address, error, attempts, (count, version + 1)),
+            })
+    })
+    .into_box()
+}
+
+// Put a new count of links, resubmitting it if the request times out. See `write_count`.
+fn put_count(
+    client: &impl Client,
+    address: MDataAddress,
+    count: u64,
+    permissions: BTreeMap<PublicKey, MDataPermissionSet>,
+) -> Box<NfsFuture<()>> {
+    let client = client.clone();
+    let data = new_link_count(&client, address, count, permissions);
+
+    future::loop_fn(0, move |attempts| {
+        let client2 = client.clone();
+
+        client
+            .put_seq_mutable_data(data.clone())
+            .map_err(NfsError::from)
+            .then(move |result| match result {
+                Ok(()) => ok!(Loop::Break(())),
+                Err(error) => if_applied(&client2, address, error, attempts, (count, 0)),
+            })
+    })
+    .into_box()
+}
+
+// Handle the error of a write of the number of links and its version `written`: resubmit the
+// write after a timeout, and after a conflict which followed a timeout, check whether the write
+// was applied.
+fn if_applied(
+    client: &impl Client,
+    address: MDataAddress,
+    error: NfsError,
+    attempts: usize,
+    written: (u64, u64),
+) -> Box<NfsFuture<Loop<(), usize>>> {
+    match error {
+        NfsError::CoreError(CoreError::RequestTimeout) if attempts < MAX_ATTEMPTS => {
+            ok!(Loop::Continue(attempts + 1))
+        }
+        error if attempts > 0 && is_conflict(&error) => get_count(client, address)
+            .and_then(move |current| {
+                if current == Some(written) {
+                    Ok(Loop::Break(()))
+                } else {
+                    Err(error)
+                }
+            })
+            .into_box(),
+        error => err!(error),
+    }
+}
+
+// Retry an update of a link count which lost a race with another one, including one deleting the
+// count.
+fn retry_on_conflict<T>(
+    result: Result<T, NfsError>,
+    attempts: usize,
+) -> Result<Loop<T, usize>, NfsError> {
+    match result {
+        Ok(value) => Ok(Loop::Break(value)),
+        Err(ref error) if attempts < MAX_ATTEMPTS && is_conflict(error) => {
+            Ok(Loop::Continue(attempts + 1))
+        }
+        Err(error) => Err(error),
+    }
+}
+
+fn is_conflict(error: &NfsError) -> bool {
+    match *error {
+        NfsError::CoreError(CoreError::DataError(SndError::DataExists))
+        | NfsError::CoreError(CoreError::DataError(SndError::NoSuchData)) => true,
+        NfsError::CoreError(CoreError::DataError(SndError::InvalidEntryActions(ref errors))) => {
+            errors.values().all(|error| match *error {
+                EntryError::InvalidSuccessor(_) => true,
+                _ => false,
+            })
+        }
+        _ => false,
+    }
+}
+
+fn new_link_count(
+    client: &impl Client,
+    address: MDataAddress,
+    count: u64,
+    permissions: BTreeMap<PublicKey, MDataPermissionSet>,
+) -> SeqMutableData {
+    let entries = btree_map![
+        LINK_COUNT_KEY.to_vec() => MDataSeqValue {
+            data: count.to_be_bytes().to_vec(),
+            version: 0,
+        }
+    ];
+
+    SeqMutableData::new_with_data(
+        *address.name(),
+        address.tag(),
+        entries,
+        permissions,
+        client.owner_key(),
+    )
+}
+
+// Address of the data holding the number of links to the data map with the given name.
+fn link_count_address(data_map_name: XorName) -> MDataAddress {
+    MDataAddress::Seq {
+        name: XorName(sha3_256(&data_map_name.0)),
+        tag: FILE_LINK_COUNT_TAG,
+    }
+}
+
+fn decode_count(data: &[u8]) -> Result<u64, NfsError> {
+    let bytes = data
+        .try_into()
+        .map_err(|_| NfsError::Unexpected("Invalid link count".to_string()))?;
+    Ok(u64::from_be_bytes(bytes))
+}
@@ -0,0 +1,179 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::nfs::NfsError;
+use crate::utils::SymEncKey;
+use safe_nd::XorName;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Length of the SHA3-256 hash of the content of a file.
+pub const CONTENT_HASH_LEN: usize = 32;
+
+/// Structured metadata of a `File`, as opposed to the opaque user metadata.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[serde(from = "VersionedMetadata", into = "VersionedMetadata")]
+pub struct Metadata {
+    mime_type: Option<String>,
+    content_hash: Option<[u8; CONTENT_HASH_LEN]>,
+    xattrs: BTreeMap<String, AttrValue>,
+    history: Option<History>,
+}
+
+// Location of the version history of a file, and the key its records are encrypted with. It is
+// kept with the file, so the history follows the file when it is renamed or moved.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub(crate) struct History {
+    pub name: XorName,
+    pub enc_key: SymEncKey,
+}
+
+/// Value of an extended attribute.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub enum AttrValue {
+    /// Boolean value.
+    Bool(bool),
+    /// Signed integer value.
+    Int(i64),
+    /// Text value.
+    Text(String),
+    /// Binary value.
+    Bytes(Vec<u8>),
+}
+
+// Serialised form of `Metadata`. New versions are added as new variants, so the metadata of files
+// stored by older versions of the library can still be decoded.
+#[derive(Serialize, Deserialize)]
+enum VersionedMetadata {
+    V1 {
+        mime_type: Option<String>,
+        content_hash: Option<[u8; CONTENT_HASH_LEN]>,
+        xattrs: BTreeMap<String, AttrValue>,
+    },
+    V2 {
+        mime_type: Option<String>,
+        content_hash: Option<[u8; CONTENT_HASH_LEN]>,
+        xattrs: BTreeMap<String, AttrValue>,
+        history: Option<History>,
+    },
+}
+
+impl Metadata {
+    /// Get the MIME type of the content, if known.
+    pub fn mime_type(&self) -> Option<&str> {
+        self.mime_type.as_ref().map(String::as_str)
+    }
+
+    /// Set the MIME type of the content. Fails if it is empty or contains control characters.
+    pub fn set_mime_type(&mut self, mime_type: Option<String>) -> Result<(), NfsError> {
+        if let Some(ref mime_type) = mime_type {
+            if !is_valid_name(mime_type) {
+                return Err(NfsError::Unexpected("Invalid MIME type".to_string()));
+            }
+        }
+        self.mime_type = mime_type;
+        Ok(())
+    }
+
+    /// Get the SHA3-256 hash of the content. It is set by `Writer` when the content is written from
+    /// scratch, and cleared when existing content is appended to or modified.
+    pub fn content_hash(&self) -> Option<&[u8; CONTENT_HASH_LEN]> {
+        self.content_hash.as_ref()
+    }
+
+    /// Get all extended attributes, sorted by name.
+    pub fn xattrs(&self) -> &BTreeMap<String, AttrValue> {
+        &self.xattrs
+    }
+
+    /// Get the extended attribute with the given name.
+    pub fn xattr(&self, name: &str) -> Option<&AttrValue> {
+        self.xattrs.get(name)
+    }
+
+    /// Set an extended attribute, returning its previous value. Fails if the name is empty or
+    /// contains control characters.
+    pub fn set_xattr<S>(&mut self, name: S, value: AttrValue) -> Result<Option<AttrValue>, NfsError>
+    where
+        S: Into<String>,
+    {
+        let name = name.into();
+        if !is_valid_name(&name) {
+            return Err(NfsError::Unexpected(
+                "Invalid extended attribute name".to_string(),
+            ));
+        }
+        Ok(self.xattrs.insert(name, value))
+    }
+
+    /// Remove an extended attribute, returning its value.
+    pub fn remove_xattr(&mut self, name: &str) -> Option<AttrValue> {
+        self.xattrs.remove(name)
+    }
+
+    /// Check whether version history is enabled for the file, see `nfs::file_history`.
+    pub fn has_history(&self) -> bool {
+        self.history.is_some()
+    }
+
+    pub(crate) fn set_content_hash(&mut self, content_hash: Option<[u8; CONTENT_HASH_LEN]>) {
+        self.content_hash = content_hash;
+    }
+
+    pub(crate) fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    pub(crate) fn set_history(&mut self, history: Option<History>) {
+        self.history = history;
+    }
+}
+
+impl From<VersionedMetadata> for Metadata {
+    fn from(versioned: VersionedMetadata) -> Self {
+        match versioned {
+            VersionedMetadata::V1 {
+                mime_type,
+                content_hash,
+                xattrs,
+            } => Metadata {
+                mime_type,
+                content_hash,
+                xattrs,
+                history: None,
+            },
+            VersionedMetadata::V2 {
+                mime_type,
+                content_hash,
+                xattrs,
+                history,
+            } => Metadata {
+                mime_type,
+                content_hash,
+                xattrs,
+                history,
+            },
+        }
+    }
+}
+
+impl From<Metadata> for VersionedMetadata {
+    fn from(metadata: Metadata) -> Self {
+        VersionedMetadata::V2 {
+            mime_type: metadata.mime_type,
+            content_hash: metadata.content_hash,
+            xattrs: metadata.xattrs,
+            history: metadata.history,
+        }
+    }
+}
+
+// Names and MIME types are passed over FFI as C strings, so they can't contain nul characters.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.chars().any(char::is_control)
+}
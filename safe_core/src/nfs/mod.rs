@@ -6,13 +6,34 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+/// Export and import of directories as tar and zip archives.
+pub mod archive;
+/// Backup of directories along with the content of their files.
+pub mod backup;
+/// Resolution of conflicts between concurrent updates of files.
+pub mod conflict;
 /// `FileHelper` provides functions for CRUD on file.
 pub mod file_helper;
+/// Version history of files.
+pub mod file_history;
+/// Symbolic and hard links.
+pub mod link;
+/// Publishing unpublished files in place.
+pub mod publish;
+/// Two-way synchronisation of local directory trees.
+pub mod sync;
+/// Soft deletion of files into a trash directory.
+pub mod trash;
+/// Storage usage of directories.
+pub mod usage;
+/// Watching directories for changes.
+pub mod watch;
 
 mod data_map;
 mod dir;
 mod errors;
 mod file;
+mod metadata;
 mod reader;
 #[cfg(test)]
 mod tests;
@@ -20,10 +41,15 @@ mod writer;
 
 pub use self::dir::create_dir;
 pub use self::errors::NfsError;
-pub use self::file::File;
-pub use self::reader::Reader;
+pub use self::file::{file_entries_into_vec, File};
+pub use self::metadata::{AttrValue, Metadata, CONTENT_HASH_LEN};
+pub use self::reader::{Reader, DEFAULT_READ_AHEAD};
 pub use self::writer::{Mode, Writer};
+use futures::stream::Stream;
 use futures::Future;
 
 /// Helper type for futures that can result in `NfsError`.
 pub type NfsFuture<T> = dyn Future<Item = T, Error = NfsError>;
+
+/// Helper type for streams that can result in `NfsError`.
+pub type NfsStream<T> = dyn Stream<Item = T, Error = NfsError>;
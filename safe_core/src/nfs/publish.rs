@@ -0,0 +1,258 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Publishing unpublished files in place.
+//!
+//! The chunks of a file are encrypted by self-encryption independently of whether they are stored
+//! as published or unpublished immutable data. Only their names differ, and the names are not used
+//! for decryption. So a file is published by copying its encrypted chunks one at a time into
+//! published immutable data and storing a data map pointing to the copies, without decrypting the
+//! content.
+
+use crate::client::{Client, MDataInfo, COST_OF_PUT};
+use crate::errors::CoreError;
+use crate::ffi::nfs::PublishReport as FfiPublishReport;
+use crate::nfs::file_helper::{self, Version};
+use crate::nfs::link::{self, Resolved};
+use crate::nfs::{data_map, File, NfsError, NfsFuture};
+use crate::utils::FutureExt;
+use futures::future::{self, Future};
+use futures::stream::{self, Stream};
+use safe_nd::{Coins, Error as SndError, IDataAddress, PubImmutableData, XorName, XOR_NAME_LEN};
+use self_encryption::{ChunkDetails, DataMap};
+
+/// Outcome of publishing a file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PublishReport {
+    /// Version of the directory entry holding the published file.
+    pub version: u64,
+    /// Number of chunks copied into published immutable data.
+    pub chunks: u64,
+    /// Number of mutations performed, including storing the chunks, the data map and updating the
+    /// directory entry. Deletions are not counted.
+    pub mutations: u64,
+    /// Cost of the mutations, assuming each costs the same as storing a chunk.
+    pub cost: Coins,
+    /// Number of unpublished chunks and data maps deleted afterwards.
+    pub deleted: u64,
+}
+
+impl PublishReport {
+    /// Construct the FFI representation of the report.
+    pub fn into_repr_c(self) -> FfiPublishReport {
+        FfiPublishReport {
+            version: self.version,
+            chunks: self.chunks,
+            mutations: self.mutations,
+            cost: self.cost.as_nano(),
+            deleted: self.deleted,
+        }
+    }
+}
+
+/// Publish the file with the given name, updating its directory entry.
+///
+/// Symbolic links are followed, in which case the file they point to is published. Publishing a
+/// file which is already published does nothing.
+///
+/// If `delete_originals` is set, the unpublished chunks and data map are deleted afterwards,
+/// unless the data map is shared with hard links, in which case they are kept for the other links,
+/// or history is enabled for the file, in which case they are kept for its previous versions.
+/// Otherwise, they are left untouched.
+pub fn publish<S>(
+    client: impl Client,
+    parent: MDataInfo,
+    name: S,
+    delete_originals: bool,
+) -> Box<NfsFuture<PublishReport>>
+where
+    S: AsRef<str>,
+{
+    let name = name.as_ref().to_owned();
+    trace!("Publishing file '{}'", name);
+
+    let client2 = client.clone();
+
+    link::follow(client, parent, name, false)
+        .and_then(move |resolved| match resolved {
+            Resolved::File {
+                parent,
+                name,
+                version,
+                file,
+            } => {
+                if file.published() {
+                    return ok!(PublishReport {
+                        version,
+                        chunks: 0,
+                        mutations: 0,
+                        cost: fry!(cost(0)),
+                        deleted: 0,
+                    });
+                }
+                publish_file(client2, parent, name, version, file, delete_originals)
+            }
+            Resolved::Dir(_) => err!(NfsError::FileNotFound),
+        })
+        .into_box()
+}
+
+fn publish_file(
+    client: impl Client,
+    parent: MDataInfo,
+    name: String,
+    version: u64,
+    file: File,
+    delete_originals: bool,
+) -> Box<NfsFuture<PublishReport>> {
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let client4 = client.clone();
+    let enc_key = parent.enc_key().cloned();
+    let enc_key2 = enc_key.clone();
+    let original = file.clone();
+
+    data_map::get(&client, file.data_address(), enc_key)
+        .and_then(move |data_map| {
+            let chunks = data_map.get_chunks();
+            let published = match data_map {
+                DataMap::Chunks(_) => publish_chunks(client2, chunks.clone())
+                    .map(DataMap::Chunks)
+                    .into_box(),
+                data_map => ok!(data_map),
+            };
+            published.map(move |published| (published, chunks))
+        })
+        .and_then(move |(data_map, chunks)| {
+            data_map::put(&client3, &data_map, true, enc_key2)
+                .map(move |data_map_name| (data_map_name, chunks))
+        })
+        .and_then(move |(data_map_name, chunks)| {
+            let mut file = file;
+            file.set_data_map_name(data_map_name);
+            file.set_published(true);
+
+            file_helper::update(
+                client4.clone(),
+                parent,
+                &name,
+                &file,
+                Version::Custom(version + 1),
+            )
+            .map(move |version| (client4, version, chunks))
+        })
+        .and_then(move |(client, version, chunks)| {
+            let published = chunks.len() as u64;
+            // The chunks, the data map and the directory entry.
+            let mutations = published + 2;
+            let cost = fry!(cost(mutations));
+
+            let deleted = if delete_originals && !original.metadata().has_history() {
+                delete_unpublished(client, original, chunks)
+            } else {
+                ok!(0)
+            };
+            deleted
+                .map(move |deleted| PublishReport {
+                    version,
+                    chunks: published,
+                    mutations,
+                    cost,
+                    deleted,
+                })
+                .into_box()
+        })
+        .into_box()
+}
+
+// Copy the chunks into published immutable data one at a time, returning their new details.
+fn publish_chunks(
+    client: impl Client,
+    chunks: Vec<ChunkDetails>,
+) -> Box<NfsFuture<Vec<ChunkDetails>>> {
+    stream::iter_ok(chunks)
+        .and_then(move |mut chunk| {
+            let client2 = client.clone();
+            let name = fry!(chunk_name(&chunk));
+
+            client
+                .get_idata(IDataAddress::Unpub(name))
+                .and_then(move |data| {
+                    let data = PubImmutableData::new(data.value().clone());
+                    chunk.hash = data.name().0.to_vec();
+
+                    client2.put_idata(data).then(move |result| match result {
+                        Ok(()) | Err(CoreError::DataError(SndError::DataExists)) => Ok(chunk),
+                        Err(error) => Err(error),
+                    })
+                })
+                .map_err(NfsError::from)
+                .into_box()
+        })
+        .collect()
+        .into_box()
+}
+
+// Delete the unpublished data map of the file and its chunks, unless the data map is shared with
+// hard links. Returns the number of deleted immutable data.
+fn delete_unpublished(
+    client: impl Client,
+    file: File,
+    chunks: Vec<ChunkDetails>,
+) -> Box<NfsFuture<u64>> {
+    let client2 = client.clone();
+    let file2 = file.clone();
+
+    link::link_count(&client, &file)
+        .and_then(move |count| {
+            // Releasing the link also deletes the data map if this was the last link.
+            link::release(&client2, &file2).map(move |()| (client2, count))
+        })
+        .and_then(move |(client, count)| {
+            if count > 1 {
+                return ok!(0);
+            }
+
+            let deletions: Vec<_> = chunks
+                .iter()
+                .filter_map(|chunk| chunk_name(chunk).ok())
+                .map(|name| {
+                    client.del_unpub_idata(name).then(|result| match result {
+                        Ok(()) => Ok(1),
+                        Err(CoreError::DataError(SndError::NoSuchData)) => Ok(0),
+                        Err(error) => Err(NfsError::from(error)),
+                    })
+                })
+                .collect();
+
+            // The data map was deleted by the release.
+            future::join_all(deletions)
+                .map(|deleted| deleted.iter().sum::<u64>() + 1)
+                .into_box()
+        })
+        .into_box()
+}
+
+// Cost of the given number of mutations.
+fn cost(mutations: u64) -> Result<Coins, NfsError> {
+    COST_OF_PUT
+        .as_nano()
+        .checked_mul(mutations)
+        .and_then(|nano| Coins::from_nano(nano).ok())
+        .ok_or_else(|| NfsError::Unexpected("Cost overflow".to_string()))
+}
+
+fn chunk_name(chunk: &ChunkDetails) -> Result<XorName, NfsError> {
+    if chunk.hash.len() != XOR_NAME_LEN {
+        return Err(NfsError::Unexpected("Invalid chunk name".to_string()));
+    }
+
+    let mut name = [0; XOR_NAME_LEN];
+    name.copy_from_slice(&chunk.hash);
+    Ok(XorName(name))
+}
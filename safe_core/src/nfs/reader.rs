@@ -8,18 +8,46 @@
 
 use crate::client::Client;
 use crate::crypto::shared_secretbox;
-use crate::nfs::{data_map, File, NfsError, NfsFuture};
+use crate::nfs::{data_map, File, NfsError, NfsFuture, CONTENT_HASH_LEN};
 use crate::self_encryption_storage::SelfEncryptionStorage;
 use crate::utils::FutureExt;
+use futures::future::{self, Shared};
 use futures::Future;
-use self_encryption::SelfEncryptor;
+use self_encryption::{DataMap, SelfEncryptor};
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::rc::{Rc, Weak};
+use tiny_keccak::Keccak;
+use tokio::runtime::current_thread::TaskExecutor;
+
+/// Default number of chunks prefetched ahead of each read.
+pub const DEFAULT_READ_AHEAD: usize = 2;
+
+// Number of decrypted chunks cached by a reader in addition to its read-ahead window.
+const CACHED_CHUNKS: usize = 4;
+
+type ChunkFuture = Shared<Box<NfsFuture<Rc<Vec<u8>>>>>;
 
 /// `Reader` is used to read contents of a `File`. It can read in chunks if the `File` happens to be
 /// very large.
+///
+/// If the metadata of the `File` contains the hash of its content, the content is verified once
+/// all of it has been read in consecutive reads starting at position 0. The read completing the
+/// content then fails with `NfsError::ContentHashMismatch` if the content doesn't match the hash.
+///
+/// The content of large files is read by whole chunks, which are kept in a small cache of decrypted
+/// chunks, so reads overlapping cached chunks are served locally. After each read, the chunks
+/// following it are prefetched in the background, see `set_read_ahead`.
 #[allow(dead_code)]
 pub struct Reader<C: Client> {
     client: C,
     self_encryptor: SelfEncryptor<SelfEncryptionStorage<C>>,
+    content_hash: Option<[u8; CONTENT_HASH_LEN]>,
+    // Position the next consecutive read starts at, and the hash of the content read so far.
+    verifier: Rc<RefCell<Option<(u64, Keccak)>>>,
+    chunks: Rc<RefCell<ChunkCache>>,
+    read_ahead: usize,
 }
 
 impl<C: Client> Reader<C> {
@@ -30,18 +58,37 @@ impl<C: Client> Reader<C> {
         file: &File,
         encryption_key: Option<shared_secretbox::Key>,
     ) -> Box<NfsFuture<Self>> {
+        let content_hash = file.metadata().content_hash().cloned();
+
         data_map::get(&client, file.data_address(), encryption_key)
             .and_then(move |data_map| {
+                let chunks = ChunkCache::new(&data_map, DEFAULT_READ_AHEAD + CACHED_CHUNKS);
                 let self_encryptor = SelfEncryptor::new(storage, data_map)?;
 
                 Ok(Self {
                     client,
                     self_encryptor,
+                    content_hash,
+                    verifier: Rc::new(RefCell::new(None)),
+                    chunks: Rc::new(RefCell::new(chunks)),
+                    read_ahead: DEFAULT_READ_AHEAD,
                 })
             })
             .into_box()
     }
 
+    /// Set the number of chunks prefetched in the background after each read. Zero disables the
+    /// read-ahead, which suits random access. Defaults to `DEFAULT_READ_AHEAD`.
+    pub fn set_read_ahead(&mut self, chunks: usize) {
+        self.read_ahead = chunks;
+        self.chunks.borrow_mut().capacity = chunks + CACHED_CHUNKS;
+    }
+
+    /// Returns the number of chunks prefetched after each read.
+    pub fn read_ahead(&self) -> usize {
+        self.read_ahead
+    }
+
     /// Returns the total size of the file/blob.
     pub fn size(&self) -> u64 {
         self.self_encryptor.len()
@@ -63,10 +110,239 @@ impl<C: Client> Reader<C> {
                 len = length,
                 pos = position
             );
-            self.self_encryptor
-                .read(position, length)
-                .map_err(From::from)
-                .into_box()
+            let content_hash = self.content_hash;
+            let verifier = Rc::clone(&self.verifier);
+            let size = self.size();
+
+            let data = if length == 0 || self.chunks.borrow().ranges.is_empty() {
+                self.self_encryptor
+                    .read(position, length)
+                    .map_err(From::from)
+                    .into_box()
+            } else {
+                self.read_chunks(position, length)
+            };
+
+            data.and_then(move |data| {
+                if let Some(content_hash) = content_hash {
+                    verify(&verifier, &content_hash, size, position, &data)?;
+                }
+                Ok(data)
+            })
+            .into_box()
+        }
+    }
+
+    // Read the data from the chunks overlapping the range, then prefetch the chunks following it.
+    fn read_chunks(&self, position: u64, length: u64) -> Box<NfsFuture<Vec<u8>>> {
+        let end = position + length;
+        let (first, last) = {
+            let chunks = self.chunks.borrow();
+            (chunks.index_of(position), chunks.index_of(end - 1))
+        };
+
+        let loads: Vec<_> = (first..=last)
+            .map(|index| {
+                let start = self.chunks.borrow().ranges[index].0;
+                self.load(index).map(move |data| (start, data))
+            })
+            .collect();
+
+        self.prefetch(last + 1);
+
+        future::join_all(loads)
+            .map(move |chunks| {
+                let mut data = Vec::with_capacity(length as usize);
+                for (start, chunk) in chunks {
+                    let from = cmp::max(position, start) - start;
+                    let to = cmp::min(end, start + chunk.len() as u64) - start;
+                    data.extend_from_slice(&chunk[from as usize..to as usize]);
+                }
+                data
+            })
+            .into_box()
+    }
+
+    // Start fetching the chunks of the read-ahead window beginning with the given chunk, unless
+    // they are cached or already being fetched.
+    fn prefetch(&self, first: usize) {
+        let last = cmp::min(first + self.read_ahead, self.chunks.borrow().ranges.len());
+
+        for index in first..last {
+            if self.chunks.borrow().contains(index) {
+                continue;
+            }
+
+            let prefetch = self.load(index).map(|_| ()).map_err(move |error| {
+                debug!("Failed to prefetch chunk {}: {:?}", index, error);
+            });
+            // Without an executor, the chunk is fetched by the first read needing it instead.
+            if let Err(error) = TaskExecutor::current().spawn_local(Box::new(prefetch)) {
+                debug!("Failed to spawn prefetch of chunk {}: {:?}", index, error);
+            }
+        }
+    }
+
+    // Get the decrypted chunk, from the cache or by fetching it. Every chunk is fetched only once
+    // at a time, as the self-encryptor fails to serve concurrent reads of the same chunk.
+    fn load(&self, index: usize) -> Box<NfsFuture<Rc<Vec<u8>>>> {
+        let pending = {
+            let mut chunks = self.chunks.borrow_mut();
+            if let Some(data) = chunks.get(index) {
+                return ok!(data);
+            }
+            if let Some(pending) = chunks.pending.get(&index) {
+                pending.clone()
+            } else {
+                let (start, end) = chunks.ranges[index];
+                let cache = Rc::downgrade(&self.chunks);
+                let pending = self
+                    .self_encryptor
+                    .read(start, end - start)
+                    .map_err(NfsError::from)
+                    .then(move |result| complete(&cache, index, result))
+                    .into_box()
+                    .shared();
+                let _ = chunks.pending.insert(index, pending.clone());
+                pending
+            }
+        };
+
+        pending
+            .map(|data| Rc::clone(&*data))
+            .map_err(|error| NfsError::Unexpected(error.to_string()))
+            .into_box()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn cached_chunks(&self) -> Vec<usize> {
+        let mut cached: Vec<_> = self
+            .chunks
+            .borrow()
+            .cached
+            .iter()
+            .map(|&(index, _)| index)
+            .collect();
+        cached.sort_unstable();
+        cached
+    }
+}
+
+// Move the fetched chunk from the pending chunks into the cache, if the reader still exists.
+fn complete(
+    cache: &Weak<RefCell<ChunkCache>>,
+    index: usize,
+    result: Result<Vec<u8>, NfsError>,
+) -> Result<Rc<Vec<u8>>, NfsError> {
+    let cache = cache.upgrade();
+    if let Some(ref cache) = cache {
+        let _ = cache.borrow_mut().pending.remove(&index);
+    }
+
+    let data = Rc::new(result?);
+    if let Some(cache) = cache {
+        cache.borrow_mut().insert(index, Rc::clone(&data));
+    }
+    Ok(data)
+}
+
+// Decrypted chunks of the content, with the least recently used evicted first.
+struct ChunkCache {
+    // Start and end positions of the chunks of the content.
+    ranges: Vec<(u64, u64)>,
+    // Cached chunks by index, least recently used first.
+    cached: VecDeque<(usize, Rc<Vec<u8>>)>,
+    // Chunks being fetched.
+    pending: HashMap<usize, ChunkFuture>,
+    capacity: usize,
+}
+
+impl ChunkCache {
+    fn new(data_map: &DataMap, capacity: usize) -> Self {
+        let mut ranges = Vec::new();
+        if let DataMap::Chunks(ref chunks) = *data_map {
+            let mut start = 0;
+            for chunk in chunks {
+                ranges.push((start, start + chunk.source_size));
+                start += chunk.source_size;
+            }
         }
+
+        Self {
+            ranges,
+            cached: VecDeque::new(),
+            pending: HashMap::new(),
+            capacity,
+        }
+    }
+
+    // Index of the chunk holding the byte at the given position.
+    fn index_of(&self, position: u64) -> usize {
+        match self
+            .ranges
+            .binary_search_by(|&(start, _)| start.cmp(&position))
+        {
+            Ok(index) => index,
+            Err(index) => index - 1,
+        }
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        self.pending.contains_key(&index) || self.cached.iter().any(|&(i, _)| i == index)
     }
+
+    fn get(&mut self, index: usize) -> Option<Rc<Vec<u8>>> {
+        let pos = self.cached.iter().position(|&(i, _)| i == index)?;
+        let entry = self.cached.remove(pos)?;
+        let data = Rc::clone(&entry.1);
+        self.cached.push_back(entry);
+        Some(data)
+    }
+
+    fn insert(&mut self, index: usize, data: Rc<Vec<u8>>) {
+        self.cached.retain(|&(i, _)| i != index);
+        self.cached.push_back((index, data));
+        while self.cached.len() > self.capacity {
+            let _ = self.cached.pop_front();
+        }
+    }
+}
+
+// Add the data read at the given position to the hash of the content if it continues the
+// previous read, and check the hash once the whole content has been read.
+fn verify(
+    verifier: &RefCell<Option<(u64, Keccak)>>,
+    content_hash: &[u8; CONTENT_HASH_LEN],
+    size: u64,
+    position: u64,
+    data: &[u8],
+) -> Result<(), NfsError> {
+    let mut verifier = verifier.borrow_mut();
+
+    if position == 0 {
+        *verifier = Some((0, Keccak::new_sha3_256()));
+    }
+
+    let next = match *verifier {
+        Some((ref mut next, ref mut hasher)) if *next == position => {
+            hasher.update(data);
+            *next += data.len() as u64;
+            *next
+        }
+        _ => return Ok(()),
+    };
+
+    if next < size {
+        return Ok(());
+    }
+
+    if let Some((_, hasher)) = verifier.take() {
+        let mut hash = [0; CONTENT_HASH_LEN];
+        hasher.finalize(&mut hash);
+        if hash != *content_hash {
+            return Err(NfsError::ContentHashMismatch);
+        }
+    }
+
+    Ok(())
 }
@@ -0,0 +1,814 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Two-way synchronisation of a local directory tree with an NFS directory.
+//!
+//! Files in the local tree are stored in the NFS directory under their paths relative to the root
+//! of the tree, with components separated by `/`. A `SyncState` records the size, modification
+//! time, content hash and directory entry version of every file as of the last synchronisation,
+//! which is how changes made on either side since then are detected.
+//!
+//! Local file system operations block, so they are run on a small pool of long-lived worker
+//! threads rather than on the event loop.
+
+use crate::client::{Client, MDataInfo};
+use crate::errors::CoreError;
+use crate::nfs::file_helper::{self, ListOptions, Version};
+use crate::nfs::{File, Mode, NfsError, NfsFuture, CONTENT_HASH_LEN};
+use crate::utils::FutureExt;
+use bincode::{deserialize, serialize};
+use chrono::{DateTime, Utc};
+use crossbeam_channel::{self, Sender};
+use futures::future::{self, Loop};
+use futures::sync::oneshot;
+use futures::Future;
+use self_encryption::MAX_CHUNK_SIZE;
+use serde::{Deserialize, Serialize};
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+use tiny_keccak::Keccak;
+
+// Suffix of the temporary files written while downloading files and saving the state.
+const PARTIAL_SUFFIX: &str = ".partial";
+
+/// State of a synchronised directory tree as of the last synchronisation.
+///
+/// The state has to be persisted between synchronisations, e.g. using `save` and `load`. It
+/// should be kept outside of the local tree, otherwise it is synchronised as well.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SyncState {
+    files: BTreeMap<String, SyncedFile>,
+}
+
+// A file as of the last synchronisation.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct SyncedFile {
+    size: u64,
+    local_modified: DateTime<Utc>,
+    content_hash: [u8; CONTENT_HASH_LEN],
+    remote_version: u64,
+}
+
+impl SyncState {
+    /// Create an empty state, for a tree which hasn't been synchronised yet.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Load the state from a file, returning an empty state if the file doesn't exist.
+    pub fn load<P: Into<PathBuf>>(path: P) -> Box<NfsFuture<Self>> {
+        let path = path.into();
+
+        blocking(move || match fs::read(path) {
+            Ok(encoded) => Ok(Some(encoded)),
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        })
+        .and_then(|encoded| match encoded {
+            Some(encoded) => Ok(deserialize(&encoded)?),
+            None => Ok(Self::new()),
+        })
+        .into_box()
+    }
+
+    /// Save the state to a file. The file is replaced atomically, so an interrupted save leaves
+    /// the previous state intact.
+    pub fn save<P: Into<PathBuf>>(&self, path: P) -> Box<NfsFuture<()>> {
+        let path = path.into();
+        let encoded = fry!(serialize(self));
+
+        blocking(move || {
+            let partial = partial_path(&path);
+            fs::write(&partial, encoded)?;
+            fs::rename(&partial, &path)
+        })
+    }
+
+    /// Get the names of the synchronised files.
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(String::as_str)
+    }
+}
+
+/// How to resolve a file changed on both sides since the last synchronisation.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ConflictPolicy {
+    /// Leave both sides unchanged and report the conflict.
+    Report,
+    /// Replace the remote file with the local one, or delete it if the local file was deleted.
+    PreferLocal,
+    /// Replace the local file with the remote one, or delete it if the remote file was deleted.
+    PreferRemote,
+}
+
+/// Changes made by a synchronisation, listed by file name.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SyncReport {
+    /// Files uploaded to the NFS directory.
+    pub uploaded: Vec<String>,
+    /// Files downloaded to the local tree.
+    pub downloaded: Vec<String>,
+    /// Files deleted from the NFS directory.
+    pub deleted_remote: Vec<String>,
+    /// Files deleted from the local tree.
+    pub deleted_local: Vec<String>,
+    /// Files changed on both sides, which were left unchanged under `ConflictPolicy::Report`.
+    pub conflicts: Vec<String>,
+    /// Remote files whose names can't be mapped to a path in the local tree, such as names
+    /// containing `..` components.
+    pub skipped: Vec<String>,
+}
+
+/// Synchronise the local tree under `root` with the NFS directory, and return the new state.
+///
+/// Files created, changed or deleted on one side since the last synchronisation are uploaded,
+/// downloaded or deleted on the other side. Local files are compared by size and modification
+/// time, and by their content hash if those differ. Remote files are compared by the version of
+/// their entry and their content hash. Files changed on both sides are resolved by `policy`,
+/// unless their content is the same.
+///
+/// Empty directories and symbolic links are not synchronised. If the synchronisation fails, no
+/// state is returned, but it can be retried with the previous state.
+pub fn sync<P>(
+    client: impl Client,
+    root: P,
+    dir: MDataInfo,
+    state: SyncState,
+    policy: ConflictPolicy,
+) -> Box<NfsFuture<(SyncState, SyncReport)>>
+where
+    P: Into<PathBuf>,
+{
+    let root = root.into();
+    trace!("Synchronising {:?} with {:?}", root, dir.address());
+
+    let client2 = client.clone();
+
+    file_helper::list(client, dir.clone(), ListOptions::default())
+        .and_then(move |remote| {
+            let remote = remote
+                .into_iter()
+                .map(|(name, version, file)| (name, (version, file)))
+                .collect();
+
+            blocking(move || {
+                let local = scan_local(&root)?;
+                let actions = plan(&root, local, remote, &state, policy)?;
+                Ok((root, state, actions))
+            })
+        })
+        .and_then(move |(root, state, actions)| {
+            let report = SyncReport::default();
+
+            future::loop_fn(
+                (actions.into_iter(), state, report),
+                move |(mut actions, state, report)| {
+                    let action = match actions.next() {
+                        Some(action) => action,
+                        None => return ok!(Loop::Break((state, report))),
+                    };
+
+                    apply(&client2, &root, &dir, action, state, report)
+                        .map(move |(state, report)| Loop::Continue((actions, state, report)))
+                        .into_box()
+                },
+            )
+        })
+        .into_box()
+}
+
+// A file found in the local tree.
+struct LocalFile {
+    path: PathBuf,
+    size: u64,
+    modified: DateTime<Utc>,
+}
+
+// Change of a file on one side since the last synchronisation.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Change {
+    // Neither the file nor its record exist.
+    None,
+    Unchanged,
+    Changed,
+    Deleted,
+}
+
+// How a file is synchronised.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Decision {
+    Upload,
+    Download,
+    DeleteRemote,
+    DeleteLocal,
+    // Update the record of a file which is the same on both sides.
+    Record,
+    // Remove the record of a file deleted on both sides.
+    Forget,
+    Conflict,
+}
+
+// Step of a synchronisation.
+#[allow(clippy::large_enum_variant)]
+enum Action {
+    Upload {
+        name: String,
+        path: PathBuf,
+        existing: Option<(u64, File)>,
+    },
+    Download {
+        name: String,
+        path: PathBuf,
+        version: u64,
+        file: File,
+    },
+    DeleteRemote {
+        name: String,
+        version: u64,
+        published: bool,
+    },
+    DeleteLocal {
+        name: String,
+        path: PathBuf,
+    },
+    Record {
+        name: String,
+        synced: SyncedFile,
+    },
+    Forget {
+        name: String,
+    },
+    Conflict {
+        name: String,
+    },
+    Skip {
+        name: String,
+    },
+}
+
+// Decide how to synchronise every file found on either side or in the state.
+fn plan(
+    root: &Path,
+    mut local: BTreeMap<String, LocalFile>,
+    mut remote: BTreeMap<String, (u64, File)>,
+    state: &SyncState,
+    policy: ConflictPolicy,
+) -> io::Result<Vec<Action>> {
+    let names: BTreeSet<String> = local
+        .keys()
+        .chain(remote.keys())
+        .chain(state.files.keys())
+        .cloned()
+        .collect();
+    let mut actions = Vec::with_capacity(names.len());
+
+    for name in names {
+        let record = state.files.get(&name);
+        let local_file = local.remove(&name);
+        let remote_file = remote.remove(&name);
+
+        let path = match local_file {
+            Some(ref local_file) => local_file.path.clone(),
+            None => match local_path(root, &name) {
+                Some(path) => path,
+                None => {
+                    actions.push(Action::Skip { name });
+                    continue;
+                }
+            },
+        };
+
+        // Only hash the local file if its size or modification time has changed.
+        let local_hash = match (&local_file, record) {
+            (Some(local_file), Some(record))
+                if local_file.size == record.size
+                    && local_file.modified == record.local_modified =>
+            {
+                Some(record.content_hash)
+            }
+            (Some(local_file), _) => Some(hash_local(&local_file.path)?),
+            (None, _) => None,
+        };
+
+        let local_change = match (local_hash, record) {
+            (Some(hash), Some(record)) if hash == record.content_hash => Change::Unchanged,
+            (Some(_), _) => Change::Changed,
+            (None, Some(_)) => Change::Deleted,
+            (None, None) => Change::None,
+        };
+        let remote_change = match (&remote_file, record) {
+            (Some((version, file)), Some(record))
+                if *version == record.remote_version
+                    || file.metadata().content_hash() == Some(&record.content_hash) =>
+            {
+                Change::Unchanged
+            }
+            (Some(_), _) => Change::Changed,
+            (None, Some(_)) => Change::Deleted,
+            (None, None) => Change::None,
+        };
+        let same_content = match (local_hash, &remote_file) {
+            (Some(hash), Some((_, file))) => file.metadata().content_hash() == Some(&hash),
+            _ => false,
+        };
+
+        let decision = match (local_change, remote_change) {
+            (Change::Unchanged, Change::Unchanged) => Decision::Record,
+            (Change::Changed, Change::Changed) if same_content => Decision::Record,
+            (Change::Changed, Change::Unchanged) | (Change::Changed, Change::None) => {
+                Decision::Upload
+            }
+            (Change::Unchanged, Change::Changed) | (Change::None, Change::Changed) => {
+                Decision::Download
+            }
+            (Change::Deleted, Change::Unchanged) => Decision::DeleteRemote,
+            (Change::Unchanged, Change::Deleted) => Decision::DeleteLocal,
+            (Change::Deleted, Change::Deleted) | (Change::None, _) | (_, Change::None) => {
+                Decision::Forget
+            }
+            // Changed on both sides.
+            (local_change, remote_change) => match policy {
+                ConflictPolicy::Report => Decision::Conflict,
+                ConflictPolicy::PreferLocal if local_change == Change::Deleted => {
+                    Decision::DeleteRemote
+                }
+                ConflictPolicy::PreferLocal => Decision::Upload,
+                ConflictPolicy::PreferRemote if remote_change == Change::Deleted => {
+                    Decision::DeleteLocal
+                }
+                ConflictPolicy::PreferRemote => Decision::Download,
+            },
+        };
+
+        let action = match (decision, local_file, remote_file, local_hash) {
+            (Decision::Record, Some(local_file), Some((version, _)), Some(content_hash)) => {
+                Action::Record {
+                    name,
+                    synced: SyncedFile {
+                        size: local_file.size,
+                        local_modified: local_file.modified,
+                        content_hash,
+                        remote_version: version,
+                    },
+                }
+            }
+            (Decision::Upload, Some(_), existing, _) => Action::Upload {
+                name,
+                path,
+                existing,
+            },
+            (Decision::Download, _, Some((version, file)), _) => Action::Download {
+                name,
+                path,
+                version,
+                file,
+            },
+            (Decision::DeleteRemote, _, Some((version, file)), _) => Action::DeleteRemote {
+                name,
+                version,
+                published: file.published(),
+            },
+            (Decision::DeleteLocal, Some(_), _, _) => Action::DeleteLocal { name, path },
+            (Decision::Conflict, ..) => Action::Conflict { name },
+            _ => Action::Forget { name },
+        };
+
+        actions.push(action);
+    }
+
+    Ok(actions)
+}
+
+// Perform a single step of a synchronisation, and update the state and report accordingly.
+fn apply(
+    client: &impl Client,
+    root: &Path,
+    dir: &MDataInfo,
+    action: Action,
+    mut state: SyncState,
+    mut report: SyncReport,
+) -> Box<NfsFuture<(SyncState, SyncReport)>> {
+    match action {
+        Action::Upload {
+            name,
+            path,
+            existing,
+        } => {
+            trace!("Uploading '{}'", name);
+            upload(client, dir, name.clone(), path, existing)
+                .map(move |synced| {
+                    let _ = state.files.insert(name.clone(), synced);
+                    report.uploaded.push(name);
+                    (state, report)
+                })
+                .into_box()
+        }
+        Action::Download {
+            name,
+            path,
+            version,
+            file,
+        } => {
+            trace!("Downloading '{}'", name);
+            download(client, dir, path, version, &file)
+                .map(move |synced| {
+                    let _ = state.files.insert(name.clone(), synced);
+                    report.downloaded.push(name);
+                    (state, report)
+                })
+                .into_box()
+        }
+        Action::DeleteRemote {
+            name,
+            version,
+            published,
+        } => {
+            trace!("Deleting remote '{}'", name);
+            file_helper::delete(
+                client.clone(),
+                dir.clone(),
+                &name,
+                published,
+                Version::Custom(version + 1),
+            )
+            .map(move |_| {
+                let _ = state.files.remove(&name);
+                report.deleted_remote.push(name);
+                (state, report)
+            })
+            .into_box()
+        }
+        Action::DeleteLocal { name, path } => {
+            trace!("Deleting local '{}'", name);
+            let root = root.to_path_buf();
+
+            blocking(move || remove_local(&root, &path))
+                .map(move |()| {
+                    let _ = state.files.remove(&name);
+                    report.deleted_local.push(name);
+                    (state, report)
+                })
+                .into_box()
+        }
+        Action::Record { name, synced } => {
+            let _ = state.files.insert(name, synced);
+            ok!((state, report))
+        }
+        Action::Forget { name } => {
+            let _ = state.files.remove(&name);
+            ok!((state, report))
+        }
+        Action::Conflict { name } => {
+            report.conflicts.push(name);
+            ok!((state, report))
+        }
+        Action::Skip { name } => {
+            report.skipped.push(name);
+            ok!((state, report))
+        }
+    }
+}
+
+// Store the local file in the directory, replacing the existing file if there is one. The
+// existing file is updated rather than replaced, so its metadata is kept.
+fn upload(
+    client: &impl Client,
+    dir: &MDataInfo,
+    name: String,
+    path: PathBuf,
+    existing: Option<(u64, File)>,
+) -> Box<NfsFuture<SyncedFile>> {
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let dir2 = dir.clone();
+    let enc_key = dir.enc_key().cloned();
+    let (version, file) = match existing {
+        Some((version, file)) => (Some(version), file),
+        None => (None, File::new(Vec::new(), false)),
+    };
+
+    // The modification time is taken before reading, so that changes made while uploading are
+    // detected by the next synchronisation.
+    blocking(move || {
+        let local_modified = fs::metadata(&path)?.modified()?;
+        let source = fs::File::open(&path)?;
+        Ok((local_modified, source))
+    })
+    .and_then(move |(local_modified, source)| {
+        file_helper::write(client3, file, Mode::Overwrite, enc_key)
+            .map(move |writer| (local_modified, source, writer))
+    })
+    .and_then(move |(local_modified, source, writer)| {
+        future::loop_fn((writer, source), |(writer, mut source)| {
+            blocking(move || {
+                let mut buffer = vec![0; MAX_CHUNK_SIZE as usize];
+                let len = source.read(&mut buffer)?;
+                buffer.truncate(len);
+                Ok((source, buffer))
+            })
+            .and_then(move |(source, buffer)| {
+                if buffer.is_empty() {
+                    return ok!(Loop::Break(writer));
+                }
+
+                writer
+                    .write(&buffer)
+                    .map(move |()| Loop::Continue((writer, source)))
+                    .into_box()
+            })
+        })
+        .map(move |writer| (local_modified, writer))
+    })
+    .and_then(|(local_modified, writer)| writer.close().map(move |file| (local_modified, file)))
+    .and_then(move |(local_modified, file)| {
+        let content_hash = *fry!(file
+            .metadata()
+            .content_hash()
+            .ok_or_else(|| NfsError::Unexpected("Missing content hash".to_string())));
+        let size = file.size();
+
+        match version {
+            Some(version) => {
+                file_helper::update(client2, dir2, name, &file, Version::Custom(version + 1))
+            }
+            None => file_helper::insert(client2, dir2, name, &file)
+                .map(|()| 0)
+                .into_box(),
+        }
+        .map(move |remote_version| SyncedFile {
+            size,
+            local_modified: local_modified.into(),
+            content_hash,
+            remote_version,
+        })
+        .into_box()
+    })
+    .into_box()
+}
+
+// Store the content of the file at the local path. The content is written to a temporary file
+// first, which then replaces the local file.
+fn download(
+    client: &impl Client,
+    dir: &MDataInfo,
+    path: PathBuf,
+    version: u64,
+    file: &File,
+) -> Box<NfsFuture<SyncedFile>> {
+    let client = client.clone();
+    let file = file.clone();
+    let enc_key = dir.enc_key().cloned();
+    let partial = partial_path(&path);
+    let partial2 = partial.clone();
+    let partial3 = partial.clone();
+
+    blocking(move || {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let target = fs::File::create(&partial)?;
+        Ok((path, target))
+    })
+    .and_then(move |(path, target)| {
+        file_helper::read(client, &file, enc_key).map(move |reader| (path, target, reader))
+    })
+    .and_then(move |(path, target, reader)| {
+        let size = reader.size();
+
+        future::loop_fn(
+            (reader, target, 0, Keccak::new_sha3_256()),
+            move |(reader, mut target, position, mut hasher)| {
+                if position >= size {
+                    let mut hash = [0; CONTENT_HASH_LEN];
+                    hasher.finalize(&mut hash);
+                    return ok!(Loop::Break((target, hash)));
+                }
+
+                let length = cmp::min(u64::from(MAX_CHUNK_SIZE), size - position);
+                reader
+                    .read(position, length)
+                    .and_then(move |data| {
+                        hasher.update(&data);
+                        blocking(move || target.write_all(&data).map(|()| target)).map(
+                            move |target| {
+                                Loop::Continue((reader, target, position + length, hasher))
+                            },
+                        )
+                    })
+                    .into_box()
+            },
+        )
+        .map(move |(target, content_hash)| (path, target, content_hash))
+    })
+    .and_then(move |(path, target, content_hash)| {
+        blocking(move || {
+            target.sync_all()?;
+            drop(target);
+            fs::rename(&partial2, &path)?;
+            fs::metadata(&path)
+        })
+        .and_then(move |metadata| {
+            Ok(SyncedFile {
+                size: metadata.len(),
+                local_modified: metadata.modified().map_err(io_error)?.into(),
+                content_hash,
+                remote_version: version,
+            })
+        })
+    })
+    .or_else(move |error| {
+        blocking(move || {
+            let _ = fs::remove_file(&partial3);
+            Ok(())
+        })
+        .then(move |_| Err(error))
+    })
+    .into_box()
+}
+
+// Find all files in the local tree, creating its root if it doesn't exist yet.
+fn scan_local(root: &Path) -> io::Result<BTreeMap<String, LocalFile>> {
+    fs::create_dir_all(root)?;
+
+    let mut files = BTreeMap::new();
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let metadata = entry.metadata()?;
+
+            if metadata.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            if !metadata.is_file() || is_partial(&path) {
+                continue;
+            }
+
+            let name = match remote_name(root, &path) {
+                Some(name) => name,
+                None => {
+                    warn!("Skipping file with non UTF-8 path {:?}", path);
+                    continue;
+                }
+            };
+
+            let _ = files.insert(
+                name,
+                LocalFile {
+                    size: metadata.len(),
+                    modified: metadata.modified()?.into(),
+                    path,
+                },
+            );
+        }
+    }
+
+    Ok(files)
+}
+
+// Delete the local file, along with any directories left empty by that, up to the root.
+fn remove_local(root: &Path, path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => (),
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => (),
+        Err(error) => return Err(error),
+    }
+
+    let mut dir = path.parent();
+    while let Some(current) = dir {
+        if current == root || fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+
+    Ok(())
+}
+
+// Compute the hash of the content of the local file.
+fn hash_local(path: &Path) -> io::Result<[u8; CONTENT_HASH_LEN]> {
+    let mut source = fs::File::open(path)?;
+    let mut buffer = vec![0; MAX_CHUNK_SIZE as usize];
+    let mut hasher = Keccak::new_sha3_256();
+
+    loop {
+        let len = source.read(&mut buffer)?;
+        if len == 0 {
+            break;
+        }
+        hasher.update(&buffer[..len]);
+    }
+
+    let mut hash = [0; CONTENT_HASH_LEN];
+    hasher.finalize(&mut hash);
+    Ok(hash)
+}
+
+// Name of the local file in the directory, or `None` if its path isn't valid UTF-8.
+fn remote_name(root: &Path, path: &Path) -> Option<String> {
+    let components = path
+        .strip_prefix(root)
+        .ok()?
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?;
+    Some(components.join("/"))
+}
+
+// Path of the file with the given name in the local tree, or `None` if the name doesn't map to a
+// path which maps back to the same name, or maps to a path outside of the tree.
+fn local_path(root: &Path, name: &str) -> Option<PathBuf> {
+    let mut path = root.to_path_buf();
+
+    for component in name.split('/') {
+        let mut components = Path::new(component).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(normal)), None) if normal == component => path.push(normal),
+            _ => return None,
+        }
+    }
+
+    if is_partial(&path) {
+        None
+    } else {
+        Some(path)
+    }
+}
+
+// Path of the temporary file used while writing the file at the given path.
+fn partial_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}{}", file_name, PARTIAL_SUFFIX))
+}
+
+fn is_partial(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map_or(false, |name| {
+            name.starts_with('.') && name.ends_with(PARTIAL_SUFFIX)
+        })
+}
+
+// Number of threads running the blocking file system operations.
+const WORKER_THREADS: usize = 4;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+lazy_static! {
+    // Queue of the blocking file system operations, shared by all the worker threads. The workers
+    // are started on first use and live as long as the process.
+    static ref JOBS: Sender<Job> = {
+        let (job_tx, job_rx) = crossbeam_channel::unbounded::<Job>();
+        for index in 0..WORKER_THREADS {
+            let job_rx = job_rx.clone();
+            let _ = thread::Builder::new()
+                .name(format!("nfs-sync-{}", index))
+                .spawn(move || {
+                    // A panicking operation drops its result sender, which fails its future,
+                    // and mustn't take the worker down with it.
+                    for job in job_rx {
+                        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                    }
+                });
+        }
+        job_tx
+    };
+}
+
+// Run the blocking file system operation on one of the worker threads.
+fn blocking<T, F>(operation: F) -> Box<NfsFuture<T>>
+where
+    T: Send + 'static,
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+{
+    let (result_tx, result_rx) = oneshot::channel();
+
+    let _ = JOBS.send(Box::new(move || {
+        let _ = result_tx.send(operation());
+    }));
+
+    result_rx
+        .map_err(|_| NfsError::Unexpected("File system operation stopped".to_string()))
+        .and_then(|result| result.map_err(io_error))
+        .into_box()
+}
+
+fn io_error(error: io::Error) -> NfsError {
+    NfsError::from(CoreError::from(error))
+}
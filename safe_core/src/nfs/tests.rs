@@ -6,24 +6,43 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+use crate::client::backup::MDataBackup;
 use crate::client::core_client::CoreClient;
-use crate::client::MDataInfo;
+use crate::client::{Client, MDataInfo};
 use crate::crypto::shared_secretbox;
 use crate::errors::CoreError;
-use crate::nfs::file_helper::{self, Version};
+use crate::ipc::resp::METADATA_KEY;
+use crate::nfs::archive::{self, ArchiveFormat};
+use crate::nfs::backup;
+use crate::nfs::conflict::{self, MergeStrategy, Resolution};
+use crate::nfs::file_helper::{self, ListOptions, MoveRecovery, SortBy, Version};
+use crate::nfs::file_history;
+use crate::nfs::link::{self, Entry, Link, Resolved};
+use crate::nfs::publish;
 use crate::nfs::reader::Reader;
+use crate::nfs::sync::{self, ConflictPolicy, SyncReport, SyncState};
+use crate::nfs::trash;
+use crate::nfs::usage::{self, Usage};
+use crate::nfs::watch::{self, DirEvent, WatchOptions};
 use crate::nfs::writer::Writer;
-use crate::nfs::{create_dir, File, Mode, NfsError, NfsFuture};
+use crate::nfs::{create_dir, AttrValue, File, Mode, NfsError, NfsFuture};
 use crate::utils::test_utils::random_client;
 use crate::utils::{self, generate_random_vector, FutureExt};
 use crate::DIR_TAG;
 use futures::future::{self, Loop};
-use futures::Future;
-use safe_nd::{Error as SndError, MDataKind};
+use futures::{Future, Stream};
+use safe_nd::{Coins, Error as SndError, MDataKind, MDataSeqValue};
 use self_encryption::MIN_CHUNK_SIZE;
 use std;
+use std::fs;
+use std::io::Cursor;
+use std::rc::Rc;
 use std::sync::mpsc;
 use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::tempdir;
+use tiny_keccak::sha3_256;
+use tokio::timer::Delay;
 
 const APPEND_SIZE: usize = 10;
 const ORIG_SIZE: usize = 5555;
@@ -68,6 +87,53 @@ fn create_test_file(client: &CoreClient, published: bool) -> Box<NfsFuture<(MDat
     create_test_file_with_size(client, published, ORIG_SIZE)
 }
 
+// Wait long enough for background tasks, like polling a directory or prefetching chunks, to run.
+fn pause() -> Box<NfsFuture<()>> {
+    Delay::new(Instant::now() + Duration::from_millis(100))
+        .map_err(|error| NfsError::Unexpected(error.to_string()))
+        .into_box()
+}
+
+// Read the whole content of the file with the given name from the directory.
+fn read_file(client: &CoreClient, dir: &MDataInfo, name: &str) -> Box<NfsFuture<Vec<u8>>> {
+    let client2 = client.clone();
+    let enc_key = dir.enc_key().cloned();
+
+    file_helper::fetch(client.clone(), dir.clone(), name)
+        .and_then(move |(_, file)| file_helper::read(client2, &file, enc_key))
+        .and_then(|reader| {
+            let size = reader.size();
+            reader.read(0, size)
+        })
+        .into_box()
+}
+
+// Check that "hello.txt" is either in the source or in the destination directory, but not both.
+fn check_file_location(
+    client: &CoreClient,
+    src: MDataInfo,
+    dst: MDataInfo,
+    in_src: bool,
+) -> Box<NfsFuture<(MDataInfo, MDataInfo)>> {
+    read_file(client, &src, "hello.txt")
+        .then(Ok)
+        .join(read_file(client, &dst, "hello.txt").then(Ok))
+        .map(move |(src_res, dst_res)| {
+            let (present, missing) = if in_src {
+                (src_res, dst_res)
+            } else {
+                (dst_res, src_res)
+            };
+            assert_eq!(unwrap!(present), vec![0u8; ORIG_SIZE]);
+            match missing {
+                Err(NfsError::FileNotFound) => (),
+                res => panic!("Unexpected result {:?}", res),
+            }
+            (src, dst)
+        })
+        .into_box()
+}
+
 // Test inserting files to, and fetching from, a public mdata.
 // 1. Create a private mdata with random bytes in `enc_info` and `new_enc_info`.
 // 2. Create a directory for the mdata.
@@ -357,6 +423,76 @@ fn file_read_chunks() {
     });
 }
 
+// Test that reads prefetch the chunks following them, and are served from the cached chunks.
+#[test]
+fn file_read_ahead() {
+    const CHUNK_SIZE: usize = 3000;
+
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let dir = unwrap!(MDataInfo::random_private(MDataKind::Seq, DIR_TAG));
+        let dir2 = dir.clone();
+        let content: Vec<u8> = unwrap!(generate_random_vector(3 * CHUNK_SIZE));
+        let content2 = content.clone();
+
+        create_dir(client, &dir, btree_map![], btree_map![])
+            .then(move |res| {
+                unwrap!(res);
+                file_helper::write(
+                    c2,
+                    File::new(Vec::new(), false),
+                    Mode::Overwrite,
+                    dir.enc_key().cloned(),
+                )
+            })
+            .then(move |res| {
+                let writer = unwrap!(res);
+                writer.write(&content).and_then(move |()| writer.close())
+            })
+            .then(move |res| {
+                let file = unwrap!(res);
+                file_helper::read(c3, &file, dir2.enc_key().cloned())
+            })
+            .then(move |res| {
+                let mut reader = unwrap!(res);
+                reader.set_read_ahead(1);
+
+                reader
+                    .read(0, 100)
+                    .map(move |data| {
+                        assert_eq!(data[..], content2[..100]);
+                        (reader, content2)
+                    })
+                    .join(pause())
+            })
+            .then(move |res| {
+                let ((reader, content), ()) = unwrap!(res);
+                // The first chunk was read and the second one prefetched.
+                assert_eq!(reader.cached_chunks(), vec![0, 1]);
+
+                let position = CHUNK_SIZE as u64 - 100;
+                reader
+                    .read(position, 200)
+                    .map(move |data| {
+                        assert_eq!(data[..], content[CHUNK_SIZE - 100..CHUNK_SIZE + 100]);
+                        (reader, content)
+                    })
+                    .join(pause())
+            })
+            .then(move |res| {
+                let ((mut reader, content), ()) = unwrap!(res);
+                assert_eq!(reader.cached_chunks(), vec![0, 1, 2]);
+
+                reader.set_read_ahead(0);
+                let size = reader.size();
+                reader.read(0, size).map(move |data| {
+                    assert_eq!(data, content);
+                })
+            })
+    });
+}
+
 // Test writing to files in chunks.
 #[test]
 fn file_write_chunks() {
@@ -589,6 +725,120 @@ fn file_update_append() {
     })
 }
 
+// Test modifying a file at arbitrary positions.
+// 1. Open a file in the modify mode, overwrite a few bytes in the middle and truncate it.
+// 2. Read it back and check that only the modified bytes have changed.
+// 3. Extend the file by truncating it to a bigger size and check it is padded with zeros.
+#[test]
+fn file_update_modify() {
+    random_client(move |client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+
+        const POSITION: usize = 2 * MIN_CHUNK_SIZE as usize;
+        const TRUNCATED_SIZE: usize = ORIG_SIZE - 1000;
+        const EXTENDED_SIZE: usize = ORIG_SIZE + 1000;
+
+        create_test_file(client, true)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_helper::write(c2, file, Mode::Modify, dir.enc_key().cloned())
+                    .map(move |writer| (dir, writer))
+            })
+            .then(move |res| {
+                let (dir, writer) = unwrap!(res);
+                writer
+                    .write_at(&[3u8; NEW_SIZE], POSITION as u64)
+                    .and_then(move |_| writer.truncate(TRUNCATED_SIZE as u64).map(|_| writer))
+                    .and_then(move |writer| {
+                        assert_eq!(writer.len(), TRUNCATED_SIZE as u64);
+                        writer.close()
+                    })
+                    .map(move |file| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                assert_eq!(file.size(), TRUNCATED_SIZE as u64);
+                file_helper::read(c3, &file, dir.enc_key().cloned())
+                    .and_then(|reader| {
+                        let size = reader.size();
+                        reader.read(0, size)
+                    })
+                    .map(move |data| (dir, file, data))
+            })
+            .then(move |res| {
+                let (dir, file, data) = unwrap!(res);
+                let mut expected = vec![0u8; TRUNCATED_SIZE];
+                expected[POSITION..POSITION + NEW_SIZE].copy_from_slice(&[3u8; NEW_SIZE]);
+                assert_eq!(data, expected);
+
+                file_helper::write(c4, file, Mode::Modify, dir.enc_key().cloned())
+                    .and_then(|writer| {
+                        writer
+                            .truncate(EXTENDED_SIZE as u64)
+                            .and_then(move |_| writer.close())
+                    })
+                    .map(move |file| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_helper::read(c5, &file, dir.enc_key().cloned())
+            })
+            .then(|res| {
+                let reader = unwrap!(res);
+                let size = reader.size();
+                reader.read(0, size)
+            })
+            .map(|data| {
+                assert_eq!(data.len(), EXTENDED_SIZE);
+                assert_eq!(&data[POSITION..POSITION + NEW_SIZE], &[3u8; NEW_SIZE][..]);
+                assert!(data[TRUNCATED_SIZE..].iter().all(|&byte| byte == 0));
+            })
+    })
+}
+
+// Test that the sequential modes reject positional writes other than at the end of the file, and
+// truncation.
+#[test]
+fn file_update_sequential_write_at() {
+    random_client(move |client| {
+        let c2 = client.clone();
+
+        create_test_file(client, true)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_helper::write(c2, file, Mode::Append, dir.enc_key().cloned())
+            })
+            .then(|res| {
+                let writer = unwrap!(res);
+                writer
+                    .write_at(&[1u8; APPEND_SIZE], 0)
+                    .then(move |res| match res {
+                        Err(NfsError::InvalidRange) => Ok(writer),
+                        res => panic!("Unexpected result {:?}", res),
+                    })
+            })
+            .then(|res: Result<_, NfsError>| {
+                let writer = unwrap!(res);
+                writer.truncate(0).then(move |res| match res {
+                    Err(NfsError::Unexpected(_)) => Ok(writer),
+                    res => panic!("Unexpected result {:?}", res),
+                })
+            })
+            .then(|res: Result<_, NfsError>| {
+                let writer = unwrap!(res);
+                writer
+                    .write_at(&[1u8; APPEND_SIZE], ORIG_SIZE as u64)
+                    .and_then(move |_| writer.close())
+            })
+            .map(|file| {
+                assert_eq!(file.size(), (ORIG_SIZE + APPEND_SIZE) as u64);
+            })
+    })
+}
+
 #[test]
 fn file_update_metadata() {
     random_client(|client| {
@@ -617,6 +867,76 @@ fn file_update_metadata() {
             })
     });
 }
+
+// Test the structured metadata of files.
+// 1. Check that the hash of the content is set when a file is written from scratch.
+// 2. Append to the file and check that the hash is cleared.
+// 3. Set a MIME type and an extended attribute, update the file and fetch it back.
+// 4. Read the file in chunks and check that a wrong hash is detected once all of it is read.
+#[test]
+fn file_structured_metadata() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+
+        create_test_file(client, false)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                let content = vec![0u8; ORIG_SIZE];
+                assert_eq!(file.metadata().content_hash(), Some(&sha3_256(&content)));
+
+                file_helper::write(c2, file, Mode::Append, dir.enc_key().cloned())
+                    .and_then(|writer| {
+                        writer
+                            .write(&[1u8; APPEND_SIZE])
+                            .and_then(move |_| writer.close())
+                    })
+                    .map(move |file| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, mut file) = unwrap!(res);
+                assert_eq!(file.metadata().content_hash(), None);
+
+                unwrap!(file
+                    .metadata_mut()
+                    .set_mime_type(Some("text/plain".to_string())));
+                let _ = unwrap!(file.metadata_mut().set_xattr("rating", AttrValue::Int(5)));
+
+                file_helper::update(c3, dir.clone(), "hello.txt", &file, Version::GetNext)
+                    .map(move |_| dir)
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::fetch(c4, dir.clone(), "hello.txt").map(move |(_, file)| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, mut file) = unwrap!(res);
+                assert_eq!(file.metadata().mime_type(), Some("text/plain"));
+                assert_eq!(file.metadata().xattr("rating"), Some(&AttrValue::Int(5)));
+
+                file.metadata_mut().set_content_hash(Some([0; 32]));
+                file_helper::read(c5, &file, dir.enc_key().cloned())
+            })
+            .then(|res| {
+                let reader = unwrap!(res);
+                let split = ORIG_SIZE as u64;
+                let rest = reader.size() - split;
+
+                reader
+                    .read(0, split)
+                    .map(move |_| reader)
+                    .and_then(move |reader| reader.read(split, rest))
+            })
+            .then(|res| -> Result<_, NfsError> {
+                match res {
+                    Err(NfsError::ContentHashMismatch) => Ok(()),
+                    res => panic!("Unexpected result {:?}", res),
+                }
+            })
+    });
+}
 #[test]
 fn file_delete() {
     random_client(|client| {
@@ -649,113 +969,826 @@ fn file_delete() {
     });
 }
 
-// Test deleting an entry and then re-adding it.
-// We should be able to successfully open and read the re-added file.
+// Test listing the files in a directory.
+// 1. Create a directory containing a metadata entry and insert several files.
+// 2. List the files and check that the metadata entry is skipped.
+// 3. Check sorting, prefix filtering and pagination.
 #[test]
-fn file_delete_then_add() {
+fn file_list() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+
+        let root = unwrap!(MDataInfo::random_private(MDataKind::Seq, DIR_TAG));
+        let metadata = btree_map![
+            METADATA_KEY.to_vec() => MDataSeqValue { data: vec![1, 2, 3], version: 0 }
+        ];
+
+        create_dir(client, &root, metadata, btree_map![])
+            .and_then(move |()| {
+                let inserts = vec![("b.txt", 10), ("a.txt", 30), ("ab.txt", 20)]
+                    .into_iter()
+                    .map(|(name, size)| {
+                        let mut file = File::new(Vec::new(), true);
+                        file.set_size(size);
+                        file_helper::insert(c2.clone(), root.clone(), name, &file)
+                    })
+                    .collect::<Vec<_>>();
+
+                future::join_all(inserts).map(move |_| root)
+            })
+            .and_then(move |root| {
+                file_helper::list(c3, root.clone(), ListOptions::default()).map(move |files| {
+                    let names: Vec<_> = files.iter().map(|(name, _, _)| name.as_str()).collect();
+                    assert_eq!(names, vec!["a.txt", "ab.txt", "b.txt"]);
+                    assert!(files.iter().all(|&(_, version, _)| version == 0));
+                    root
+                })
+            })
+            .and_then(move |root| {
+                let options = ListOptions {
+                    sort_by: SortBy::Size,
+                    descending: true,
+                    ..Default::default()
+                };
+                file_helper::list(c4, root.clone(), options).map(move |files| {
+                    let sizes: Vec<_> = files.iter().map(|(_, _, file)| file.size()).collect();
+                    assert_eq!(sizes, vec![30, 20, 10]);
+                    root
+                })
+            })
+            .and_then(move |root| {
+                let options = ListOptions {
+                    prefix: Some("a".to_string()),
+                    offset: 1,
+                    limit: Some(1),
+                    ..Default::default()
+                };
+                file_helper::list(c5, root, options).map(move |files| {
+                    assert_eq!(files.len(), 1);
+                    assert_eq!(files[0].0, "ab.txt");
+                })
+            })
+    });
+}
+
+// Test renaming a file within a directory.
+// 1. Create a file and rename it.
+// 2. Check that only the new name exists.
+// 3. Rename and move the file to its own name, and check the content is preserved.
+// 4. Check that renaming onto an existing file fails.
+#[test]
+fn file_rename() {
     random_client(|client| {
         let c2 = client.clone();
         let c3 = client.clone();
         let c4 = client.clone();
         let c5 = client.clone();
         let c6 = client.clone();
+        let c7 = client.clone();
+        let c8 = client.clone();
 
-        create_test_file(client, true)
+        create_test_file(client, false)
             .then(move |res| {
-                let (dir, file) = unwrap!(res);
-                file_helper::delete(c2, dir.clone(), "hello.txt", true, Version::Custom(1))
-                    .map(move |_| (dir, file))
+                let (dir, _file) = unwrap!(res);
+                file_helper::rename(c2, dir.clone(), "hello.txt", "world.txt", Version::GetNext)
+                    .map(move |()| dir)
             })
             .then(move |res| {
-                let (dir, file) = unwrap!(res);
-
-                file_helper::write(c3, file, Mode::Overwrite, dir.enc_key().cloned())
-                    .map(move |writer| (writer, dir))
+                let dir = unwrap!(res);
+                file_helper::fetch(c3, dir.clone(), "hello.txt").then(move |res| match res {
+                    Err(NfsError::FileNotFound) => Ok::<_, NfsError>(dir),
+                    res => panic!("Unexpected result {:?}", res),
+                })
             })
             .then(move |res| {
-                let (writer, dir) = unwrap!(res);
-
-                writer
-                    .write(&[1u8; NEW_SIZE])
-                    .and_then(move |_| writer.close())
-                    .map(move |file| (file, dir))
+                let dir = unwrap!(res);
+                file_helper::rename(c7, dir.clone(), "world.txt", "world.txt", Version::GetNext)
+                    .map(move |()| dir)
             })
             .then(move |res| {
-                let (file, dir) = unwrap!(res);
-                file_helper::insert(c4, dir.clone(), "hello.txt", &file).map(move |_| dir)
+                let dir = unwrap!(res);
+                file_helper::move_file(c8, dir.clone(), "world.txt", dir.clone(), "world.txt")
+                    .map(move |()| dir)
             })
             .then(move |res| {
                 let dir = unwrap!(res);
-                file_helper::fetch(c5, dir.clone(), "hello.txt")
-                    .map(move |(version, file)| (version, file, dir))
+                read_file(&c4, &dir, "world.txt").map(move |content| (content, dir))
             })
             .then(move |res| {
-                let (version, file, dir) = unwrap!(res);
-                assert_eq!(version, 0);
-                file_helper::read(c6, &file, dir.enc_key().cloned())
+                let (content, dir) = unwrap!(res);
+                assert_eq!(content, vec![0u8; ORIG_SIZE]);
+
+                let file = File::new(Vec::new(), true);
+                file_helper::insert(c5, dir.clone(), "hello.txt", &file).map(move |()| dir)
             })
             .then(move |res| {
-                let reader = unwrap!(res);
-                let size = reader.size();
-                trace!("reading {} bytes", size);
-                reader.read(0, size)
+                let dir = unwrap!(res);
+                file_helper::rename(c6, dir, "hello.txt", "world.txt", Version::GetNext)
             })
-            .map(move |data| {
-                assert_eq!(data, vec![1u8; NEW_SIZE]);
+            .then(|res| -> Result<_, NfsError> {
+                match res {
+                    Err(NfsError::FileExists) => Ok(()),
+                    res => panic!("Unexpected result {:?}", res),
+                }
             })
     });
 }
 
-// Test closing files immediately after opening them in the different modes.
+// Test copying and moving files between directories with different encryption keys.
+// 1. Copy a file into another directory and move it there under a different name.
+// 2. Check that the source entry is gone and both files can be read with the new key.
+// 3. Delete the moved file and check that the copy is still readable.
 #[test]
-fn file_open_close() {
+fn file_copy_and_move() {
     random_client(|client| {
         let c2 = client.clone();
         let c3 = client.clone();
         let c4 = client.clone();
         let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+        let c8 = client.clone();
+        let c9 = client.clone();
 
-        create_test_file(client, true)
+        let dst = unwrap!(MDataInfo::random_private(MDataKind::Seq, DIR_TAG));
+
+        create_test_file(client, false)
+            .join(create_dir(client, &dst, btree_map![], btree_map![]).map_err(NfsError::from))
             .then(move |res| {
-                let (dir, file) = unwrap!(res);
-                // Open the file for reading
-                file_helper::read(c2, &file, dir.enc_key().cloned())
-                    .map(move |reader| (reader, file, dir))
+                let ((src, _file), ()) = unwrap!(res);
+                file_helper::copy(c2, src.clone(), "hello.txt", dst.clone(), "copy.txt")
+                    .map(move |()| (src, dst))
             })
             .then(move |res| {
-                // The reader should get dropped implicitly
-                let (_reader, file, dir) = unwrap!(res);
-                // Open the file for writing
-                file_helper::write(c3, file.clone(), Mode::Overwrite, dir.enc_key().cloned())
-                    .map(move |writer| (writer, file, dir))
+                let (src, dst) = unwrap!(res);
+                file_helper::move_file(c3, src.clone(), "hello.txt", dst.clone(), "moved.txt")
+                    .map(move |()| (src, dst))
             })
             .then(move |res| {
-                let (writer, file, dir) = unwrap!(res);
-                // Close the file
-                let _ = writer.close();
-                // Open the file for appending
-                file_helper::write(c4, file.clone(), Mode::Append, dir.enc_key().cloned())
-                    .map(move |writer| (writer, file, dir))
+                let (src, dst) = unwrap!(res);
+                file_helper::fetch(c4, src, "hello.txt").then(move |res| match res {
+                    Err(NfsError::FileNotFound) => Ok::<_, NfsError>(dst),
+                    res => panic!("Unexpected result {:?}", res),
+                })
             })
             .then(move |res| {
-                let (writer, file, dir) = unwrap!(res);
-                // Close the file
-                let _ = writer.close();
-                // Open the file for reading, ensure it has original contents
-                file_helper::read(c5, &file, dir.enc_key().cloned())
+                let dst = unwrap!(res);
+                read_file(&c5, &dst, "moved.txt").map(move |content| (content, dst))
             })
             .then(move |res| {
-                let reader = unwrap!(res);
-                let size = reader.size();
-                reader.read(0, size)
+                let (content, dst) = unwrap!(res);
+                assert_eq!(content, vec![0u8; ORIG_SIZE]);
+                read_file(&c6, &dst, "copy.txt").map(move |content| (content, dst))
             })
-            .map(move |data| {
-                assert_eq!(data, vec![0u8; ORIG_SIZE]);
+            .then(move |res| {
+                let (content, dst) = unwrap!(res);
+                assert_eq!(content, vec![0u8; ORIG_SIZE]);
+                file_helper::fetch(c7, dst.clone(), "moved.txt")
+                    .join(file_helper::fetch(c8, dst.clone(), "copy.txt"))
+                    .map(move |((_, moved), (_, copied))| {
+                        assert_ne!(moved.data_map_name(), copied.data_map_name());
+                        dst
+                    })
+            })
+            .then(move |res| {
+                let dst = unwrap!(res);
+                file_helper::delete(
+                    c9.clone(),
+                    dst.clone(),
+                    "moved.txt",
+                    false,
+                    Version::GetNext,
+                )
+                .and_then(move |_| read_file(&c9, &dst, "copy.txt"))
+            })
+            .map(move |content| {
+                assert_eq!(content, vec![0u8; ORIG_SIZE]);
             })
     });
 }
 
-// Test opening the same file multiple times concurrently.
+// Test copying an unpublished file within a directory without encryption key, where the copy
+// can't get a data map of its own.
+// 1. Copy the file and check that the copy is counted as a link to the shared data map.
+// 2. Delete the original and check that the copy is still readable.
+#[test]
+fn file_copy_unencrypted() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+
+        let dir = unwrap!(MDataInfo::random_public(MDataKind::Seq, DIR_TAG));
+        let dir2 = dir.clone();
+
+        create_dir(client, &dir, btree_map![], btree_map![])
+            .map_err(NfsError::from)
+            .then(move |res| {
+                unwrap!(res);
+                file_helper::write(c2, File::new(Vec::new(), false), Mode::Overwrite, None)
+            })
+            .then(move |res| {
+                let writer = unwrap!(res);
+                writer
+                    .write(&[2u8; NEW_SIZE])
+                    .and_then(move |_| writer.close())
+            })
+            .then(move |res| {
+                let file = unwrap!(res);
+                file_helper::insert(c3, dir2.clone(), "a.txt", &file).map(move |()| (dir2, file))
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_helper::copy(c4, dir.clone(), "a.txt", dir.clone(), "b.txt")
+                    .map(move |()| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                link::link_count(&c5, &file).map(move |count| {
+                    assert_eq!(count, 2);
+                    dir
+                })
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::delete(c6, dir.clone(), "a.txt", false, Version::GetNext)
+                    .map(move |_| dir)
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                read_file(&c7, &dir, "b.txt")
+            })
+            .map(|content| {
+                assert_eq!(content, vec![2u8; NEW_SIZE]);
+            })
+    });
+}
+
+// Test recovering from an interrupted move.
+// 1. Copy a file into another directory, as if a move was interrupted after the insertion.
+// 2. Finish the move and check the file is only in the destination.
+// 3. Roll the completed move back and check the file is only in the source again.
+// 4. Interrupt another move and roll it back.
+#[test]
+fn file_recover_move() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+        let c8 = client.clone();
+        let c9 = client.clone();
+
+        let dst = unwrap!(MDataInfo::random_private(MDataKind::Seq, DIR_TAG));
+
+        create_test_file(client, false)
+            .join(create_dir(client, &dst, btree_map![], btree_map![]).map_err(NfsError::from))
+            .then(move |res| {
+                let ((src, _file), ()) = unwrap!(res);
+                file_helper::copy(c2, src.clone(), "hello.txt", dst.clone(), "hello.txt")
+                    .map(move |()| (src, dst))
+            })
+            .then(move |res| {
+                let (src, dst) = unwrap!(res);
+                file_helper::recover_move(
+                    c3,
+                    src.clone(),
+                    "hello.txt",
+                    dst.clone(),
+                    "hello.txt",
+                    MoveRecovery::Finish,
+                )
+                .map(move |()| (src, dst))
+            })
+            .then(move |res| {
+                let (src, dst) = unwrap!(res);
+                check_file_location(&c4, src, dst, false)
+            })
+            .then(move |res| {
+                let (src, dst) = unwrap!(res);
+                file_helper::recover_move(
+                    c5,
+                    src.clone(),
+                    "hello.txt",
+                    dst.clone(),
+                    "hello.txt",
+                    MoveRecovery::RollBack,
+                )
+                .map(move |()| (src, dst))
+            })
+            .then(move |res| {
+                let (src, dst) = unwrap!(res);
+                check_file_location(&c6, src, dst, true)
+            })
+            .then(move |res| {
+                let (src, dst) = unwrap!(res);
+                file_helper::copy(c7, src.clone(), "hello.txt", dst.clone(), "hello.txt")
+                    .map(move |()| (src, dst))
+            })
+            .then(move |res| {
+                let (src, dst) = unwrap!(res);
+                file_helper::recover_move(
+                    c8,
+                    src.clone(),
+                    "hello.txt",
+                    dst.clone(),
+                    "hello.txt",
+                    MoveRecovery::RollBack,
+                )
+                .map(move |()| (src, dst))
+            })
+            .then(move |res| {
+                let (src, dst) = unwrap!(res);
+                check_file_location(&c9, src, dst, true)
+            })
+            .map(|_| ())
+    });
+}
+
+// Test the version history of a file.
+// 1. Enable history for a file and update its content.
+// 2. Rename the file and check that the previous version is still listed and can be read.
+// 3. Restore the previous version and check that the current content is recorded too.
+// 4. Copy the file and check that the copy has no history.
+#[test]
+fn file_history() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+        let c8 = client.clone();
+        let c9 = client.clone();
+        let c10 = client.clone();
+        let c11 = client.clone();
+        let c12 = client.clone();
+
+        create_test_file(client, false)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_history::enable(c2, dir.clone(), "hello.txt").map(move |()| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_helper::write(c3, file, Mode::Overwrite, dir.enc_key().cloned())
+                    .and_then(|writer| writer.write(&[1u8; NEW_SIZE]).and_then(|_| writer.close()))
+                    .map(move |file| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                // The written file has no history of its own, so it inherits that of the entry.
+                file_helper::update(c4, dir.clone(), "hello.txt", &file, Version::GetNext).map(
+                    move |version| {
+                        assert_eq!(version, 2);
+                        dir
+                    },
+                )
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::rename(
+                    c5,
+                    dir.clone(),
+                    "hello.txt",
+                    "renamed.txt",
+                    Version::GetNext,
+                )
+                .map(move |()| dir)
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_history::list(c6, dir.clone(), "renamed.txt").map(move |versions| {
+                    assert_eq!(versions.len(), 1);
+                    assert_eq!(versions[0].0, 0);
+                    assert_eq!(versions[0].1.size(), ORIG_SIZE as u64);
+                    dir
+                })
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_history::read(c7, dir.clone(), "renamed.txt", 0)
+                    .and_then(|reader| {
+                        let size = reader.size();
+                        reader.read(0, size)
+                    })
+                    .map(move |content| {
+                        assert_eq!(content, vec![0u8; ORIG_SIZE]);
+                        dir
+                    })
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_history::restore(c8, dir.clone(), "renamed.txt", 0).map(move |version| {
+                    assert_eq!(version, 1);
+                    dir
+                })
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                read_file(&c9, &dir, "renamed.txt").map(move |content| (content, dir))
+            })
+            .then(move |res| {
+                let (content, dir) = unwrap!(res);
+                assert_eq!(content, vec![0u8; ORIG_SIZE]);
+                file_history::list(c10, dir.clone(), "renamed.txt")
+                    .map(move |versions| (versions, dir))
+            })
+            .then(move |res| {
+                let (versions, dir) = unwrap!(res);
+                let sizes: Vec<_> = versions.iter().map(|(_, file)| file.size()).collect();
+                assert_eq!(sizes, vec![ORIG_SIZE as u64, NEW_SIZE as u64]);
+
+                file_helper::copy(c11, dir.clone(), "renamed.txt", dir.clone(), "copy.txt")
+                    .map(move |()| dir)
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::fetch(c12, dir, "copy.txt")
+            })
+            .map(|(_, file)| {
+                // The copy doesn't share the history of the original.
+                assert!(!file.metadata().has_history());
+            })
+    });
+}
+
+// Test following symbolic links to files and directories, and detecting loops of links.
+#[test]
+fn file_symlinks() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+        let c8 = client.clone();
+        let c9 = client.clone();
+
+        let shared = unwrap!(MDataInfo::random_private(MDataKind::Seq, DIR_TAG));
+
+        create_test_file(client, false)
+            .join(create_dir(client, &shared, btree_map![], btree_map![]).map_err(NfsError::from))
+            .then(move |res| {
+                let ((dir, _file), ()) = unwrap!(res);
+                let to_file = Link::Path("hello.txt".to_string());
+                let to_shared = Link::Entry {
+                    dir: shared.clone(),
+                    name: None,
+                };
+                let back = Link::Entry {
+                    dir: dir.clone(),
+                    name: Some("alias.txt".to_string()),
+                };
+
+                link::symlink(c2.clone(), dir.clone(), "alias.txt", &to_file)
+                    .join3(
+                        link::symlink(c2.clone(), dir.clone(), "shared", &to_shared),
+                        link::symlink(c2, shared.clone(), "back.txt", &back),
+                    )
+                    .map(move |_| (dir, shared))
+            })
+            .then(move |res| {
+                let (dir, shared) = unwrap!(res);
+                read_file(&c3, &dir, "alias.txt").map(move |content| (content, dir, shared))
+            })
+            .then(move |res| {
+                let (content, dir, shared) = unwrap!(res);
+                assert_eq!(content, vec![0u8; ORIG_SIZE]);
+
+                link::resolve(c4.clone(), dir.clone(), "shared/back.txt")
+                    .join(link::resolve(c4, dir.clone(), "shared/"))
+                    .map(move |(file, shared_dir)| {
+                        match file {
+                            Resolved::File {
+                                ref parent,
+                                ref name,
+                                ..
+                            } => {
+                                assert_eq!(*parent, dir);
+                                assert_eq!(name, "hello.txt");
+                            }
+                            resolved => panic!("Unexpected resolution {:?}", resolved),
+                        }
+                        assert_eq!(shared_dir, Resolved::Dir(shared));
+                        dir
+                    })
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::list(c5.clone(), dir.clone(), ListOptions::default())
+                    .join(link::list(c5, dir.clone()))
+                    .map(move |(files, links)| {
+                        let files: Vec<_> = files.into_iter().map(|(name, ..)| name).collect();
+                        let links: Vec<_> = links.into_iter().map(|(name, ..)| name).collect();
+                        assert_eq!(files, vec!["hello.txt"]);
+                        assert_eq!(links.len(), 2);
+                        assert!(links.contains(&"alias.txt".to_string()));
+                        assert!(links.contains(&"shared".to_string()));
+                        dir
+                    })
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                let to_b = Link::Path("loop_b".to_string());
+                let to_a = Link::Path("loop_a".to_string());
+
+                link::symlink(c6.clone(), dir.clone(), "loop_a", &to_b)
+                    .join(link::symlink(c6, dir.clone(), "loop_b", &to_a))
+                    .map(move |_| dir)
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::fetch(c7, dir.clone(), "loop_a").then(move |res| match res {
+                    Err(NfsError::TooManyLinks) => Ok::<_, NfsError>(dir),
+                    res => panic!("Unexpected result {:?}", res),
+                })
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::delete(c8, dir.clone(), "alias.txt", false, Version::GetNext)
+                    .map(move |_| dir)
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                read_file(&c9, &dir, "hello.txt")
+            })
+            .map(move |content| {
+                assert_eq!(content, vec![0u8; ORIG_SIZE]);
+            })
+    });
+}
+
+// Test that the data map of an unpublished file is only deleted with its last hard link.
+#[test]
+fn file_hard_links() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+
+        create_test_file(client, false)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                link::hard_link(c2, dir.clone(), "hello.txt", dir.clone(), "link.txt")
+                    .map(move |()| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                link::link_count(&c3, &file).map(move |count| {
+                    assert_eq!(count, 2);
+                    (dir, file)
+                })
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_helper::delete(c4, dir.clone(), "hello.txt", false, Version::GetNext)
+                    .map(move |_| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                read_file(&c5, &dir, "link.txt").map(move |content| {
+                    assert_eq!(content, vec![0u8; ORIG_SIZE]);
+                    (dir, file)
+                })
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_helper::delete(c6, dir, "link.txt", false, Version::GetNext).map(|_| file)
+            })
+            .then(move |res| {
+                let file = unwrap!(res);
+                c7.get_idata(file.data_address())
+            })
+            .then(|res| -> Result<_, CoreError> {
+                match res {
+                    Err(CoreError::DataError(SndError::NoSuchData)) => Ok(()),
+                    res => panic!("Unexpected result {:?}", res),
+                }
+            })
+    });
+}
+
+// Test that updating one hard link to new content stops it sharing the old data map.
+// 1. Link a file and update the original entry with newly written content.
+// 2. Delete the link and check that the old data map is deleted with it.
+#[test]
+fn file_hard_link_update() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+
+        create_test_file(client, false)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                link::hard_link(c2, dir.clone(), "hello.txt", dir.clone(), "link.txt")
+                    .map(move |()| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_helper::write(c3, file.clone(), Mode::Overwrite, dir.enc_key().cloned())
+                    .and_then(|writer| writer.write(&[1u8; NEW_SIZE]).and_then(|_| writer.close()))
+                    .map(move |new_file| (dir, file, new_file))
+            })
+            .then(move |res| {
+                let (dir, file, new_file) = unwrap!(res);
+                file_helper::update(c4, dir.clone(), "hello.txt", &new_file, Version::GetNext)
+                    .map(move |_| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                link::link_count(&c5, &file).map(move |count| {
+                    assert_eq!(count, 1);
+                    (dir, file)
+                })
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_helper::delete(c6, dir, "link.txt", false, Version::GetNext).map(|_| file)
+            })
+            .then(move |res| {
+                let file = unwrap!(res);
+                c7.get_idata(file.data_address())
+            })
+            .then(|res| -> Result<_, CoreError> {
+                match res {
+                    Err(CoreError::DataError(SndError::NoSuchData)) => Ok(()),
+                    res => panic!("Unexpected result {:?}", res),
+                }
+            })
+    });
+}
+
+// Test that updating a symbolic link updates the file it points to, leaving the link in place.
+#[test]
+fn file_update_through_symlink() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+
+        create_test_file(client, false)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                let to_file = Link::Path("hello.txt".to_string());
+                link::symlink(c2, dir.clone(), "alias.txt", &to_file).map(move |()| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, mut file) = unwrap!(res);
+                file.set_user_metadata(b"updated".to_vec());
+                file_helper::update(c3, dir.clone(), "alias.txt", &file, Version::GetNext).map(
+                    move |version| {
+                        assert_eq!(version, 1);
+                        dir
+                    },
+                )
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                link::read_link(c4, dir.clone(), "alias.txt").map(move |(version, _)| {
+                    assert_eq!(version, 0);
+                    dir
+                })
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                link::fetch_entry(&c5, &dir, "hello.txt")
+            })
+            .map(|(version, entry)| {
+                assert_eq!(version, 1);
+                match entry {
+                    Entry::File(file) => assert_eq!(file.user_metadata(), b"updated"),
+                    Entry::Link(_) => panic!("Unexpected link"),
+                }
+            })
+    });
+}
+
+// Test deleting an entry and then re-adding it.
+// We should be able to successfully open and read the re-added file.
+#[test]
+fn file_delete_then_add() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+
+        create_test_file(client, true)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                file_helper::delete(c2, dir.clone(), "hello.txt", true, Version::Custom(1))
+                    .map(move |_| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+
+                file_helper::write(c3, file, Mode::Overwrite, dir.enc_key().cloned())
+                    .map(move |writer| (writer, dir))
+            })
+            .then(move |res| {
+                let (writer, dir) = unwrap!(res);
+
+                writer
+                    .write(&[1u8; NEW_SIZE])
+                    .and_then(move |_| writer.close())
+                    .map(move |file| (file, dir))
+            })
+            .then(move |res| {
+                let (file, dir) = unwrap!(res);
+                file_helper::insert(c4, dir.clone(), "hello.txt", &file).map(move |_| dir)
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                file_helper::fetch(c5, dir.clone(), "hello.txt")
+                    .map(move |(version, file)| (version, file, dir))
+            })
+            .then(move |res| {
+                let (version, file, dir) = unwrap!(res);
+                assert_eq!(version, 0);
+                file_helper::read(c6, &file, dir.enc_key().cloned())
+            })
+            .then(move |res| {
+                let reader = unwrap!(res);
+                let size = reader.size();
+                trace!("reading {} bytes", size);
+                reader.read(0, size)
+            })
+            .map(move |data| {
+                assert_eq!(data, vec![1u8; NEW_SIZE]);
+            })
+    });
+}
+
+// Test closing files immediately after opening them in the different modes.
+#[test]
+fn file_open_close() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+
+        create_test_file(client, true)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+                // Open the file for reading
+                file_helper::read(c2, &file, dir.enc_key().cloned())
+                    .map(move |reader| (reader, file, dir))
+            })
+            .then(move |res| {
+                // The reader should get dropped implicitly
+                let (_reader, file, dir) = unwrap!(res);
+                // Open the file for writing
+                file_helper::write(c3, file.clone(), Mode::Overwrite, dir.enc_key().cloned())
+                    .map(move |writer| (writer, file, dir))
+            })
+            .then(move |res| {
+                let (writer, file, dir) = unwrap!(res);
+                // Close the file
+                let _ = writer.close();
+                // Open the file for appending
+                file_helper::write(c4, file.clone(), Mode::Append, dir.enc_key().cloned())
+                    .map(move |writer| (writer, file, dir))
+            })
+            .then(move |res| {
+                let (writer, file, dir) = unwrap!(res);
+                // Close the file
+                let _ = writer.close();
+                // Open the file for reading, ensure it has original contents
+                file_helper::read(c5, &file, dir.enc_key().cloned())
+            })
+            .then(move |res| {
+                let reader = unwrap!(res);
+                let size = reader.size();
+                reader.read(0, size)
+            })
+            .map(move |data| {
+                assert_eq!(data, vec![0u8; ORIG_SIZE]);
+            })
+    });
+}
+
+// Test opening the same file multiple times concurrently.
 #[test]
 fn file_open_concurrent() {
     random_client(|client| {
@@ -910,3 +1943,679 @@ fn encryption() {
         })
     })
 }
+
+// Test synchronising two local trees through a directory.
+// 1. Sync the first tree, uploading its files.
+// 2. Sync an empty second tree, downloading the files.
+// 3. Change and delete files in the second tree and sync it.
+// 4. Save and load the state of the first tree, then sync it and check the changes have been
+//    applied to it.
+// 5. Change a file in both trees and check the conflict is reported, then resolved.
+#[test]
+fn file_sync() {
+    let first = unwrap!(tempdir());
+    let second = unwrap!(tempdir());
+    let first_root = first.path().to_path_buf();
+    let second_root = second.path().to_path_buf();
+    let state_dir = unwrap!(tempdir());
+    let state_path = state_dir.path().join("state");
+
+    unwrap!(fs::create_dir(first_root.join("sub")));
+    unwrap!(fs::write(first_root.join("a.txt"), b"first"));
+    unwrap!(fs::write(first_root.join("sub/b.txt"), b"second"));
+
+    random_client(move |client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+
+        let dir = unwrap!(MDataInfo::random_private(MDataKind::Seq, DIR_TAG));
+        let dir2 = dir.clone();
+        let dir3 = dir.clone();
+        let dir4 = dir.clone();
+        let dir5 = dir.clone();
+        let dir6 = dir.clone();
+        let first_root2 = first_root.clone();
+        let first_root3 = first_root.clone();
+        let second_root2 = second_root.clone();
+        let second_root3 = second_root.clone();
+
+        create_dir(client, &dir, btree_map![], btree_map![])
+            .and_then(move |()| {
+                sync::sync(
+                    c2,
+                    first_root,
+                    dir2,
+                    SyncState::new(),
+                    ConflictPolicy::Report,
+                )
+            })
+            .and_then(move |(first_state, report)| {
+                assert_eq!(report.uploaded, vec!["a.txt", "sub/b.txt"]);
+
+                sync::sync(
+                    c3,
+                    second_root,
+                    dir3,
+                    SyncState::new(),
+                    ConflictPolicy::Report,
+                )
+                .map(move |(second_state, report)| (first_state, second_state, report))
+            })
+            .and_then(move |(first_state, second_state, report)| {
+                assert_eq!(report.downloaded, vec!["a.txt", "sub/b.txt"]);
+                assert_eq!(unwrap!(fs::read(second_root2.join("sub/b.txt"))), b"second");
+
+                unwrap!(fs::write(second_root2.join("a.txt"), b"changed"));
+                unwrap!(fs::remove_file(second_root2.join("sub/b.txt")));
+
+                sync::sync(c4, second_root2, dir4, second_state, ConflictPolicy::Report)
+                    .map(move |(second_state, report)| (first_state, second_state, report))
+            })
+            .and_then(move |(first_state, second_state, report)| {
+                assert_eq!(report.uploaded, vec!["a.txt"]);
+                assert_eq!(report.deleted_remote, vec!["sub/b.txt"]);
+
+                first_state
+                    .save(state_path.clone())
+                    .and_then(move |()| SyncState::load(state_path))
+                    .map(move |loaded| {
+                        assert_eq!(loaded, first_state);
+                        loaded
+                    })
+                    .and_then(move |first_state| {
+                        sync::sync(c5, first_root2, dir5, first_state, ConflictPolicy::Report)
+                    })
+                    .map(move |(first_state, report)| (first_state, second_state, report))
+            })
+            .and_then(move |(first_state, second_state, report)| {
+                assert_eq!(report.downloaded, vec!["a.txt"]);
+                assert_eq!(report.deleted_local, vec!["sub/b.txt"]);
+                assert_eq!(unwrap!(fs::read(first_root3.join("a.txt"))), b"changed");
+                assert!(!first_root3.join("sub").exists());
+                assert_eq!(first_state.files().collect::<Vec<_>>(), vec!["a.txt"]);
+
+                unwrap!(fs::write(first_root3.join("a.txt"), b"changed first"));
+                unwrap!(fs::write(second_root3.join("a.txt"), b"changed second too"));
+
+                sync::sync(c6, second_root3, dir6, second_state, ConflictPolicy::Report)
+                    .map(move |_| (first_root3, first_state))
+            })
+            .and_then(move |(first_root, first_state)| {
+                let first_root2 = first_root.clone();
+                let dir2 = dir.clone();
+                let c8 = c7.clone();
+
+                sync::sync(c7, first_root, dir, first_state, ConflictPolicy::Report).and_then(
+                    move |(first_state, report)| {
+                        assert_eq!(
+                            report,
+                            SyncReport {
+                                conflicts: vec!["a.txt".to_string()],
+                                ..Default::default()
+                            }
+                        );
+
+                        sync::sync(
+                            c8,
+                            first_root2.clone(),
+                            dir2,
+                            first_state,
+                            ConflictPolicy::PreferRemote,
+                        )
+                        .map(move |(_, report)| (first_root2, report))
+                    },
+                )
+            })
+            .map(|(first_root, report)| {
+                assert_eq!(report.downloaded, vec!["a.txt"]);
+                assert_eq!(
+                    unwrap!(fs::read(first_root.join("a.txt"))),
+                    b"changed second too"
+                );
+            })
+    });
+}
+
+// Test exporting a directory to archives and importing them back.
+// 1. Create a directory with a nested file carrying user and structured metadata.
+// 2. Export it as a tar archive and import it into a new directory.
+// 3. Export the nested subtree as a zip archive and import it next to the other files.
+// 4. Import the tar archive again, which fails unless existing files are replaced.
+#[test]
+fn file_archive() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+        let c8 = client.clone();
+        let c9 = client.clone();
+        let c10 = client.clone();
+        let c11 = client.clone();
+
+        let other = unwrap!(MDataInfo::random_private(MDataKind::Seq, DIR_TAG));
+        let other2 = other.clone();
+        let other3 = other.clone();
+        let other4 = other.clone();
+        let other5 = other.clone();
+        let other6 = other.clone();
+
+        create_test_file(client, false)
+            .then(move |res| {
+                let (dir, _) = unwrap!(res);
+                let mut file = File::new(b"user".to_vec(), false);
+                let _ = unwrap!(file.metadata_mut().set_xattr("rating", AttrValue::Int(5)));
+
+                file_helper::write(c2, file, Mode::Overwrite, dir.enc_key().cloned())
+                    .and_then(|writer| writer.write(b"nested").and_then(move |_| writer.close()))
+                    .and_then(move |file| {
+                        file_helper::insert(c3, dir.clone(), "docs/b.txt", &file).map(move |_| dir)
+                    })
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                let writer = Cursor::new(Vec::new());
+                archive::export(c4, dir.clone(), None, ArchiveFormat::Tar, writer)
+                    .map(move |tar| (dir, tar.into_inner()))
+            })
+            .then(move |res| {
+                let (dir, tar) = unwrap!(res);
+                create_dir(&c5, &other, btree_map![], btree_map![])
+                    .and_then(move |()| {
+                        archive::import_tar(c6, other2, Cursor::new(tar.clone()), false)
+                    })
+                    .map(move |imported| (dir, tar, imported))
+            })
+            .then(move |res| {
+                let (dir, tar, imported) = unwrap!(res);
+                assert_eq!(imported, vec!["docs/b.txt", "hello.txt"]);
+
+                file_helper::fetch(c7, other3, "docs/b.txt").map(move |(_, file)| (dir, tar, file))
+            })
+            .then(move |res| {
+                let (dir, tar, file) = unwrap!(res);
+                assert_eq!(file.user_metadata(), b"user");
+                assert_eq!(file.metadata().xattr("rating"), Some(&AttrValue::Int(5)));
+                assert_eq!(file.metadata().content_hash(), Some(&sha3_256(b"nested")));
+
+                let writer = Cursor::new(Vec::new());
+                archive::export(c8, dir, Some("docs"), ArchiveFormat::Zip, writer)
+                    .and_then(move |zip| {
+                        let reader = Cursor::new(zip.into_inner());
+                        archive::import_zip(c9, other4, reader, false)
+                    })
+                    .map(move |imported| (tar, imported))
+            })
+            .then(move |res| {
+                let (tar, imported) = unwrap!(res);
+                assert_eq!(imported, vec!["b.txt"]);
+
+                read_file(&c10, &other5, "b.txt").map(move |content| (tar, content))
+            })
+            .then(move |res| {
+                let (tar, content) = unwrap!(res);
+                assert_eq!(content, b"nested");
+
+                archive::import_tar(c11.clone(), other6.clone(), Cursor::new(tar.clone()), false)
+                    .then(move |res| {
+                        match res {
+                            Err(NfsError::FileExists) => (),
+                            res => panic!("Unexpected result {:?}", res),
+                        }
+                        archive::import_tar(c11, other6, Cursor::new(tar), true)
+                    })
+            })
+            .map(|imported| {
+                assert_eq!(imported, vec!["docs/b.txt", "hello.txt"]);
+            })
+    });
+}
+
+// Test resolving conflicts between concurrent updates of a file.
+// 1. Update a file, then update it again based on its first version, merging both updates.
+// 2. Update it again based on its first version, keeping both updates, twice.
+#[test]
+fn file_update_conflicts() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+
+        create_test_file(client, false)
+            .then(move |res| {
+                let (dir, mut file) = unwrap!(res);
+                file.set_user_metadata(b"first".to_vec());
+
+                file_helper::update(c2, dir.clone(), "hello.txt", &file, Version::Custom(1))
+                    .map(move |_| (dir, file))
+            })
+            .then(move |res| {
+                let (dir, mut file) = unwrap!(res);
+                file.set_user_metadata(b"second".to_vec());
+
+                let merge = |file: &File, current: &File| -> Result<File, NfsError> {
+                    let mut merged = file.clone();
+                    let mut user_metadata = current.user_metadata().to_vec();
+                    user_metadata.extend_from_slice(file.user_metadata());
+                    merged.set_user_metadata(user_metadata);
+                    Ok(merged)
+                };
+
+                conflict::update(
+                    c3,
+                    dir.clone(),
+                    "hello.txt",
+                    &file,
+                    Version::Custom(1),
+                    MergeStrategy::Custom(Rc::new(merge)),
+                )
+                .map(move |resolution| (dir, file, resolution))
+            })
+            .then(move |res| {
+                let (dir, file, resolution) = unwrap!(res);
+                assert_eq!(resolution, Resolution::Merged(2));
+
+                file_helper::fetch(c4, dir.clone(), "hello.txt")
+                    .map(move |(_, fetched)| (dir, file, fetched))
+            })
+            .then(move |res| {
+                let (dir, file, fetched) = unwrap!(res);
+                assert_eq!(fetched.user_metadata(), b"firstsecond");
+
+                conflict::update(
+                    c5,
+                    dir.clone(),
+                    "hello.txt",
+                    &file,
+                    Version::Custom(1),
+                    MergeStrategy::KeepBoth,
+                )
+                .map(move |resolution| (dir, file, resolution))
+            })
+            .then(move |res| {
+                let (dir, file, resolution) = unwrap!(res);
+                assert_eq!(
+                    resolution,
+                    Resolution::KeptBoth("hello (conflict 2).txt".to_string())
+                );
+
+                conflict::update(
+                    c6,
+                    dir,
+                    "hello.txt",
+                    &file,
+                    Version::Custom(1),
+                    MergeStrategy::KeepBoth,
+                )
+            })
+            .map(|resolution| {
+                assert_eq!(
+                    resolution,
+                    Resolution::KeptBoth("hello (conflict 3).txt".to_string())
+                );
+            })
+    });
+}
+
+// Test that watching a directory yields the changes of its files.
+#[test]
+fn dir_watch() {
+    random_client(|client| {
+        let c2 = client.clone();
+
+        create_test_file(client, false).then(move |res| {
+            let (dir, file) = unwrap!(res);
+            let options = WatchOptions {
+                min_interval: Duration::from_millis(10),
+                max_interval: Duration::from_millis(20),
+            };
+            let events = watch::watch(c2.clone(), dir.clone(), options)
+                .take(3)
+                .collect();
+
+            let c3 = c2.clone();
+            let c4 = c2.clone();
+            let dir2 = dir.clone();
+            let dir3 = dir.clone();
+            let file2 = file.clone();
+            let changes = pause()
+                .and_then(move |()| file_helper::insert(c2, dir, "world.txt", &file))
+                .and_then(|()| pause())
+                .and_then(move |()| {
+                    file_helper::update(c3, dir2, "hello.txt", &file2, Version::GetNext)
+                })
+                .and_then(|_| pause())
+                .and_then(move |()| {
+                    file_helper::delete(c4, dir3, "world.txt", false, Version::GetNext)
+                });
+
+            events.join(changes).map(|(events, _)| {
+                let events: Vec<_> = events
+                    .into_iter()
+                    .map(|event| match event {
+                        DirEvent::Added { name, .. } => format!("added {}", name),
+                        DirEvent::Updated { name, version, .. } => {
+                            format!("updated {} {}", name, version)
+                        }
+                        DirEvent::Removed { name } => format!("removed {}", name),
+                    })
+                    .collect();
+
+                assert_eq!(
+                    events,
+                    vec![
+                        "added world.txt",
+                        "updated hello.txt 1",
+                        "removed world.txt"
+                    ]
+                );
+            })
+        })
+    });
+}
+
+// Test computing the storage usage of a directory and its subdirectories.
+#[test]
+fn dir_usage() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+
+        create_test_file(client, false)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+
+                file_helper::insert(c2, dir.clone(), "docs/copy.txt", &file).map(move |_| dir)
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                let target = Link::Path("hello.txt".to_string());
+
+                link::symlink(c3, dir.clone(), "docs/link", &target).map(move |_| dir)
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+                usage::dir_usage(c4, dir)
+            })
+            .map(|usage| {
+                // Content of this size is split into three chunks.
+                let size = ORIG_SIZE as u64;
+
+                assert_eq!(
+                    usage.total,
+                    Usage {
+                        entries: 3,
+                        files: 2,
+                        size: 2 * size,
+                        chunks: 6,
+                    }
+                );
+                assert_eq!(usage.subdirs.len(), 1);
+                assert_eq!(
+                    usage.subdirs["docs"].total,
+                    Usage {
+                        entries: 2,
+                        files: 1,
+                        size,
+                        chunks: 3,
+                    }
+                );
+            })
+    });
+}
+
+// Test moving files to the trash, restoring them and emptying the trash.
+#[test]
+fn file_trash() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+        let c8 = client.clone();
+        let c9 = client.clone();
+        let c10 = client.clone();
+        let c11 = client.clone();
+        let c12 = client.clone();
+
+        let trash = unwrap!(MDataInfo::random_private(MDataKind::Seq, DIR_TAG));
+        let retention = Duration::from_secs(3600);
+
+        create_dir(client, &trash, btree_map![], btree_map![])
+            .join(create_test_file(client, false))
+            .then(move |res| {
+                let ((), (dir, _)) = unwrap!(res);
+
+                trash::soft_delete(c2, dir.clone(), "hello.txt", trash.clone())
+                    .map(move |id| (dir, trash, unwrap!(id)))
+            })
+            .then(move |res| {
+                let (dir, trash, id) = unwrap!(res);
+
+                file_helper::fetch(c3, dir.clone(), "hello.txt").then(move |res| {
+                    match res {
+                        Err(NfsError::FileNotFound) => (),
+                        x => panic!("Unexpected {:?}", x),
+                    }
+                    Ok((dir, trash, id))
+                })
+            })
+            .then(move |res: Result<_, NfsError>| {
+                let (dir, trash, id) = unwrap!(res);
+
+                trash::list(c4, trash.clone()).map(move |items| (dir, trash, id, items))
+            })
+            .then(move |res| {
+                let (dir, trash, id, items) = unwrap!(res);
+                assert_eq!(items.len(), 1);
+                assert_eq!(items[0].id, id);
+                assert_eq!(items[0].dir, dir);
+                assert_eq!(items[0].name, "hello.txt");
+                assert_eq!(items[0].file.size(), ORIG_SIZE as u64);
+                assert!(items[0].file.metadata().xattrs().is_empty());
+
+                // The content is still there.
+                read_file(&c5, &trash, &id).map(move |content| (dir, trash, id, content))
+            })
+            .then(move |res| {
+                let (dir, trash, id, content) = unwrap!(res);
+                assert_eq!(content, vec![0u8; ORIG_SIZE]);
+
+                trash::restore(c6, trash.clone(), id).map(move |()| (dir, trash))
+            })
+            .then(move |res| {
+                let (dir, trash) = unwrap!(res);
+
+                read_file(&c7, &dir, "hello.txt").map(move |content| (dir, trash, content))
+            })
+            .then(move |res| {
+                let (dir, trash, content) = unwrap!(res);
+                assert_eq!(content, vec![0u8; ORIG_SIZE]);
+
+                trash::list(c8, trash.clone()).map(move |items| (dir, trash, items))
+            })
+            .then(move |res| {
+                let (dir, trash, items) = unwrap!(res);
+                assert!(items.is_empty());
+
+                trash::soft_delete(c9, dir, "hello.txt", trash.clone()).map(move |_| trash)
+            })
+            .then(move |res| {
+                let trash = unwrap!(res);
+
+                // Nothing has been in the trash for long enough to be purged.
+                trash::purge(c10, trash.clone(), retention).map(move |purged| (trash, purged))
+            })
+            .then(move |res| {
+                let (trash, purged) = unwrap!(res);
+                assert_eq!(purged, 0);
+
+                trash::empty(c11, trash.clone()).map(move |emptied| (trash, emptied))
+            })
+            .then(move |res| {
+                let (trash, emptied) = unwrap!(res);
+                assert_eq!(emptied, 1);
+
+                trash::list(c12, trash)
+            })
+            .map(|items| assert!(items.is_empty()))
+    });
+}
+
+// Test publishing an unpublished file in place.
+#[test]
+fn file_publish() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+
+        create_test_file(client, false)
+            .then(move |res| {
+                let (dir, file) = unwrap!(res);
+
+                publish::publish(c2, dir.clone(), "hello.txt", true)
+                    .map(move |report| (dir, file, report))
+            })
+            .then(move |res| {
+                let (dir, original, report) = unwrap!(res);
+                // Content of this size is split into three chunks.
+                assert_eq!(report.version, 1);
+                assert_eq!(report.chunks, 3);
+                assert_eq!(report.mutations, 5);
+                assert_eq!(report.cost, unwrap!(Coins::from_nano(5)));
+                assert_eq!(report.deleted, 4);
+
+                file_helper::fetch(c3, dir.clone(), "hello.txt")
+                    .map(move |(_, file)| (dir, original, file))
+            })
+            .then(move |res| {
+                let (dir, original, file) = unwrap!(res);
+                assert!(file.published());
+                assert_eq!(file.size(), original.size());
+
+                read_file(&c4, &dir, "hello.txt").map(move |content| (dir, original, content))
+            })
+            .then(move |res| {
+                let (dir, original, content) = unwrap!(res);
+                assert_eq!(content, vec![0u8; ORIG_SIZE]);
+
+                c5.get_idata(original.data_address()).then(move |res| {
+                    match res {
+                        Err(CoreError::DataError(SndError::NoSuchData)) => (),
+                        x => panic!("Unexpected {:?}", x),
+                    }
+                    Ok::<_, NfsError>(dir)
+                })
+            })
+            .then(move |res| {
+                let dir = unwrap!(res);
+
+                // Publishing a published file does nothing.
+                publish::publish(c6, dir, "hello.txt", true)
+            })
+            .map(|report| {
+                assert_eq!(report.version, 1);
+                assert_eq!(report.mutations, 0);
+            })
+    });
+}
+
+// Test backing up a directory and restoring it.
+// 1. Create a directory with a file and back it up.
+// 2. Delete the file along with its content and restore the directory in place.
+// 3. Restore the backup into a new directory with different encryption.
+#[test]
+fn dir_backup() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+        let c6 = client.clone();
+        let c7 = client.clone();
+        let target = unwrap!(MDataInfo::random_private(MDataKind::Seq, DIR_TAG));
+        let target2 = target.clone();
+
+        create_test_file_with_size(client, false, NEW_SIZE)
+            .and_then(move |(dir, _)| backup::export_dir(c2, dir.clone()).map(move |b| (dir, b)))
+            .and_then(move |(dir, backup)| {
+                assert_eq!(backup.files["hello.txt"], vec![0u8; NEW_SIZE]);
+                let backup = unwrap!(MDataBackup::deserialise(&unwrap!(backup.serialise())));
+
+                file_helper::delete(c3, dir.clone(), "hello.txt", false, Version::GetNext)
+                    .map(move |_| (dir, backup))
+            })
+            .and_then(move |(dir, backup)| {
+                backup::restore_dir(c4, backup.clone(), dir.clone()).map(move |()| (dir, backup))
+            })
+            .and_then(move |(dir, backup)| {
+                read_file(&c5, &dir, "hello.txt").map(move |content| {
+                    assert_eq!(content, vec![0u8; NEW_SIZE]);
+                    backup
+                })
+            })
+            .and_then(move |backup| backup::restore_dir(c6, backup, target))
+            .and_then(move |()| read_file(&c7, &target2, "hello.txt"))
+            .map(|content| assert_eq!(content, vec![0u8; NEW_SIZE]))
+    });
+}
+
+// Test backing up a directory along with its sub-directories.
+// 1. Create a directory with a file and a sub-directory with another file, linking back to the
+//    parent directory.
+// 2. Back up the parent directory and check the loop of links isn't followed.
+// 3. Delete the file in the sub-directory and restore the parent directory.
+#[test]
+fn dir_backup_sub_dirs() {
+    random_client(|client| {
+        let c2 = client.clone();
+        let c3 = client.clone();
+        let c4 = client.clone();
+        let c5 = client.clone();
+
+        create_test_file(client, false)
+            .join(create_test_file_with_size(client, false, NEW_SIZE))
+            .and_then(move |((dir, _), (sub, _))| {
+                let to_sub = Link::Entry {
+                    dir: sub.clone(),
+                    name: None,
+                };
+                let to_parent = Link::Entry {
+                    dir: dir.clone(),
+                    name: None,
+                };
+
+                link::symlink(c2.clone(), dir.clone(), "sub", &to_sub)
+                    .join(link::symlink(c2.clone(), sub.clone(), "parent", &to_parent))
+                    .and_then(move |_| backup::export_dir(c2, dir.clone()).map(|b| (dir, sub, b)))
+            })
+            .and_then(move |(dir, sub, backup)| {
+                assert_eq!(backup.files["hello.txt"], vec![0u8; ORIG_SIZE]);
+                assert_eq!(backup.dirs.len(), 1);
+                assert_eq!(backup.dirs["sub"].files["hello.txt"], vec![0u8; NEW_SIZE]);
+                assert!(backup.dirs["sub"].dirs.is_empty());
+
+                file_helper::delete(c3, sub.clone(), "hello.txt", false, Version::GetNext)
+                    .map(move |_| (dir, sub, backup))
+            })
+            .and_then(move |(dir, sub, backup)| {
+                backup::restore_dir(c4, backup, dir).map(move |()| sub)
+            })
+            .and_then(move |sub| read_file(&c5, &sub, "hello.txt"))
+            .map(|content| assert_eq!(content, vec![0u8; NEW_SIZE]))
+    });
+}
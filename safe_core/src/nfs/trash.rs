@@ -0,0 +1,300 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Soft deletion of files into a trash directory.
+//!
+//! Instead of being deleted, a file is moved into the trash directory, which is normally the
+//! `_trash` standard container of the account. The file is stored there under a unique ID, with
+//! its original directory, name and time of deletion recorded in extended attributes (see
+//! `Metadata::xattrs`), which are removed again when the file is restored. The content of the file
+//! is kept until the item is purged from the trash.
+
+use crate::client::{Client, MDataInfo};
+use crate::ffi::nfs::TrashItem as FfiTrashItem;
+use crate::nfs::file_helper::{self, Version};
+use crate::nfs::link::{self, Entry};
+use crate::nfs::{AttrValue, File, NfsError, NfsFuture};
+use crate::utils::{self, FutureExt};
+use bincode::{deserialize, serialize};
+use chrono::{DateTime, TimeZone, Utc};
+use futures::future::{self, Future};
+use std::ffi::{CString, NulError};
+use std::time::Duration;
+
+/// Extended attribute holding the serialised `MDataInfo` of the directory a trashed file was
+/// deleted from.
+pub const XATTR_ORIGIN_DIR: &str = "safe.trash.dir";
+/// Extended attribute holding the name a trashed file had in its original directory.
+pub const XATTR_ORIGIN_NAME: &str = "safe.trash.name";
+/// Extended attribute holding the time a trashed file was deleted, in milliseconds since the
+/// Unix epoch.
+pub const XATTR_DELETED: &str = "safe.trash.deleted";
+
+/// Default time after which items are purged from the trash.
+pub const DEFAULT_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+const ID_SUFFIX_LEN: usize = 8;
+
+/// File in the trash.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrashItem {
+    /// ID of the item, which is its name in the trash directory.
+    pub id: String,
+    /// Directory the file was deleted from.
+    pub dir: MDataInfo,
+    /// Name of the file in the directory it was deleted from.
+    pub name: String,
+    /// Time the file was deleted.
+    pub deleted: DateTime<Utc>,
+    /// The file, without the extended attributes recording its origin.
+    pub file: File,
+}
+
+impl TrashItem {
+    /// Construct the FFI representation of the item.
+    ///
+    /// The `ffi::nfs::TrashItem` struct has a `Drop` impl which frees the allocated ID and name
+    /// once it goes out of scope.
+    pub fn into_repr_c(self) -> Result<FfiTrashItem, NulError> {
+        Ok(FfiTrashItem {
+            id: CString::new(self.id)?.into_raw(),
+            dir: self.dir.into_repr_c(),
+            name: CString::new(self.name)?.into_raw(),
+            deleted_sec: self.deleted.timestamp(),
+            deleted_nsec: self.deleted.timestamp_subsec_nanos(),
+            file: self.file.into_repr_c(),
+        })
+    }
+}
+
+/// Move the file from the directory into the trash, returning the ID of the new trash item.
+///
+/// Expired items aren't purged here, so deletion doesn't have to list the whole trash; call
+/// `purge` separately, e.g. periodically. Symbolic links hold no content, so they are deleted
+/// immediately instead, in which case `None` is returned.
+pub fn soft_delete<S>(
+    client: impl Client,
+    parent: MDataInfo,
+    name: S,
+    trash: MDataInfo,
+) -> Box<NfsFuture<Option<String>>>
+where
+    S: AsRef<str>,
+{
+    let name = name.as_ref().to_owned();
+    trace!("Moving file '{}' to the trash", name);
+
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let client4 = client.clone();
+
+    link::fetch_entry(&client, &parent, &name)
+        .and_then(move |(version, entry)| {
+            let file = match entry {
+                Entry::File(ref file) => file.clone(),
+                Entry::Link(_) => {
+                    return file_helper::delete(
+                        client2,
+                        parent,
+                        name,
+                        false,
+                        Version::Custom(version + 1),
+                    )
+                    .map(|_| None)
+                    .into_box();
+                }
+            };
+
+            let deleted = Utc::now();
+            let id = fry!(item_id(&deleted));
+            let src_key = parent.enc_key().cloned();
+
+            file_helper::copy_data_map(&client2, file, src_key, &trash, true)
+                .and_then(move |mut file| {
+                    set_origin(&mut file, &parent, &name, &deleted)?;
+                    Ok((parent, name, file))
+                })
+                .and_then(move |(parent, name, file)| {
+                    let moved = Entry::File(file);
+                    file_helper::insert_entry(client3, trash, &id, &moved).and_then(move |()| {
+                        file_helper::remove_moved(
+                            &client4,
+                            &parent,
+                            &name,
+                            version + 1,
+                            &entry,
+                            &moved,
+                        )
+                        .map(move |()| Some(id))
+                    })
+                })
+                .into_box()
+        })
+        .into_box()
+}
+
+/// List the items in the trash, sorted by the time of deletion, oldest first.
+///
+/// Entries of the trash directory which were not put there by `soft_delete` are skipped.
+pub fn list(client: impl Client, trash: MDataInfo) -> Box<NfsFuture<Vec<TrashItem>>> {
+    trace!("Listing the trash {:?}", trash.address());
+
+    file_helper::list(client, trash, Default::default())
+        .map(|files| {
+            let mut items: Vec<_> = files
+                .into_iter()
+                .filter_map(|(id, _, file)| take_origin(id, file))
+                .collect();
+            items.sort_by(|a, b| a.deleted.cmp(&b.deleted).then_with(|| a.id.cmp(&b.id)));
+            items
+        })
+        .into_box()
+}
+
+/// Restore the item from the trash into the directory it was deleted from, under its original
+/// name.
+///
+/// Fails with `NfsError::FileExists` if the directory already contains a file with that name, and
+/// with `NfsError::FileNotFound` if the trash contains no item with the given ID.
+pub fn restore<S>(client: impl Client, trash: MDataInfo, id: S) -> Box<NfsFuture<()>>
+where
+    S: AsRef<str>,
+{
+    let id = id.as_ref().to_owned();
+    trace!("Restoring item '{}' from the trash", id);
+
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let client4 = client.clone();
+
+    link::fetch_entry(&client, &trash, &id)
+        .and_then(move |(version, entry)| {
+            let item = match entry {
+                Entry::File(ref file) => take_origin(id.clone(), file.clone()),
+                Entry::Link(_) => None,
+            };
+            let item = match item {
+                Some(item) => item,
+                None => return err!(NfsError::FileNotFound),
+            };
+
+            let src_key = trash.enc_key().cloned();
+            let TrashItem {
+                dir, name, file, ..
+            } = item;
+
+            file_helper::copy_data_map(&client2, file, src_key, &dir, true)
+                .and_then(move |file| {
+                    let moved = Entry::File(file);
+                    file_helper::insert_entry(client3, dir, &name, &moved).and_then(move |()| {
+                        file_helper::remove_moved(
+                            &client4,
+                            &trash,
+                            &id,
+                            version + 1,
+                            &entry,
+                            &moved,
+                        )
+                    })
+                })
+                .into_box()
+        })
+        .into_box()
+}
+
+/// Permanently delete all items from the trash, returning their number.
+pub fn empty(client: impl Client, trash: MDataInfo) -> Box<NfsFuture<usize>> {
+    trace!("Emptying the trash {:?}", trash.address());
+    delete_items(client, trash, |_| true)
+}
+
+/// Permanently delete the items which have been in the trash for longer than `retention`,
+/// returning their number.
+pub fn purge(client: impl Client, trash: MDataInfo, retention: Duration) -> Box<NfsFuture<usize>> {
+    trace!("Purging the trash {:?}", trash.address());
+
+    let retention = fry!(chrono::Duration::from_std(retention)
+        .map_err(|error| NfsError::Unexpected(format!("Invalid retention: {}", error))));
+    let cutoff = Utc::now() - retention;
+
+    delete_items(client, trash, move |item| item.deleted < cutoff)
+}
+
+// Delete the items matching the predicate, along with their content.
+fn delete_items<F>(client: impl Client, trash: MDataInfo, predicate: F) -> Box<NfsFuture<usize>>
+where
+    F: Fn(&TrashItem) -> bool + 'static,
+{
+    let client2 = client.clone();
+    let trash2 = trash.clone();
+
+    list(client, trash)
+        .and_then(move |items| {
+            let deletions: Vec<_> = items
+                .into_iter()
+                .filter(&predicate)
+                .map(|item| {
+                    file_helper::delete(
+                        client2.clone(),
+                        trash2.clone(),
+                        item.id,
+                        item.file.published(),
+                        Version::GetNext,
+                    )
+                })
+                .collect();
+            future::join_all(deletions).map(|deleted| deleted.len())
+        })
+        .into_box()
+}
+
+// Unique ID of an item deleted at the given time. The IDs of items sort by the time of deletion.
+fn item_id(deleted: &DateTime<Utc>) -> Result<String, NfsError> {
+    Ok(format!(
+        "{:016}-{}",
+        deleted.timestamp_millis(),
+        utils::generate_readable_string(ID_SUFFIX_LEN)?
+    ))
+}
+
+// Record the origin of the file in its extended attributes.
+fn set_origin(
+    file: &mut File,
+    dir: &MDataInfo,
+    name: &str,
+    deleted: &DateTime<Utc>,
+) -> Result<(), NfsError> {
+    let metadata = file.metadata_mut();
+    let _ = metadata.set_xattr(XATTR_ORIGIN_DIR, AttrValue::Bytes(serialize(dir)?))?;
+    let _ = metadata.set_xattr(XATTR_ORIGIN_NAME, AttrValue::Text(name.to_owned()))?;
+    let _ = metadata.set_xattr(XATTR_DELETED, AttrValue::Int(deleted.timestamp_millis()))?;
+    Ok(())
+}
+
+// Remove the origin from the extended attributes of the trashed file, returning the item.
+fn take_origin(id: String, mut file: File) -> Option<TrashItem> {
+    let metadata = file.metadata_mut();
+    let dir = metadata.remove_xattr(XATTR_ORIGIN_DIR);
+    let name = metadata.remove_xattr(XATTR_ORIGIN_NAME);
+    let deleted = metadata.remove_xattr(XATTR_DELETED);
+
+    match (dir, name, deleted) {
+        (
+            Some(AttrValue::Bytes(dir)),
+            Some(AttrValue::Text(name)),
+            Some(AttrValue::Int(deleted)),
+        ) => Some(TrashItem {
+            id,
+            dir: deserialize(&dir).ok()?,
+            name,
+            deleted: Utc.timestamp_millis_opt(deleted).single()?,
+            file,
+        }),
+        _ => None,
+    }
+}
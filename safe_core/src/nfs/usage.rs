@@ -0,0 +1,202 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Storage usage of directories.
+//!
+//! Directories are flat, with subdirectories formed by names containing `/`, so the usage of a
+//! directory is broken down by the path components of the names of its entries, like `du` does.
+
+use crate::client::{Client, MDataInfo};
+use crate::crypto::shared_secretbox;
+use crate::ffi::nfs::Usage as FfiUsage;
+use crate::ipc::resp::METADATA_KEY;
+use crate::nfs::link::Entry;
+use crate::nfs::{data_map, File, NfsFuture};
+use crate::utils::FutureExt;
+use futures::future::{self, Future};
+use safe_nd::XorName;
+use self_encryption::DataMap;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::AddAssign;
+
+/// Storage used by a set of directory entries.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Usage {
+    /// Number of entries, including symbolic links.
+    pub entries: u64,
+    /// Number of files.
+    pub files: u64,
+    /// Total size of the file contents, in bytes.
+    pub size: u64,
+    /// Number of chunks holding the file contents. Contents small enough to be stored in the data
+    /// map itself have no chunks.
+    pub chunks: u64,
+}
+
+impl Usage {
+    /// Construct the FFI representation of the usage.
+    pub fn into_repr_c(self) -> FfiUsage {
+        FfiUsage {
+            entries: self.entries,
+            files: self.files,
+            size: self.size,
+            chunks: self.chunks,
+        }
+    }
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        self.entries += other.entries;
+        self.files += other.files;
+        self.size += other.size;
+        self.chunks += other.chunks;
+    }
+}
+
+/// Storage used by a directory, or by a subdirectory formed by the path components of names.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DirUsage {
+    /// Usage of all the entries in the directory, including its subdirectories.
+    pub total: Usage,
+    /// Usage of the subdirectories, by the name of their path component.
+    pub subdirs: BTreeMap<String, DirUsage>,
+}
+
+impl DirUsage {
+    // Add the usage of the entry with the given name to this directory and its subdirectories.
+    fn add(&mut self, name: &str, usage: Usage) {
+        self.total += usage;
+
+        let mut components: Vec<_> = name.split('/').filter(|c| !c.is_empty()).collect();
+        // The last component is the entry itself.
+        let _ = components.pop();
+
+        let mut dir = self;
+        for component in components {
+            dir = dir.subdirs.entry(component.to_string()).or_default();
+            dir.total += usage;
+        }
+    }
+}
+
+/// Compute the storage usage of the directory.
+///
+/// The data map of every file is fetched to count the chunks of its content. Files sharing a data
+/// map (hard links) are counted once for each entry. The metadata entry of the directory is not
+/// counted, and symbolic links are counted as entries without following them.
+pub fn dir_usage(client: impl Client, dir: MDataInfo) -> Box<NfsFuture<DirUsage>> {
+    trace!("Computing storage usage of directory {:?}", dir.address());
+
+    let c2 = client.clone();
+    let enc_key = dir.enc_key().cloned();
+
+    client
+        .list_seq_mdata_entries(dir.name(), dir.type_tag())
+        .map_err(From::from)
+        .and_then(move |entries| {
+            let entries: Vec<_> = entries
+                .into_iter()
+                .filter(|(key, _)| key.as_slice() != METADATA_KEY)
+                .filter_map(|(key, value)| {
+                    let name = String::from_utf8(dir.decrypt(&key).ok()?).ok()?;
+                    let entry = Entry::decode(&dir.decrypt(&value.data).ok()?).ok()?;
+                    Some((name, entry))
+                })
+                .collect();
+
+            // Fetch every distinct data map only once.
+            let mut data_maps = HashMap::new();
+            for (_, entry) in &entries {
+                if let Entry::File(ref file) = *entry {
+                    let _ = data_maps
+                        .entry(*file.data_map_name())
+                        .or_insert_with(|| file.clone());
+                }
+            }
+            let counts = data_maps.into_iter().map(move |(name, file)| {
+                chunk_count(&c2, &file, enc_key.clone()).map(move |count| (name, count))
+            });
+
+            future::join_all(counts).map(move |counts| {
+                let counts: HashMap<XorName, u64> = counts.into_iter().collect();
+                let mut usage = DirUsage::default();
+
+                for (name, entry) in entries {
+                    let entry_usage = match entry {
+                        Entry::File(file) => Usage {
+                            entries: 1,
+                            files: 1,
+                            size: file.size(),
+                            chunks: counts.get(file.data_map_name()).cloned().unwrap_or(0),
+                        },
+                        Entry::Link(_) => Usage {
+                            entries: 1,
+                            ..Usage::default()
+                        },
+                    };
+                    usage.add(&name, entry_usage);
+                }
+
+                usage
+            })
+        })
+        .into_box()
+}
+
+// Get the number of chunks holding the content of the file.
+fn chunk_count(
+    client: &impl Client,
+    file: &File,
+    encryption_key: Option<shared_secretbox::Key>,
+) -> Box<NfsFuture<u64>> {
+    data_map::get(client, file.data_address(), encryption_key)
+        .map(|data_map| match data_map {
+            DataMap::Chunks(chunks) => chunks.len() as u64,
+            DataMap::Content(_) | DataMap::None => 0,
+        })
+        .into_box()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that the usage of entries is added to the subdirectories of their names.
+    #[test]
+    fn subdir_totals() {
+        let file = Usage {
+            entries: 1,
+            files: 1,
+            size: 10,
+            chunks: 3,
+        };
+        let link = Usage {
+            entries: 1,
+            ..Usage::default()
+        };
+
+        let mut usage = DirUsage::default();
+        usage.add("top.txt", file);
+        usage.add("a/b/deep.txt", file);
+        usage.add("a/shallow.txt", file);
+        usage.add("/a//link", link);
+
+        assert_eq!(usage.total.entries, 4);
+        assert_eq!(usage.total.files, 3);
+        assert_eq!(usage.total.size, 30);
+        assert_eq!(usage.total.chunks, 9);
+        assert_eq!(usage.subdirs.len(), 1);
+
+        let a = &usage.subdirs["a"];
+        assert_eq!(a.total.entries, 3);
+        assert_eq!(a.total.size, 20);
+        assert_eq!(a.subdirs["b"].total, file);
+        assert!(a.subdirs["b"].subdirs.is_empty());
+    }
+}
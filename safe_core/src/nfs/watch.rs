@@ -0,0 +1,298 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Watching directories for changes.
+//!
+//! The network doesn't notify clients of changes, so a watcher polls the directory and compares
+//! its entries with a snapshot taken by the previous poll. The version of the directory data can't
+//! be used to detect changes cheaply, because it only changes with the permissions and not with the
+//! entries. The polling interval doubles while nothing changes, up to a maximum, and drops back to
+//! the minimum as soon as something does.
+
+use crate::client::tail::MIN_POLL_INTERVAL;
+use crate::client::{Client, MDataInfo};
+use crate::errors::CoreError;
+use crate::ffi::nfs::{
+    DirEvent as FfiDirEvent, DIR_EVENT_ADDED, DIR_EVENT_REMOVED, DIR_EVENT_UPDATED,
+};
+use crate::ipc::resp::METADATA_KEY;
+use crate::nfs::link::Entry;
+use crate::nfs::{File, NfsError, NfsFuture, NfsStream};
+use crate::utils::FutureExt;
+use futures::future::{self, Loop};
+use futures::stream::{self, Stream};
+use futures::Future;
+use safe_nd::{MDataSeqEntries, MDataSeqValue};
+use std::cmp;
+use std::ffi::{CString, NulError};
+use std::ptr;
+use std::time::{Duration, Instant};
+use tokio::timer::Delay;
+
+/// Change of a file in a watched directory.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DirEvent {
+    /// A file was added.
+    Added {
+        /// Name of the file.
+        name: String,
+        /// Version of the directory entry holding the file.
+        version: u64,
+        /// The file itself.
+        file: File,
+    },
+    /// A file was updated.
+    Updated {
+        /// Name of the file.
+        name: String,
+        /// Version of the directory entry holding the file.
+        version: u64,
+        /// The file itself.
+        file: File,
+    },
+    /// A file was removed.
+    Removed {
+        /// Name of the file.
+        name: String,
+    },
+}
+
+impl DirEvent {
+    /// Name of the changed file.
+    pub fn name(&self) -> &str {
+        match *self {
+            DirEvent::Added { ref name, .. }
+            | DirEvent::Updated { ref name, .. }
+            | DirEvent::Removed { ref name } => name,
+        }
+    }
+
+    /// Construct the FFI representation of the event.
+    ///
+    /// The `ffi::nfs::DirEvent` struct has a `Drop` impl which frees the allocated name and file
+    /// once it goes out of scope.
+    pub fn into_repr_c(self) -> Result<FfiDirEvent, NulError> {
+        let (kind, name, version, file) = match self {
+            DirEvent::Added {
+                name,
+                version,
+                file,
+            } => (DIR_EVENT_ADDED, name, version, Some(file)),
+            DirEvent::Updated {
+                name,
+                version,
+                file,
+            } => (DIR_EVENT_UPDATED, name, version, Some(file)),
+            DirEvent::Removed { name } => (DIR_EVENT_REMOVED, name, 0, None),
+        };
+
+        Ok(FfiDirEvent {
+            kind,
+            name: CString::new(name)?.into_raw(),
+            version,
+            file: file.map_or(ptr::null(), |file| {
+                Box::into_raw(Box::new(file.into_repr_c()))
+            }),
+        })
+    }
+}
+
+/// Options controlling how often a directory is polled.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct WatchOptions {
+    /// Interval between polls right after a change.
+    pub min_interval: Duration,
+    /// Longest interval between polls, reached while nothing changes.
+    pub max_interval: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            min_interval: Duration::from_secs(1),
+            max_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+impl WatchOptions {
+    /// Check the options, raising intervals shorter than `client::tail::MIN_POLL_INTERVAL` to it.
+    /// Fails if the minimum interval is longer than the maximum one.
+    pub fn checked(self) -> Result<Self, NfsError> {
+        if self.min_interval > self.max_interval {
+            return Err(NfsError::Unexpected(
+                "Minimum polling interval is longer than the maximum one".to_string(),
+            ));
+        }
+
+        Ok(WatchOptions {
+            min_interval: cmp::max(self.min_interval, MIN_POLL_INTERVAL),
+            max_interval: cmp::max(self.max_interval, MIN_POLL_INTERVAL),
+        })
+    }
+}
+
+/// Watch the directory, returning a stream of changes of its files.
+///
+/// The entries of the directory at the time of the first poll, which happens immediately, are the
+/// baseline for the changes. Each poll yields the changes since the previous one, sorted by file
+/// name. Symbolic links and other entries which are not files are ignored.
+///
+/// The stream never ends on its own, but stops with an error if polling fails for a reason other
+/// than a timeout, or right away if the options are invalid (see `WatchOptions::checked`).
+/// Dropping the stream, e.g. together with the event loop running it, stops watching.
+pub fn watch(
+    client: impl Client,
+    dir: MDataInfo,
+    options: WatchOptions,
+) -> Box<NfsStream<DirEvent>> {
+    trace!("Watching directory {:?}", dir.address());
+
+    let options = match options.checked() {
+        Ok(options) => options,
+        Err(error) => return Box::new(stream::once(Err(error))),
+    };
+    let state = Watcher {
+        client,
+        dir,
+        options,
+        snapshot: None,
+        interval: options.min_interval,
+    };
+
+    Box::new(
+        stream::unfold(state, |watcher| Some(watcher.next_changes()))
+            .map(stream::iter_ok)
+            .flatten(),
+    )
+}
+
+struct Watcher<C> {
+    client: C,
+    dir: MDataInfo,
+    options: WatchOptions,
+    // Entries as of the previous poll.
+    snapshot: Option<MDataSeqEntries>,
+    interval: Duration,
+}
+
+impl<C: Client> Watcher<C> {
+    // Poll the directory until something changes.
+    fn next_changes(self) -> Box<NfsFuture<(Vec<DirEvent>, Self)>> {
+        future::loop_fn(self, |watcher| {
+            // The first poll only takes the snapshot, without waiting.
+            let delay = if watcher.snapshot.is_some() {
+                Delay::new(Instant::now() + watcher.interval)
+                    .map_err(|error| NfsError::Unexpected(format!("Timer error: {}", error)))
+                    .into_box()
+            } else {
+                ok!(())
+            };
+
+            delay
+                .and_then(move |()| {
+                    watcher
+                        .client
+                        .list_seq_mdata_entries(watcher.dir.name(), watcher.dir.type_tag())
+                        .then(move |result| Ok((watcher, result)))
+                })
+                .and_then(|(watcher, result)| watcher.update(result))
+        })
+        .into_box()
+    }
+
+    // Compare the entries with the snapshot, backing off if nothing changed.
+    fn update(
+        mut self,
+        result: Result<MDataSeqEntries, CoreError>,
+    ) -> Result<Loop<(Vec<DirEvent>, Self), Self>, NfsError> {
+        let entries = match result {
+            Ok(entries) => entries,
+            Err(CoreError::RequestTimeout) => {
+                self.back_off();
+                return Ok(Loop::Continue(self));
+            }
+            Err(error) => return Err(NfsError::from(error)),
+        };
+
+        let snapshot = match self.snapshot.take() {
+            Some(snapshot) => snapshot,
+            None => {
+                // This was the first poll, taking the baseline snapshot.
+                self.snapshot = Some(entries);
+                return Ok(Loop::Continue(self));
+            }
+        };
+        let events = diff(&self.dir, &snapshot, &entries);
+        self.snapshot = Some(entries);
+
+        if events.is_empty() {
+            self.back_off();
+            Ok(Loop::Continue(self))
+        } else {
+            self.interval = self.options.min_interval;
+            Ok(Loop::Break((events, self)))
+        }
+    }
+
+    fn back_off(&mut self) {
+        self.interval = cmp::min(self.interval * 2, self.options.max_interval);
+    }
+}
+
+// Find the changes of files between the two sets of entries.
+fn diff(dir: &MDataInfo, old: &MDataSeqEntries, new: &MDataSeqEntries) -> Vec<DirEvent> {
+    let mut events: Vec<_> = new
+        .iter()
+        .filter(|(key, value)| old.get(*key) != Some(*value))
+        .filter_map(|(key, value)| {
+            let name = decode_name(dir, key)?;
+            let old_file = old.get(key).and_then(|value| decode_file(dir, value));
+
+            match (old_file, decode_file(dir, value)) {
+                (None, Some(file)) => Some(DirEvent::Added {
+                    name,
+                    version: value.version,
+                    file,
+                }),
+                (Some(_), Some(file)) => Some(DirEvent::Updated {
+                    name,
+                    version: value.version,
+                    file,
+                }),
+                (Some(_), None) => Some(DirEvent::Removed { name }),
+                (None, None) => None,
+            }
+        })
+        .collect();
+
+    events.extend(
+        old.iter()
+            .filter(|(key, _)| !new.contains_key(*key))
+            .filter(|(_, value)| decode_file(dir, value).is_some())
+            .filter_map(|(key, _)| decode_name(dir, key))
+            .map(|name| DirEvent::Removed { name }),
+    );
+
+    events.sort_by(|a, b| a.name().cmp(b.name()));
+    events
+}
+
+fn decode_name(dir: &MDataInfo, key: &[u8]) -> Option<String> {
+    if key == METADATA_KEY {
+        return None;
+    }
+    String::from_utf8(dir.decrypt(key).ok()?).ok()
+}
+
+fn decode_file(dir: &MDataInfo, value: &MDataSeqValue) -> Option<File> {
+    match Entry::decode(&dir.decrypt(&value.data).ok()?).ok()? {
+        Entry::File(file) => Some(file),
+        Entry::Link(_) => None,
+    }
+}
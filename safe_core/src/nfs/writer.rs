@@ -9,13 +9,16 @@
 use crate::client::Client;
 use crate::crypto::shared_secretbox;
 use crate::errors::CoreError;
-use crate::nfs::{data_map, File, NfsError, NfsFuture};
+use crate::nfs::{data_map, File, NfsError, NfsFuture, CONTENT_HASH_LEN};
 use crate::self_encryption_storage::SelfEncryptionStorage;
 use crate::utils::FutureExt;
 use chrono::Utc;
+use futures::future;
 use futures::Future;
 use safe_nd::Error as SndError;
-use self_encryption::{DataMap, SequentialEncryptor};
+use self_encryption::{DataMap, SelfEncryptor, SequentialEncryptor};
+use std::cell::RefCell;
+use tiny_keccak::Keccak;
 
 /// Mode of the writer.
 #[derive(Clone, Copy, Debug)]
@@ -24,15 +27,30 @@ pub enum Mode {
     Overwrite,
     /// Will append content to the existing data.
     Append,
+    /// Will modify the existing data at arbitrary positions. Only the chunks affected by the
+    /// changes are encrypted and stored again.
+    Modify,
 }
 
 /// Writer is used to write contents to a File and especially in chunks if the
 /// file happens to be too large.
+///
+/// The hash of the content is stored in the metadata of the file on close. It is only computed if
+/// the content is written from scratch: appending to or modifying existing content clears it, as
+/// computing it would require reading the whole content back.
 pub struct Writer<C: Client> {
     client: C,
     file: File,
-    self_encryptor: SequentialEncryptor<SelfEncryptionStorage<C>>,
+    self_encryptor: Encryptor<C>,
     encryption_key: Option<shared_secretbox::Key>,
+    // Hash of the data written so far, if it's all the content of the file.
+    hasher: RefCell<Option<Keccak>>,
+}
+
+// Sequential encryptor for the `Overwrite` and `Append` modes, random-access one for `Modify`.
+enum Encryptor<C: Client> {
+    Sequential(SequentialEncryptor<SelfEncryptionStorage<C>>),
+    RandomAccess(SelfEncryptor<SelfEncryptionStorage<C>>),
 }
 
 impl<C: Client> Writer<C> {
@@ -45,9 +63,11 @@ impl<C: Client> Writer<C> {
         encryption_key: Option<shared_secretbox::Key>,
     ) -> Box<NfsFuture<Writer<C>>> {
         let fut = match mode {
-            Mode::Append => data_map::get(client, file.data_address(), encryption_key.clone())
-                .map(Some)
-                .into_box(),
+            Mode::Append | Mode::Modify => {
+                data_map::get(client, file.data_address(), encryption_key.clone())
+                    .map(Some)
+                    .into_box()
+            }
             Mode::Overwrite => ok!(None),
         };
         let client = client.clone();
@@ -59,53 +79,134 @@ impl<C: Client> Writer<C> {
                 _ => err!(err),
             }
         })
-        .and_then(move |data_map| SequentialEncryptor::new(storage, data_map).map_err(From::from))
-        .map(move |self_encryptor| Writer {
+        .and_then(move |data_map| match mode {
+            Mode::Modify => future::result(
+                SelfEncryptor::new(storage, data_map.unwrap_or(DataMap::None))
+                    .map(|encryptor| (Encryptor::RandomAccess(encryptor), None))
+                    .map_err(From::from),
+            )
+            .into_box(),
+            Mode::Overwrite | Mode::Append => {
+                let hasher = if data_map.is_none() {
+                    Some(Keccak::new_sha3_256())
+                } else {
+                    None
+                };
+                SequentialEncryptor::new(storage, data_map)
+                    .map(move |encryptor| (Encryptor::Sequential(encryptor), hasher))
+                    .map_err(From::from)
+                    .into_box()
+            }
+        })
+        .map(move |(self_encryptor, hasher)| Writer {
             client,
             file,
             self_encryptor,
             encryption_key,
+            hasher: RefCell::new(hasher),
         })
         .map_err(From::from)
         .into_box()
     }
 
     /// Data of a file/blob can be written in smaller chunks.
+    ///
+    /// The data is written at the end of the file.
     pub fn write(&self, data: &[u8]) -> Box<NfsFuture<()>> {
         trace!(
             "Writer writing file data of size {} into self-encryptor.",
             data.len()
         );
-        self.self_encryptor
-            .write(data)
-            .map_err(From::from)
-            .into_box()
+        if let Some(ref mut hasher) = *self.hasher.borrow_mut() {
+            hasher.update(data);
+        }
+        match self.self_encryptor {
+            Encryptor::Sequential(ref encryptor) => encryptor.write(data),
+            Encryptor::RandomAccess(ref encryptor) => encryptor.write(data, encryptor.len()),
+        }
+        .map_err(From::from)
+        .into_box()
+    }
+
+    /// Write data starting at the given position, overwriting any data already there and
+    /// extending the file if needed.
+    ///
+    /// Unless the writer is in `Mode::Modify`, the position must be at the end of the file.
+    pub fn write_at(&self, data: &[u8], position: u64) -> Box<NfsFuture<()>> {
+        trace!(
+            "Writer writing file data of size {} at position {} into self-encryptor.",
+            data.len(),
+            position
+        );
+        match self.self_encryptor {
+            Encryptor::RandomAccess(ref encryptor) => encryptor
+                .write(data, position)
+                .map_err(From::from)
+                .into_box(),
+            Encryptor::Sequential(_) if position == self.len() => self.write(data),
+            Encryptor::Sequential(_) => err!(NfsError::InvalidRange),
+        }
+    }
+
+    /// Truncate or extend the file to the given size. If the file is extended, it is filled
+    /// with zeros.
+    ///
+    /// Only supported in `Mode::Modify`.
+    pub fn truncate(&self, size: u64) -> Box<NfsFuture<()>> {
+        trace!("Writer truncating file data to size {}.", size);
+        match self.self_encryptor {
+            Encryptor::RandomAccess(ref encryptor) => {
+                encryptor.truncate(size).map_err(From::from).into_box()
+            }
+            Encryptor::Sequential(_) => err!(NfsError::Unexpected(
+                "Truncating a file requires Mode::Modify".to_string()
+            )),
+        }
+    }
+
+    /// Get the current size of the file being written.
+    pub fn len(&self) -> u64 {
+        match self.self_encryptor {
+            Encryptor::Sequential(ref encryptor) => encryptor.len(),
+            Encryptor::RandomAccess(ref encryptor) => encryptor.len(),
+        }
+    }
+
+    /// Check whether the file being written is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 
     /// close() should be invoked only after all the data is completely written. The file/blob is
     /// saved only when close() is invoked. Returns the final `File` with the data_map stored on the
-    /// network.
+    /// network and the hash of the content, if known, set in its metadata.
     pub fn close(self) -> Box<NfsFuture<File>> {
         trace!("Writer induced self-encryptor close.");
 
+        let size = self.len();
         let mut file = self.file;
-        let size = self.self_encryptor.len();
         let client = self.client;
         let encryption_key = self.encryption_key;
         let published = file.published();
+        let hash = self.hasher.into_inner().map(|hasher| {
+            let mut hash = [0; CONTENT_HASH_LEN];
+            hasher.finalize(&mut hash);
+            hash
+        });
 
-        self.self_encryptor
-            .close()
-            .map_err(From::from)
-            .and_then(move |(data_map, _)| {
-                data_map::put(&client, &data_map, published, encryption_key)
-            })
-            .map(move |data_map_name| {
-                file.set_data_map_name(data_map_name);
-                file.set_modified_time(Utc::now());
-                file.set_size(size);
-                file
-            })
-            .into_box()
+        match self.self_encryptor {
+            Encryptor::Sequential(encryptor) => encryptor.close(),
+            Encryptor::RandomAccess(encryptor) => encryptor.close(),
+        }
+        .map_err(From::from)
+        .and_then(move |(data_map, _)| data_map::put(&client, &data_map, published, encryption_key))
+        .map(move |data_map_name| {
+            file.set_data_map_name(data_map_name);
+            file.set_modified_time(Utc::now());
+            file.set_size(size);
+            file.metadata_mut().set_content_hash(hash);
+            file
+        })
+        .into_box()
     }
 }
@@ -0,0 +1,985 @@
+// Copyright 2019 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Map sharded across several sequenced mutable data.
+//!
+//! A single mutable data can only hold a limited number of entries of a limited total size. A
+//! sharded map spreads its entries across several mutable data, called shards, by the SHA-3 hash
+//! of their (unencrypted) keys. Each shard holds the entries whose hashes start with the bits of
+//! its prefix, and the prefixes of all shards together cover every possible hash. The shards are
+//! listed in an index, stored in a single entry of the root mutable data.
+//!
+//! Initially there is a single shard with the empty prefix. When a shard fills up, either because
+//! it holds `max_shard_entries` entries or because the network rejects a mutation with
+//! `TooManyEntries` or `ExceededSize`, it is split in two by the next bit of the hashes: the
+//! entries with the bit set are moved into a new shard and the index is updated.
+//!
+//! All shards share the type tag and encryption info of the root, so entries are moved between
+//! shards without re-encrypting them. New shards are given the permissions of the root.
+//!
+//! Besides the entries of the map, each shard holds a metadata entry, which keeps count of the
+//! entries of the shard. Every mutation of a shard also updates its metadata entry, so mutations
+//! of the same shard are serialised by the version of that entry. A split starts by sealing the
+//! shard through its metadata entry, which makes any concurrent mutation of the shard fail, and
+//! ends by removing the moved entries and unsealing the shard. A client finding a sealed shard
+//! completes the split itself before retrying its mutation, so a split interrupted half-way
+//! doesn't leave the shard sealed forever.
+//!
+//! The authenticator keeps the registered apps in a sharded map, moving the apps of existing
+//! accounts out of the old entry of its config root directory on first use. The NFS directories
+//! still keep all their entries in a single mutable data.
+
+use crate::client::recovery::{self, MAX_ATTEMPTS};
+use crate::client::{Client, MDataInfo};
+use crate::errors::CoreError;
+use crate::event_loop::CoreFuture;
+use crate::utils::FutureExt;
+use bincode::{deserialize, serialize};
+use futures::future::{self, Loop};
+use futures::Future;
+use safe_nd::{
+    Error as SndError, MDataAction, MDataAddress, MDataKind, MDataPermissionSet, MDataSeqEntries,
+    MDataSeqEntryActions, MDataSeqValue, SeqMutableData, XorName,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tiny_keccak::sha3_256;
+
+/// Default maximum number of entries of a shard, after which it is split.
+pub const DEFAULT_MAX_SHARD_ENTRIES: usize = 1000;
+
+// Key of the root entry holding the index.
+const INDEX_KEY: &[u8] = b"index";
+// Key of the shard entry holding the metadata of the shard. The keys of the map are stored with
+// `ENTRY_TAG` prepended, so they never collide with it.
+const META_KEY: &[u8] = &[0];
+const ENTRY_TAG: u8 = 1;
+// Number of bits of the hashes of keys, which is the maximum length of a prefix.
+const HASH_BITS: usize = 256;
+
+// Leading bits of the hashes of the keys held by a shard.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+struct Prefix {
+    bit_count: usize,
+    bits: Vec<u8>,
+}
+
+impl Prefix {
+    fn matches(&self, hash: &[u8]) -> bool {
+        (0..self.bit_count).all(|i| bit(&self.bits, i) == bit(hash, i))
+    }
+
+    // The prefix extended by the given bit.
+    fn pushed(&self, value: bool) -> Self {
+        let i = self.bit_count;
+        let mut bits = self.bits.clone();
+        if bits.len() <= i / 8 {
+            bits.push(0);
+        }
+        if value {
+            bits[i / 8] |= 0x80 >> (i % 8);
+        }
+
+        Self {
+            bit_count: i + 1,
+            bits,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+struct Shard {
+    prefix: Prefix,
+    name: XorName,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+struct Index {
+    shards: Vec<Shard>,
+}
+
+impl Index {
+    // Position of the shard holding the key.
+    fn find(&self, key: &[u8]) -> Result<usize, CoreError> {
+        let hash = sha3_256(key);
+        self.shards
+            .iter()
+            .position(|shard| shard.prefix.matches(&hash))
+            .ok_or_else(|| CoreError::Unexpected("Sharded map index is incomplete".to_string()))
+    }
+}
+
+// Metadata of a shard, stored in the shard itself.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+struct Meta {
+    // Prefix of the shard as of the last completed split.
+    prefix: Prefix,
+    // Whether the shard is being split, in which case it mustn't be mutated.
+    sealed: bool,
+    // Number of entries of the map held by the shard.
+    entries: u64,
+}
+
+// Shard holding a key, as found in a version of the index, along with the metadata of the shard.
+struct Located {
+    index_version: u64,
+    index: Index,
+    position: usize,
+    shard: MDataInfo,
+    key: Vec<u8>,
+    meta: Meta,
+    meta_version: u64,
+}
+
+impl Located {
+    fn prefix(&self) -> &Prefix {
+        &self.index.shards[self.position].prefix
+    }
+
+    // Whether the shard is being split, or the index is out of date.
+    fn is_splitting(&self) -> bool {
+        self.meta.sealed || self.meta.prefix != *self.prefix()
+    }
+
+    // Add the update of the metadata of the shard to the actions, so they fail if the shard is
+    // mutated or sealed concurrently.
+    fn fence(
+        &self,
+        actions: MDataSeqEntryActions,
+        meta: &Meta,
+    ) -> Result<MDataSeqEntryActions, CoreError> {
+        let key = self.shard.enc_entry_key(META_KEY)?;
+        let value = self.shard.enc_entry_value(&serialize(meta)?)?;
+        Ok(actions.update(key, value, self.meta_version + 1))
+    }
+}
+
+// Change of an entry of the map.
+#[derive(Clone)]
+enum Change {
+    Insert(Vec<u8>),
+    Remove,
+}
+
+/// Map sharded across several sequenced mutable data.
+pub struct ShardedMap<C: Client> {
+    client: C,
+    root: MDataInfo,
+    max_shard_entries: usize,
+}
+
+impl<C: Client> ShardedMap<C> {
+    /// Create a map over the existing root mutable data.
+    pub fn new(client: C, root: MDataInfo) -> Self {
+        Self {
+            client,
+            root,
+            max_shard_entries: DEFAULT_MAX_SHARD_ENTRIES,
+        }
+    }
+
+    /// Put an empty map on the network, consisting of the root mutable data and a single empty
+    /// shard, both owned by the owner of the client. The key of the client is allowed to read and
+    /// mutate their entries, so apps can use maps they create. Shards created when splitting get
+    /// the permissions of the root.
+    pub fn create(client: C, root: MDataInfo) -> Box<CoreFuture<Self>> {
+        if root.kind() != MDataKind::Seq {
+            return err!(CoreError::from(
+                "Sharded map requires sequenced mutable data"
+            ));
+        }
+
+        let shard = Shard {
+            prefix: Prefix::default(),
+            name: rand::random(),
+        };
+        let index = Index {
+            shards: vec![shard.clone()],
+        };
+        let key = fry!(root.enc_entry_key(INDEX_KEY));
+        let value = fry!(encode_index(&root, &index));
+        let entries = fry!(new_shard_entries(
+            &root,
+            MDataSeqEntries::new(),
+            &Meta {
+                prefix: shard.prefix,
+                sealed: false,
+                entries: 0,
+            }
+        ));
+
+        let permissions = btree_map![
+            client.public_key() => MDataPermissionSet::new()
+                .allow(MDataAction::Read)
+                .allow(MDataAction::Insert)
+                .allow(MDataAction::Update)
+                .allow(MDataAction::Delete)
+        ];
+
+        let shard = SeqMutableData::new_with_data(
+            shard.name,
+            root.type_tag(),
+            entries,
+            permissions.clone(),
+            client.owner_key(),
+        );
+        let data = SeqMutableData::new_with_data(
+            root.name(),
+            root.type_tag(),
+            btree_map![key => MDataSeqValue { data: value, version: 0 }],
+            permissions,
+            client.owner_key(),
+        );
+
+        client
+            .put_seq_mutable_data(shard)
+            .and_then({
+                let client = client.clone();
+                move |()| client.put_seq_mutable_data(data)
+            })
+            .map(move |()| Self::new(client, root))
+            .into_box()
+    }
+
+    /// Set the maximum number of entries of a shard, after which it is split. Defaults to
+    /// `DEFAULT_MAX_SHARD_ENTRIES`.
+    pub fn set_max_shard_entries(&mut self, entries: usize) {
+        self.max_shard_entries = entries;
+    }
+
+    /// Returns the `MDataInfo` of the root mutable data.
+    pub fn root(&self) -> &MDataInfo {
+        &self.root
+    }
+
+    /// Returns the `MDataInfo`s of the shards.
+    pub fn shards(&self) -> Box<CoreFuture<Vec<MDataInfo>>> {
+        let root = self.root.clone();
+
+        fetch_index(&self.client, &self.root)
+            .map(move |(_, index)| {
+                index
+                    .shards
+                    .iter()
+                    .map(|shard| shard_info(&root, shard.name))
+                    .collect()
+            })
+            .into_box()
+    }
+
+    /// Get the value of the key, or `None` if the map doesn't contain the key.
+    pub fn get(&self, key: &[u8]) -> Box<CoreFuture<Option<Vec<u8>>>> {
+        let client = self.client.clone();
+        let root = self.root.clone();
+        let key = key.to_vec();
+
+        fetch_index(&self.client, &self.root)
+            .and_then(move |(_, index)| {
+                let position = index.find(&key)?;
+                let shard = shard_info(&root, index.shards[position].name);
+                let key = shard.enc_entry_key(&entry_key(&key))?;
+                Ok((shard, key))
+            })
+            .and_then(move |(shard, key)| {
+                fetch_value(&client, &shard, key).and_then(move |value| match value {
+                    Some((data, _)) => Ok(Some(shard.decrypt(&data)?)),
+                    None => Ok(None),
+                })
+            })
+            .into_box()
+    }
+
+    /// Insert the value, replacing any existing value of the key. The shard holding the key is
+    /// split first if it is full.
+    pub fn insert(&self, key: &[u8], value: &[u8]) -> Box<CoreFuture<()>> {
+        self.change(key, Change::Insert(value.to_vec()))
+            .map(|_| ())
+            .into_box()
+    }
+
+    /// Remove the key from the map, returning whether it was contained in the map. Shards are
+    /// never merged, so removing entries doesn't reduce the number of shards.
+    pub fn remove(&self, key: &[u8]) -> Box<CoreFuture<bool>> {
+        self.change(key, Change::Remove)
+    }
+
+    /// Returns all entries of the map, decrypted.
+    pub fn entries(&self) -> Box<CoreFuture<BTreeMap<Vec<u8>, Vec<u8>>>> {
+        let client = self.client.clone();
+        let root = self.root.clone();
+
+        fetch_index(&self.client, &self.root)
+            .and_then(move |(_, index)| {
+                let lists = index.shards.into_iter().map(move |Shard { prefix, name }| {
+                    let shard = shard_info(&root, name);
+                    client
+                        .list_seq_mdata_entries(shard.name(), shard.type_tag())
+                        .and_then(move |entries| {
+                            // A shard being split may still hold entries moved out of it.
+                            Ok(decrypt_entries(&shard, entries)?
+                                .into_iter()
+                                .filter(|(key, _)| prefix.matches(&sha3_256(key)))
+                                .map(|(key, value)| Ok((key, shard.decrypt(&value.data)?)))
+                                .collect::<Result<Vec<_>, CoreError>>()?)
+                        })
+                });
+                future::join_all(lists)
+            })
+            .map(|lists| lists.into_iter().flatten().collect())
+            .into_box()
+    }
+
+    // Apply the change to the entry of the key, returning whether the map contained the key.
+    fn change(&self, key: &[u8], change: Change) -> Box<CoreFuture<bool>> {
+        let client = self.client.clone();
+        let root = self.root.clone();
+        let max_shard_entries = self.max_shard_entries;
+        let key = key.to_vec();
+
+        future::loop_fn(0, move |attempts| {
+            if attempts > MAX_ATTEMPTS {
+                return err!(CoreError::Unexpected(
+                    "Failed to apply the change to the sharded map".to_string()
+                ));
+            }
+
+            let client2 = client.clone();
+            let root2 = root.clone();
+            let change = change.clone();
+
+            locate(&client, &root, &key)
+                .and_then(move |located| {
+                    if located.is_splitting() {
+                        return resume_split(&client2, &root2, located)
+                            .map(move |()| Loop::Continue(attempts + 1))
+                            .into_box();
+                    }
+
+                    let client3 = client2.clone();
+                    fetch_value(&client2, &located.shard, located.key.clone())
+                        .and_then(move |current| {
+                            let existed = current.is_some();
+                            let entries = located.meta.entries;
+                            let (actions, entries) = match (change, current) {
+                                (Change::Insert(value), Some((_, version))) => {
+                                    let data = fry!(located.shard.enc_entry_value(&value));
+                                    let actions = MDataSeqEntryActions::new().update(
+                                        located.key.clone(),
+                                        data,
+                                        version + 1,
+                                    );
+                                    (actions, entries)
+                                }
+                                (Change::Insert(value), None) => {
+                                    return insert_entry(
+                                        &client3,
+                                        &root2,
+                                        located,
+                                        value,
+                                        max_shard_entries,
+                                    )
+                                    .map(move |inserted| {
+                                        if inserted {
+                                            Loop::Break(false)
+                                        } else {
+                                            Loop::Continue(attempts + 1)
+                                        }
+                                    })
+                                    .into_box();
+                                }
+                                (Change::Remove, Some((_, version))) => {
+                                    let actions = MDataSeqEntryActions::new()
+                                        .del(located.key.clone(), version + 1);
+                                    (actions, entries.saturating_sub(1))
+                                }
+                                (Change::Remove, None) => return ok!(Loop::Break(false)),
+                            };
+                            let meta = Meta {
+                                entries,
+                                ..located.meta.clone()
+                            };
+
+                            mutate_shard(&client3, &root2, located, actions, &meta)
+                                .map(move |mutated| {
+                                    if mutated {
+                                        Loop::Break(existed)
+                                    } else {
+                                        Loop::Continue(attempts + 1)
+                                    }
+                                })
+                                .into_box()
+                        })
+                        .into_box()
+                })
+                .or_else(move |error| {
+                    if is_conflict(&error) && attempts < MAX_ATTEMPTS {
+                        Ok(Loop::Continue(attempts + 1))
+                    } else {
+                        Err(error)
+                    }
+                })
+                .into_box()
+        })
+        .into_box()
+    }
+}
+
+// Insert the new entry into the located shard, splitting the shard instead if it is full.
+// Returns whether the entry was inserted.
+fn insert_entry(
+    client: &impl Client,
+    root: &MDataInfo,
+    located: Located,
+    value: Vec<u8>,
+    max_shard_entries: usize,
+) -> Box<CoreFuture<bool>> {
+    if located.meta.entries >= max_shard_entries as u64 {
+        return seal(client, root, located).map(|()| false).into_box();
+    }
+
+    let data = fry!(located.shard.enc_entry_value(&value));
+    let actions = MDataSeqEntryActions::new().ins(located.key.clone(), data, 0);
+    let meta = Meta {
+        entries: located.meta.entries + 1,
+        ..located.meta.clone()
+    };
+    mutate_shard(client, root, located, actions, &meta)
+}
+
+// Apply the actions to the located shard, updating its metadata to `meta` in the same mutation. If
+// the shard is full, it is split instead. Returns whether the actions were applied.
+fn mutate_shard(
+    client: &impl Client,
+    root: &MDataInfo,
+    located: Located,
+    actions: MDataSeqEntryActions,
+    meta: &Meta,
+) -> Box<CoreFuture<bool>> {
+    let actions = fry!(located.fence(actions, meta));
+    let shard = located.shard.clone();
+    let client = client.clone();
+    let root = root.clone();
+
+    client
+        .mutate_seq_mdata_entries(shard.name(), shard.type_tag(), actions)
+        .map(|()| true)
+        .or_else(move |error| {
+            if is_full(&error) {
+                seal(&client, &root, located).map(|()| false).into_box()
+            } else {
+                err!(error)
+            }
+        })
+        .into_box()
+}
+
+// Find the shard holding the key in the current version of the index, and fetch its metadata.
+fn locate(client: &impl Client, root: &MDataInfo, key: &[u8]) -> Box<CoreFuture<Located>> {
+    let client = client.clone();
+    let root = root.clone();
+    let key = key.to_vec();
+
+    fetch_index(&client, &root)
+        .and_then(move |(index_version, index)| {
+            let position = fry!(index.find(&key));
+            let shard = shard_info(&root, index.shards[position].name);
+            let key = fry!(shard.enc_entry_key(&entry_key(&key)));
+
+            fetch_meta(&client, &shard)
+                .map(move |(meta, meta_version)| Located {
+                    index_version,
+                    index,
+                    position,
+                    shard,
+                    key,
+                    meta,
+                    meta_version,
+                })
+                .into_box()
+        })
+        .into_box()
+}
+
+// Seal the located shard and split it. If the shard was mutated concurrently, nothing is changed
+// and the operation should be retried.
+fn seal(client: &impl Client, root: &MDataInfo, mut located: Located) -> Box<CoreFuture<()>> {
+    if located.prefix().bit_count >= HASH_BITS {
+        return err!(CoreError::Unexpected(
+            "Sharded map shard can't be split further".to_string()
+        ));
+    }
+    trace!("Sealing shard {:?} of sharded map", located.shard.address());
+
+    let meta = Meta {
+        sealed: true,
+        ..located.meta.clone()
+    };
+    let actions = fry!(located.fence(MDataSeqEntryActions::new(), &meta));
+    let shard = located.shard.clone();
+    let client = client.clone();
+    let root = root.clone();
+
+    client
+        .mutate_seq_mdata_entries(shard.name(), shard.type_tag(), actions)
+        .then(move |result| match result {
+            Ok(()) => {
+                located.meta = meta;
+                located.meta_version += 1;
+                split(&client, &root, located)
+            }
+            Err(ref error) if recovery::is_version_conflict(error) => ok!(()),
+            Err(error) => err!(error),
+        })
+        .into_box()
+}
+
+// Complete the split of the located shard which another client started, or which was interrupted.
+fn resume_split(client: &impl Client, root: &MDataInfo, located: Located) -> Box<CoreFuture<()>> {
+    let prefix = located.prefix().clone();
+
+    if located.meta.prefix == prefix {
+        // The shard is sealed, but the index wasn't updated yet.
+        split(client, root, located)
+    } else if located.meta.prefix.bit_count < prefix.bit_count {
+        // The index was updated, but the moved entries weren't removed from the shard yet.
+        finish_split(client, &located.shard, prefix)
+    } else {
+        // The index was updated after it was fetched.
+        ok!(())
+    }
+}
+
+// Split the sealed shard in two by the next bit of the hashes of its keys: copy the entries with
+// the bit set into a new shard, add the new shard to the index and remove the moved entries from
+// the sealed shard. If the index was changed concurrently, the new shard is deleted and the
+// operation should be retried with the new index.
+fn split(client: &impl Client, root: &MDataInfo, located: Located) -> Box<CoreFuture<()>> {
+    let Located {
+        index_version,
+        mut index,
+        position,
+        shard,
+        ..
+    } = located;
+
+    let prefix = index.shards[position].prefix.clone();
+    if prefix.bit_count >= HASH_BITS {
+        return err!(CoreError::Unexpected(
+            "Sharded map shard can't be split further".to_string()
+        ));
+    }
+    trace!("Splitting shard {:?} of sharded map", shard.address());
+
+    let client2 = client.clone();
+    let client3 = client.clone();
+    let client4 = client.clone();
+    let root = root.clone();
+    let prefix0 = prefix.pushed(false);
+    let prefix1 = prefix.pushed(true);
+    let shard2 = shard.clone();
+
+    let entries = client.list_seq_mdata_entries(shard.name(), shard.type_tag());
+    let permissions = client.list_mdata_permissions(*root.address());
+
+    entries
+        .join(permissions)
+        .and_then(move |(entries, permissions)| {
+            let moved = fry!(partition(&shard, &prefix1, entries));
+            let meta = Meta {
+                prefix: prefix1.clone(),
+                sealed: false,
+                entries: moved.len() as u64,
+            };
+            let entries = fry!(new_shard_entries(&shard, moved, &meta));
+            let name = rand::random();
+            let data = SeqMutableData::new_with_data(
+                name,
+                shard.type_tag(),
+                entries,
+                permissions,
+                client2.owner_key(),
+            );
+
+            index.shards[position].prefix = prefix0.clone();
+            index.shards.push(Shard {
+                prefix: prefix1,
+                name,
+            });
+
+            client2
+                .put_seq_mutable_data(data)
+                .map(move |()| (name, index, prefix0))
+                .into_box()
+        })
+        .and_then(move |(name, index, prefix0)| {
+            let key = fry!(root.enc_entry_key(INDEX_KEY));
+            let value = fry!(encode_index(&root, &index));
+            let actions = MDataSeqEntryActions::new().update(key, value, index_version + 1);
+
+            client3
+                .mutate_seq_mdata_entries(root.name(), root.type_tag(), actions)
+                .then(move |result| match result {
+                    Ok(()) => ok!(Some(prefix0)),
+                    Err(ref error) if recovery::is_version_conflict(error) => {
+                        // Another client changed the index first, so the new shard is unused.
+                        let address =
+                            MDataAddress::from_kind(MDataKind::Seq, name, root.type_tag());
+                        client3.delete_mdata(address).then(|_| Ok(None)).into_box()
+                    }
+                    Err(error) => err!(error),
+                })
+                .into_box()
+        })
+        .and_then(move |prefix0| match prefix0 {
+            Some(prefix0) => finish_split(&client4, &shard2, prefix0),
+            None => ok!(()),
+        })
+        .into_box()
+}
+
+// Remove the entries not matching the new prefix of the sealed shard, and unseal it. If another
+// client did so first, nothing is changed.
+fn finish_split(client: &impl Client, shard: &MDataInfo, prefix: Prefix) -> Box<CoreFuture<()>> {
+    let client = client.clone();
+    let shard = shard.clone();
+
+    client
+        .list_seq_mdata_entries(shard.name(), shard.type_tag())
+        .and_then(move |entries| {
+            let meta_key = fry!(shard.enc_entry_key(META_KEY));
+            let meta_version = match entries.get(&meta_key) {
+                Some(value) => value.version,
+                None => return err!(missing_meta()),
+            };
+            let (kept, moved): (Vec<_>, Vec<_>) = fry!(decrypt_entries(&shard, entries))
+                .into_iter()
+                .partition(|(key, _)| prefix.matches(&sha3_256(key)));
+
+            let mut actions = MDataSeqEntryActions::new();
+            for (key, value) in moved {
+                let key = fry!(shard.enc_entry_key(&entry_key(&key)));
+                actions = actions.del(key, value.version + 1);
+            }
+            let meta = Meta {
+                prefix,
+                sealed: false,
+                entries: kept.len() as u64,
+            };
+            let value = fry!(shard.enc_entry_value(&fry!(serialize(&meta))));
+            let actions = actions.update(meta_key, value, meta_version + 1);
+
+            client
+                .mutate_seq_mdata_entries(shard.name(), shard.type_tag(), actions)
+                .then(move |result| match result {
+                    Err(ref error) if recovery::is_entry_conflict(error) => {
+                        trace!(
+                            "Split of shard {:?} was finished concurrently",
+                            shard.address()
+                        );
+                        Ok(())
+                    }
+                    result => result,
+                })
+                .into_box()
+        })
+        .into_box()
+}
+
+// Take the entries of the shard whose keys match the prefix.
+fn partition(
+    shard: &MDataInfo,
+    prefix: &Prefix,
+    entries: MDataSeqEntries,
+) -> Result<MDataSeqEntries, CoreError> {
+    let mut moved = MDataSeqEntries::new();
+    for (key, value) in entries {
+        let plain = shard.decrypt(&key)?;
+        if plain.first() == Some(&ENTRY_TAG) && prefix.matches(&sha3_256(&plain[1..])) {
+            let _ = moved.insert(key, value);
+        }
+    }
+    Ok(moved)
+}
+
+// Decrypt the keys of the entries of the map held by the shard, skipping its metadata entry.
+fn decrypt_entries(
+    shard: &MDataInfo,
+    entries: MDataSeqEntries,
+) -> Result<Vec<(Vec<u8>, MDataSeqValue)>, CoreError> {
+    let mut decrypted = Vec::with_capacity(entries.len());
+    for (key, value) in entries {
+        let mut plain = shard.decrypt(&key)?;
+        if plain.first() == Some(&ENTRY_TAG) {
+            let _ = plain.remove(0);
+            decrypted.push((plain, value));
+        }
+    }
+    Ok(decrypted)
+}
+
+// Entries of a new shard: the given entries along with the metadata entry.
+fn new_shard_entries(
+    shard: &MDataInfo,
+    mut entries: MDataSeqEntries,
+    meta: &Meta,
+) -> Result<MDataSeqEntries, CoreError> {
+    let key = shard.enc_entry_key(META_KEY)?;
+    let value = shard.enc_entry_value(&serialize(meta)?)?;
+    let _ = entries.insert(
+        key,
+        MDataSeqValue {
+            data: value,
+            version: 0,
+        },
+    );
+    Ok(entries)
+}
+
+// Key of the shard entry holding the value of the key of the map, before encryption.
+fn entry_key(key: &[u8]) -> Vec<u8> {
+    let mut entry_key = Vec::with_capacity(key.len() + 1);
+    entry_key.push(ENTRY_TAG);
+    entry_key.extend_from_slice(key);
+    entry_key
+}
+
+// Fetch the current index along with the version of its entry.
+fn fetch_index(client: &impl Client, root: &MDataInfo) -> Box<CoreFuture<(u64, Index)>> {
+    let key = fry!(root.enc_entry_key(INDEX_KEY));
+    let root = root.clone();
+
+    client
+        .get_seq_mdata_value(root.name(), root.type_tag(), key)
+        .and_then(move |value| {
+            let index = deserialize(&root.decrypt(&value.data)?)?;
+            Ok((value.version, index))
+        })
+        .into_box()
+}
+
+// Fetch the metadata of the shard along with the version of its entry.
+fn fetch_meta(client: &impl Client, shard: &MDataInfo) -> Box<CoreFuture<(Meta, u64)>> {
+    let key = fry!(shard.enc_entry_key(META_KEY));
+    let shard = shard.clone();
+
+    fetch_value(client, &shard, key)
+        .and_then(move |value| match value {
+            Some((data, version)) => Ok((deserialize(&shard.decrypt(&data)?)?, version)),
+            None => Err(missing_meta()),
+        })
+        .into_box()
+}
+
+// Fetch the encrypted value of the encrypted key along with its version, or `None` if the shard
+// doesn't contain the key.
+fn fetch_value(
+    client: &impl Client,
+    shard: &MDataInfo,
+    key: Vec<u8>,
+) -> Box<CoreFuture<Option<(Vec<u8>, u64)>>> {
+    client
+        .get_seq_mdata_value(shard.name(), shard.type_tag(), key)
+        .then(|result| match result {
+            Ok(value) => Ok(Some((value.data, value.version))),
+            Err(CoreError::DataError(SndError::NoSuchEntry)) => Ok(None),
+            Err(error) => Err(error),
+        })
+        .into_box()
+}
+
+fn encode_index(root: &MDataInfo, index: &Index) -> Result<Vec<u8>, CoreError> {
+    root.enc_entry_value(&serialize(index)?)
+}
+
+fn missing_meta() -> CoreError {
+    CoreError::Unexpected("Sharded map shard has no metadata".to_string())
+}
+
+// `MDataInfo` of the shard with the given name, sharing the type tag and encryption info of the
+// root.
+fn shard_info(root: &MDataInfo, name: XorName) -> MDataInfo {
+    MDataInfo {
+        address: MDataAddress::from_kind(MDataKind::Seq, name, root.type_tag()),
+        ..root.clone()
+    }
+}
+
+// Whether the mutation failed because the shard is full.
+fn is_full(error: &CoreError) -> bool {
+    match *error {
+        CoreError::DataError(SndError::TooManyEntries)
+        | CoreError::DataError(SndError::ExceededSize) => true,
+        _ => false,
+    }
+}
+
+// Whether the operation failed because the entry or the index was mutated concurrently, or the
+// request timed out, so it should be retried.
+fn is_conflict(error: &CoreError) -> bool {
+    match *error {
+        CoreError::RequestTimeout => true,
+        ref error => recovery::is_entry_conflict(error),
+    }
+}
+
+fn bit(bytes: &[u8], i: usize) -> bool {
+    bytes[i / 8] & (0x80 >> (i % 8)) != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::random_client;
+    use futures::stream::{self, Stream};
+    use std::rc::Rc;
+
+    // Test matching and extending prefixes.
+    #[test]
+    fn prefixes() {
+        let hash = [0b1010_0000; 32];
+        let empty = Prefix::default();
+        assert!(empty.matches(&hash));
+
+        let one = empty.pushed(true);
+        assert!(one.matches(&hash));
+        assert!(!empty.pushed(false).matches(&hash));
+
+        let mut prefix = one;
+        for i in 1..9 {
+            prefix = prefix.pushed(bit(&hash, i));
+        }
+        assert_eq!(prefix.bit_count, 9);
+        assert!(prefix.matches(&hash));
+        assert!(!prefix.pushed(!bit(&hash, 9)).matches(&hash));
+    }
+
+    // Test that a map splits its shards as they fill up, keeping all entries accessible.
+    #[test]
+    fn splits() {
+        random_client(|client| {
+            let root = unwrap!(MDataInfo::random_private(MDataKind::Seq, 15_003));
+            let keys: Vec<Vec<u8>> = (0..20u8).map(|i| vec![i]).collect();
+
+            ShardedMap::create(client.clone(), root).and_then(move |mut map| {
+                map.set_max_shard_entries(4);
+
+                let inserts = keys
+                    .iter()
+                    .map(|key| {
+                        let mut value = key.clone();
+                        value.push(0);
+                        value
+                    })
+                    .collect::<Vec<_>>();
+                let map = Rc::new(map);
+                let map2 = map.clone();
+
+                stream::iter_ok(keys.clone().into_iter().zip(inserts))
+                    .for_each(move |(key, value)| map2.insert(&key, &value))
+                    .and_then({
+                        let map = map.clone();
+                        move |()| map.shards().join(map.entries())
+                    })
+                    .and_then(move |(shards, entries)| {
+                        assert!(shards.len() >= 5);
+                        for shard in &shards {
+                            // The shards share the encryption of the root.
+                            assert_eq!(shard.enc_info, map.root().enc_info);
+                        }
+
+                        assert_eq!(entries.len(), keys.len());
+                        for key in &keys {
+                            assert_eq!(entries[key][..], [key[0], 0][..]);
+                        }
+
+                        map.insert(&[3], &[3, 1])
+                            .and_then({
+                                let map = map.clone();
+                                move |()| map.get(&[3])
+                            })
+                            .and_then({
+                                let map = map.clone();
+                                move |value| {
+                                    assert_eq!(value, Some(vec![3, 1]));
+                                    map.remove(&[3])
+                                }
+                            })
+                            .and_then(move |removed| {
+                                assert!(removed);
+                                map.get(&[3]).join(map.remove(&[3]))
+                            })
+                            .map(|(value, removed)| {
+                                assert_eq!(value, None);
+                                assert!(!removed);
+                            })
+                    })
+            })
+        });
+    }
+
+    // Test that a sealed shard can't be mutated, and that the split of a sealed shard is completed
+    // by the next mutation of the map.
+    #[test]
+    fn sealed_shard() {
+        random_client(|client| {
+            let root = unwrap!(MDataInfo::random_private(MDataKind::Seq, 15_004));
+            let client2 = client.clone();
+            let client3 = client.clone();
+            let client4 = client.clone();
+
+            ShardedMap::create(client.clone(), root.clone())
+                .and_then(move |map| {
+                    let map = Rc::new(map);
+                    let map2 = map.clone();
+
+                    stream::iter_ok((0..4u8).map(|i| vec![i]))
+                        .for_each(move |key| map2.insert(&key, &key))
+                        .map(move |()| map)
+                })
+                .and_then(move |map| {
+                    locate(&client2, &root, &[0])
+                        .join(locate(&client2, &root, &[0]))
+                        .map(move |(located, stale)| (map, located, stale))
+                })
+                .and_then(move |(map, located, stale)| {
+                    // Seal the shard without splitting it, as if the split was interrupted.
+                    let meta = Meta {
+                        sealed: true,
+                        ..located.meta.clone()
+                    };
+                    let actions = unwrap!(located.fence(MDataSeqEntryActions::new(), &meta));
+                    let shard = located.shard;
+
+                    client3
+                        .mutate_seq_mdata_entries(shard.name(), shard.type_tag(), actions)
+                        .map(move |()| (map, stale))
+                })
+                .and_then(move |(map, stale)| {
+                    // A mutation based on the metadata fetched before the shard was sealed fails.
+                    let actions = MDataSeqEntryActions::new().del(stale.key.clone(), 1);
+                    let meta = stale.meta.clone();
+
+                    mutate_shard(&client4, map.root(), stale, actions, &meta).then(move |result| {
+                        match result {
+                            Err(ref error) if recovery::is_version_conflict(error) => (),
+                            result => panic!("Unexpected result {:?}", result),
+                        }
+                        map.insert(&[4], &[4])
+                            .and_then(move |()| map.shards().join(map.entries()))
+                    })
+                })
+                .map(|(shards, entries)| {
+                    assert!(shards.len() >= 2);
+                    assert_eq!(entries.len(), 5);
+                    for i in 0..5u8 {
+                        assert_eq!(entries[&vec![i]], vec![i]);
+                    }
+                })
+        });
+    }
+}